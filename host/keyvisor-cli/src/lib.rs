@@ -0,0 +1,306 @@
+//! Talking to the tester over its serial console.
+//!
+//! The USB-serial-JTAG port carries console replies, lines of the event stream, screenshot frames
+//! and defmt log frames. [`Tester`] sends console commands and sorts out what comes back: log
+//! frames are dropped, and event lines received while waiting for a reply are kept for
+//! [`Tester::next_event`]. See `src/console.rs` in the firmware for the commands.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+use keyvisor::{
+    history,
+    kbd::{
+        Debouncer, KeyEvent, TickCount,
+        recording::{self, Run},
+    },
+};
+use keyvisor_protocol::find_event;
+use keyvisor_screenshot::Screenshot;
+
+/// Delimiter of defmt frames, which never occurs in console replies.
+const FRAME_END: u8 = 0x00;
+
+pub struct Tester<P> {
+    port: BufReader<P>,
+    /// Bytes of a line that timed out before it was complete.
+    partial: Vec<u8>,
+    events: VecDeque<String>,
+}
+
+/// Raw key scans recorded by the tester.
+pub struct Trace {
+    pub scan_hz: u64,
+    /// Debounce setting the trace was recorded with.
+    pub debounce_ticks: TickCount,
+    pub runs: Vec<Run>,
+}
+
+/// Test results as exported by the tester, newest first.
+pub struct History {
+    pub header: String,
+    pub rows: Vec<String>,
+}
+
+impl<P: Read + Write> Tester<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port: BufReader::new(port),
+            partial: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Runs a console command and returns the lines of its reply, without the final `ok`. An
+    /// `error:` reply is returned as an error.
+    pub fn command(&mut self, line: &str) -> io::Result<Vec<String>> {
+        let port = self.port.get_mut();
+        port.write_all(line.as_bytes())?;
+        port.write_all(b"\n")?;
+        port.flush()?;
+
+        let mut reply = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if let Some(event) = find_event(line.as_bytes()) {
+                self.events.push_back(event.to_owned());
+            } else if line == "ok" {
+                return Ok(reply);
+            } else if let Some(message) = line.strip_prefix("error:") {
+                return Err(io::Error::other(format!("tester: {}", message.trim())));
+            } else {
+                reply.push(line);
+            }
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: u8) -> io::Result<()> {
+        self.command(&format!("set layout {layout}")).map(drop)
+    }
+
+    /// Selects the board profile, or detects the board if `None`. Takes effect after a restart.
+    pub fn set_board(&mut self, board: Option<u8>) -> io::Result<()> {
+        match board {
+            Some(id) => self.command(&format!("set board {id}")).map(drop),
+            None => self.command("set board auto").map(drop),
+        }
+    }
+
+    /// Switches the event stream on or off. The stream starts with a `hello` event.
+    pub fn set_streaming(&mut self, enabled: bool) -> io::Result<()> {
+        let state = if enabled { "on" } else { "off" };
+        self.command(&format!("stream {state}")).map(drop)
+    }
+
+    /// Waits for the next line of the event stream and returns the event in it, to be decoded
+    /// with [`keyvisor_protocol::Event::parse`]. Times out like the port.
+    pub fn next_event(&mut self) -> io::Result<String> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            let line = self.read_line()?;
+            if let Some(event) = find_event(line.as_bytes()) {
+                return Ok(event.to_owned());
+            }
+        }
+    }
+
+    pub fn export_history(&mut self) -> io::Result<History> {
+        let reply = self.command("results export")?;
+
+        let mut lines = exported(reply, history::EXPORT_BEGIN, history::EXPORT_END);
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid_data("results export without CSV header".to_owned()))?;
+
+        Ok(History {
+            header,
+            rows: lines.collect(),
+        })
+    }
+
+    pub fn start_trace(&mut self) -> io::Result<()> {
+        self.command("trace record").map(drop)
+    }
+
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        self.command("trace stop").map(drop)
+    }
+
+    /// Stops recording and returns the trace.
+    pub fn export_trace(&mut self) -> io::Result<Trace> {
+        let reply = self.command("trace export")?;
+
+        let mut text = String::new();
+        for line in exported(reply, recording::EXPORT_BEGIN, recording::EXPORT_END) {
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        Trace::parse(&text)
+    }
+
+    pub fn screenshot(&mut self) -> io::Result<Screenshot> {
+        // The tester replies before it starts sending the frame.
+        self.command("screenshot")?;
+        Screenshot::read_from(&mut self.port)
+    }
+
+    /// Reads a line, without the line break and any log frames in front of it.
+    fn read_line(&mut self) -> io::Result<String> {
+        self.port.read_until(b'\n', &mut self.partial)?;
+        if self.partial.last() != Some(&b'\n') {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+
+        let line = std::mem::take(&mut self.partial);
+        let text = line
+            .rsplit(|&byte| byte == FRAME_END)
+            .next()
+            .unwrap_or_default();
+
+        Ok(String::from_utf8_lossy(text).trim_end().to_owned())
+    }
+}
+
+impl Trace {
+    /// Parses a trace as written by [`to_text`](Self::to_text).
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut trace = Self {
+            scan_hz: 0,
+            debounce_ticks: 0,
+            runs: Vec::new(),
+        };
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if line == recording::EXPORT_BEGIN || line == recording::EXPORT_END {
+                continue;
+            }
+
+            let invalid = || invalid_data(format!("invalid trace line `{line}`"));
+            match line.split_once(' ') {
+                Some(("scan_hz", value)) => trace.scan_hz = value.parse().map_err(|_| invalid())?,
+                Some(("debounce", value)) => {
+                    trace.debounce_ticks = value.parse().map_err(|_| invalid())?;
+                }
+                _ => trace.runs.push(Run::parse(line).ok_or_else(invalid)?),
+            }
+        }
+
+        if trace.scan_hz == 0 {
+            return Err(invalid_data("trace without scan rate".to_owned()));
+        }
+
+        Ok(trace)
+    }
+
+    /// Writes the trace in the format exported by the tester.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\nscan_hz {}\ndebounce {}\n",
+            recording::EXPORT_BEGIN,
+            self.scan_hz,
+            self.debounce_ticks
+        );
+        for run in &self.runs {
+            let _ = writeln!(text, "{run}");
+        }
+        text.push_str(recording::EXPORT_END);
+        text.push('\n');
+        text
+    }
+
+    /// Runs the recorded scans through the debouncer of the firmware and returns the key events
+    /// it reports, along with the time since the start of the recording in milliseconds.
+    pub fn replay(&self, debounce_ticks: TickCount) -> Vec<(u64, KeyEvent)> {
+        let mut debouncer = Debouncer::new();
+        let mut events = Vec::new();
+        let mut tick = 0u64;
+
+        for run in &self.runs {
+            for _ in 0..run.ticks {
+                for (col, &mask) in run.masks.iter().enumerate() {
+                    let update = debouncer.update(col, mask, debounce_ticks);
+                    let ms = tick * 1000 / self.scan_hz;
+                    events.extend(update.events(col).map(|event| (ms, event)));
+                }
+                tick += 1;
+            }
+        }
+
+        events
+    }
+}
+
+impl History {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for line in std::iter::once(&self.header).chain(&self.rows) {
+            csv.push_str(line);
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Converts the results to a JSON array of objects named after the CSV columns. Numeric
+    /// columns become numbers, all others strings.
+    pub fn to_json(&self) -> String {
+        let columns: Vec<&str> = self.header.split(',').collect();
+
+        let mut json = String::from("[");
+        for (i, row) in self.rows.iter().enumerate() {
+            json.push_str(if i == 0 { "\n  {" } else { ",\n  {" });
+
+            for (j, (column, value)) in columns.iter().zip(row.split(',')).enumerate() {
+                if j > 0 {
+                    json.push_str(", ");
+                }
+                write_json_string(&mut json, column);
+                json.push_str(": ");
+                if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) {
+                    json.push_str(value);
+                } else {
+                    write_json_string(&mut json, value);
+                }
+            }
+
+            json.push('}');
+        }
+        json.push_str(if self.rows.is_empty() { "]\n" } else { "\n]\n" });
+
+        json
+    }
+}
+
+/// Returns the lines between the `begin` and `end` lines of an export.
+fn exported(reply: Vec<String>, begin: &str, end: &str) -> impl Iterator<Item = String> {
+    reply
+        .into_iter()
+        .skip_while(move |line| line != begin)
+        .skip(1)
+        .take_while(move |line| line != end)
+}
+
+fn write_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,294 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, Write as _},
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use keyvisor::kbd::KeyEvent;
+use keyvisor_cli::{Tester, Trace};
+use keyvisor_protocol::Event;
+
+/// Talks to the tester over its USB-serial-JTAG port.
+#[derive(Parser)]
+struct Args {
+    /// Serial port of the tester, e.g. /dev/ttyACM0. Required by all commands but `replay`.
+    #[arg(short, long)]
+    port: Option<String>,
+
+    /// Seconds to wait for a reply.
+    #[arg(long, default_value_t = 5)]
+    timeout: u64,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the board, settings and number of results.
+    Status,
+    /// Show key presses and test verdicts as they happen.
+    Events {
+        /// Also show the key scanner statistics, once a second.
+        #[arg(long)]
+        stats: bool,
+        /// Stop after this many key events instead of running until interrupted.
+        #[arg(long)]
+        count: Option<usize>,
+    },
+    /// List the keypad layouts built into the firmware, or select one.
+    Layout { layout: Option<u8> },
+    /// Select a board profile built into the firmware by its ID, or `auto` to detect it. Takes
+    /// effect after a restart.
+    Board {
+        #[arg(value_parser = parse_board)]
+        board: Board,
+    },
+    /// Save the test results, newest first.
+    History {
+        #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        /// Where to write the results, standard output if omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Save the screen contents as a PNG image.
+    Screenshot { output: PathBuf },
+    /// Record the raw key scans, to replay them with other debounce settings.
+    Trace {
+        #[command(subcommand)]
+        action: TraceAction,
+    },
+    /// Run a recorded trace through the debouncer of the firmware and show the key events each
+    /// debounce setting produces. Doesn't need the tester.
+    Replay {
+        trace: PathBuf,
+        /// Debounce settings to compare, in scans. Defaults to the one the trace was recorded with.
+        #[arg(short, long, value_delimiter = ',', value_parser = clap::value_parser!(u8).range(1..))]
+        debounce: Vec<u8>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TraceAction {
+    /// Start a new recording, which stops by itself after 20 seconds.
+    Record,
+    /// Stop recording.
+    Stop,
+    /// Stop recording and save the trace.
+    Export { output: PathBuf },
+}
+
+#[derive(Clone, Copy)]
+enum Board {
+    Auto,
+    Id(u8),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    if let Command::Replay { trace, debounce } = &args.command {
+        return replay(&Trace::parse(&fs::read_to_string(trace)?)?, debounce);
+    }
+
+    let Some(port) = &args.port else {
+        return Err("the tester's serial port has to be given with --port".into());
+    };
+    let port = serialport::new(port, 115_200)
+        .timeout(Duration::from_secs(args.timeout))
+        .open()?;
+    let mut tester = Tester::new(port);
+
+    match args.command {
+        Command::Status => print_lines(&tester.command("status")?),
+        Command::Events { stats, count } => show_events(&mut tester, stats, count)?,
+        Command::Layout { layout: None } => print_lines(&tester.command("layout list")?),
+        Command::Layout {
+            layout: Some(layout),
+        } => tester.set_layout(layout)?,
+        Command::Board { board } => tester.set_board(match board {
+            Board::Auto => None,
+            Board::Id(id) => Some(id),
+        })?,
+        Command::History { format, output } => {
+            let history = tester.export_history()?;
+            let text = match format {
+                Format::Csv => history.to_csv(),
+                Format::Json => history.to_json(),
+            };
+
+            match output {
+                Some(path) => {
+                    File::create(&path)?.write_all(text.as_bytes())?;
+                    println!("wrote {} results to {}", history.rows.len(), path.display());
+                }
+                None => io::stdout().write_all(text.as_bytes())?,
+            }
+        }
+        Command::Screenshot { output } => {
+            let screenshot = tester.screenshot()?;
+            screenshot.write_png(File::create(&output)?)?;
+            println!(
+                "wrote {}x{} screenshot to {}",
+                screenshot.width,
+                screenshot.height,
+                output.display()
+            );
+        }
+        Command::Trace {
+            action: TraceAction::Record,
+        } => tester.start_trace()?,
+        Command::Trace {
+            action: TraceAction::Stop,
+        } => tester.stop_trace()?,
+        Command::Trace {
+            action: TraceAction::Export { output },
+        } => {
+            let trace = tester.export_trace()?;
+            fs::write(&output, trace.to_text())?;
+            let ticks: u32 = trace.runs.iter().map(|run| run.ticks).sum();
+            println!("wrote {ticks} scans to {}", output.display());
+        }
+        Command::Replay { .. } => unreachable!("handled without the tester"),
+    }
+
+    Ok(())
+}
+
+fn replay(trace: &Trace, debounce: &[u8]) -> Result<(), Box<dyn Error>> {
+    let recorded = [trace.debounce_ticks];
+    let settings = if debounce.is_empty() {
+        &recorded[..]
+    } else {
+        debounce
+    };
+
+    for (i, &debounce_ticks) in settings.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        let events = trace.replay(debounce_ticks);
+        let recorded = if debounce_ticks == trace.debounce_ticks {
+            " (recorded)"
+        } else {
+            ""
+        };
+        println!(
+            "debounce {debounce_ticks}{recorded}: {} events",
+            events.len()
+        );
+
+        for (ms, event) in events {
+            let (key, state) = match event {
+                KeyEvent::KeyDown(key) => (key, "down"),
+                KeyEvent::KeyUp(key) => (key, "up"),
+            };
+            println!("{:>10} key {} {state}", time(ms), key.char());
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_board(arg: &str) -> Result<Board, String> {
+    match arg {
+        "auto" => Ok(Board::Auto),
+        id => id
+            .parse()
+            .map(Board::Id)
+            .map_err(|_| "expected a board ID or `auto`".to_owned()),
+    }
+}
+
+fn print_lines(lines: &[String]) {
+    for line in lines {
+        println!("{line}");
+    }
+}
+
+fn show_events<P: io::Read + io::Write>(
+    tester: &mut Tester<P>,
+    stats: bool,
+    count: Option<usize>,
+) -> io::Result<()> {
+    tester.set_streaming(true)?;
+
+    let mut keys = 0;
+    while count.is_none_or(|count| keys < count) {
+        let json = match tester.next_event() {
+            Ok(json) => json,
+            // Nothing happened on the tester.
+            Err(error) if error.kind() == io::ErrorKind::TimedOut => continue,
+            Err(error) => return Err(error),
+        };
+
+        // Events a newer firmware added or that were garbled by log frames.
+        let Ok(event) = Event::parse(&json) else {
+            continue;
+        };
+
+        match event {
+            Event::Hello { firmware, board } => {
+                println!("connected to {board}, firmware {firmware}");
+            }
+            Event::Key {
+                ms,
+                key,
+                row,
+                col,
+                pressed,
+            } => {
+                let state = if pressed { "down" } else { "up" };
+                println!(
+                    "{:>10} key {key} {state:<4} (row {row}, col {col})",
+                    time(ms)
+                );
+                keys += 1;
+            }
+            Event::Scan {
+                ms,
+                scans,
+                changes,
+                events,
+                debounce,
+            } if stats => {
+                println!(
+                    "{:>10} {scans} scans, {changes} changes, {events} events, debounce {debounce}",
+                    time(ms)
+                );
+            }
+            Event::Scan { .. } => {}
+            Event::Verdict {
+                ms,
+                board,
+                passed,
+                failed,
+                chatter,
+            } => {
+                let result = if passed { "PASS" } else { "FAIL" };
+                print!("{:>10} board {board}: {result}", time(ms));
+                if !failed.is_empty() {
+                    print!(", failed {failed}");
+                }
+                println!(", chatter {chatter}");
+            }
+        }
+    }
+
+    tester.set_streaming(false)
+}
+
+fn time(ms: u64) -> String {
+    format!("{}.{:03} s", ms / 1000, ms % 1000)
+}
@@ -0,0 +1,279 @@
+//! Runs `keyvisor-cli` against a fake tester on a pseudo-terminal.
+//!
+//! The fake tester answers each command line with a canned reply, preceded by a defmt-like log
+//! frame as the USB-serial-JTAG port of the real tester would carry.
+
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    os::fd::{FromRawFd, OwnedFd},
+    path::PathBuf,
+    process::{Command, Output},
+    ptr,
+    thread::{self, JoinHandle},
+};
+
+const LOG_FRAME: &[u8] = b"\xff\x00\x03\x12\x34\x00";
+
+struct FakeTester {
+    path: PathBuf,
+    /// Kept open so that the master side doesn't see a hangup before the CLI opens the port.
+    slave: OwnedFd,
+    commands: JoinHandle<Vec<String>>,
+}
+
+impl FakeTester {
+    fn start(reply: impl Fn(&str) -> Vec<u8> + Send + 'static) -> Self {
+        let (mut master, mut slave) = (0, 0);
+        let mut name = [0; 64];
+        // SAFETY: the buffer is larger than any pty path, and the termios and window size are
+        // optional.
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                name.as_mut_ptr(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        assert_eq!(result, 0, "openpty failed");
+
+        // SAFETY: openpty returned two fresh descriptors.
+        let (master, slave) = unsafe { (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        // SAFETY: the name was NUL-terminated by openpty.
+        let path = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+        let path = PathBuf::from(path.to_str().unwrap());
+
+        let commands = thread::spawn(move || {
+            let mut writer = master.try_clone().unwrap();
+            let mut commands = Vec::new();
+
+            // Reading fails once the CLI and the test closed the slave side.
+            for line in BufReader::new(master).lines().map_while(Result::ok) {
+                writer.write_all(LOG_FRAME).unwrap();
+                writer.write_all(&reply(&line)).unwrap();
+                commands.push(line);
+            }
+
+            commands
+        });
+
+        Self {
+            path,
+            slave,
+            commands,
+        }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_keyvisor-cli"))
+            .arg("--port")
+            .arg(&self.path)
+            .args(["--timeout", "2"])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Returns the command lines the fake tester received.
+    fn finish(self) -> Vec<String> {
+        drop(self.slave);
+        self.commands.join().unwrap()
+    }
+}
+
+fn stdout(output: &Output) -> &str {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("pty");
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+#[test]
+fn status_is_printed_without_log_frames() {
+    let tester = FakeTester::start(|_| b"board: PCB rev 2 (2)\r\nresults: 3\r\nok\r\n".to_vec());
+
+    let output = tester.run(&["status"]);
+    assert_eq!(stdout(&output), "board: PCB rev 2 (2)\nresults: 3\n");
+    assert_eq!(tester.finish(), ["status"]);
+}
+
+#[test]
+fn layout_and_board_are_selected() {
+    let tester = FakeTester::start(|line| match line {
+        "layout list" => b"* 0 phone: 3x4 keypad, 1 2 3 at the top\nok\n".to_vec(),
+        _ => b"ok\n".to_vec(),
+    });
+
+    let output = tester.run(&["layout"]);
+    assert_eq!(stdout(&output), "* 0 phone: 3x4 keypad, 1 2 3 at the top\n");
+    assert_eq!(stdout(&tester.run(&["layout", "0"])), "");
+    assert_eq!(stdout(&tester.run(&["board", "auto"])), "");
+    assert_eq!(stdout(&tester.run(&["board", "2"])), "");
+
+    assert_eq!(
+        tester.finish(),
+        [
+            "layout list",
+            "set layout 0",
+            "set board auto",
+            "set board 2"
+        ]
+    );
+}
+
+#[test]
+fn errors_fail_the_command() {
+    let tester = FakeTester::start(|_| b"error: usage: set layout <n>\n".to_vec());
+
+    let output = tester.run(&["layout", "7"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("tester: usage: set layout <n>"));
+    tester.finish();
+}
+
+fn export_reply(_: &str) -> Vec<u8> {
+    concat!(
+        "#keyvisor-history 1\n",
+        "board,result,failed_keys,chatter,uptime_ms,firmware\n",
+        "42,fail,4 #,5:2,61000,0.1.0\n",
+        "41,pass,,,30500,0.1.0\n",
+        "#end\n",
+        "ok\n",
+    )
+    .into()
+}
+
+#[test]
+fn history_is_saved_as_csv() {
+    let tester = FakeTester::start(export_reply);
+
+    let output = tester.run(&["history"]);
+    assert_eq!(
+        stdout(&output),
+        concat!(
+            "board,result,failed_keys,chatter,uptime_ms,firmware\n",
+            "42,fail,4 #,5:2,61000,0.1.0\n",
+            "41,pass,,,30500,0.1.0\n",
+        )
+    );
+    assert_eq!(tester.finish(), ["results export"]);
+}
+
+#[test]
+fn history_is_saved_as_json() {
+    let tester = FakeTester::start(export_reply);
+    let path = temp_path("history.json");
+
+    let output = tester.run(&["history", "--format", "json", "-o", path.to_str().unwrap()]);
+    assert!(stdout(&output).starts_with("wrote 2 results to "));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        concat!(
+            "[\n",
+            r#"  {"board": 42, "result": "fail", "failed_keys": "4 #", "chatter": "5:2", "uptime_ms": 61000, "firmware": "0.1.0"},"#,
+            "\n",
+            r#"  {"board": 41, "result": "pass", "failed_keys": "", "chatter": "", "uptime_ms": 30500, "firmware": "0.1.0"}"#,
+            "\n]\n",
+        )
+    );
+    tester.finish();
+}
+
+#[test]
+fn screenshot_is_saved_as_png() {
+    let tester = FakeTester::start(|_| {
+        let mut reply = b"ok\n".to_vec();
+        reply.extend_from_slice(LOG_FRAME);
+        reply.extend_from_slice(b"KVSS\x02\x01");
+        reply.extend_from_slice(&2u16.to_le_bytes());
+        reply.extend_from_slice(&1u16.to_le_bytes());
+        reply.extend_from_slice(&4u32.to_le_bytes());
+        // CRC-32 of the pixels.
+        reply.extend_from_slice(&0xef9e_eca1u32.to_le_bytes());
+        reply.extend_from_slice(&[0xf8, 0x00, 0x00, 0x1f]);
+        reply
+    });
+    let path = temp_path("screen.png");
+
+    let output = tester.run(&["screenshot", path.to_str().unwrap()]);
+    assert!(stdout(&output).starts_with("wrote 2x1 screenshot to "));
+    assert_eq!(tester.finish(), ["screenshot"]);
+
+    let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+    let mut reader = decoder.read_info().unwrap();
+    let mut rgb = vec![0; reader.output_buffer_size().unwrap()];
+    reader.next_frame(&mut rgb).unwrap();
+    assert_eq!(rgb, [255, 0, 0, 0, 0, 255]);
+}
+
+/// Events following the reply to `stream on`, including one of an unsupported version.
+const EVENTS: &str = r#"{"v":1,"type":"key","ms":1500,"key":"5","row":1,"col":1,"state":"down"}
+{"v":1,"type":"scan","ms":2000,"scans":400,"changes":3,"events":1,"debounce":10}
+{"v":2,"type":"key"}
+{"v":1,"type":"verdict","ms":2100,"board":42,"result":"fail","failed":"4 #","chatter":2}
+{"v":1,"type":"key","ms":2250,"key":"5","row":1,"col":1,"state":"up"}
+"#;
+
+#[test]
+fn events_are_shown_live() {
+    let tester = FakeTester::start(|line| match line {
+        "stream on" => {
+            let mut reply =
+                br#"{"v":1,"type":"hello","firmware":"0.1.0","board":"PCB rev 2"}"#.to_vec();
+            reply.extend_from_slice(b"\nok\n");
+            reply.extend_from_slice(EVENTS.as_bytes());
+            reply
+        }
+        _ => b"ok\n".to_vec(),
+    });
+
+    let output = tester.run(&["events", "--count", "2"]);
+    assert_eq!(
+        stdout(&output),
+        concat!(
+            "connected to PCB rev 2, firmware 0.1.0\n",
+            "   1.500 s key 5 down (row 1, col 1)\n",
+            "   2.100 s board 42: FAIL, failed 4 #, chatter 2\n",
+            "   2.250 s key 5 up   (row 1, col 1)\n",
+        )
+    );
+    assert_eq!(tester.finish(), ["stream on", "stream off"]);
+}
+
+#[test]
+fn trace_is_saved() {
+    let tester = FakeTester::start(|line| match line {
+        "trace export" => concat!(
+            "#keyvisor-trace 1\n",
+            "scan_hz 400\n",
+            "debounce 10\n",
+            "100 0 0 0\n",
+            "20 0 2 0\n",
+            "#end\n",
+            "ok\n",
+        )
+        .into(),
+        _ => b"ok\n".to_vec(),
+    });
+    let path = temp_path("trace.txt");
+
+    assert_eq!(stdout(&tester.run(&["trace", "record"])), "");
+    let output = tester.run(&["trace", "export", path.to_str().unwrap()]);
+    assert!(stdout(&output).starts_with("wrote 120 scans to "));
+    assert_eq!(tester.finish(), ["trace record", "trace export"]);
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "#keyvisor-trace 1\nscan_hz 400\ndebounce 10\n100 0 0 0\n20 0 2 0\n#end\n"
+    );
+}
@@ -0,0 +1,78 @@
+//! Replays traces through the debouncer, which doesn't need a tester.
+
+use std::{fs, path::PathBuf, process::Command};
+
+use keyvisor::kbd::{Key, KeyEvent};
+use keyvisor_cli::Trace;
+
+/// Key 5 bouncing for 4 scans on press and release.
+const BOUNCY_PRESS: &str = "\
+#keyvisor-trace 1
+scan_hz 400
+debounce 4
+10 0 0 0
+2 0 2 0
+2 0 0 0
+20 0 2 0
+2 0 0 0
+2 0 2 0
+20 0 0 0
+#end
+";
+
+const KEY_5: Key = Key { col: 1, row: 1 };
+
+#[test]
+fn trace_survives_formatting() {
+    let trace = Trace::parse(BOUNCY_PRESS).unwrap();
+
+    assert_eq!(trace.scan_hz, 400);
+    assert_eq!(trace.debounce_ticks, 4);
+    assert_eq!(trace.runs.len(), 7);
+    assert_eq!(trace.to_text(), BOUNCY_PRESS);
+}
+
+#[test]
+fn invalid_traces_are_rejected() {
+    assert!(Trace::parse("#keyvisor-trace 1\nscan_hz 400\n5 0 0\n").is_err());
+    assert!(Trace::parse("#keyvisor-trace 1\n5 0 0 0\n").is_err());
+}
+
+#[test]
+fn debounce_setting_changes_the_events() {
+    let trace = Trace::parse(BOUNCY_PRESS).unwrap();
+
+    // The press becomes stable at scan 14, the release at scan 38.
+    assert_eq!(
+        trace.replay(4),
+        [
+            (47, KeyEvent::KeyDown(KEY_5)),
+            (107, KeyEvent::KeyUp(KEY_5))
+        ]
+    );
+    // Every bounce is reported without debouncing.
+    assert_eq!(trace.replay(0).len(), 6);
+    // Longer than the key was held.
+    assert_eq!(trace.replay(25), []);
+}
+
+#[test]
+fn replay_compares_settings() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("bouncy.trace");
+    fs::write(&path, BOUNCY_PRESS).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_keyvisor-cli"))
+        .args(["replay", path.to_str().unwrap(), "--debounce", "4,25"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "debounce 4 (recorded): 2 events\n\
+         \x20  0.047 s key 5 down\n\
+         \x20  0.107 s key 5 up\n\
+         \n\
+         debounce 25: 0 events\n"
+    );
+}
@@ -0,0 +1,131 @@
+use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource, Operation};
+
+/// A device with 256 registers from a pointer that the first byte written sets, and that
+/// increments with every byte written or read.
+pub struct MockI2cDevice {
+    address: u8,
+    registers: [u8; 256],
+    pointer: u8,
+}
+
+impl MockI2cDevice {
+    /// A device at `address` whose registers are cleared except for `registers`.
+    pub fn new(address: u8, registers: &[(u8, u8)]) -> Self {
+        let mut device = Self {
+            address,
+            registers: [0; 256],
+            pointer: 0,
+        };
+        for &(register, value) in registers {
+            device.registers[usize::from(register)] = value;
+        }
+        device
+    }
+}
+
+impl embedded_hal::i2c::ErrorType for MockI2cDevice {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockI2cDevice {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    let Some((&register, data)) = bytes.split_first() else {
+                        continue;
+                    };
+                    self.pointer = register;
+                    for &byte in data {
+                        self.registers[usize::from(self.pointer)] = byte;
+                        self.pointer = self.pointer.wrapping_add(1);
+                    }
+                }
+                Operation::Read(bytes) => {
+                    for byte in bytes.iter_mut() {
+                        *byte = self.registers[usize::from(self.pointer)];
+                        self.pointer = self.pointer.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Devices on a shared bus, each transaction going to the one that acknowledges its address.
+#[derive(Default)]
+pub struct MockI2cBus {
+    devices: Vec<Box<dyn I2c<Error = ErrorKind>>>,
+    fault: Option<BusFault>,
+}
+
+#[derive(Clone, Copy)]
+enum BusFault {
+    /// Every transfer fails the same way, as with SCL held low.
+    Error(ErrorKind),
+    /// Every address seems acknowledged and reads zeros.
+    SdaLow,
+}
+
+impl MockI2cBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, device: impl I2c<Error = ErrorKind> + 'static) -> Self {
+        self.devices.push(Box::new(device));
+        self
+    }
+
+    /// Makes every transfer fail with `kind`, as when a line has no pull-up.
+    pub fn fail_with(&mut self, kind: ErrorKind) {
+        self.fault = Some(BusFault::Error(kind));
+    }
+
+    /// Shorts SDA to ground, which reads as an acknowledgement from every address.
+    pub fn short_sda(&mut self) {
+        self.fault = Some(BusFault::SdaLow);
+    }
+}
+
+impl embedded_hal::i2c::ErrorType for MockI2cBus {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockI2cBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        match self.fault {
+            Some(BusFault::Error(kind)) => return Err(kind),
+            Some(BusFault::SdaLow) => {
+                for operation in operations {
+                    if let Operation::Read(bytes) = operation {
+                        bytes.fill(0);
+                    }
+                }
+                return Ok(());
+            }
+            None => {}
+        }
+
+        for device in &mut self.devices {
+            match device.transaction(address, operations) {
+                Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)) => {}
+                result => return result,
+            }
+        }
+        Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+    rc::Rc,
+};
+
+use embedded_hal::{
+    digital::{self, InputPin, OutputPin},
+    i2c::{self, ErrorKind, I2c, NoAcknowledgeSource, Operation},
+    spi::{self, SpiBus},
+};
+use keyvisor::kbd::{Key, KeySet, N_COLS, N_ROWS, backend::ExpanderChip};
+
+/// Keys held down on a keypad with a diode at each key, shared by the doubles of the hardware
+/// that reads it.
+#[derive(Clone, Default)]
+pub struct MockKeypad {
+    pressed: Rc<Cell<KeySet>>,
+}
+
+impl MockKeypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&self, key: Key) {
+        self.set(key, true);
+    }
+
+    pub fn release(&self, key: Key) {
+        self.set(key, false);
+    }
+
+    fn set(&self, key: Key, pressed: bool) {
+        let mut keys = self.pressed.get();
+        keys.set(key.index(), pressed);
+        self.pressed.set(keys);
+    }
+
+    /// Levels of the rows, set for high, when the columns whose bits are cleared in `columns`
+    /// are driven low and the rows are pulled up.
+    fn rows(&self, columns: u8) -> u8 {
+        let pressed = self.pressed.get();
+        Key::all()
+            .filter(|key| pressed[key.index()] && columns & (1 << key.col) == 0)
+            .fold(0xff, |rows, key| rows & !(1 << key.row))
+    }
+
+    /// Pins of the columns and rows, for a matrix on GPIOs.
+    pub fn gpio_pins(&self) -> ([MockColumnPin; N_COLS], [MockRowPin; N_ROWS]) {
+        let columns = Rc::new(Cell::new(0xff));
+        (
+            std::array::from_fn(|col| MockColumnPin {
+                columns: columns.clone(),
+                col,
+            }),
+            std::array::from_fn(|row| MockRowPin {
+                keypad: self.clone(),
+                columns: columns.clone(),
+                row,
+            }),
+        )
+    }
+
+    /// Pins of the columns and row pins, for a duplex matrix, see [`Key::duplex`].
+    pub fn duplex_pins(&self) -> ([MockDuplexPin; N_COLS], [MockDuplexPin; N_ROWS / 2]) {
+        let driven = Rc::new(Cell::new(DrivenLines::default()));
+        let pin = |line| MockDuplexPin {
+            keypad: self.clone(),
+            driven: driven.clone(),
+            line,
+        };
+        (
+            std::array::from_fn(|col| pin(DuplexLine::Column(col))),
+            std::array::from_fn(|row_pin| pin(DuplexLine::Row(row_pin))),
+        )
+    }
+
+    /// A 74HC595 and 74HC165 wired to the keypad, and their latch.
+    pub fn shift_registers(&self) -> (MockShiftRegisters, MockLatch) {
+        let state = Rc::new(RefCell::new(ShiftRegisterState {
+            shifted: 0xff,
+            columns: 0xff,
+            rows: 0xff,
+            latch_high: true,
+        }));
+        (
+            MockShiftRegisters {
+                state: state.clone(),
+            },
+            MockLatch {
+                keypad: self.clone(),
+                state,
+            },
+        )
+    }
+
+    /// An I/O expander at `address` in its power-on state, wired to the keypad.
+    pub fn expander(&self, chip: ExpanderChip, address: u8) -> MockExpander {
+        let mut registers = [0; 0x16];
+        match chip {
+            // IODIRA and IODIRB.
+            ExpanderChip::Mcp23017 => registers[..2].fill(0xff),
+            // Output and configuration ports.
+            ExpanderChip::Pca9555 => {
+                registers[2..4].fill(0xff);
+                registers[6..8].fill(0xff);
+            }
+        }
+        MockExpander {
+            keypad: self.clone(),
+            chip,
+            address,
+            registers,
+            pointer: 0,
+        }
+    }
+}
+
+/// An open-drain column pin. Column bits are cleared while driven low.
+pub struct MockColumnPin {
+    columns: Rc<Cell<u8>>,
+    col: usize,
+}
+
+impl digital::ErrorType for MockColumnPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockColumnPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.columns.set(self.columns.get() & !(1 << self.col));
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.columns.set(self.columns.get() | 1 << self.col);
+        Ok(())
+    }
+}
+
+/// A row pin with a pull-up.
+pub struct MockRowPin {
+    keypad: MockKeypad,
+    columns: Rc<Cell<u8>>,
+    row: usize,
+}
+
+impl digital::ErrorType for MockRowPin {
+    type Error = Infallible;
+}
+
+impl InputPin for MockRowPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.keypad.rows(self.columns.get()) & 1 << self.row != 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
+
+/// Lines of a duplex matrix that are driven low, bit n for line n.
+#[derive(Clone, Copy, Default)]
+struct DrivenLines {
+    columns: u8,
+    rows: u8,
+}
+
+#[derive(Clone, Copy)]
+enum DuplexLine {
+    Column(usize),
+    Row(usize),
+}
+
+/// An open-drain, pulled-up line of a duplex matrix. It reads low while driven low, or while a
+/// pressed key connects it to a line that is, through the key's diode: a column pulls down the
+/// row pin of the key above it, and a row pin pulls down the column of the key below it.
+pub struct MockDuplexPin {
+    keypad: MockKeypad,
+    driven: Rc<Cell<DrivenLines>>,
+    line: DuplexLine,
+}
+
+impl MockDuplexPin {
+    fn drive(&mut self, low: bool) {
+        let mut driven = self.driven.get();
+        let (lines, i) = match self.line {
+            DuplexLine::Column(col) => (&mut driven.columns, col),
+            DuplexLine::Row(row_pin) => (&mut driven.rows, row_pin),
+        };
+        if low {
+            *lines |= 1 << i;
+        } else {
+            *lines &= !(1 << i);
+        }
+        self.driven.set(driven);
+    }
+}
+
+impl digital::ErrorType for MockDuplexPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockDuplexPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.drive(true);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.drive(false);
+        Ok(())
+    }
+}
+
+impl InputPin for MockDuplexPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_low()?)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        let driven = self.driven.get();
+        let pressed = self.keypad.pressed.get();
+        let low = match self.line {
+            DuplexLine::Column(col) => {
+                driven.columns & 1 << col != 0
+                    || (0..N_ROWS / 2).any(|row_pin| {
+                        driven.rows & 1 << row_pin != 0
+                            && pressed[Key::duplex(col, row_pin, false).index()]
+                    })
+            }
+            DuplexLine::Row(row_pin) => {
+                driven.rows & 1 << row_pin != 0
+                    || (0..N_COLS).any(|col| {
+                        driven.columns & 1 << col != 0
+                            && pressed[Key::duplex(col, row_pin, true).index()]
+                    })
+            }
+        };
+        Ok(low)
+    }
+}
+
+struct ShiftRegisterState {
+    /// Shift register of the 595.
+    shifted: u8,
+    /// Outputs of the 595, Q0 in bit 0.
+    columns: u8,
+    /// Shift register of the 165, D0 in bit 0.
+    rows: u8,
+    latch_high: bool,
+}
+
+/// The SPI bus to a 74HC595 on MOSI and a 74HC165 on MISO, whose serial input is tied high.
+pub struct MockShiftRegisters {
+    state: Rc<RefCell<ShiftRegisterState>>,
+}
+
+impl MockShiftRegisters {
+    /// Outputs of the 595, Q0 in bit 0.
+    pub fn columns(&self) -> u8 {
+        self.state.borrow().columns
+    }
+
+    fn shift(&mut self, byte: u8) -> u8 {
+        let mut state = self.state.borrow_mut();
+        state.shifted = byte;
+        let rows = state.rows;
+        state.rows = 0xff;
+        rows
+    }
+}
+
+impl spi::ErrorType for MockShiftRegisters {
+    type Error = Infallible;
+}
+
+impl SpiBus for MockShiftRegisters {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        for word in words {
+            *word = self.shift(0);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        for &word in words {
+            self.shift(word);
+        }
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        for i in 0..read.len().max(write.len()) {
+            let word = self.shift(write.get(i).copied().unwrap_or(0));
+            if let Some(read) = read.get_mut(i) {
+                *read = word;
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        for word in words {
+            *word = self.shift(*word);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// RCLK of the 595 and SH/LD of the 165.
+pub struct MockLatch {
+    keypad: MockKeypad,
+    state: Rc<RefCell<ShiftRegisterState>>,
+}
+
+impl digital::ErrorType for MockLatch {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockLatch {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        let mut state = self.state.borrow_mut();
+        state.rows = self.keypad.rows(state.columns);
+        state.latch_high = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let mut state = self.state.borrow_mut();
+        if !state.latch_high {
+            state.columns = state.shifted;
+        }
+        state.latch_high = true;
+        Ok(())
+    }
+}
+
+/// An MCP23017 or PCA9555 with the columns on its first port and the rows on its second.
+///
+/// Rows without a pull-up read low. Register addresses advance after each byte.
+pub struct MockExpander {
+    keypad: MockKeypad,
+    chip: ExpanderChip,
+    address: u8,
+    registers: [u8; 0x16],
+    pointer: usize,
+}
+
+impl MockExpander {
+    /// Levels of the column port, set for high.
+    fn columns(&self) -> u8 {
+        let (direction, output) = match self.chip {
+            ExpanderChip::Mcp23017 => (self.registers[0x00], self.registers[0x14]),
+            ExpanderChip::Pca9555 => (self.registers[0x06], self.registers[0x02]),
+        };
+        // Inputs float high as far as the keys are concerned.
+        direction | output
+    }
+
+    fn read_register(&self, register: usize) -> u8 {
+        let rows = self.keypad.rows(self.columns());
+        match (self.chip, register) {
+            // GPIOB, with the pull-ups of GPPUB.
+            (ExpanderChip::Mcp23017, 0x13) => rows & self.registers[0x0d],
+            // Input port 1.
+            (ExpanderChip::Pca9555, 0x01) => rows,
+            _ => self.registers[register],
+        }
+    }
+}
+
+impl i2c::ErrorType for MockExpander {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockExpander {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    let Some((&register, data)) = bytes.split_first() else {
+                        continue;
+                    };
+                    self.pointer = usize::from(register);
+                    for &byte in data {
+                        *self
+                            .registers
+                            .get_mut(self.pointer)
+                            .ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data))? = byte;
+                        self.pointer += 1;
+                    }
+                }
+                Operation::Read(bytes) => {
+                    for byte in bytes.iter_mut() {
+                        if self.pointer >= self.registers.len() {
+                            return Err(ErrorKind::Other);
+                        }
+                        *byte = self.read_register(self.pointer);
+                        self.pointer += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+    rc::Rc,
+};
+
+use embedded_hal::{
+    digital::{self, OutputPin},
+    i2c::{self, ErrorKind, I2c, NoAcknowledgeSource, Operation},
+    pwm::{self, SetDutyCycle},
+};
+use keyvisor::key_leds::LedDriverChip;
+
+/// A PWM channel, whose duty cycle can be read from any of its clones.
+#[derive(Clone)]
+pub struct MockPwmChannel {
+    max: u16,
+    duty: Rc<Cell<u16>>,
+}
+
+impl MockPwmChannel {
+    pub fn new(max: u16) -> Self {
+        Self {
+            max,
+            duty: Rc::new(Cell::new(max)),
+        }
+    }
+
+    pub fn duty(&self) -> u16 {
+        self.duty.get()
+    }
+}
+
+impl pwm::ErrorType for MockPwmChannel {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for MockPwmChannel {
+    fn max_duty_cycle(&self) -> u16 {
+        self.max
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        assert!(duty <= self.max, "duty {duty} out of {}", self.max);
+        self.duty.set(duty);
+        Ok(())
+    }
+}
+
+/// An active-high LED on an output pin, whose state can be read from any of its clones.
+#[derive(Clone, Default)]
+pub struct MockLedPin {
+    lit: Rc<Cell<bool>>,
+}
+
+impl MockLedPin {
+    pub fn is_lit(&self) -> bool {
+        self.lit.get()
+    }
+}
+
+impl digital::ErrorType for MockLedPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockLedPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.lit.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.lit.set(true);
+        Ok(())
+    }
+}
+
+const COMMAND_REGISTER: u8 = 0xfd;
+const COMMAND_LOCK: u8 = 0xfe;
+
+/// An IS31FL3731 or IS31FL3733 on the bus, with its pages of registers and the LEDs they light.
+///
+/// Like the real chips, each transaction starts with a register, which increments with every byte
+/// written or read, and the IS31FL3733 ignores a page selection unless it was unlocked first.
+/// Clones share the registers.
+#[derive(Clone)]
+pub struct MockLedDriver {
+    chip: LedDriverChip,
+    address: u8,
+    registers: Rc<RefCell<Registers>>,
+}
+
+struct Registers {
+    pages: [[u8; 256]; 16],
+    page: usize,
+    unlocked: bool,
+}
+
+impl MockLedDriver {
+    /// A driver just out of reset, with its registers cleared and in software shutdown.
+    pub fn new(chip: LedDriverChip, address: u8) -> Self {
+        Self {
+            chip,
+            address,
+            registers: Rc::new(RefCell::new(Registers {
+                pages: [[0; 256]; 16],
+                page: 0,
+                unlocked: false,
+            })),
+        }
+    }
+
+    /// Brightness LED `led` is lit with, counting from the first LED of the first row of the
+    /// matrix, or 0 while the LED is off, disabled, or the chip shut down.
+    pub fn brightness(&self, led: usize) -> u8 {
+        let pages = &self.registers.borrow().pages;
+        let enabled = |page: usize| pages[page][led / 8] & 1 << (led % 8) != 0;
+        match self.chip {
+            LedDriverChip::Is31fl3731 => {
+                let function = &pages[0x0b];
+                let on = function[0x0a] & 0x01 != 0;
+                let picture_mode = function[0x00] & 0x18 == 0;
+                let frame = usize::from(function[0x01] & 0x07);
+                if on && picture_mode && enabled(frame) {
+                    pages[frame][0x24 + led]
+                } else {
+                    0
+                }
+            }
+            LedDriverChip::Is31fl3733 => {
+                let function = &pages[0x03];
+                let on = function[0x00] & 0x01 != 0 && function[0x01] != 0;
+                if on && enabled(0x00) {
+                    pages[0x01][led]
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// The LEDs that are lit, see [`brightness`](Self::brightness).
+    pub fn lit(&self) -> Vec<usize> {
+        let leds = match self.chip {
+            LedDriverChip::Is31fl3731 => 144,
+            LedDriverChip::Is31fl3733 => 192,
+        };
+        (0..leds).filter(|&led| self.brightness(led) > 0).collect()
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        let registers = &mut *self.registers.borrow_mut();
+        match register {
+            COMMAND_REGISTER if self.chip == LedDriverChip::Is31fl3731 => {
+                registers.page = usize::from(value & 0x0f);
+            }
+            COMMAND_REGISTER => {
+                if registers.unlocked {
+                    registers.page = usize::from(value & 0x03);
+                }
+                registers.unlocked = false;
+            }
+            COMMAND_LOCK if self.chip == LedDriverChip::Is31fl3733 => {
+                registers.unlocked = value == 0xc5;
+            }
+            _ => registers.pages[registers.page][usize::from(register)] = value,
+        }
+    }
+}
+
+impl i2c::ErrorType for MockLedDriver {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockLedDriver {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+
+        // Consecutive writes go out as one, starting with the register.
+        let mut register = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        match register {
+                            None => register = Some(byte),
+                            Some(current) => {
+                                self.write_register(current, byte);
+                                register = Some(current.wrapping_add(1));
+                            }
+                        }
+                    }
+                }
+                Operation::Read(bytes) => {
+                    let registers = self.registers.borrow();
+                    let mut current = register.unwrap_or(0);
+                    for byte in bytes.iter_mut() {
+                        *byte = registers.pages[registers.page][usize::from(current)];
+                        current = current.wrapping_add(1);
+                    }
+                    register = None;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Test doubles for the hardware the firmware talks to.
+//!
+//! The tests themselves are in the `tests` directory, one file per firmware module. Tests that
+//! end up calling into defmt need to link this crate for its [`NullLogger`].
+
+mod bus;
+mod keypad;
+mod leds;
+mod oled;
+
+pub use self::bus::{MockI2cBus, MockI2cDevice};
+pub use self::keypad::{
+    MockColumnPin, MockDuplexPin, MockExpander, MockKeypad, MockLatch, MockRowPin,
+    MockShiftRegisters,
+};
+pub use self::leds::{MockLedDriver, MockLedPin, MockPwmChannel};
+pub use self::oled::MockSsd1306;
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// NOR flash in memory, with the geometry of the ESP32-C6's flash.
+///
+/// Like the real thing, erasing sets all bits of a sector and writing can only clear bits. Erase
+/// cycles are counted per sector, and a reset in the middle of a write can be simulated with
+/// [`cut_power_after`](Self::cut_power_after).
+pub struct MockFlash {
+    data: Vec<u8>,
+    erase_counts: Vec<u32>,
+    /// Bytes that can still be written before the simulated power loss.
+    write_budget: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MockFlashError {
+    NotAligned,
+    OutOfBounds,
+    PowerLoss,
+}
+
+impl MockFlash {
+    pub const SECTOR_SIZE: usize = 4096;
+
+    /// Creates an erased flash of the given number of sectors.
+    pub fn new(sectors: usize) -> Self {
+        Self {
+            data: vec![0xff; sectors * Self::SECTOR_SIZE],
+            erase_counts: vec![0; sectors],
+            write_budget: None,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Gives direct access to the contents, e.g. to corrupt them.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn erase_counts(&self) -> &[u32] {
+        &self.erase_counts
+    }
+
+    /// Makes writes fail once the given number of bytes was written.
+    pub fn cut_power_after(&mut self, bytes: usize) {
+        self.write_budget = Some(bytes);
+    }
+
+    pub fn restore_power(&mut self) {
+        self.write_budget = None;
+    }
+
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<usize, MockFlashError> {
+        let offset = offset as usize;
+
+        if !offset.is_multiple_of(align) || !len.is_multiple_of(align) {
+            return Err(MockFlashError::NotAligned);
+        }
+        if offset + len > self.data.len() {
+            return Err(MockFlashError::OutOfBounds);
+        }
+
+        Ok(offset)
+    }
+}
+
+impl NorFlashError for MockFlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            MockFlashError::NotAligned => NorFlashErrorKind::NotAligned,
+            MockFlashError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            MockFlashError::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = MockFlashError;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = self.check(offset, bytes.len(), Self::READ_SIZE)?;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = Self::SECTOR_SIZE;
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+
+        for (cell, byte) in self.data[offset..].iter_mut().zip(bytes) {
+            if let Some(budget) = &mut self.write_budget {
+                if *budget == 0 {
+                    return Err(MockFlashError::PowerLoss);
+                }
+                *budget -= 1;
+            }
+            *cell &= byte;
+        }
+
+        Ok(())
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let len = to.checked_sub(from).ok_or(MockFlashError::OutOfBounds)? as usize;
+        let from = self.check(from, len, Self::ERASE_SIZE)?;
+
+        self.data[from..from + len].fill(0xff);
+        for sector in from / Self::SECTOR_SIZE..(from + len) / Self::SECTOR_SIZE {
+            self.erase_counts[sector] += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Discards defmt output. Some of the firmware's dependencies log through defmt, which doesn't
+/// link without a global logger.
+#[defmt::global_logger]
+pub struct NullLogger;
+
+// SAFETY: Nothing is written anywhere, so there is no state to protect from concurrent use.
+unsafe impl defmt::Logger for NullLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("");
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
@@ -0,0 +1,196 @@
+use std::{cell::RefCell, rc::Rc};
+
+use embedded_hal::i2c::{self, ErrorKind, I2c, NoAcknowledgeSource, Operation};
+
+const WIDTH: usize = 128;
+const PAGES: usize = 8;
+
+/// An SSD1306 on the bus, driving a panel of 128 columns and `rows` rows.
+///
+/// Like the real controller, the first byte of each transaction tells whether commands or display
+/// data follow, and data fills the columns and pages set with `0x21` and `0x22`. The panel shows
+/// the memory upright with the columns remapped (`0xa1`) and the rows scanned from the bottom
+/// (`0xc8`), as the usual modules are mounted, and stays dark until the charge pump and the
+/// display are on. Clones share the controller's state.
+#[derive(Clone)]
+pub struct MockSsd1306 {
+    address: u8,
+    rows: usize,
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    memory: [[u8; WIDTH]; PAGES],
+    on: bool,
+    charge_pump: bool,
+    inverted: bool,
+    multiplex: usize,
+    remapped: bool,
+    scan_reversed: bool,
+    horizontal: bool,
+    columns: (usize, usize),
+    pages: (usize, usize),
+    column: usize,
+    page: usize,
+    /// Writes aren't acknowledged past their first byte, as with a weak pull-up on SDA.
+    nack_data: bool,
+}
+
+impl MockSsd1306 {
+    /// A controller just out of reset, with random memory and the display off.
+    pub fn new(address: u8, rows: usize) -> Self {
+        Self {
+            address,
+            rows,
+            state: Rc::new(RefCell::new(State {
+                memory: [[0xa5; WIDTH]; PAGES],
+                on: false,
+                charge_pump: false,
+                inverted: false,
+                multiplex: 64,
+                remapped: false,
+                scan_reversed: false,
+                // Page addressing, which fills only the current page.
+                horizontal: false,
+                columns: (0, WIDTH - 1),
+                pages: (0, PAGES - 1),
+                column: 0,
+                page: 0,
+                nack_data: false,
+            })),
+        }
+    }
+
+    /// Whether the pixel at `x`, `y` of the panel lights up.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let state = self.state.borrow();
+        if !state.on || !state.charge_pump || y >= self.rows || y >= state.multiplex {
+            return false;
+        }
+        let column = if state.remapped { x } else { WIDTH - 1 - x };
+        let row = if state.scan_reversed {
+            y
+        } else {
+            state.multiplex - 1 - y
+        };
+        let bit = state.memory[row / 8][column] & 1 << (row % 8) != 0;
+        bit != state.inverted
+    }
+
+    /// The panel's pixels that light up, by row.
+    pub fn lit(&self) -> Vec<(usize, usize)> {
+        (0..self.rows)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.pixel(x, y))
+            .collect()
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.state.borrow().inverted
+    }
+
+    /// Stops acknowledging the bytes written after the control byte, while reads still work.
+    pub fn nack_data(&self) {
+        self.state.borrow_mut().nack_data = true;
+    }
+
+    fn command(&self, command: &[u8]) {
+        let state = &mut *self.state.borrow_mut();
+        let arg = |i: usize| usize::from(command[i]);
+        match command[0] {
+            0x20 => state.horizontal = command[1] == 0x00,
+            0x21 => {
+                state.columns = (arg(1), arg(2));
+                state.column = arg(1);
+            }
+            0x22 => {
+                state.pages = (arg(1) & 0x07, arg(2) & 0x07);
+                state.page = arg(1) & 0x07;
+            }
+            0x8d => state.charge_pump = command[1] & 0x04 != 0,
+            0xa0 | 0xa1 => state.remapped = command[0] == 0xa1,
+            0xa6 | 0xa7 => state.inverted = command[0] == 0xa7,
+            0xa8 => state.multiplex = arg(1) % 64 + 1,
+            0xae | 0xaf => state.on = command[0] == 0xaf,
+            0xc0 | 0xc8 => state.scan_reversed = command[0] == 0xc8,
+            _ => {}
+        }
+    }
+
+    fn data(&self, byte: u8) {
+        let state = &mut *self.state.borrow_mut();
+        state.memory[state.page][state.column] = byte;
+        if state.column < state.columns.1 {
+            state.column += 1;
+            return;
+        }
+        state.column = state.columns.0;
+        if state.horizontal {
+            state.page = if state.page < state.pages.1 {
+                state.page + 1
+            } else {
+                state.pages.0
+            };
+        }
+    }
+}
+
+/// Bytes of arguments following each command that takes any.
+fn argument_count(command: u8) -> usize {
+    match command {
+        0x26 | 0x27 => 6,
+        0x29 | 0x2a => 5,
+        0x21 | 0x22 | 0xa3 => 2,
+        0x20 | 0x81 | 0x8d | 0xa8 | 0xd3 | 0xd5 | 0xd9 | 0xda | 0xdb => 1,
+        _ => 0,
+    }
+}
+
+impl i2c::ErrorType for MockSsd1306 {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockSsd1306 {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+
+        // Consecutive writes go out as one, starting with the control byte.
+        let mut control = None;
+        let mut command = Vec::new();
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        if control.is_some() && self.state.borrow().nack_data {
+                            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data));
+                        }
+                        match control {
+                            None => control = Some(byte),
+                            Some(0x40) => self.data(byte),
+                            Some(_) => {
+                                command.push(byte);
+                                if command.len() > argument_count(command[0]) {
+                                    self.command(&command);
+                                    command.clear();
+                                }
+                            }
+                        }
+                    }
+                }
+                // The status byte, with the display's state in bit 6.
+                Operation::Read(bytes) => {
+                    let off = u8::from(!self.state.borrow().on) << 6;
+                    bytes.fill(off);
+                }
+            }
+        }
+        assert!(command.is_empty(), "incomplete command {command:02x?}");
+        Ok(())
+    }
+}
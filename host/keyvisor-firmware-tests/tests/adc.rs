@@ -0,0 +1,109 @@
+use keyvisor::{
+    adc::{Check, FULL_SCALE, InputStats, MOVED_DISTANCE, Thresholds, WINDOW},
+    board::{BREADBOARD_HALL_EFFECT, BREADBOARD_MCP23017},
+};
+
+/// Pushes `count` samples cycling through `readings`.
+fn push(stats: &mut InputStats, readings: &[u16], count: usize) {
+    for &reading in readings.iter().cycle().take(count) {
+        stats.push(reading);
+    }
+}
+
+#[test]
+fn a_resting_input_sets_the_centre_and_noise() {
+    let mut stats = InputStats::new();
+    assert_eq!(stats.latest(), None);
+    assert_eq!(stats.min_max(), None);
+
+    push(&mut stats, &[2000, 2002], WINDOW - 1);
+    assert_eq!(stats.centre(), None);
+    assert_eq!(stats.noise_tenths(), None);
+
+    stats.push(2002);
+    assert_eq!(stats.centre(), Some(2001));
+    assert_eq!(stats.noise_tenths(), Some(10));
+    assert_eq!(stats.latest(), Some(2002));
+    assert_eq!(stats.min_max(), Some((2000, 2002)));
+    assert_eq!(stats.drift(), None);
+}
+
+#[test]
+fn drift_is_measured_after_moving() {
+    let mut stats = InputStats::new();
+    push(&mut stats, &[2000], WINDOW);
+
+    // Resting close to the centre without having moved isn't drift.
+    push(&mut stats, &[2030], WINDOW);
+    assert_eq!(stats.drift(), None);
+
+    // Resting at either end isn't drift either.
+    push(&mut stats, &[FULL_SCALE], WINDOW);
+    push(&mut stats, &[0], WINDOW);
+    assert_eq!(stats.drift(), None);
+
+    push(&mut stats, &[2060], WINDOW);
+    assert_eq!(stats.centre(), Some(2000));
+    assert_eq!(stats.drift(), Some(60));
+    assert_eq!(stats.range(), FULL_SCALE);
+
+    let verdict = stats.verdict(&Thresholds::default());
+    assert_eq!(verdict.range, Check::Passed);
+    assert_eq!(verdict.noise, Check::Passed);
+    assert_eq!(verdict.drift, Check::Passed);
+    assert_eq!(verdict.overall(), Check::Passed);
+
+    push(&mut stats, &[1900], WINDOW);
+    assert_eq!(stats.drift(), Some(-100));
+    assert_eq!(
+        stats.verdict(&Thresholds::default()).overall(),
+        Check::Failed
+    );
+}
+
+#[test]
+fn inputs_left_away_from_the_centre_pass_without_drift() {
+    let mut stats = InputStats::new();
+    push(&mut stats, &[2000], WINDOW);
+    push(&mut stats, &[0], WINDOW);
+    push(&mut stats, &[FULL_SCALE], WINDOW);
+    push(&mut stats, &[3000], WINDOW);
+
+    let verdict = stats.verdict(&Thresholds::default());
+    assert_eq!(verdict.drift, Check::Pending);
+    assert_eq!(verdict.overall(), Check::Passed);
+}
+
+#[test]
+fn range_is_pending_until_the_input_moved_end_to_end() {
+    let mut stats = InputStats::new();
+    push(&mut stats, &[2000], WINDOW);
+    push(&mut stats, &[2000 + MOVED_DISTANCE], 4);
+
+    let verdict = stats.verdict(&Thresholds::default());
+    assert_eq!(verdict.range, Check::Pending);
+    assert_eq!(verdict.drift, Check::Pending);
+    assert_eq!(verdict.overall(), Check::Pending);
+}
+
+#[test]
+fn noisy_inputs_fail() {
+    let mut stats = InputStats::new();
+    push(&mut stats, &[2000, 2020], WINDOW);
+
+    assert_eq!(stats.noise_tenths(), Some(100));
+    assert_eq!(stats.verdict(&Thresholds::default()).noise, Check::Failed);
+
+    // Moving isn't mistaken for noise.
+    let mut stats = InputStats::new();
+    push(&mut stats, &[1000, 3000], WINDOW);
+    assert_eq!(stats.noise_tenths(), None);
+}
+
+#[test]
+fn only_pins_left_free_by_the_profile_are_tested() {
+    // The expander takes GPIO0 and GPIO1, and the display GPIO4-6.
+    assert_eq!(BREADBOARD_MCP23017.free_adc_pins(0b0111_1111), 0b0000_1100);
+    assert_eq!(BREADBOARD_MCP23017.free_adc_pins(0b0000_0100), 0b0000_0100);
+    assert_eq!(BREADBOARD_HALL_EFFECT.free_adc_pins(0b0111_1111), 0);
+}
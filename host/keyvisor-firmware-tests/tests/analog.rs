@@ -0,0 +1,131 @@
+use keyvisor::{
+    board::{AnalogPins, BREADBOARD_HALL_EFFECT, KeypadPins},
+    kbd::{
+        ColumnState, Key, N_COLS, N_KEYS,
+        analog::{AnalogKeys, Calibration, FULL_TRAVEL},
+    },
+};
+
+const FIVE: Key = Key { col: 1, row: 1 };
+
+/// Sensor reading of every key at rest.
+const REST: u16 = 2000;
+
+/// Readings with `key` at `reading` and the others at rest.
+fn readings(key: Key, reading: u16) -> [Option<u16>; N_KEYS] {
+    let mut readings = [Some(REST); N_KEYS];
+    readings[key.index()] = Some(reading);
+    readings
+}
+
+/// Whether `key` is pressed in a scan.
+fn pressed(tick: [ColumnState; N_COLS], key: Key) -> bool {
+    tick[usize::from(key.col)][usize::from(key.row)]
+}
+
+#[test]
+fn travel_is_measured_from_rest_to_bottom_out() {
+    let mut calibration = Calibration::at_rest(2000);
+    assert_eq!(calibration.travel(2000), None);
+
+    calibration.update(2600);
+    assert!(calibration.is_complete());
+    assert_eq!(calibration.travel(2000), Some(0));
+    assert_eq!(calibration.travel(2300), Some(FULL_TRAVEL / 2));
+    assert_eq!(calibration.travel(2600), Some(FULL_TRAVEL));
+
+    // Beyond either end.
+    assert_eq!(calibration.travel(1900), Some(0));
+    assert_eq!(calibration.travel(2700), Some(FULL_TRAVEL));
+}
+
+#[test]
+fn falling_readings_are_calibrated_too() {
+    let mut calibration = Calibration::at_rest(3000);
+    calibration.update(2200);
+    calibration.update(2500);
+
+    assert_eq!(calibration.bottom_out, 2200);
+    assert_eq!(calibration.travel(2600), Some(FULL_TRAVEL / 2));
+}
+
+#[test]
+fn noise_doesnt_complete_the_calibration() {
+    let mut calibration = Calibration::at_rest(2000);
+    calibration.update(2040);
+
+    assert!(!calibration.is_complete());
+    assert_eq!(calibration.travel(2040), None);
+}
+
+#[test]
+fn keys_actuate_with_hysteresis() {
+    let mut keys = AnalogKeys::new();
+    keys.update([Some(REST); N_KEYS], 500);
+
+    // Calibrating by bottoming out presses the key.
+    assert!(pressed(keys.update(readings(FIVE, 2800), 500), FIVE));
+
+    // Still pressed just above the actuation point, released well above it.
+    assert!(pressed(keys.update(readings(FIVE, 2380), 500), FIVE));
+    assert!(!pressed(keys.update(readings(FIVE, 2300), 500), FIVE));
+    assert_eq!(keys.travel(FIVE), Some(375));
+
+    assert!(!pressed(keys.update(readings(FIVE, 2380), 500), FIVE));
+    assert!(pressed(keys.update(readings(FIVE, 2400), 500), FIVE));
+    assert!(!pressed(keys.update(readings(FIVE, 2400), 600), FIVE));
+}
+
+#[test]
+fn recalibration_forgets_the_bottom_out() {
+    let mut keys = AnalogKeys::new();
+    keys.update([Some(REST); N_KEYS], 500);
+    keys.update(readings(FIVE, 2800), 500);
+
+    keys.recalibrate();
+    let tick = keys.update(readings(FIVE, 2100), 500);
+
+    assert!(!pressed(tick, FIVE));
+    assert_eq!(keys.calibration(FIVE), Some(Calibration::at_rest(2100)));
+    assert_eq!(keys.travel(FIVE), None);
+}
+
+#[test]
+fn failed_readings_keep_the_previous_state() {
+    let mut keys = AnalogKeys::new();
+    let mut failed = [Some(REST); N_KEYS];
+    failed[FIVE.index()] = None;
+
+    // Not taken as the rest position.
+    keys.update(failed, 500);
+    assert_eq!(keys.calibration(FIVE), None);
+    keys.update([Some(REST); N_KEYS], 500);
+    keys.update(readings(FIVE, 2800), 500);
+
+    // Neither as a release.
+    assert!(pressed(keys.update(failed, 500), FIVE));
+    assert_eq!(keys.reading(FIVE), 2800);
+    assert_eq!(keys.calibration(FIVE).map(|c| c.bottom_out), Some(2800));
+}
+
+#[test]
+fn sensors_fill_each_multiplexer_in_turn() {
+    let pins = AnalogPins {
+        inputs: &[0, 1],
+        select: &[2, 3, 4],
+    };
+    assert!(pins.covers_keys());
+    assert_eq!(pins.sensor(Key::from_index(7)), (0, 7));
+    assert_eq!(pins.sensor(Key::from_index(8)), (1, 0));
+
+    let direct = AnalogPins {
+        inputs: &[0, 1, 2, 3, 4, 5, 6],
+        select: &[],
+    };
+    assert!(!direct.covers_keys());
+
+    let KeypadPins::Analog(breadboard) = &BREADBOARD_HALL_EFFECT.keypad else {
+        panic!("not an analog keypad");
+    };
+    assert!(Key::all().all(|key| breadboard.sensor(key) == (0, key.index() as u8)));
+}
@@ -0,0 +1,111 @@
+use keyvisor::kbd::{
+    ColumnState, Key, N_COLS, N_ROWS,
+    backend::{Expander, ExpanderChip, GpioMatrix, MatrixBackend, ShiftRegisters},
+};
+use keyvisor_firmware_tests::MockKeypad;
+
+const FIVE: Key = Key { col: 1, row: 1 };
+const HASH: Key = Key { col: 2, row: 3 };
+
+/// Reads every column like the scanner does.
+fn read_tick<B: MatrixBackend>(backend: &mut B) -> Result<[ColumnState; N_COLS], B::Error> {
+    let mut tick = [ColumnState::ZERO; N_COLS];
+    for (col, mask) in tick.iter_mut().enumerate() {
+        backend.select_column(col)?;
+        *mask = backend.read_rows()?;
+    }
+    Ok(tick)
+}
+
+/// Columns as read with `keys` pressed.
+fn expected(keys: &[Key]) -> [ColumnState; N_COLS] {
+    let mut tick = [ColumnState::ZERO; N_COLS];
+    for key in keys {
+        tick[usize::from(key.col)].set(usize::from(key.row), true);
+    }
+    tick
+}
+
+/// Checks that `backend` reads the keys pressed on `keypad`, and only these.
+fn assert_reads_keys<B>(keypad: &MockKeypad, backend: &mut B)
+where
+    B: MatrixBackend,
+    B::Error: std::fmt::Debug,
+{
+    backend.init().unwrap();
+    assert_eq!(read_tick(backend).unwrap(), expected(&[]));
+
+    keypad.press(FIVE);
+    keypad.press(HASH);
+    assert_eq!(read_tick(backend).unwrap(), expected(&[FIVE, HASH]));
+
+    keypad.release(FIVE);
+    assert_eq!(read_tick(backend).unwrap(), expected(&[HASH]));
+}
+
+#[test]
+fn gpio_matrix_reads_the_selected_column() {
+    let keypad = MockKeypad::new();
+    let (columns, rows) = keypad.gpio_pins();
+
+    assert_reads_keys(&keypad, &mut GpioMatrix::new(columns, rows));
+}
+
+#[test]
+fn shift_registers_read_the_selected_column() {
+    let keypad = MockKeypad::new();
+    let (spi, latch) = keypad.shift_registers();
+
+    assert_reads_keys(&keypad, &mut ShiftRegisters::new(spi, latch));
+}
+
+#[test]
+fn shift_registers_keep_the_column_while_reading() {
+    let keypad = MockKeypad::new();
+    keypad.press(HASH);
+    let (mut spi, mut latch) = keypad.shift_registers();
+
+    let mut registers = ShiftRegisters::new(&mut spi, &mut latch);
+    registers.init().unwrap();
+    registers.select_column(2).unwrap();
+    for _ in 0..2 {
+        assert_eq!(registers.read_rows().unwrap(), expected(&[HASH])[2]);
+    }
+
+    assert_eq!(spi.columns(), 0b1111_1011);
+}
+
+#[test]
+fn expanders_read_the_selected_column() {
+    for chip in [ExpanderChip::Mcp23017, ExpanderChip::Pca9555] {
+        let keypad = MockKeypad::new();
+        let mut expander = Expander::new(keypad.expander(chip, 0x21), chip, 0x21);
+
+        assert_reads_keys(&keypad, &mut expander);
+    }
+}
+
+#[test]
+fn mcp23017_rows_float_until_initialised() {
+    let keypad = MockKeypad::new();
+    let chip = ExpanderChip::Mcp23017;
+    let mut expander = Expander::new(keypad.expander(chip, 0x20), chip, 0x20);
+
+    // Without the pull-ups, every row reads low.
+    expander.select_column(0).unwrap();
+    assert!(expander.read_rows().unwrap()[..N_ROWS].all());
+
+    expander.init().unwrap();
+    expander.select_column(0).unwrap();
+    assert!(expander.read_rows().unwrap().not_any());
+}
+
+#[test]
+fn missing_expander_fails() {
+    let keypad = MockKeypad::new();
+    let chip = ExpanderChip::Pca9555;
+    let mut expander = Expander::new(keypad.expander(chip, 0x20), chip, 0x27);
+
+    assert!(expander.init().is_err());
+    assert!(read_tick(&mut expander).is_err());
+}
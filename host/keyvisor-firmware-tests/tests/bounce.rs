@@ -0,0 +1,70 @@
+use keyvisor::{
+    bounce::{Capture, MAX_CAPTURE_TIME, MAX_EDGES, SETTLE_TIME},
+    kbd::Key,
+};
+
+const KEY: Key = Key { col: 1, row: 1 };
+
+/// Captures a contact that changes at 0 and at `edges`, sampled every 4 µs.
+fn capture(press: bool, edges: &[u32]) -> Capture {
+    let closed_before = !press;
+    let mut capture = Capture::new(KEY, closed_before);
+
+    let mut t = 4;
+    while capture.sample(
+        t,
+        closed_before ^ (edges.iter().filter(|&&edge| edge <= t).count() % 2 == 0),
+    ) {
+        t += 4;
+    }
+    capture
+}
+
+#[test]
+fn bounce_of_press_is_measured() {
+    let capture = capture(true, &[120, 180, 400, 448]);
+
+    assert!(capture.is_press());
+    assert!(capture.closed_after());
+    assert_eq!(capture.edges(), [0, 120, 180, 400, 448]);
+    assert_eq!(capture.edge_count(), 5);
+    assert_eq!(capture.bounce_us(), 448);
+    assert_eq!(capture.duration_us, 448 + SETTLE_TIME.as_micros() as u32);
+    assert_eq!(capture.sample_rate_hz(), 250_000);
+}
+
+#[test]
+fn clean_release_has_no_bounce() {
+    let capture = capture(false, &[]);
+
+    assert!(!capture.is_press());
+    assert!(!capture.closed_after());
+    assert_eq!(capture.edge_count(), 1);
+    assert_eq!(capture.bounce_us(), 0);
+}
+
+#[test]
+fn chatter_ends_capture_after_max_time() {
+    let edges: Vec<u32> = (1..500).map(|i| i * 100).collect();
+    let capture = capture(true, &edges);
+
+    let max_us = MAX_CAPTURE_TIME.as_micros() as u32;
+    assert_eq!(capture.duration_us, max_us);
+    assert_eq!(capture.edges().len(), MAX_EDGES);
+    assert_eq!(capture.edge_count(), max_us / 100 + 1);
+    assert_eq!(capture.dropped_edges, max_us / 100 + 1 - MAX_EDGES as u32);
+    assert_eq!(capture.bounce_us(), max_us);
+}
+
+#[test]
+fn waveform_is_looked_up_by_time() {
+    let capture = capture(true, &[120, 180]);
+
+    assert!(!capture.closed_at(-10));
+    assert!(capture.closed_at(0));
+    assert!(!capture.closed_at(150));
+    assert!(capture.closed_at(180));
+    assert_eq!(capture.edges_between(-10, 0), 0);
+    assert_eq!(capture.edges_between(0, 121), 2);
+    assert_eq!(capture.edges_between(121, 1000), 1);
+}
@@ -0,0 +1,433 @@
+use embassy_futures::block_on;
+use embassy_time::Instant;
+use keyvisor::{
+    board::{self, BoardProfile},
+    console::{self, Command, Device, Line, LineBuffer, ParseError, Setting},
+    history::{FIRMWARE_VERSION, HistoryLog, TestResult},
+    kbd::{ColumnState, Key, KeyEvent, KeySet, N_KEYS, latency::LatencyMeter, recording::Recorder},
+    settings::{Rotation, Settings},
+    storage::{self, Cursor},
+};
+use keyvisor_firmware_tests::{MockFlash, MockFlashError};
+use keyvisor_protocol::Event;
+
+struct TestDevice {
+    settings: Settings,
+    saved: Vec<Settings>,
+    screenshots: usize,
+    streaming: bool,
+    recorder: Box<Recorder>,
+    latency_meter: LatencyMeter,
+    history: HistoryLog<MockFlash>,
+}
+
+impl TestDevice {
+    fn new() -> Self {
+        Self {
+            settings: Settings::default(),
+            saved: Vec::new(),
+            screenshots: 0,
+            streaming: false,
+            recorder: Box::default(),
+            latency_meter: LatencyMeter::new(),
+            history: HistoryLog::open(MockFlash::new(2)).unwrap(),
+        }
+    }
+
+    fn run(&mut self, line: &str) -> String {
+        let mut out = Vec::new();
+        block_on(console::dispatch(line.as_bytes(), &mut out, self)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+}
+
+impl Device for TestDevice {
+    type Error = &'static str;
+
+    fn board(&self) -> &'static BoardProfile {
+        &board::PCB_REV2
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        90_500
+    }
+
+    fn settings(&self) -> Settings {
+        self.settings.clone()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), Self::Error> {
+        self.settings = settings.clone();
+        self.saved.push(settings.clone());
+        Ok(())
+    }
+
+    fn request_screenshot(&mut self) {
+        self.screenshots += 1;
+    }
+
+    fn set_streaming(&mut self, enabled: bool) {
+        self.streaming = enabled;
+    }
+
+    fn with_recorder<R>(&mut self, f: impl FnOnce(&mut Recorder) -> R) -> R {
+        f(&mut self.recorder)
+    }
+
+    fn with_latency_meter<R>(&mut self, f: impl FnOnce(&mut LatencyMeter) -> R) -> R {
+        f(&mut self.latency_meter)
+    }
+
+    fn results_newest_first(&mut self) -> Result<Cursor, Self::Error> {
+        Ok(self.history.newest_first())
+    }
+
+    fn next_result(&mut self, cursor: &mut Cursor) -> Result<Option<TestResult>, Self::Error> {
+        self.history
+            .read_next(cursor)
+            .map_err(|_: storage::Error<MockFlashError>| "flash failed")
+    }
+}
+
+fn result(board_id: u32) -> TestResult {
+    TestResult {
+        board_id,
+        passed: !KeySet::ZERO,
+        chatter: [0; N_KEYS],
+        uptime_ms: 1000,
+        firmware: [0, 1, 0],
+    }
+}
+
+#[test]
+fn lines_are_split_at_line_breaks() {
+    let mut lines = LineBuffer::<8>::new();
+    let mut complete = Vec::new();
+
+    for &byte in b"help\r\nstatus\n" {
+        if let Some(line) = lines.push(byte) {
+            complete.push(match line {
+                Line::Complete(line) => String::from_utf8(line.to_vec()).unwrap(),
+                Line::TooLong => panic!("line too long"),
+            });
+        }
+    }
+
+    assert_eq!(complete, ["help", "", "status"]);
+}
+
+#[test]
+fn overlong_line_is_dropped() {
+    let mut lines = LineBuffer::<4>::new();
+
+    for &byte in b"screenshot" {
+        assert_eq!(lines.push(byte), None);
+    }
+    assert_eq!(lines.push(b'\n'), Some(Line::TooLong));
+
+    for &byte in b"help" {
+        lines.push(byte);
+    }
+    assert_eq!(lines.push(b'\n'), Some(Line::Complete(b"help")));
+}
+
+#[test]
+fn commands_are_parsed() {
+    assert_eq!(Command::parse("  "), Ok(None));
+    assert_eq!(Command::parse("help"), Ok(Some(Command::Help)));
+    assert_eq!(
+        Command::parse(" set  debounce 5 "),
+        Ok(Some(Command::Set(Setting::Debounce(5))))
+    );
+    assert_eq!(
+        Command::parse("set rotation 270"),
+        Ok(Some(Command::Set(Setting::Rotation(Rotation::Deg270))))
+    );
+    assert_eq!(
+        Command::parse("set board auto"),
+        Ok(Some(Command::Set(Setting::Board(None))))
+    );
+    assert_eq!(
+        Command::parse("set actuation 35"),
+        Ok(Some(Command::Set(Setting::Actuation(35))))
+    );
+    assert_eq!(
+        Command::parse("set analog 4,6"),
+        Ok(Some(Command::Set(Setting::AnalogPins(0b0101_0000))))
+    );
+    assert_eq!(
+        Command::parse("set chain 96"),
+        Ok(Some(Command::Set(Setting::ChainLen(96))))
+    );
+    assert_eq!(
+        Command::parse("set i2c 6,7"),
+        Ok(Some(Command::Set(Setting::I2cPins(Some((6, 7))))))
+    );
+    assert_eq!(
+        Command::parse("set i2c board"),
+        Ok(Some(Command::Set(Setting::I2cPins(None))))
+    );
+    assert_eq!(Command::parse("layout list"), Ok(Some(Command::LayoutList)));
+    assert_eq!(
+        Command::parse("results export"),
+        Ok(Some(Command::ResultsExport))
+    );
+    assert_eq!(
+        Command::parse("trace record"),
+        Ok(Some(Command::TraceRecord))
+    );
+}
+
+#[test]
+fn invalid_commands_are_rejected() {
+    assert_eq!(Command::parse("reboot"), Err(ParseError::UnknownCommand));
+    assert_eq!(
+        Command::parse("layout"),
+        Err(ParseError::Usage("layout list"))
+    );
+    assert_eq!(
+        Command::parse("set speed 3"),
+        Err(ParseError::UnknownSetting)
+    );
+    assert_eq!(
+        Command::parse("set debounce 0"),
+        Err(ParseError::Usage("set debounce <1-255>"))
+    );
+    assert_eq!(
+        Command::parse("set brightness"),
+        Err(ParseError::Usage("set brightness <1-100>"))
+    );
+    assert_eq!(
+        Command::parse("set rotation 45"),
+        Err(ParseError::Usage("set rotation <0|90|180|270>"))
+    );
+    assert_eq!(
+        Command::parse("set board 42"),
+        Err(ParseError::Usage("set board <id|auto>"))
+    );
+    assert_eq!(
+        Command::parse("set actuation 95"),
+        Err(ParseError::Usage("set actuation <10-90>"))
+    );
+    for pins in ["7", "4,x", "0,1,2,3,4"] {
+        assert_eq!(
+            Command::parse(&format!("set analog {pins}")),
+            Err(ParseError::Usage("set analog <gpio,..|none>"))
+        );
+    }
+    for len in ["0", "129"] {
+        assert_eq!(
+            Command::parse(&format!("set chain {len}")),
+            Err(ParseError::Usage("set chain <1-128>"))
+        );
+    }
+    for pins in ["6", "6,6", "6,24", "x,7", "6,7,8"] {
+        assert_eq!(
+            Command::parse(&format!("set i2c {pins}")),
+            Err(ParseError::Usage("set i2c <sda,scl|board>"))
+        );
+    }
+    assert_eq!(
+        Command::parse("trace"),
+        Err(ParseError::Usage("trace <record|stop|export>"))
+    );
+    assert_eq!(
+        Command::parse("status now"),
+        Err(ParseError::Usage("status"))
+    );
+}
+
+#[test]
+fn help_lists_every_command() {
+    let reply = TestDevice::new().run("help");
+
+    for command in ["status", "set debounce", "layout list", "results export"] {
+        assert!(reply.contains(command), "`{command}` missing in:\n{reply}");
+    }
+    assert!(reply.ends_with("ok\n"));
+}
+
+#[test]
+fn set_saves_settings() {
+    let mut device = TestDevice::new();
+
+    assert_eq!(device.run("set debounce 5"), "ok\n");
+    assert_eq!(
+        device.saved,
+        [Settings {
+            debounce_ticks: 5,
+            ..Settings::default()
+        }]
+    );
+
+    assert_eq!(
+        device.run("set debounce 300"),
+        "error: usage: set debounce <1-255>\n"
+    );
+    assert_eq!(device.saved.len(), 1);
+}
+
+#[test]
+fn status_shows_board_and_settings() {
+    let mut device = TestDevice::new();
+    device.history.append(&result(1)).unwrap();
+    device.run("set brightness 70");
+    device.run("set analog 5,4");
+
+    let [major, minor, patch] = FIRMWARE_VERSION;
+    assert_eq!(
+        device.run("status"),
+        format!(
+            "firmware: {major}.{minor}.{patch}\n\
+             board: PCB rev 2 (2)\n\
+             uptime: 90 s\n\
+             brightness: 70\n\
+             debounce: 10\n\
+             layout: 0 (phone)\n\
+             rotation: 0\n\
+             board setting: auto\n\
+             actuation: 50\n\
+             analog: 4,5\n\
+             chain: 64\n\
+             i2c: board\n\
+             trace: 0 ticks\n\
+             latency: 0 events\n\
+             results: 1\n\
+             ok\n"
+        )
+    );
+}
+
+#[test]
+fn layout_list_marks_the_current_layout() {
+    assert_eq!(
+        TestDevice::new().run("layout list"),
+        "* 0 phone: 3x4 keypad, 1 2 3 at the top\nok\n"
+    );
+}
+
+#[test]
+fn screenshot_is_requested() {
+    let mut device = TestDevice::new();
+
+    assert_eq!(device.run("screenshot"), "ok\n");
+    assert_eq!(device.screenshots, 1);
+}
+
+#[test]
+fn results_are_exported_newest_first() {
+    let mut device = TestDevice::new();
+    device.history.append(&result(1)).unwrap();
+    device.history.append(&result(2)).unwrap();
+
+    assert_eq!(
+        device.run("results export"),
+        "#keyvisor-history 1\n\
+         board,result,failed_keys,chatter,uptime_ms,firmware\n\
+         2,pass,,,1000,0.1.0\n\
+         1,pass,,,1000,0.1.0\n\
+         #end\n\
+         ok\n"
+    );
+}
+
+#[test]
+fn stream_starts_with_hello() {
+    let mut device = TestDevice::new();
+
+    let reply = device.run("stream on");
+    let (hello, rest) = reply.split_once('\n').unwrap();
+    assert!(matches!(
+        Event::parse(hello),
+        Ok(Event::Hello {
+            board: "PCB rev 2",
+            ..
+        })
+    ));
+    assert_eq!(rest, "ok\n");
+    assert!(device.streaming);
+
+    assert_eq!(device.run("stream off"), "ok\n");
+    assert!(!device.streaming);
+}
+
+#[test]
+fn trace_is_exported_as_runs() {
+    let mut device = TestDevice::new();
+    device.run("set debounce 4");
+
+    assert_eq!(device.run("trace record"), "ok\n");
+    let idle = [ColumnState::ZERO; 3];
+    let mut pressed = idle;
+    pressed[1].set(2, true);
+    for tick in [idle, idle, pressed, idle] {
+        device.recorder.push(tick);
+    }
+    assert!(device.run("status").contains("trace: 4 ticks, recording\n"));
+
+    assert_eq!(
+        device.run("trace export"),
+        "#keyvisor-trace 1\n\
+         scan_hz 400\n\
+         debounce 4\n\
+         2 0 0 0\n\
+         1 0 4 0\n\
+         1 0 0 0\n\
+         #end\n\
+         ok\n"
+    );
+    assert!(!device.recorder.is_recording());
+}
+
+#[test]
+fn latency_is_shown_per_key() {
+    let mut device = TestDevice::new();
+
+    assert_eq!(device.run("latency start"), "ok\n");
+    let key = Key { col: 1, row: 1 };
+    let mut pressed = ColumnState::ZERO;
+    pressed.set(1, true);
+    for (mask, event, t, latency_us) in [
+        (pressed, KeyEvent::KeyDown(key), 0, 5_250),
+        (ColumnState::ZERO, KeyEvent::KeyUp(key), 100_000, 7_500),
+    ] {
+        let meter = &mut device.latency_meter;
+        meter.read(1, mask, 2, Instant::from_micros(t));
+        meter.published(event, Instant::from_micros(t + latency_us));
+    }
+    assert!(
+        device
+            .run("status")
+            .contains("latency: 2 events, running\n")
+    );
+
+    assert_eq!(
+        device.run("latency show"),
+        "latency in ms, from the scan that read a change to its event\n\
+         key    n     min     avg     max     p99\n\
+         all    2    5.25    6.37    7.50    7.50\n\
+         5      2    5.25    6.37    7.50    7.50\n\
+         histogram, ms: count\n  \
+         5-6       1\n  \
+         7-8       1\n\
+         ok\n"
+    );
+
+    assert_eq!(device.run("latency stop"), "ok\n");
+    assert!(!device.latency_meter.is_running());
+}
+
+#[test]
+fn errors_end_the_reply() {
+    let mut device = TestDevice::new();
+
+    assert_eq!(
+        device.run("frobnicate"),
+        "error: unknown command, try `help`\n"
+    );
+    assert_eq!(device.run(""), "");
+
+    let mut out = Vec::new();
+    block_on(console::dispatch(b"\xffhelp", &mut out, &mut device)).unwrap();
+    assert_eq!(out, b"error: not a text line\n");
+}
@@ -0,0 +1,97 @@
+use keyvisor::encoder::{Direction, EncoderEvent, EncoderUpdate, QuadratureDecoder};
+
+/// Levels of A and B going clockwise from the rest state.
+const CLOCKWISE: [(bool, bool); 4] = [(false, true), (false, false), (true, false), (true, true)];
+
+/// Feeds levels to a decoder starting at rest and collects the events.
+fn decode(levels: &[(bool, bool)]) -> Vec<EncoderEvent> {
+    let mut decoder = QuadratureDecoder::new(true, true);
+    levels
+        .iter()
+        .flat_map(|&(a, b)| decoder.update(a, b).events())
+        .collect()
+}
+
+#[test]
+fn full_cycle_is_one_detent() {
+    assert_eq!(
+        decode(&CLOCKWISE),
+        [EncoderEvent::Detent(Direction::Clockwise)]
+    );
+
+    let mut counter_clockwise = CLOCKWISE;
+    counter_clockwise[..3].reverse();
+    assert_eq!(
+        decode(&counter_clockwise),
+        [EncoderEvent::Detent(Direction::CounterClockwise)]
+    );
+}
+
+#[test]
+fn unchanged_levels_are_ignored() {
+    let mut levels = Vec::new();
+    for level in CLOCKWISE {
+        levels.extend([level, level]);
+    }
+    assert_eq!(
+        decode(&levels),
+        [EncoderEvent::Detent(Direction::Clockwise)]
+    );
+}
+
+#[test]
+fn bounce_back_to_rest_is_not_a_detent() {
+    // A closes, bounces open and closes again, then the encoder springs back.
+    let levels = [(false, true), (true, true), (false, true), (true, true)];
+    assert_eq!(
+        decode(&levels),
+        [EncoderEvent::Bounce, EncoderEvent::Bounce]
+    );
+
+    // A bounce halfway through a detent is still followed by the detent.
+    let levels = [
+        (false, true),
+        (false, false),
+        (false, true),
+        (false, false),
+        (true, false),
+        (true, true),
+    ];
+    assert_eq!(
+        decode(&levels),
+        [
+            EncoderEvent::Bounce,
+            EncoderEvent::Bounce,
+            EncoderEvent::Detent(Direction::Clockwise)
+        ]
+    );
+}
+
+#[test]
+fn missed_state_keeps_the_direction() {
+    let levels = [(false, true), (true, false), (true, true)];
+    assert_eq!(
+        decode(&levels),
+        [
+            EncoderEvent::Missed,
+            EncoderEvent::Detent(Direction::Clockwise)
+        ]
+    );
+}
+
+#[test]
+fn problems_come_before_the_detent() {
+    let update = EncoderUpdate {
+        detent: Some(Direction::CounterClockwise),
+        missed: true,
+        bounced: true,
+    };
+    assert_eq!(
+        update.events().collect::<Vec<_>>(),
+        [
+            EncoderEvent::Missed,
+            EncoderEvent::Bounce,
+            EncoderEvent::Detent(Direction::CounterClockwise)
+        ]
+    );
+}
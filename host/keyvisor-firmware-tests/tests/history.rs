@@ -0,0 +1,150 @@
+use keyvisor::{
+    history::{CSV_HEADER, FIRMWARE_VERSION, HistoryLog, SLOT_SIZE, TestResult},
+    kbd::{Key, KeySet, N_KEYS},
+    storage::RecordLog,
+};
+use keyvisor_firmware_tests::MockFlash;
+
+fn key(label: char) -> Key {
+    Key::all().find(|key| key.char() == label).unwrap()
+}
+
+fn result(board_id: u32) -> TestResult {
+    TestResult {
+        board_id,
+        passed: KeySet::ZERO,
+        chatter: [0; N_KEYS],
+        uptime_ms: 1000 * board_id,
+        firmware: FIRMWARE_VERSION,
+    }
+}
+
+fn passing(board_id: u32) -> TestResult {
+    TestResult {
+        passed: !KeySet::ZERO,
+        ..result(board_id)
+    }
+}
+
+fn csv(result: &TestResult) -> String {
+    let mut line = String::new();
+    result.write_csv(&mut line).unwrap();
+    line
+}
+
+#[test]
+fn result_survives_encoding() {
+    let mut result = result(987_654);
+    result.passed.set(key('7').index(), true);
+    result.chatter[key('#').index()] = 3;
+
+    assert_eq!(TestResult::decode(1, &result.encode()), Some(result));
+}
+
+#[test]
+fn passing_result_as_csv() {
+    let [major, minor, patch] = FIRMWARE_VERSION;
+
+    assert_eq!(CSV_HEADER.split(',').count(), 6);
+    assert_eq!(
+        csv(&passing(42)),
+        format!("42,pass,,,42000,{major}.{minor}.{patch}")
+    );
+}
+
+#[test]
+fn failing_result_as_csv() {
+    let mut result = passing(5);
+    result.passed.set(key('1').index(), false);
+    result.passed.set(key('#').index(), false);
+    result.chatter[key('0').index()] = 2;
+    result.chatter[key('1').index()] = 1;
+    result.firmware = [1, 2, 3];
+
+    assert_eq!(csv(&result), "5,fail,1 #,1:1 0:2,5000,1.2.3");
+}
+
+#[test]
+fn results_are_read_newest_first() {
+    let mut flash = MockFlash::new(2);
+
+    let mut log = HistoryLog::open(&mut flash).unwrap();
+    for board_id in 1..=3 {
+        log.append(&passing(board_id)).unwrap();
+    }
+
+    let mut log = HistoryLog::open(&mut flash).unwrap();
+    assert_eq!(log.count().unwrap(), 3);
+    assert_eq!(log.nth_newest(0).unwrap(), Some(passing(3)));
+    assert_eq!(log.nth_newest(2).unwrap(), Some(passing(1)));
+    assert_eq!(log.nth_newest(3).unwrap(), None);
+
+    let mut cursor = log.newest_first();
+    let mut board_ids = Vec::new();
+    while let Some(result) = log.read_next(&mut cursor).unwrap() {
+        board_ids.push(result.board_id);
+    }
+    assert_eq!(board_ids, [3, 2, 1]);
+}
+
+#[test]
+fn oldest_results_are_dropped_when_full() {
+    let mut flash = MockFlash::new(2);
+    let slots = 2 * MockFlash::SECTOR_SIZE / SLOT_SIZE;
+
+    let mut log = HistoryLog::open(&mut flash).unwrap();
+    for board_id in 0..slots as u32 + 10 {
+        log.append(&result(board_id)).unwrap();
+    }
+
+    let count = log.count().unwrap();
+    assert!(count >= slots / 2 && count < slots, "{count} results kept");
+    assert_eq!(
+        log.nth_newest(0).unwrap().map(|result| result.board_id),
+        Some(slots as u32 + 9)
+    );
+}
+
+#[test]
+fn unknown_versions_are_skipped() {
+    let mut flash = MockFlash::new(2);
+
+    HistoryLog::open(&mut flash)
+        .unwrap()
+        .append(&passing(1))
+        .unwrap();
+    RecordLog::<_, SLOT_SIZE>::open(&mut flash)
+        .unwrap()
+        .append(99, &[0; 20])
+        .unwrap();
+
+    let mut log = HistoryLog::open(&mut flash).unwrap();
+    assert_eq!(log.count().unwrap(), 1);
+    assert_eq!(log.nth_newest(0).unwrap(), Some(passing(1)));
+}
+
+#[test]
+fn browsing_steps_both_ways() {
+    let mut flash = MockFlash::new(2);
+    let mut log = HistoryLog::open(&mut flash).unwrap();
+    assert!(log.browse().unwrap().is_none());
+
+    // More results than the browser remembers positions of.
+    for board_id in 1..=40 {
+        log.append(&passing(board_id)).unwrap();
+    }
+
+    let mut browser = log.browse().unwrap().unwrap();
+    assert_eq!(browser.result(), &passing(40));
+    assert!(!log.newer(&mut browser).unwrap());
+
+    while log.older(&mut browser).unwrap() {
+        assert_eq!(browser.result().board_id, 40 - browser.index() as u32);
+    }
+    assert_eq!((browser.index(), browser.result()), (39, &passing(1)));
+
+    while log.newer(&mut browser).unwrap() {
+        assert_eq!(browser.result().board_id, 40 - browser.index() as u32);
+    }
+    assert_eq!((browser.index(), browser.result()), (0, &passing(40)));
+}
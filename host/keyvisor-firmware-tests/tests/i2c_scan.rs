@@ -0,0 +1,113 @@
+use embedded_hal::i2c::ErrorKind;
+use keyvisor::{
+    board::{BREADBOARD, BREADBOARD_MCP23017},
+    i2c_scan::{Device, MAX_DEVICES, ScanError, scan},
+    kbd::backend::ExpanderChip,
+    key_leds::LedDriverChip,
+};
+use keyvisor_firmware_tests::{MockI2cBus, MockI2cDevice, MockKeypad, MockLedDriver};
+
+#[test]
+fn devices_are_listed_by_address() {
+    let keypad = MockKeypad::new();
+    let mut bus = MockI2cBus::new()
+        .with(MockI2cDevice::new(0x3c, &[]))
+        .with(keypad.expander(ExpanderChip::Pca9555, 0x21))
+        .with(keypad.expander(ExpanderChip::Mcp23017, 0x20))
+        .with(MockI2cDevice::new(0x50, &[]))
+        .with(MockLedDriver::new(LedDriverChip::Is31fl3733, 0x5f))
+        .with(MockLedDriver::new(LedDriverChip::Is31fl3731, 0x74));
+
+    let scan = scan(&mut bus).unwrap();
+    assert_eq!(
+        scan.devices(),
+        [
+            (0x20, Device::Expander(ExpanderChip::Mcp23017)),
+            (0x21, Device::Expander(ExpanderChip::Pca9555)),
+            (0x3c, Device::Oled),
+            (0x50, Device::EepromOrLedDriver),
+            (0x5f, Device::LedDriver(LedDriverChip::Is31fl3733)),
+            (0x74, Device::LedDriver(LedDriverChip::Is31fl3731)),
+        ]
+    );
+}
+
+#[test]
+fn chip_ids_tell_sensors_from_led_drivers() {
+    let mut bus = MockI2cBus::new()
+        .with(MockI2cDevice::new(0x76, &[(0xd0, 0x60)]))
+        .with(MockLedDriver::new(LedDriverChip::Is31fl3731, 0x77));
+    assert_eq!(
+        scan(&mut bus).unwrap().devices(),
+        [
+            (0x76, Device::Bme280),
+            (0x77, Device::LedDriver(LedDriverChip::Is31fl3731)),
+        ]
+    );
+
+    let mut bus = MockI2cBus::new().with(MockI2cDevice::new(0x77, &[(0xd0, 0x58)]));
+    assert_eq!(scan(&mut bus).unwrap().devices(), [(0x77, Device::Bmp280)]);
+}
+
+#[test]
+fn expanders_without_known_registers_are_named_generically() {
+    // Like a PCF8574, which has no registers and reads back its port.
+    let mut bus = MockI2cBus::new().with(MockI2cDevice::new(0x27, &[]));
+    assert_eq!(
+        scan(&mut bus).unwrap().devices(),
+        [(0x27, Device::OtherExpander)]
+    );
+}
+
+#[test]
+fn reserved_addresses_are_not_probed() {
+    let mut bus = MockI2cBus::new()
+        .with(MockI2cDevice::new(0x00, &[]))
+        .with(MockI2cDevice::new(0x78, &[]))
+        .with(MockI2cDevice::new(0x42, &[]));
+    assert_eq!(scan(&mut bus).unwrap().devices(), [(0x42, Device::Unknown)]);
+}
+
+#[test]
+fn an_empty_bus_has_no_devices() {
+    assert_eq!(scan(&mut MockI2cBus::new()).unwrap().devices(), []);
+}
+
+#[test]
+fn bus_faults_stop_the_scan() {
+    let mut bus = MockI2cBus::new().with(MockI2cDevice::new(0x3c, &[]));
+    bus.fail_with(ErrorKind::ArbitrationLoss);
+    assert_eq!(
+        scan(&mut bus).unwrap_err(),
+        ScanError::Bus(ErrorKind::ArbitrationLoss)
+    );
+
+    let mut bus = MockI2cBus::new();
+    bus.short_sda();
+    assert_eq!(scan(&mut bus).unwrap_err(), ScanError::TooManyDevices);
+
+    let mut bus = (0..MAX_DEVICES as u8).fold(MockI2cBus::new(), |bus, i| {
+        bus.with(MockI2cDevice::new(0x08 + i, &[]))
+    });
+    assert_eq!(scan(&mut bus).unwrap().devices().len(), MAX_DEVICES);
+}
+
+#[test]
+fn scan_pins_must_be_left_free() {
+    // GPIO16 and GPIO17 are free on the breadboard, the LED driver has GPIO8 and GPIO15.
+    assert_eq!(BREADBOARD.free_i2c_pins(Some((16, 17)), 0), Some((16, 17)));
+    assert_eq!(BREADBOARD.free_i2c_pins(Some((8, 15)), 0), None);
+    assert_eq!(BREADBOARD.free_i2c_pins(Some((16, 16)), 0), None);
+    assert_eq!(BREADBOARD.free_i2c_pins(Some((16, 24)), 0), None);
+    assert_eq!(BREADBOARD.free_i2c_pins(None, 0), None);
+
+    // GPIO2 and GPIO3 are free ADC pins, unless chosen as analog inputs.
+    assert_eq!(
+        BREADBOARD_MCP23017.free_i2c_pins(Some((2, 3)), 0),
+        Some((2, 3))
+    );
+    assert_eq!(
+        BREADBOARD_MCP23017.free_i2c_pins(Some((2, 3)), 0b0000_1000),
+        None
+    );
+}
@@ -0,0 +1,66 @@
+use embedded_hal::digital::InputPin;
+use keyvisor::kbd::{
+    ColumnState, Key, KeySet, N_COLS, N_KEYS, N_ROWS,
+    backend::{DUPLEX_LINES, DuplexMatrix},
+};
+use keyvisor_firmware_tests::{MockDuplexPin, MockKeypad};
+
+#[test]
+fn key_indices_round_trip() {
+    for (i, key) in Key::all().enumerate() {
+        assert_eq!(key.index(), i);
+        assert_eq!(Key::from_index(i), key);
+    }
+    assert_eq!(Key::all().count(), N_KEYS);
+}
+
+#[test]
+fn duplex_crossings_carry_distinct_keys() {
+    let mut seen = KeySet::ZERO;
+    for col in 0..N_COLS {
+        for pin in 0..N_ROWS / 2 {
+            for column_driven in [true, false] {
+                let key = Key::duplex(col, pin, column_driven);
+                assert!(!seen[key.index()], "{key:?} read twice");
+                seen.set(key.index(), true);
+            }
+        }
+    }
+    assert!(seen[..N_KEYS].all());
+
+    // The keys at the crossing of column 1 and the second row pin.
+    assert_eq!(Key::duplex(1, 1, true).char(), '8');
+    assert_eq!(Key::duplex(1, 1, false).char(), '0');
+}
+
+/// Reads every line of a duplex matrix like the scanner does.
+fn read_duplex(matrix: &mut DuplexMatrix<MockDuplexPin>) -> [ColumnState; N_COLS] {
+    let mut tick = [ColumnState::ZERO; N_COLS];
+    for line in 0..DUPLEX_LINES {
+        matrix.select_line(line).unwrap();
+        matrix.read_line(line, &mut tick).unwrap();
+    }
+    matrix.release().unwrap();
+    tick
+}
+
+#[test]
+fn duplex_scan_after_selecting_an_odd_row_key() {
+    let keypad = MockKeypad::new();
+    let (columns, rows) = keypad.duplex_pins();
+    let mut matrix = DuplexMatrix::new(columns, rows);
+    // '4' sits on the second row, read through column 0 while the first row pin is driven.
+    let four = Key { col: 0, row: 1 };
+    keypad.press(four);
+
+    let contact = matrix.select_key(four).unwrap();
+    assert!(contact.is_low().unwrap());
+
+    // The row pin driven for the bounce analyser mustn't show the keys above it as pressed.
+    let mut expected = [ColumnState::ZERO; N_COLS];
+    expected[0].set(1, true);
+    assert_eq!(read_duplex(&mut matrix), expected);
+
+    keypad.release(four);
+    assert_eq!(read_duplex(&mut matrix), [ColumnState::ZERO; N_COLS]);
+}
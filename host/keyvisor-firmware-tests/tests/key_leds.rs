@@ -0,0 +1,161 @@
+use keyvisor::{
+    kbd::{Key, KeySet, N_KEYS, backend::ExpanderChip},
+    key_leds::{KeyLedBackend, LedDriver, LedDriverChip, OnOff, PwmLeds, detect, next_led},
+};
+use keyvisor_firmware_tests::{
+    MockI2cBus, MockI2cDevice, MockKeypad, MockLedDriver, MockLedPin, MockPwmChannel,
+};
+
+const KEY_5: Key = Key { col: 1, row: 1 };
+
+fn key_set(keys: &[usize]) -> KeySet {
+    let mut set = KeySet::ZERO;
+    for &key in keys {
+        set.set(key, true);
+    }
+    set
+}
+
+#[test]
+fn pwm_leds_start_off_and_keep_dim_levels_visible() {
+    let channels: Vec<_> = (0..3).map(|_| MockPwmChannel::new(1000)).collect();
+    let mut leds = PwmLeds::new(core::array::from_fn(|i| channels.get(i).cloned()));
+    leds.init().unwrap();
+    assert!(channels.iter().all(|channel| channel.duty() == 0));
+
+    let key = Key::from_index(1);
+    leds.set(key, 1).unwrap();
+    assert_eq!(channels[1].duty(), 4);
+    leds.set(key, 128).unwrap();
+    assert_eq!(channels[1].duty(), 502);
+    leds.set(key, u8::MAX).unwrap();
+    assert_eq!(channels[1].duty(), 1000);
+    leds.set(key, 0).unwrap();
+    assert_eq!(channels[1].duty(), 0);
+}
+
+#[test]
+fn keys_beyond_the_gpios_have_no_led() {
+    let pins: Vec<_> = (0..6).map(|_| MockLedPin::default()).collect();
+    let mut leds = PwmLeds::new(core::array::from_fn(|i| pins.get(i).cloned().map(OnOff)));
+    assert_eq!(leds.leds(), key_set(&[0, 1, 2, 3, 4, 5]));
+
+    leds.set(KEY_5, 1).unwrap();
+    assert!(pins[4].is_lit());
+    leds.set(Key::from_index(N_KEYS - 1), u8::MAX).unwrap();
+    leds.init().unwrap();
+    assert!(pins.iter().all(|pin| !pin.is_lit()));
+}
+
+#[test]
+fn sequence_skips_keys_without_an_led() {
+    let leds = key_set(&[0, 4, 11]);
+    let sequence: Vec<usize> = (0..4)
+        .scan(Key::from_index(N_KEYS - 1), |key, _| {
+            *key = next_led(*key, &leds);
+            Some(key.index())
+        })
+        .collect();
+    assert_eq!(sequence, [0, 4, 11, 0]);
+
+    // Carries on after a key held down, even one without an LED.
+    assert_eq!(next_led(Key::from_index(5), &leds).index(), 11);
+    assert_eq!(next_led(KEY_5, &key_set(&[])), KEY_5);
+}
+
+#[test]
+fn driver_chips_are_told_apart_by_address() {
+    assert_eq!(
+        LedDriverChip::at_address(0x74),
+        Some(LedDriverChip::Is31fl3731)
+    );
+    assert_eq!(
+        LedDriverChip::at_address(0x77),
+        Some(LedDriverChip::Is31fl3731)
+    );
+    assert_eq!(
+        LedDriverChip::at_address(0x50),
+        Some(LedDriverChip::Is31fl3733)
+    );
+    assert_eq!(
+        LedDriverChip::at_address(0x5f),
+        Some(LedDriverChip::Is31fl3733)
+    );
+    assert_eq!(LedDriverChip::at_address(0x20), None);
+    assert!(LedDriverChip::addresses().all(|address| LedDriverChip::at_address(address).is_some()));
+}
+
+#[test]
+fn detect_finds_the_driver_on_the_bus() {
+    let mut driver = MockLedDriver::new(LedDriverChip::Is31fl3733, 0x5a);
+    assert_eq!(
+        detect(&mut driver, None),
+        Some((LedDriverChip::Is31fl3733, 0x5a))
+    );
+    let mut driver = MockLedDriver::new(LedDriverChip::Is31fl3731, 0x77);
+    assert_eq!(
+        detect(&mut driver, None),
+        Some((LedDriverChip::Is31fl3731, 0x77))
+    );
+
+    let mut expander = MockKeypad::new().expander(ExpanderChip::Mcp23017, 0x20);
+    assert_eq!(detect(&mut expander, None), None);
+}
+
+#[test]
+fn detect_leaves_eeproms_and_sensors_alone() {
+    // A 24C02 EEPROM and a BME280 at addresses the drivers can be strapped to.
+    let mut bus = MockI2cBus::new()
+        .with(MockI2cDevice::new(0x50, &[]))
+        .with(MockI2cDevice::new(0x76, &[(0xd0, 0x60)]));
+    assert_eq!(detect(&mut bus, None), None);
+
+    // An IS31FL3733 at an EEPROM's address is only used where the profile names it.
+    let mut driver = MockLedDriver::new(LedDriverChip::Is31fl3733, 0x53);
+    assert_eq!(detect(&mut driver, None), None);
+    assert_eq!(
+        detect(&mut driver, Some(0x53)),
+        Some((LedDriverChip::Is31fl3733, 0x53))
+    );
+    assert_eq!(detect(&mut driver, Some(0x54)), None);
+}
+
+#[test]
+fn drivers_light_the_led_of_each_key() {
+    for (chip, address) in [
+        (LedDriverChip::Is31fl3731, 0x74),
+        (LedDriverChip::Is31fl3733, 0x50),
+    ] {
+        let mock = MockLedDriver::new(chip, address);
+        let mut driver = LedDriver::new(mock.clone(), chip, address);
+        driver.init().unwrap();
+        assert!(mock.lit().is_empty(), "{chip:?} lit after init");
+
+        for key in Key::all() {
+            driver.set(key, 200).unwrap();
+            assert_eq!(mock.lit(), [key.index()], "{chip:?}");
+            assert_eq!(mock.brightness(key.index()), 200);
+            driver.set(key, 0).unwrap();
+        }
+
+        driver.set(KEY_5, u8::MAX).unwrap();
+        driver.init().unwrap();
+        assert!(mock.lit().is_empty(), "{chip:?} lit after init");
+    }
+}
+
+#[test]
+fn is31fl3733_pages_need_unlocking() {
+    use embedded_hal::i2c::I2c as _;
+
+    let mut mock = MockLedDriver::new(LedDriverChip::Is31fl3733, 0x50);
+    let mut driver = LedDriver::new(mock.clone(), LedDriverChip::Is31fl3733, 0x50);
+    driver.init().unwrap();
+    driver.set(KEY_5, u8::MAX).unwrap();
+    assert_eq!(mock.lit(), [KEY_5.index()]);
+
+    // Without unlocking, the shutdown meant for the function page lands among the PWM registers.
+    mock.write(0x50, &[0xfd, 0x03]).unwrap();
+    mock.write(0x50, &[0x00, 0x00]).unwrap();
+    assert_eq!(mock.lit(), [KEY_5.index()]);
+}
@@ -0,0 +1,102 @@
+use embassy_time::{Duration, Instant};
+use keyvisor::kbd::{
+    ColumnState, Debouncer, Key, KeyEvent,
+    latency::{BUCKET_US, Histogram, LatencyMeter},
+};
+
+const KEY: Key = Key { col: 2, row: 1 };
+const SCAN_US: u64 = 2500;
+
+fn mask(pressed: bool) -> ColumnState {
+    let mut mask = ColumnState::ZERO;
+    mask.set(usize::from(KEY.row), pressed);
+    mask
+}
+
+/// Feeds reads of the key's column, one per scan, through the debouncer and the meter.
+fn scan(meter: &mut LatencyMeter, debouncer: &mut Debouncer, reads: &[bool], debounce: u8) {
+    for (i, &pressed) in reads.iter().enumerate() {
+        let at = Instant::from_micros(i as u64 * SCAN_US);
+        let col = usize::from(KEY.col);
+
+        meter.read(col, mask(pressed), debounce, at);
+        for event in debouncer.update(col, mask(pressed), debounce).events(col) {
+            meter.published(event, at + Duration::from_micros(10));
+        }
+    }
+}
+
+#[test]
+fn latency_counts_from_first_bounce() {
+    let mut meter = LatencyMeter::new();
+    let mut debouncer = Debouncer::new();
+    meter.start();
+
+    // Closes at scan 2, bounces open at scan 3 and stays closed from scan 4 on.
+    let reads = [false, false, true, false, true, true, true, true, true];
+    scan(&mut meter, &mut debouncer, &reads, 2);
+
+    // Reported by scan 7 after being stable since scan 4.
+    let histogram = meter.key(KEY);
+    assert_eq!(histogram.count(), 1);
+    assert_eq!(histogram.min_us(), Some(5 * SCAN_US as u32 + 10));
+    assert_eq!(meter.overall().count(), 1);
+}
+
+#[test]
+fn glitches_are_not_counted() {
+    let mut meter = LatencyMeter::new();
+    let mut debouncer = Debouncer::new();
+    meter.start();
+
+    let mut reads = vec![false, true, false, false, false, false];
+    // A real press later must not be timed from the glitch.
+    reads.extend([true; 4]);
+    scan(&mut meter, &mut debouncer, &reads, 2);
+
+    assert_eq!(meter.overall().count(), 1);
+    assert_eq!(meter.overall().max_us(), Some(3 * SCAN_US as u32 + 10));
+}
+
+#[test]
+fn nothing_is_counted_unless_running() {
+    let mut meter = LatencyMeter::new();
+    let mut debouncer = Debouncer::new();
+
+    scan(&mut meter, &mut debouncer, &[true; 4], 1);
+    assert!(meter.overall().is_empty());
+
+    meter.start();
+    meter.read(2, mask(false), 1, Instant::from_millis(100));
+    meter.published(KeyEvent::KeyUp(KEY), Instant::from_millis(103));
+    assert_eq!(meter.key(KEY).avg_us(), Some(3000));
+
+    meter.stop();
+    meter.read(2, mask(true), 1, Instant::from_millis(200));
+    meter.published(KeyEvent::KeyDown(KEY), Instant::from_millis(205));
+    assert_eq!(meter.overall().count(), 1);
+}
+
+#[test]
+fn histogram_statistics() {
+    let mut histogram = Histogram::new();
+    assert_eq!(histogram.percentile_us(99), None);
+
+    for us in (0..100).map(|i| 2_000 + i * 10) {
+        histogram.add(us);
+    }
+    histogram.add(90_000);
+
+    assert_eq!(histogram.count(), 101);
+    assert_eq!(histogram.min_us(), Some(2_000));
+    assert_eq!(histogram.max_us(), Some(90_000));
+    assert_eq!(
+        histogram.avg_us(),
+        Some((2_000 * 100 + 49_500 + 90_000) / 101)
+    );
+    assert_eq!(histogram.buckets()[2], 100);
+    assert_eq!(histogram.overflow(), 1);
+    assert_eq!(histogram.percentile_us(50), Some(3 * BUCKET_US));
+    assert_eq!(histogram.percentile_us(99), Some(3 * BUCKET_US));
+    assert_eq!(histogram.percentile_us(100), Some(90_000));
+}
@@ -0,0 +1,178 @@
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use keyvisor::oled::{OledBuffer, OledError, OledSize, TestPattern, WIDTH, find_and_show};
+use keyvisor_firmware_tests::{MockI2cBus, MockI2cDevice, MockSsd1306};
+
+const SIZES: [OledSize; 2] = [OledSize::W128H32, OledSize::W128H64];
+
+fn drawn(pattern: TestPattern, size: OledSize) -> OledBuffer {
+    let mut buffer = OledBuffer::new(size);
+    pattern.draw(&mut buffer);
+    buffer
+}
+
+fn pixels(size: OledSize) -> impl Iterator<Item = (u32, u32)> {
+    (0..size.height()).flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+}
+
+/// Shows `pattern` on a panel of `size` and returns the panel.
+fn show(pattern: TestPattern, size: OledSize) -> MockSsd1306 {
+    let oled = MockSsd1306::new(0x3c, size.height() as usize);
+    let mut bus = MockI2cBus::new().with(oled.clone());
+    let buffer = drawn(pattern, size);
+    assert_eq!(
+        find_and_show(&mut bus, &buffer, pattern.inverted()),
+        Ok(0x3c)
+    );
+    oled
+}
+
+#[test]
+fn buffer_is_laid_out_in_pages() {
+    let mut buffer = OledBuffer::new(OledSize::W128H32);
+    assert_eq!(buffer.bytes().len(), 512);
+    assert_eq!(OledBuffer::new(OledSize::W128H64).bytes().len(), 1024);
+
+    TestPattern::Border.draw(&mut buffer);
+    let bytes = buffer.bytes();
+    // The left column, then the top and bottom rows, in the lowest and highest bits of a page.
+    assert_eq!(bytes[..4], [0xff, 0x01, 0x01, 0x01]);
+    assert_eq!(bytes[3 * 128 + 1], 0x80);
+    assert_eq!(bytes[128 + 127], 0xff);
+    assert_eq!(bytes[128 + 1], 0x00);
+}
+
+#[test]
+fn checkerboard_alternates_squares_of_a_page() {
+    for size in SIZES {
+        let oled = show(TestPattern::Checkerboard, size);
+        for (x, y) in pixels(size) {
+            let lit = (x / 8 + y / 8) % 2 == 0;
+            assert_eq!(
+                oled.pixel(x as usize, y as usize),
+                lit,
+                "{x}, {y} of {size:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn border_lights_only_the_edges() {
+    for size in SIZES {
+        let oled = show(TestPattern::Border, size);
+        let bottom = size.height() - 1;
+        for (x, y) in pixels(size) {
+            let edge = x == 0 || y == 0 || x == WIDTH - 1 || y == bottom;
+            assert_eq!(
+                oled.pixel(x as usize, y as usize),
+                edge,
+                "{x}, {y} of {size:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn text_fits_on_the_smaller_panel() {
+    let buffer = drawn(TestPattern::Text, OledSize::W128H32);
+    let lit: Vec<_> = pixels(OledSize::W128H32)
+        .filter(|&(x, y)| buffer.pixel(x, y))
+        .collect();
+    assert!(lit.len() > 100);
+    assert!(
+        lit.iter()
+            .all(|&(x, y)| (2..WIDTH - 2).contains(&x) && (2..30).contains(&y))
+    );
+
+    let oled = show(TestPattern::Text, OledSize::W128H32);
+    assert_eq!(
+        oled.lit(),
+        lit.iter()
+            .map(|&(x, y)| (x as usize, y as usize))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn inverted_pattern_is_inverted_by_the_controller() {
+    for size in SIZES {
+        let buffer = drawn(TestPattern::Inverted, size);
+        let oled = show(TestPattern::Inverted, size);
+        assert!(oled.is_inverted());
+        for (x, y) in pixels(size) {
+            assert_eq!(oled.pixel(x as usize, y as usize), !buffer.pixel(x, y));
+        }
+    }
+
+    // And set back for the next pattern.
+    let oled = MockSsd1306::new(0x3d, 32);
+    let mut bus = MockI2cBus::new().with(oled.clone());
+    for pattern in [TestPattern::Inverted, TestPattern::Checkerboard] {
+        let buffer = drawn(pattern, OledSize::W128H32);
+        find_and_show(&mut bus, &buffer, pattern.inverted()).unwrap();
+    }
+    assert!(!oled.is_inverted());
+}
+
+#[test]
+fn small_setup_leaves_the_bottom_of_a_tall_panel_dark() {
+    let oled = MockSsd1306::new(0x3c, 64);
+    let mut bus = MockI2cBus::new().with(oled.clone());
+    let buffer = drawn(TestPattern::Checkerboard, OledSize::W128H32);
+    find_and_show(&mut bus, &buffer, false).unwrap();
+    assert!(oled.pixel(8, 31));
+    assert!(oled.lit().iter().all(|&(_, y)| y < 32));
+}
+
+#[test]
+fn oled_is_looked_for_at_both_addresses() {
+    let buffer = drawn(TestPattern::Border, OledSize::W128H64);
+
+    let oled = MockSsd1306::new(0x3d, 64);
+    let mut bus = MockI2cBus::new()
+        .with(MockI2cDevice::new(0x20, &[]))
+        .with(oled.clone());
+    assert_eq!(find_and_show(&mut bus, &buffer, false), Ok(0x3d));
+    assert!(oled.pixel(127, 63));
+
+    let mut bus = MockI2cBus::new().with(MockI2cDevice::new(0x3e, &[]));
+    assert_eq!(
+        find_and_show(&mut bus, &buffer, false),
+        Err(OledError::NotFound)
+    );
+}
+
+#[test]
+fn bus_faults_are_reported() {
+    let buffer = drawn(TestPattern::Border, OledSize::W128H64);
+
+    // Nothing answers the probe on a bus that doesn't work at all.
+    let mut bus = MockI2cBus::new().with(MockSsd1306::new(0x3c, 64));
+    bus.fail_with(ErrorKind::ArbitrationLoss);
+    assert_eq!(
+        find_and_show(&mut bus, &buffer, false),
+        Err(OledError::NotFound)
+    );
+
+    let oled = MockSsd1306::new(0x3c, 64);
+    oled.nack_data();
+    let mut bus = MockI2cBus::new().with(oled.clone());
+    assert_eq!(
+        find_and_show(&mut bus, &buffer, false),
+        Err(OledError::Bus(ErrorKind::NoAcknowledge(
+            NoAcknowledgeSource::Data
+        )))
+    );
+    assert!(oled.lit().is_empty());
+}
+
+#[test]
+fn patterns_cycle() {
+    let mut pattern = TestPattern::ALL[0];
+    for expected in TestPattern::ALL.iter().cycle().skip(1).take(4) {
+        pattern = pattern.next();
+        assert_eq!(pattern, *expected);
+    }
+    assert_eq!(OledSize::W128H32.other(), OledSize::W128H64);
+    assert_eq!(OledSize::W128H64.other(), OledSize::W128H32);
+}
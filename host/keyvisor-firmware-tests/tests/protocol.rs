@@ -0,0 +1,131 @@
+use keyvisor_protocol::{DecodeError, Event, find_event};
+
+fn encode(event: &Event) -> String {
+    let mut json = String::new();
+    event.write(&mut json).unwrap();
+    json
+}
+
+#[test]
+fn events_survive_encoding() {
+    let events = [
+        Event::Hello {
+            firmware: "0.1.0",
+            board: "PCB rev 2",
+        },
+        Event::Key {
+            ms: 81_234,
+            key: "#",
+            row: 3,
+            col: 2,
+            pressed: true,
+        },
+        Event::Scan {
+            ms: 1_000,
+            scans: 400,
+            changes: 7,
+            events: 2,
+            debounce: 10,
+        },
+        Event::Verdict {
+            ms: u64::MAX,
+            board: 1234,
+            passed: false,
+            failed: "4 #",
+            chatter: 2,
+        },
+    ];
+
+    for event in events {
+        assert_eq!(Event::parse(&encode(&event)), Ok(event));
+    }
+}
+
+#[test]
+fn key_event_matches_schema() {
+    assert_eq!(
+        encode(&Event::Key {
+            ms: 81_234,
+            key: "5",
+            row: 1,
+            col: 1,
+            pressed: false,
+        }),
+        r#"{"v":1,"type":"key","ms":81234,"key":"5","row":1,"col":1,"state":"up"}"#
+    );
+}
+
+#[test]
+fn strings_never_need_escapes() {
+    let json = encode(&Event::Hello {
+        firmware: "0.1.0",
+        board: "a \"b\" \\ c\n",
+    });
+
+    assert_eq!(
+        Event::parse(&json),
+        Ok(Event::Hello {
+            firmware: "0.1.0",
+            board: "a ?b? ? c?",
+        })
+    );
+}
+
+#[test]
+fn unknown_fields_are_ignored() {
+    assert_eq!(
+        Event::parse(
+            r#" { "v": 1, "type": "key", "new": "x", "ms": 5, "key": "1", "row": 0,
+                 "col": 0, "state": "down", "flag": true } "#
+        ),
+        Ok(Event::Key {
+            ms: 5,
+            key: "1",
+            row: 0,
+            col: 0,
+            pressed: true,
+        })
+    );
+}
+
+#[test]
+fn invalid_events_are_rejected() {
+    assert_eq!(
+        Event::parse(r#"{"v":2,"type":"key"}"#),
+        Err(DecodeError::UnsupportedVersion(2))
+    );
+    assert_eq!(
+        Event::parse(r#"{"v":1,"type":"reboot"}"#),
+        Err(DecodeError::UnknownType)
+    );
+    assert_eq!(
+        Event::parse(r#"{"v":1,"type":"hello","board":"x"}"#),
+        Err(DecodeError::MissingField("firmware"))
+    );
+    assert_eq!(
+        Event::parse(r#"{"v":1,"type":"key","ms":1,"key":"1","row":300,"col":0,"state":"down"}"#),
+        Err(DecodeError::InvalidField("row"))
+    );
+    assert_eq!(
+        Event::parse(r#"{"v":1,"type":"hello","firmware":"a\"b","board":"x"}"#),
+        Err(DecodeError::Syntax)
+    );
+    assert_eq!(
+        Event::parse(r#"{"v":1,"type":"hello"} trailing"#),
+        Err(DecodeError::Syntax)
+    );
+    assert_eq!(Event::parse(r#"{"v":-1}"#), Err(DecodeError::Syntax));
+}
+
+#[test]
+fn event_is_found_after_log_frames() {
+    let mut line = vec![0xff, 0x00, 0x12, b'{', 0x80, 0x00];
+    line.extend_from_slice(br#"{"v":1,"type":"hello","firmware":"0.1.0","board":"x"}"#);
+    line.extend_from_slice(b"\r");
+
+    assert_eq!(
+        find_event(&line),
+        Some(r#"{"v":1,"type":"hello","firmware":"0.1.0","board":"x"}"#)
+    );
+    assert_eq!(find_event(b"ok"), None);
+}
@@ -0,0 +1,98 @@
+use keyvisor::kbd::{
+    ColumnState, Debouncer, Key, KeyEvent, N_COLS,
+    recording::{Recorder, Run, Tick},
+};
+
+fn tick(pressed: &[(usize, usize)]) -> Tick {
+    let mut tick = [ColumnState::ZERO; N_COLS];
+    for &(col, row) in pressed {
+        tick[col].set(row, true);
+    }
+    tick
+}
+
+#[test]
+fn recorder_collects_runs() {
+    let mut recorder = Recorder::<8>::new();
+    recorder.push(tick(&[(0, 0)]));
+    assert!(recorder.is_empty());
+
+    recorder.start(10);
+    for t in [tick(&[]), tick(&[]), tick(&[(2, 3)]), tick(&[])] {
+        recorder.push(t);
+    }
+
+    assert_eq!(
+        recorder.run_at(0),
+        Some(Run {
+            ticks: 2,
+            masks: tick(&[])
+        })
+    );
+    assert_eq!(
+        recorder.run_at(2),
+        Some(Run {
+            ticks: 1,
+            masks: tick(&[(2, 3)])
+        })
+    );
+    assert_eq!(recorder.run_at(4), None);
+    assert_eq!(recorder.debounce_ticks(), 10);
+}
+
+#[test]
+fn recording_stops_when_full() {
+    let mut recorder = Recorder::<3>::new();
+    recorder.start(1);
+
+    for _ in 0..5 {
+        recorder.push(tick(&[(1, 1)]));
+    }
+
+    assert!(!recorder.is_recording());
+    assert_eq!(recorder.len(), 3);
+
+    recorder.start(1);
+    assert!(recorder.is_empty());
+}
+
+#[test]
+fn runs_survive_formatting() {
+    let run = Run {
+        ticks: 812,
+        masks: tick(&[(0, 0), (0, 3), (2, 1)]),
+    };
+
+    assert_eq!(run.to_string(), "812 9 0 2");
+    assert_eq!(Run::parse(&run.to_string()), Some(run));
+}
+
+#[test]
+fn invalid_runs_are_rejected() {
+    for line in [
+        "",
+        "0 0 0 0",
+        "5 0 0",
+        "5 0 0 0 0",
+        "5 0 10 0",
+        "5 0 g 0",
+        "x 0 0 0",
+    ] {
+        assert_eq!(Run::parse(line), None, "{line:?}");
+    }
+}
+
+#[test]
+fn debouncer_waits_for_stable_reads() {
+    let mut debouncer = Debouncer::new();
+    let mut events = Vec::new();
+
+    // Bounces on press, then held for 3 reads.
+    for mask in [1, 0, 1, 1, 1, 1, 0, 0, 0, 0] {
+        let update = debouncer.update(1, ColumnState::new([mask]), 2);
+        events.extend(update.events(1));
+    }
+
+    let key = Key { col: 1, row: 0 };
+    assert_eq!(events, [KeyEvent::KeyDown(key), KeyEvent::KeyUp(key)]);
+}
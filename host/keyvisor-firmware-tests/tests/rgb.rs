@@ -0,0 +1,140 @@
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+use keyvisor::rgb::{
+    END, LATCH, LEVEL, MAX_FRAME_LEN, ONE, Pattern, RMT_CLOCK_MHZ, WALK_FRAMES, ZERO, encode,
+    frame_len,
+};
+
+/// Level and duration in nanoseconds of both halves of a pulse code.
+fn halves(code: u32) -> [(bool, u32); 2] {
+    let ns = |ticks: u32| ticks * 1000 / RMT_CLOCK_MHZ;
+    [
+        (code & 1 << 15 != 0, ns(code & 0x7fff)),
+        (code & 1 << 31 != 0, ns(code >> 16 & 0x7fff)),
+    ]
+}
+
+/// Decodes the bits of a frame back into bytes, checking that each code is a zero or a one.
+fn bytes(codes: &[u32]) -> Vec<u8> {
+    codes
+        .chunks(8)
+        .map(|bits| {
+            bits.iter().fold(0, |byte, &code| {
+                assert!(code == ZERO || code == ONE, "not a bit: {code:#x}");
+                byte << 1 | u8::from(code == ONE)
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn bit_timings_suit_the_ws2812_and_the_sk6812() {
+    // Both chips take ±150 ns; the SK6812 wants 300/600 ns high pulses, the WS2812 400/800 ns.
+    let [(true, zero_high), (false, zero_low)] = halves(ZERO) else {
+        panic!("a zero isn't high then low");
+    };
+    let [(true, one_high), (false, one_low)] = halves(ONE) else {
+        panic!("a one isn't high then low");
+    };
+    assert!((250..=450).contains(&zero_high), "{zero_high} ns");
+    assert!((700..=1000).contains(&zero_low), "{zero_low} ns");
+    assert!((650..=750).contains(&one_high), "{one_high} ns");
+    assert!((300..=600).contains(&one_low), "{one_low} ns");
+
+    let [(false, first), (false, second)] = halves(LATCH) else {
+        panic!("the latch isn't low");
+    };
+    assert!(first + second >= 80_000);
+    assert_eq!(END, 0);
+}
+
+#[test]
+fn colors_are_sent_green_red_blue_msb_first() {
+    let mut codes = [0; MAX_FRAME_LEN];
+    let colors = [Rgb888::new(0x12, 0x80, 0x01), Rgb888::new(0xff, 0, 0xa5)];
+
+    let len = encode(colors, &mut codes);
+
+    assert_eq!(len, frame_len(2));
+    assert_eq!(bytes(&codes[..48]), [0x80, 0x12, 0x01, 0x00, 0xff, 0xa5]);
+    assert_eq!(codes[48..len], [LATCH, END]);
+}
+
+#[test]
+fn an_empty_chain_only_gets_the_latch() {
+    let mut codes = [0; 2];
+
+    assert_eq!(encode([], &mut codes), 2);
+    assert_eq!(codes, [LATCH, END]);
+}
+
+#[test]
+fn the_walk_lights_one_led_after_the_other() {
+    let lit = |frame| {
+        (0..3)
+            .filter(|&led| Pattern::Walk.color(led, 3, frame) != Rgb888::BLACK)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(lit(0), [0]);
+    assert_eq!(lit(WALK_FRAMES - 1), [0]);
+    assert_eq!(lit(WALK_FRAMES), [1]);
+    assert_eq!(lit(3 * WALK_FRAMES), [0]);
+    assert_eq!(
+        Pattern::Walk.color(0, 3, 0),
+        Rgb888::new(LEVEL, LEVEL, LEVEL)
+    );
+}
+
+#[test]
+fn stepping_starts_at_the_walking_led_and_stops_at_the_ends() {
+    let step = Pattern::Walk.step(true, 8, 2 * WALK_FRAMES);
+    assert_eq!(step, Pattern::Step(2));
+    assert_eq!(step.lit_led(8, 0), Some(2));
+    assert_eq!(Pattern::Red.step(false, 8, 0), Pattern::Step(0));
+
+    assert_eq!(Pattern::Step(2).step(true, 8, 0), Pattern::Step(3));
+    assert_eq!(Pattern::Step(7).step(true, 8, 0), Pattern::Step(7));
+    assert_eq!(Pattern::Step(0).step(false, 8, 0), Pattern::Step(0));
+
+    assert_eq!(
+        Pattern::Step(5).color(5, 8, 99),
+        Pattern::Walk.color(0, 8, 0)
+    );
+    assert_eq!(Pattern::Step(5).color(4, 8, 99), Rgb888::BLACK);
+}
+
+#[test]
+fn patterns_cycle_and_stepping_goes_back_to_the_walk() {
+    let mut pattern = Pattern::Walk;
+    for expected in [
+        Pattern::Red,
+        Pattern::Green,
+        Pattern::Blue,
+        Pattern::Rainbow,
+        Pattern::Walk,
+    ] {
+        pattern = pattern.next();
+        assert_eq!(pattern, expected);
+    }
+    assert_eq!(Pattern::Step(4).next(), Pattern::Walk);
+}
+
+#[test]
+fn fills_and_the_rainbow_stay_at_the_level() {
+    assert_eq!(Pattern::Green.color(9, 10, 3), Rgb888::new(0, LEVEL, 0));
+
+    let rainbow: Vec<_> = (0..12)
+        .map(|led| Pattern::Rainbow.color(led, 12, 0))
+        .collect();
+    assert_eq!(rainbow[0], Rgb888::new(LEVEL, 0, 0));
+    assert!(rainbow.windows(2).all(|pair| pair[0] != pair[1]));
+    assert!(
+        rainbow
+            .iter()
+            .all(|color| color.r().max(color.g()).max(color.b()) <= LEVEL)
+    );
+    assert_ne!(
+        Pattern::Rainbow.color(0, 12, 1),
+        Pattern::Rainbow.color(0, 12, 0)
+    );
+}
@@ -0,0 +1,148 @@
+use embassy_time::{Duration, Instant};
+use keyvisor::{
+    kbd::{Key, KeyEvent},
+    session::{BoardIdAction, BoardIdInput, CHATTER_WINDOW, MAX_BOARD_ID_DIGITS, Session},
+};
+use keyvisor_firmware_tests as _;
+
+fn key(label: char) -> Key {
+    Key::all().find(|key| key.char() == label).unwrap()
+}
+
+fn type_keys(input: &mut BoardIdInput, labels: &str) -> Vec<Option<BoardIdAction>> {
+    labels
+        .chars()
+        .map(|label| {
+            input.handle(KeyEvent::KeyUp(key(label)));
+            input.handle(KeyEvent::KeyDown(key(label)))
+        })
+        .collect()
+}
+
+fn at(ms: u64) -> Instant {
+    Instant::from_millis(ms)
+}
+
+/// Presses and releases every key once, a second apart.
+fn press_all(session: &mut Session) {
+    for (i, key) in Key::all().enumerate() {
+        let t = 1000 * i as u64;
+        session.handle(KeyEvent::KeyDown(key), at(t));
+        session.handle(KeyEvent::KeyUp(key), at(t + 100));
+    }
+}
+
+#[test]
+fn board_id_is_typed_and_confirmed() {
+    let mut input = BoardIdInput::default();
+
+    let actions = type_keys(&mut input, "0427#");
+    assert_eq!(actions.last(), Some(&Some(BoardIdAction::Confirmed(427))));
+    assert_eq!(input.as_str(), "0427");
+}
+
+#[test]
+fn star_deletes_or_asks_for_history() {
+    let mut input = BoardIdInput::default();
+
+    assert_eq!(
+        type_keys(&mut input, "12**"),
+        [
+            Some(BoardIdAction::Edited),
+            Some(BoardIdAction::Edited),
+            Some(BoardIdAction::Edited),
+            Some(BoardIdAction::Edited),
+        ]
+    );
+    assert_eq!(input.as_str(), "");
+    assert_eq!(
+        type_keys(&mut input, "*"),
+        [Some(BoardIdAction::ShowHistory)]
+    );
+}
+
+#[test]
+fn board_id_length_is_limited() {
+    let mut input = BoardIdInput::default();
+
+    type_keys(&mut input, &"9".repeat(MAX_BOARD_ID_DIGITS + 2));
+    assert_eq!(input.as_str(), "9".repeat(MAX_BOARD_ID_DIGITS));
+    assert_eq!(
+        type_keys(&mut input, "#"),
+        [Some(BoardIdAction::Confirmed(999_999_999))]
+    );
+}
+
+#[test]
+fn hash_without_board_id_asks_for_tools() {
+    assert_eq!(
+        type_keys(&mut BoardIdInput::default(), "#"),
+        [Some(BoardIdAction::ShowTools)]
+    );
+}
+
+#[test]
+fn key_passes_when_released() {
+    let mut session = Session::new(7);
+
+    assert_eq!(session.handle(KeyEvent::KeyDown(key('5')), at(0)), None);
+    assert!(!session.passed(key('5')));
+    assert_eq!(
+        session.handle(KeyEvent::KeyUp(key('5')), at(80)),
+        Some(key('5'))
+    );
+    assert!(session.passed(key('5')));
+
+    session.handle(KeyEvent::KeyDown(key('5')), at(500));
+    assert_eq!(session.handle(KeyEvent::KeyUp(key('5')), at(580)), None);
+}
+
+#[test]
+fn release_of_key_held_before_session_is_ignored() {
+    let mut session = Session::new(7);
+
+    assert_eq!(session.handle(KeyEvent::KeyUp(key('#')), at(0)), None);
+    assert!(!session.passed(key('#')));
+}
+
+#[test]
+fn quick_repress_counts_as_chatter() {
+    let mut session = Session::new(7);
+    let window = CHATTER_WINDOW.as_millis();
+
+    session.handle(KeyEvent::KeyDown(key('3')), at(0));
+    session.handle(KeyEvent::KeyUp(key('3')), at(100));
+    session.handle(KeyEvent::KeyDown(key('3')), at(100 + window - 1));
+    session.handle(KeyEvent::KeyUp(key('3')), at(200 + window));
+    session.handle(KeyEvent::KeyDown(key('3')), at(200 + 2 * window));
+
+    let result = session.finish(Duration::from_secs(1));
+    assert_eq!(result.chatter[key('3').index()], 1);
+    assert_eq!(result.total_chatter(), 1);
+}
+
+#[test]
+fn session_completes_when_all_keys_released() {
+    let mut session = Session::new(7);
+    assert!(!session.is_complete());
+
+    press_all(&mut session);
+    assert!(session.is_complete());
+
+    session.handle(KeyEvent::KeyDown(key('1')), at(60_000));
+    assert!(!session.is_complete());
+}
+
+#[test]
+fn finished_session_lists_failed_keys() {
+    let mut session = Session::new(1234);
+    session.handle(KeyEvent::KeyDown(key('1')), at(0));
+    session.handle(KeyEvent::KeyUp(key('1')), at(100));
+
+    let result = session.finish(Duration::from_secs(90));
+    assert_eq!(result.board_id, 1234);
+    assert_eq!(result.uptime_ms, 90_000);
+    assert!(!result.passed());
+    assert_eq!(result.failed_keys().count(), 11);
+    assert!(result.failed_keys().all(|failed| failed != key('1')));
+}
@@ -0,0 +1,88 @@
+use keyvisor::settings::{Rotation, Settings, SettingsLog, SettingsStore};
+use keyvisor_firmware_tests::MockFlash;
+
+fn changed() -> Settings {
+    Settings {
+        brightness_pct: 75,
+        debounce_ticks: 5,
+        layout: 2,
+        rotation: Rotation::Deg180,
+        board: Some(3),
+        actuation_pct: 30,
+        analog_pins: 0b0011_0000,
+        chain_len: 96,
+        i2c_pins: Some((6, 7)),
+    }
+}
+
+#[test]
+fn blank_flash_gives_defaults() {
+    let mut flash = MockFlash::new(2);
+    let store = SettingsStore::open(&mut flash).unwrap();
+
+    assert_eq!(*store.settings(), Settings::default());
+}
+
+#[test]
+fn saved_settings_are_loaded() {
+    let mut flash = MockFlash::new(2);
+
+    let mut store = SettingsStore::open(&mut flash).unwrap();
+    store.save(&changed()).unwrap();
+    assert_eq!(*store.settings(), changed());
+
+    let store = SettingsStore::open(&mut flash).unwrap();
+    assert_eq!(*store.settings(), changed());
+}
+
+#[test]
+fn unchanged_settings_are_not_written() {
+    let mut flash = MockFlash::new(2);
+
+    let mut store = SettingsStore::open(&mut flash).unwrap();
+    store.save(&Settings::default()).unwrap();
+
+    assert!(flash.data().iter().all(|&byte| byte == 0xff));
+}
+
+#[test]
+fn records_of_unknown_versions_are_ignored() {
+    let mut flash = MockFlash::new(2);
+
+    let mut log = SettingsLog::open(&mut flash).unwrap();
+    log.append(99, &changed().encode()).unwrap();
+
+    let store = SettingsStore::open(&mut flash).unwrap();
+    assert_eq!(*store.settings(), Settings::default());
+}
+
+#[test]
+fn missing_fields_get_defaults() {
+    let settings = Settings::decode(1, &[20]).unwrap();
+
+    assert_eq!(
+        settings,
+        Settings {
+            brightness_pct: 20,
+            ..Settings::default()
+        }
+    );
+}
+
+#[test]
+fn invalid_fields_get_defaults() {
+    let settings = Settings::decode(1, &[101, 0, 1, 4, 0, 95, 0, 0, 5, 5]).unwrap();
+
+    assert_eq!(
+        settings,
+        Settings {
+            layout: 1,
+            ..Settings::default()
+        }
+    );
+}
+
+#[test]
+fn settings_fit_their_slot() {
+    assert!(changed().encode().len() <= SettingsLog::<MockFlash>::MAX_PAYLOAD_LEN);
+}
@@ -0,0 +1,126 @@
+use keyvisor::storage::{Error, Record, RecordLog};
+use keyvisor_firmware_tests::{MockFlash, MockFlashError};
+
+const SLOT_SIZE: usize = 32;
+const SLOTS_PER_SECTOR: usize = MockFlash::SECTOR_SIZE / SLOT_SIZE;
+
+type Log<'a> = RecordLog<&'a mut MockFlash, SLOT_SIZE>;
+
+fn newest_payload(log: &mut Log) -> Option<Vec<u8>> {
+    let mut buf = [0; SLOT_SIZE];
+    log.newest(&mut buf)
+        .unwrap()
+        .map(|record| record.payload.to_vec())
+}
+
+#[test]
+fn blank_flash_has_no_records() {
+    let mut flash = MockFlash::new(2);
+    let mut log = Log::open(&mut flash).unwrap();
+
+    assert_eq!(newest_payload(&mut log), None);
+}
+
+#[test]
+fn newest_record_survives_reopening() {
+    let mut flash = MockFlash::new(2);
+
+    let mut log = Log::open(&mut flash).unwrap();
+    log.append(1, b"first").unwrap();
+    log.append(2, b"second").unwrap();
+
+    let mut log = Log::open(&mut flash).unwrap();
+    let mut buf = [0; SLOT_SIZE];
+    assert_eq!(
+        log.newest(&mut buf).unwrap(),
+        Some(Record {
+            seq: 1,
+            version: 2,
+            payload: b"second",
+        })
+    );
+}
+
+#[test]
+fn appending_wraps_around_and_spreads_erases() {
+    let mut flash = MockFlash::new(4);
+    let records = (10 * 4 * SLOTS_PER_SECTOR + 7) as u32;
+
+    let mut log = Log::open(&mut flash).unwrap();
+    for i in 0..records {
+        log.append(1, &i.to_le_bytes()).unwrap();
+    }
+
+    let mut log = Log::open(&mut flash).unwrap();
+    assert_eq!(
+        newest_payload(&mut log),
+        Some((records - 1).to_le_bytes().to_vec())
+    );
+
+    let counts = flash.erase_counts();
+    let min = counts.iter().min().unwrap();
+    let max = counts.iter().max().unwrap();
+    assert!(max - min <= 1, "uneven erase counts: {counts:?}");
+}
+
+#[test]
+fn corrupted_record_is_skipped() {
+    let mut flash = MockFlash::new(2);
+
+    let mut log = Log::open(&mut flash).unwrap();
+    log.append(1, b"good").unwrap();
+    log.append(1, b"bad").unwrap();
+
+    flash.data_mut()[SLOT_SIZE + 9] ^= 0x01;
+
+    let mut log = Log::open(&mut flash).unwrap();
+    assert_eq!(newest_payload(&mut log).as_deref(), Some(&b"good"[..]));
+
+    log.append(1, b"next").unwrap();
+    let mut log = Log::open(&mut flash).unwrap();
+    assert_eq!(newest_payload(&mut log).as_deref(), Some(&b"next"[..]));
+}
+
+#[test]
+fn torn_write_keeps_previous_record() {
+    let mut flash = MockFlash::new(2);
+
+    let mut log = Log::open(&mut flash).unwrap();
+    log.append(1, b"before").unwrap();
+
+    flash.cut_power_after(10);
+    let mut log = Log::open(&mut flash).unwrap();
+    assert!(matches!(
+        log.append(1, b"during"),
+        Err(Error::Flash(MockFlashError::PowerLoss))
+    ));
+    flash.restore_power();
+
+    let mut log = Log::open(&mut flash).unwrap();
+    assert_eq!(newest_payload(&mut log).as_deref(), Some(&b"before"[..]));
+
+    log.append(1, b"after").unwrap();
+    let mut log = Log::open(&mut flash).unwrap();
+    assert_eq!(newest_payload(&mut log).as_deref(), Some(&b"after"[..]));
+
+    // The torn slot is skipped rather than written over.
+    assert_eq!(&flash.data()[2 * SLOT_SIZE + 8..][..5], b"after");
+}
+
+#[test]
+fn region_needs_two_sectors() {
+    let mut flash = MockFlash::new(1);
+
+    assert!(matches!(Log::open(&mut flash), Err(Error::RegionTooSmall)));
+}
+
+#[test]
+fn oversized_payload_is_rejected() {
+    let mut flash = MockFlash::new(2);
+    let mut log = Log::open(&mut flash).unwrap();
+
+    assert!(matches!(
+        log.append(1, &[0; Log::MAX_PAYLOAD_LEN + 1]),
+        Err(Error::PayloadTooLarge)
+    ));
+}
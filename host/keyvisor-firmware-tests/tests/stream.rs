@@ -0,0 +1,106 @@
+use embassy_time::Instant;
+use keyvisor::{
+    history::{FIRMWARE_VERSION, TestResult},
+    kbd::{Key, KeyEvent, KeySet, N_KEYS},
+    stream::{self, ScanStats, Update},
+};
+use keyvisor_firmware_tests as _;
+use keyvisor_protocol::Event;
+
+fn key(label: char) -> Key {
+    Key::all().find(|key| key.char() == label).unwrap()
+}
+
+fn encode(update: &Update) -> String {
+    let mut json = String::new();
+    update.write_json(&mut json).unwrap();
+    json
+}
+
+#[test]
+fn key_update_carries_label_and_position() {
+    let json = encode(&Update::Key {
+        event: KeyEvent::KeyDown(key('#')),
+        at: Instant::from_millis(1500),
+    });
+
+    assert_eq!(
+        Event::parse(&json),
+        Ok(Event::Key {
+            ms: 1500,
+            key: "#",
+            row: 3,
+            col: 2,
+            pressed: true,
+        })
+    );
+}
+
+#[test]
+fn scan_update_carries_stats() {
+    let json = encode(&Update::Scan {
+        stats: ScanStats {
+            scans: 400,
+            changes: 9,
+            events: 4,
+        },
+        debounce_ticks: 5,
+        at: Instant::from_millis(2000),
+    });
+
+    assert_eq!(
+        Event::parse(&json),
+        Ok(Event::Scan {
+            ms: 2000,
+            scans: 400,
+            changes: 9,
+            events: 4,
+            debounce: 5,
+        })
+    );
+}
+
+#[test]
+fn verdict_lists_failed_keys() {
+    let mut result = TestResult {
+        board_id: 77,
+        passed: KeySet::ZERO,
+        chatter: [0; N_KEYS],
+        uptime_ms: 0,
+        firmware: FIRMWARE_VERSION,
+    };
+    for key in Key::all().filter(|key| !['1', '#'].contains(&key.char())) {
+        result.passed.set(key.index(), true);
+    }
+    result.chatter[key('5').index()] = 3;
+
+    let json = encode(&Update::Verdict {
+        result,
+        at: Instant::from_millis(60_000),
+    });
+
+    assert_eq!(
+        Event::parse(&json),
+        Ok(Event::Verdict {
+            ms: 60_000,
+            board: 77,
+            passed: false,
+            failed: "1 #",
+            chatter: 3,
+        })
+    );
+}
+
+#[test]
+fn hello_names_firmware_and_board() {
+    let mut json = String::new();
+    stream::write_hello("breadboard", &mut json).unwrap();
+
+    let [major, minor, patch] = FIRMWARE_VERSION;
+    assert_eq!(
+        json,
+        format!(
+            r#"{{"v":1,"type":"hello","firmware":"{major}.{minor}.{patch}","board":"breadboard"}}"#
+        )
+    );
+}
@@ -0,0 +1,130 @@
+//! Decoding of framebuffer dumps sent by the firmware over the USB-serial-JTAG port.
+//!
+//! The port also carries defmt log frames, so the decoder skips everything up to the frame magic.
+//! A log frame that landed in the middle of the pixels fails the frame's CRC. See
+//! `src/screenshot.rs` in the firmware for the wire format.
+
+use std::io::{self, BufWriter, Read, Write};
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+
+pub const MAGIC: [u8; 4] = *b"KVSS";
+pub const VERSION: u8 = 2;
+
+const HEADER_SIZE: usize = 18;
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const PIXEL_FORMAT_RGB565_BE: u8 = 1;
+
+pub struct Screenshot {
+    pub width: u16,
+    pub height: u16,
+    /// RGB565 pixels, big-endian, row-major.
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    /// Reads the next screenshot frame from the stream, discarding any data preceding it.
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        skip_to_magic(reader)?;
+
+        let mut header = [0u8; HEADER_SIZE - MAGIC.len()];
+        reader.read_exact(&mut header)?;
+
+        let version = header[0];
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported screenshot version {version}"
+            )));
+        }
+
+        let format = header[1];
+        if format != PIXEL_FORMAT_RGB565_BE {
+            return Err(invalid_data(format!("unsupported pixel format {format}")));
+        }
+
+        let width = u16::from_le_bytes([header[2], header[3]]);
+        let height = u16::from_le_bytes([header[4], header[5]]);
+        let payload_len = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+        let payload_crc = u32::from_le_bytes([header[10], header[11], header[12], header[13]]);
+
+        if payload_len as usize != usize::from(width) * usize::from(height) * 2 {
+            return Err(invalid_data(format!(
+                "payload of {payload_len} bytes doesn't match {width}x{height} RGB565"
+            )));
+        }
+
+        let mut pixels = vec![0u8; payload_len as usize];
+        reader.read_exact(&mut pixels)?;
+
+        let crc = CRC.checksum(&pixels);
+        if crc != payload_crc {
+            return Err(invalid_data(format!(
+                "payload CRC {crc:08x} doesn't match {payload_crc:08x}, the frame was corrupted"
+            )));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Converts the pixels to 8-bit-per-channel RGB.
+    pub fn to_rgb888(&self) -> Vec<u8> {
+        self.pixels
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .flat_map(|&px| {
+                let raw = u16::from_be_bytes(px);
+                let r = ((raw >> 11) & 0x1f) as u8;
+                let g = ((raw >> 5) & 0x3f) as u8;
+                let b = (raw & 0x1f) as u8;
+                [
+                    (r << 3) | (r >> 2),
+                    (g << 2) | (g >> 4),
+                    (b << 3) | (b >> 2),
+                ]
+            })
+            .collect()
+    }
+
+    pub fn write_png(&self, writer: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(writer),
+            self.width.into(),
+            self.height.into(),
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.to_rgb888())
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+fn skip_to_magic(reader: &mut impl Read) -> io::Result<()> {
+    let mut matched = 0;
+    let mut byte = [0u8];
+
+    while matched < MAGIC.len() {
+        reader.read_exact(&mut byte)?;
+        matched = if byte[0] == MAGIC[matched] {
+            matched + 1
+        } else if byte[0] == MAGIC[0] {
+            1
+        } else {
+            0
+        };
+    }
+
+    Ok(())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Write as _},
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::Parser;
+use keyvisor_screenshot::Screenshot;
+
+/// Captures the tester's screen as a PNG image.
+#[derive(Parser)]
+struct Args {
+    /// Serial port of the tester, e.g. /dev/ttyACM0.
+    #[arg(
+        short,
+        long,
+        conflicts_with = "input",
+        required_unless_present = "input"
+    )]
+    port: Option<String>,
+
+    /// Decode a previously recorded serial stream instead of requesting a screenshot.
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+
+    /// Where to write the PNG image.
+    output: PathBuf,
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+
+    let screenshot = match (args.port, args.input) {
+        (Some(port), _) => {
+            let mut port = serialport::new(port, 115_200)
+                .timeout(Duration::from_secs(5))
+                .open()?;
+            port.write_all(b"screenshot\n")?;
+            Screenshot::read_from(&mut BufReader::new(port))?
+        }
+        (None, Some(input)) => Screenshot::read_from(&mut BufReader::new(File::open(input)?))?,
+        (None, None) => unreachable!("enforced by clap"),
+    };
+
+    screenshot.write_png(File::create(&args.output)?)?;
+
+    println!(
+        "wrote {}x{} screenshot to {}",
+        screenshot.width,
+        screenshot.height,
+        args.output.display()
+    );
+
+    Ok(())
+}
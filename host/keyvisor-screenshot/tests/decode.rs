@@ -0,0 +1,58 @@
+use std::io::{Cursor, ErrorKind};
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+use keyvisor_screenshot::{MAGIC, Screenshot, VERSION};
+
+/// A red and a blue pixel.
+const PIXELS: [u8; 4] = [0xf8, 0x00, 0x00, 0x1f];
+
+/// A defmt log frame, as sent by other tasks in between.
+const LOG_FRAME: &[u8] = b"\xff\x00\x03\x12\x34\x00";
+
+/// The header of a frame of 2x1 pixels, with the CRC of `crc_of`.
+fn frame(version: u8, crc_of: &[u8]) -> Vec<u8> {
+    let mut frame = MAGIC.to_vec();
+    frame.extend_from_slice(&[version, 1]);
+    frame.extend_from_slice(&2u16.to_le_bytes());
+    frame.extend_from_slice(&1u16.to_le_bytes());
+    frame.extend_from_slice(&4u32.to_le_bytes());
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(crc_of);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+#[test]
+fn frame_is_found_after_log_frames() {
+    let mut stream = LOG_FRAME.to_vec();
+    stream.extend(frame(VERSION, &PIXELS));
+    stream.extend_from_slice(&PIXELS);
+
+    let screenshot = Screenshot::read_from(&mut Cursor::new(stream)).unwrap();
+    assert_eq!((screenshot.width, screenshot.height), (2, 1));
+    assert_eq!(screenshot.to_rgb888(), [255, 0, 0, 0, 0, 255]);
+}
+
+#[test]
+fn log_frame_within_the_pixels_is_rejected() {
+    let mut stream = frame(VERSION, &PIXELS);
+    stream.extend_from_slice(&PIXELS[..1]);
+    stream.extend_from_slice(LOG_FRAME);
+    stream.extend_from_slice(&PIXELS[1..]);
+
+    let error = Screenshot::read_from(&mut Cursor::new(stream))
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(error.to_string().contains("CRC"), "{error}");
+}
+
+#[test]
+fn frames_without_a_crc_are_rejected() {
+    let mut stream = frame(1, &PIXELS);
+    stream.extend_from_slice(&PIXELS);
+
+    let error = Screenshot::read_from(&mut Cursor::new(stream))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "unsupported screenshot version 1");
+}
@@ -0,0 +1,344 @@
+use std::{
+    convert::Infallible,
+    fmt::{self, Write as _},
+    sync::Mutex,
+};
+
+use embassy_futures::{
+    block_on,
+    select::{Either, select},
+    yield_now,
+};
+use embassy_time::{Duration, Instant, MockDriver, with_timeout};
+use embedded_graphics::{pixelcolor::Rgb888, primitives::Rectangle};
+use embedded_hal::i2c::{self, ErrorKind, I2c, NoAcknowledgeSource, Operation};
+use keyvisor::{
+    board::{BoardProfile, KeyLedPins},
+    bounce::Capture,
+    encoder::EncoderEvent,
+    history::{Browser, HistoryLog, TestResult},
+    i2c_scan::{self, Scan, ScanError},
+    kbd::{Key, KeyEvent, KeySet, analog::AnalogKeys, latency::LatencyMeter},
+    key_leds::LedDriverChip,
+    oled::{self, OledBuffer, OledError},
+    settings::Settings,
+    storage,
+    stream::Update,
+    text::TextBuf,
+    ui::app::{self, Device, Hardware, Input, KeyLedControl, NO_I2C_PINS, NO_LED_DRIVER},
+};
+use keyvisor_firmware_tests::{MockFlash, MockFlashError};
+
+use crate::{Canvas, Step};
+
+/// Time that passes at once while the script waits, short enough for the UI's timers to fire in
+/// order.
+const TICK: Duration = Duration::from_millis(1);
+
+/// Sectors of the simulated `history` partition.
+const HISTORY_SECTORS: usize = 4;
+
+/// The clock of the UI is the global [`MockDriver`], so only one simulation runs at a time.
+static CLOCK: Mutex<()> = Mutex::new(());
+
+/// The simulated `history` partition failed.
+#[derive(Debug)]
+pub struct SimError(storage::Error<MockFlashError>);
+
+/// Why the UI stopped.
+#[derive(Debug, defmt::Format)]
+enum Stop {
+    /// It waited for a key after the last step.
+    ScriptEnded,
+    Flash(#[defmt(Debug2Format)] storage::Error<MockFlashError>),
+}
+
+impl From<Infallible> for Stop {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+impl From<storage::Error<MockFlashError>> for Stop {
+    fn from(error: storage::Error<MockFlashError>) -> Self {
+        Stop::Flash(error)
+    }
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "history flash failed: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for SimError {}
+
+/// Runs the UI of a tester with the given board on a fresh clock and history, feeding it the
+/// steps. Returns the screen before the first step and after each step.
+///
+/// Only the keypad of the board is simulated: the tools find no hardware answering, so bounces,
+/// encoder events and analog readings never come, and the I2C bus is empty.
+pub fn run(board: &'static BoardProfile, steps: &[Step]) -> Result<Vec<Canvas>, SimError> {
+    let _clock = CLOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    MockDriver::get().reset();
+
+    let mut simulator = Simulator {
+        board,
+        settings: Settings::default(),
+        canvas: Canvas::new(),
+        steps,
+        done: 0,
+        wait_until: None,
+        frames: Vec::new(),
+        history: HistoryLog::open(MockFlash::new(HISTORY_SECTORS)).map_err(SimError)?,
+    };
+    let mut hardware = SimHardware {
+        board,
+        settings: Settings::default(),
+        latency_meter: LatencyMeter::new(),
+        analog_keys: AnalogKeys::new(),
+    };
+
+    match block_on(app::run(&mut simulator, &mut hardware)) {
+        Err(Stop::ScriptEnded) => Ok(simulator.frames),
+        Err(Stop::Flash(error)) => Err(SimError(error)),
+    }
+}
+
+struct Simulator<'a> {
+    board: &'static BoardProfile,
+    settings: Settings,
+    canvas: Canvas,
+    steps: &'a [Step],
+    /// Steps taken so far.
+    done: usize,
+    /// End of the wait at `steps[done]` once it started.
+    wait_until: Option<Instant>,
+    frames: Vec<Canvas>,
+    history: HistoryLog<MockFlash>,
+}
+
+impl Simulator<'_> {
+    /// Takes the next key event from the script, letting time pass for the waits before it.
+    async fn next_key(&mut self) -> Result<KeyEvent, Stop> {
+        loop {
+            // Recorded once the UI waits for the next step, which is when it's done drawing.
+            if self.frames.len() == self.done {
+                self.frames.push(self.canvas.clone());
+            }
+
+            match self.steps.get(self.done) {
+                None => return Err(Stop::ScriptEnded),
+                Some(&Step::Key(event)) => {
+                    self.done += 1;
+                    return Ok(event);
+                }
+                Some(&Step::Wait(duration)) => {
+                    let until = *self
+                        .wait_until
+                        .get_or_insert_with(|| Instant::now() + duration);
+                    if Instant::now() >= until {
+                        self.wait_until = None;
+                        self.done += 1;
+                    } else {
+                        MockDriver::get().advance(TICK);
+                    }
+                    // Lets the UI's timers that are due fire before the screen is recorded.
+                    yield_now().await;
+                }
+            }
+        }
+    }
+}
+
+impl Device for Simulator<'_> {
+    type Frame = Canvas;
+    type Error = Stop;
+
+    fn board(&self) -> &'static BoardProfile {
+        self.board
+    }
+
+    fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    fn frame(&mut self) -> &mut Canvas {
+        &mut self.canvas
+    }
+
+    async fn flush(&mut self) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    async fn flush_area(&mut self, _bounds: Rectangle) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    async fn wake(&mut self) {}
+
+    fn dim(&mut self) {}
+
+    async fn next_input<T>(
+        &mut self,
+        timeout: Duration,
+        other: impl Future<Output = T>,
+    ) -> Result<Option<Input<T>>, Stop> {
+        match with_timeout(timeout, select(self.next_key(), other)).await {
+            Ok(Either::First(event)) => Ok(Some(Input::Key(event?))),
+            Ok(Either::Second(output)) => Ok(Some(Input::Other(output))),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn emit(&mut self, _update: Update) {}
+
+    fn history_available(&self) -> bool {
+        true
+    }
+
+    fn save_result(&mut self, result: &TestResult) -> Result<(), Stop> {
+        Ok(self.history.append(result)?)
+    }
+
+    fn result_count(&mut self) -> Result<usize, Stop> {
+        Ok(self.history.count()?)
+    }
+
+    fn browse_results(&mut self) -> Result<Option<Browser>, Stop> {
+        Ok(self.history.browse()?)
+    }
+
+    fn older_result(&mut self, browser: &mut Browser) -> Result<bool, Stop> {
+        Ok(self.history.older(browser)?)
+    }
+
+    fn newer_result(&mut self, browser: &mut Browser) -> Result<bool, Stop> {
+        Ok(self.history.newer(browser)?)
+    }
+}
+
+/// The tool hardware of a board that has nothing but its keypad connected.
+struct SimHardware {
+    board: &'static BoardProfile,
+    settings: Settings,
+    latency_meter: LatencyMeter,
+    analog_keys: AnalogKeys,
+}
+
+impl SimHardware {
+    /// The pins of the bus the I2C tools use, chosen like the tester does.
+    fn i2c_bus(&self) -> Result<TextBuf<24>, &'static str> {
+        let pins = self
+            .board
+            .free_i2c_pins(self.settings.i2c_pins, self.settings.analog_pins);
+        let (sda, scl) = match (pins, &self.board.key_leds) {
+            (Some(pins), _) => pins,
+            (None, Some(KeyLedPins::I2c { sda, scl, .. })) => (*sda, *scl),
+            _ => return Err(NO_I2C_PINS),
+        };
+        let mut bus = TextBuf::new();
+        let _ = write!(bus, "SDA {sda}, SCL {scl}");
+        Ok(bus)
+    }
+}
+
+impl Hardware for SimHardware {
+    type Error = Infallible;
+
+    fn analyse_bounce(&mut self, _key: Option<Key>) {}
+
+    async fn next_capture(&mut self) -> Capture {
+        core::future::pending().await
+    }
+
+    fn with_latency_meter<R>(&mut self, f: impl FnOnce(&mut LatencyMeter) -> R) -> R {
+        f(&mut self.latency_meter)
+    }
+
+    fn follow_encoder(&mut self, _follow: bool) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    async fn next_encoder_event(&mut self) -> EncoderEvent {
+        core::future::pending().await
+    }
+
+    fn with_analog_keys<R>(&mut self, f: impl FnOnce(&mut AnalogKeys) -> R) -> R {
+        f(&mut self.analog_keys)
+    }
+
+    async fn read_adc(&mut self, _gpio: u8) -> Option<u16> {
+        None
+    }
+
+    fn has_led_chain(&self) -> bool {
+        self.board.rgb_chain.is_some()
+    }
+
+    fn show_leds(&mut self, _colors: impl IntoIterator<Item = Rgb888>) {}
+
+    async fn with_key_leds<R>(
+        &mut self,
+        f: impl AsyncFnOnce(Result<&mut dyn KeyLedControl, &'static str>) -> R,
+    ) -> R {
+        match self.board.key_leds {
+            Some(KeyLedPins::Gpio(pins)) => {
+                let mut leds = KeySet::ZERO;
+                leds[..pins.len()].fill(true);
+                f(Ok(&mut GpioLeds(leds))).await
+            }
+            // Nothing answers on the simulated bus.
+            Some(KeyLedPins::I2c { .. }) | None => f(Err(NO_LED_DRIVER)).await,
+        }
+    }
+
+    fn scan_i2c(&mut self) -> Result<(TextBuf<24>, Result<Scan, ScanError>), &'static str> {
+        Ok((self.i2c_bus()?, i2c_scan::scan(&mut EmptyBus)))
+    }
+
+    fn show_oled(
+        &mut self,
+        buffer: &OledBuffer,
+        inverted: bool,
+    ) -> Result<(TextBuf<24>, Result<u8, OledError>), &'static str> {
+        Ok((
+            self.i2c_bus()?,
+            oled::find_and_show(EmptyBus, buffer, inverted),
+        ))
+    }
+}
+
+/// LEDs on the tester's GPIOs, which nobody sees in the simulator.
+struct GpioLeds(KeySet);
+
+impl KeyLedControl for GpioLeds {
+    fn driver(&self) -> Option<(LedDriverChip, u8)> {
+        None
+    }
+
+    fn leds(&self) -> KeySet {
+        self.0
+    }
+
+    fn set(&mut self, _key: Key, _brightness: u8) {}
+}
+
+/// An I2C bus without devices, where no address is acknowledged.
+struct EmptyBus;
+
+impl i2c::ErrorType for EmptyBus {
+    type Error = ErrorKind;
+}
+
+impl I2c for EmptyBus {
+    fn transaction(
+        &mut self,
+        _address: u8,
+        _operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    }
+}
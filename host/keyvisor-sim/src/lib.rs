@@ -0,0 +1,193 @@
+//! Host-side rendering of the tester UI.
+//!
+//! The firmware's `ui` module draws to any [`DrawTarget`], so the simulator draws to an in-memory
+//! [`Canvas`] of the same size as the tester's display. [`run`] drives the firmware's UI state
+//! machine with a script of key events on it, recording a frame after each step.
+
+use std::{fmt, io};
+
+use embassy_time::Duration;
+use embedded_graphics::{
+    pixelcolor::{Rgb565, raw::RawU16},
+    prelude::*,
+};
+use keyvisor::{
+    display,
+    kbd::{Key, KeyEvent, N_COLS, N_ROWS},
+};
+use keyvisor_screenshot::Screenshot;
+
+mod device;
+
+pub use self::device::{SimError, run};
+
+/// In-memory framebuffer with the dimensions of the tester's display.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Canvas {
+    pixels: Vec<Rgb565>,
+}
+
+impl Canvas {
+    pub const SIZE: Size = Size::new(display::WIDTH as u32, display::HEIGHT as u32);
+
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![Rgb565::BLACK; (Self::SIZE.width * Self::SIZE.height) as usize],
+        }
+    }
+
+    /// Converts the canvas to the same representation as a screenshot taken from the device.
+    pub fn to_screenshot(&self) -> Screenshot {
+        Screenshot {
+            width: display::WIDTH,
+            height: display::HEIGHT,
+            pixels: self
+                .pixels
+                .iter()
+                .flat_map(|&px| RawU16::from(px).into_inner().to_be_bytes())
+                .collect(),
+        }
+    }
+
+    pub fn write_png(&self, writer: impl io::Write) -> io::Result<()> {
+        self.to_screenshot().write_png(writer)
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Self::SIZE
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Rgb565;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+
+        for Pixel(point, color) in pixels {
+            if bounds.contains(point) {
+                let index = point.y as u32 * Self::SIZE.width + point.x as u32;
+                self.pixels[index as usize] = color;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Looks up a key by the label shown on its button.
+pub fn key_by_label(label: char) -> Option<Key> {
+    (0..N_ROWS)
+        .flat_map(|row| (0..N_COLS).map(move |col| (row, col)))
+        .map(|(row, col)| Key {
+            col: col as u8,
+            row: row as u8,
+        })
+        .find(|key| key.char() == label)
+}
+
+/// A step of a script, see [`parse_script`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    Key(KeyEvent),
+    /// Lets time pass without touching a key, e.g. for the backlight to dim or a session to time
+    /// out.
+    Wait(Duration),
+}
+
+/// Parses a script of key events.
+///
+/// Each line holds one command: `down <key>`, `up <key>` or `tap <key>`, the latter being a
+/// shorthand for pressing and releasing the key, or `wait <ms>`. Keys are referred to by their
+/// labels. Empty lines and lines starting with `#` are ignored.
+pub fn parse_script(script: &str) -> Result<Vec<Step>, ScriptError> {
+    let mut steps = Vec::new();
+
+    for (index, line) in script.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |kind| ScriptError {
+            line: line_no,
+            kind,
+        };
+
+        let (command, argument) = line
+            .split_once(char::is_whitespace)
+            .ok_or(error(ScriptErrorKind::MissingArgument))?;
+        let argument = argument.trim();
+
+        if command == "wait" {
+            let ms = argument
+                .parse()
+                .map_err(|_| error(ScriptErrorKind::InvalidDuration(argument.to_owned())))?;
+            steps.push(Step::Wait(Duration::from_millis(ms)));
+            continue;
+        }
+
+        let mut label_chars = argument.chars();
+        let key = match (label_chars.next(), label_chars.next()) {
+            (Some(c), None) => key_by_label(c),
+            _ => None,
+        }
+        .ok_or_else(|| error(ScriptErrorKind::UnknownKey(argument.to_owned())))?;
+
+        match command {
+            "down" => steps.push(Step::Key(KeyEvent::KeyDown(key))),
+            "up" => steps.push(Step::Key(KeyEvent::KeyUp(key))),
+            "tap" => steps.extend([KeyEvent::KeyDown(key), KeyEvent::KeyUp(key)].map(Step::Key)),
+            _ => return Err(error(ScriptErrorKind::UnknownCommand(command.to_owned()))),
+        }
+    }
+
+    Ok(steps)
+}
+
+#[derive(Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub kind: ScriptErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ScriptErrorKind {
+    MissingArgument,
+    UnknownKey(String),
+    InvalidDuration(String),
+    UnknownCommand(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            ScriptErrorKind::MissingArgument => {
+                write!(f, "expected a key or duration after the command")
+            }
+            ScriptErrorKind::UnknownKey(key) => write!(f, "unknown key `{key}`"),
+            ScriptErrorKind::InvalidDuration(ms) => {
+                write!(f, "invalid duration `{ms}`, expected milliseconds")
+            }
+            ScriptErrorKind::UnknownCommand(command) => write!(f, "unknown command `{command}`"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
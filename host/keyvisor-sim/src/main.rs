@@ -0,0 +1,59 @@
+use std::{error::Error, fs, fs::File, io, io::Read as _, path::PathBuf};
+
+use clap::Parser;
+use keyvisor::board::{self, BoardProfile};
+use keyvisor_sim::{Canvas, parse_script};
+
+/// Runs the tester UI on the host, writing a PNG frame of the screen before the first and after
+/// every scripted step.
+#[derive(Parser)]
+struct Args {
+    /// Script with one `down <key>`, `up <key>`, `tap <key>` or `wait <ms>` command per line.
+    /// Read from standard input if omitted.
+    script: Option<PathBuf>,
+
+    /// Directory to write the frames to.
+    #[arg(short, long, default_value = "frames")]
+    out: PathBuf,
+
+    /// ID of the board profile to simulate. Defaults to the one the firmware falls back to.
+    #[arg(short, long, value_parser = parse_board)]
+    board: Option<&'static BoardProfile>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let script = match &args.script {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut script = String::new();
+            io::stdin().read_to_string(&mut script)?;
+            script
+        }
+    };
+
+    let steps = parse_script(&script)?;
+    let frames = keyvisor_sim::run(args.board.unwrap_or(board::DEFAULT), &steps)?;
+
+    fs::create_dir_all(&args.out)?;
+    for (index, frame) in frames.iter().enumerate() {
+        write_frame(frame, &args.out, index)?;
+    }
+
+    Ok(())
+}
+
+fn parse_board(arg: &str) -> Result<&'static BoardProfile, String> {
+    arg.parse()
+        .ok()
+        .and_then(BoardProfile::by_id)
+        .ok_or_else(|| format!("no board profile with ID `{arg}`"))
+}
+
+fn write_frame(canvas: &Canvas, dir: &std::path::Path, index: usize) -> io::Result<()> {
+    let path = dir.join(format!("frame-{index:03}.png"));
+    canvas.write_png(File::create(&path)?)?;
+    println!("{}", path.display());
+    Ok(())
+}
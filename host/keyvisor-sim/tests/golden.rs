@@ -0,0 +1,565 @@
+//! Compares rendered UI screens to reference images in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to (re)generate the reference images after an intentional change.
+//! On a mismatch, the actual rendering and an image highlighting the differing pixels are written
+//! to the `golden` directory in cargo's temporary directory for integration tests.
+
+use std::{
+    env,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use embassy_time::Instant;
+use embedded_graphics::Drawable as _;
+use keyvisor::{
+    adc::{FULL_SCALE, InputStats, Thresholds},
+    bounce::Capture,
+    encoder::Direction,
+    history::TestResult,
+    i2c_scan::{Device, ScanError},
+    kbd::{
+        ColumnState, Key, KeyEvent, KeySet, N_KEYS, backend::ExpanderChip, latency::LatencyMeter,
+    },
+    key_leds::LedDriverChip,
+    oled::{OledBuffer, OledError, OledSize, TestPattern},
+    rgb::{MAX_LEDS, Pattern},
+    ui,
+};
+use keyvisor_screenshot::Screenshot;
+use keyvisor_sim::{Canvas, key_by_label};
+
+fn keypad_with(events: impl IntoIterator<Item = KeyEvent>) -> Canvas {
+    let mut canvas = Canvas::new();
+    ui::draw_keypad(&mut canvas).unwrap();
+    for event in events {
+        ui::draw_key_event(event, &mut canvas).unwrap();
+    }
+    canvas
+}
+
+#[test]
+fn keypad_released() {
+    assert_golden("keypad_released", &keypad_with([]));
+}
+
+#[test]
+fn keypad_all_pressed() {
+    assert_golden(
+        "keypad_all_pressed",
+        &keypad_with(Key::all().map(KeyEvent::KeyDown)),
+    );
+}
+
+#[test]
+fn keypad_some_pressed() {
+    let key = |label| key_by_label(label).unwrap();
+
+    assert_golden(
+        "keypad_some_pressed",
+        &keypad_with([
+            KeyEvent::KeyDown(key('1')),
+            KeyEvent::KeyDown(key('5')),
+            KeyEvent::KeyDown(key('#')),
+            KeyEvent::KeyDown(key('0')),
+            KeyEvent::KeyUp(key('0')),
+        ]),
+    );
+}
+
+#[test]
+fn error_screen() {
+    let mut canvas = Canvas::new();
+    ui::ErrorScreen {
+        code: 302,
+        kind: "SPI transfer failed",
+        subsystem: "display",
+        context: "sending a frame",
+        hint: "Check the display ribbon and the\nSPI wiring, then reset the tester.",
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("error_screen", &canvas);
+}
+
+#[test]
+fn keypad_session() {
+    let key = |label| key_by_label(label).unwrap();
+
+    let mut canvas = keypad_with([KeyEvent::KeyDown(key('8'))]);
+    for label in ['1', '2', '3', '5'] {
+        ui::draw_key_passed(key(label), &mut canvas).unwrap();
+    }
+
+    assert_golden("keypad_session", &canvas);
+}
+
+#[test]
+fn board_id_screen() {
+    let mut canvas = Canvas::new();
+    ui::BoardIdScreen { input: "10427" }
+        .draw(&mut canvas)
+        .unwrap();
+
+    assert_golden("board_id_screen", &canvas);
+}
+
+fn test_result(failed: &[char], chatter: &[(char, u8)]) -> TestResult {
+    let mut result = TestResult {
+        board_id: 10427,
+        passed: KeySet::ZERO,
+        chatter: [0; N_KEYS],
+        uptime_ms: 754_000,
+        firmware: [0, 1, 0],
+    };
+    for key in Key::all() {
+        result
+            .passed
+            .set(key.index(), !failed.contains(&key.char()));
+    }
+    for &(label, count) in chatter {
+        result.chatter[key_by_label(label).unwrap().index()] = count;
+    }
+    result
+}
+
+#[test]
+fn result_screen_passed() {
+    let mut canvas = Canvas::new();
+    ui::ResultScreen {
+        title: "Result",
+        result: &test_result(&[], &[]),
+        footer: "Press any key to continue",
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("result_screen_passed", &canvas);
+}
+
+#[test]
+fn result_screen_failed() {
+    let mut canvas = Canvas::new();
+    ui::ResultScreen {
+        title: "History 3/17",
+        result: &test_result(&['4', '#'], &[('0', 2), ('7', 1)]),
+        footer: "4 older   6 newer   # back",
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("result_screen_failed", &canvas);
+}
+
+#[test]
+fn empty_history() {
+    let mut canvas = Canvas::new();
+    ui::NoticeScreen {
+        title: "History",
+        text: "No results yet.",
+        footer: "Press any key to go back",
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("empty_history", &canvas);
+}
+
+#[test]
+fn tools_menu() {
+    let mut canvas = Canvas::new();
+    ui::MenuScreen {
+        title: "Tools",
+        items: &ui::Tool::ALL.map(ui::Tool::name),
+        footer: "# back",
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("tools_menu", &canvas);
+}
+
+/// Captures a contact that changes at 0 and at `edges`, sampled every 5 µs.
+fn capture(press: bool, edges: &[u32]) -> Capture {
+    let mut capture = Capture::new(key_by_label('5').unwrap(), !press);
+    let mut t = 5;
+    while capture.sample(
+        t,
+        press == (edges.iter().filter(|&&edge| edge <= t).count() % 2 == 0),
+    ) {
+        t += 5;
+    }
+    capture
+}
+
+#[test]
+fn bounce_screen() {
+    let press = capture(true, &[150, 210, 480, 620]);
+    let mut canvas = Canvas::new();
+    ui::BounceScreen {
+        key: key_by_label('5').unwrap(),
+        press: Some(&press),
+        release: None,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("bounce_screen", &canvas);
+}
+
+#[test]
+fn latency_screen() {
+    let mut meter = LatencyMeter::new();
+    meter.start();
+
+    let mut t = 0;
+    for (label, press_us, release_us) in [
+        ('1', 5_100, 4_900),
+        ('5', 5_050, 7_400),
+        ('5', 4_980, 5_020),
+        ('9', 12_600, 5_000),
+        ('#', 5_000, 71_000),
+    ] {
+        let key = key_by_label(label).unwrap();
+        let col = usize::from(key.col);
+        let mut pressed = ColumnState::ZERO;
+        pressed.set(usize::from(key.row), true);
+
+        for (mask, latency_us, event) in [
+            (pressed, press_us, KeyEvent::KeyDown(key)),
+            (ColumnState::ZERO, release_us, KeyEvent::KeyUp(key)),
+        ] {
+            meter.read(col, mask, 2, Instant::from_micros(t));
+            meter.published(event, Instant::from_micros(t + latency_us));
+            t += 200_000;
+        }
+    }
+
+    let mut canvas = Canvas::new();
+    ui::LatencyScreen {
+        overall: meter.overall(),
+        key_avg_us: std::array::from_fn(|i| meter.key(Key::from_index(i)).avg_us()),
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("latency_screen", &canvas);
+}
+
+#[test]
+fn encoder_screen() {
+    let mut canvas = Canvas::new();
+    ui::EncoderScreen {
+        position: -3,
+        last_direction: Some(Direction::CounterClockwise),
+        missed: 1,
+        bounces: 4,
+        switch_pressed: Some(true),
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("encoder_screen", &canvas);
+}
+
+#[test]
+fn travel_screen() {
+    let mut travel = [Some(0); N_KEYS];
+    travel[1] = Some(300);
+    travel[4] = Some(1000);
+    travel[6] = Some(620);
+    travel[11] = None;
+    let mut pressed = KeySet::ZERO;
+    pressed.set(4, true);
+    pressed.set(6, true);
+
+    let mut canvas = Canvas::new();
+    ui::TravelScreen {
+        travel,
+        pressed,
+        actuation: 500,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("travel_screen", &canvas);
+}
+
+#[test]
+fn analog_screen() {
+    let push = |stats: &mut InputStats, readings: &[u16], count| {
+        for &reading in readings.iter().cycle().take(count) {
+            stats.push(reading);
+        }
+    };
+
+    // A thumbstick axis moved end to end that springs back close to its centre.
+    let mut passing = InputStats::new();
+    push(&mut passing, &[2040, 2044], 16);
+    push(&mut passing, &[90, 4010], 8);
+    push(&mut passing, &[2060, 2064], 16);
+
+    // A noisy slider that was only moved part of the way.
+    let mut noisy = InputStats::new();
+    push(&mut noisy, &[800, 830], 16);
+    push(&mut noisy, &[1800, 2600, 3100], 3);
+
+    // A thumbstick that doesn't come back to its centre.
+    let mut drifting = InputStats::new();
+    push(&mut drifting, &[1900], 16);
+    push(&mut drifting, &[0, FULL_SCALE], 8);
+    push(&mut drifting, &[2200], 16);
+
+    let mut canvas = Canvas::new();
+    ui::AnalogScreen {
+        inputs: &[
+            (2, passing),
+            (3, noisy),
+            (5, drifting),
+            (6, InputStats::new()),
+        ],
+        thresholds: &Thresholds::default(),
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("analog_screen", &canvas);
+}
+
+#[test]
+fn rgb_chain_screen() {
+    let mut canvas = Canvas::new();
+    ui::RgbChainScreen {
+        pattern: Pattern::Rainbow,
+        len: MAX_LEDS,
+        frame: 0,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("rgb_chain_screen", &canvas);
+}
+
+#[test]
+fn rgb_chain_screen_stepping() {
+    let mut canvas = Canvas::new();
+    ui::RgbChainScreen {
+        pattern: Pattern::Step(11),
+        len: 40,
+        frame: 0,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("rgb_chain_screen_stepping", &canvas);
+}
+
+#[test]
+fn key_led_screen() {
+    let mut leds = KeySet::ZERO;
+    leds[..N_KEYS].fill(true);
+    let mut canvas = Canvas::new();
+    ui::KeyLedScreen {
+        source: "IS31FL3733 at 0x50",
+        lit: key_by_label('5').unwrap(),
+        held: false,
+        leds,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("key_led_screen", &canvas);
+}
+
+#[test]
+fn key_led_screen_without_led() {
+    let mut leds = KeySet::ZERO;
+    leds[..6].fill(true);
+    let mut canvas = Canvas::new();
+    ui::KeyLedScreen {
+        source: "6 LEDs on GPIOs",
+        lit: key_by_label('8').unwrap(),
+        held: true,
+        leds,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("key_led_screen_without_led", &canvas);
+}
+
+#[test]
+fn i2c_scan_screen() {
+    let devices = [
+        (0x20, Device::Expander(ExpanderChip::Mcp23017)),
+        (0x3c, Device::Oled),
+        (0x50, Device::EepromOrLedDriver),
+        (0x74, Device::LedDriver(LedDriverChip::Is31fl3731)),
+        (0x76, Device::Bme280),
+    ];
+    let mut canvas = Canvas::new();
+    ui::I2cScanScreen {
+        bus: "SDA 8, SCL 15",
+        result: Ok(&devices),
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("i2c_scan_screen", &canvas);
+}
+
+#[test]
+fn i2c_scan_screen_overflowing() {
+    let devices: Vec<_> = (0x20..0x28)
+        .map(|address| (address, Device::OtherExpander))
+        .collect();
+    let mut canvas = Canvas::new();
+    ui::I2cScanScreen {
+        bus: "SDA 16, SCL 17",
+        result: Ok(&devices),
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("i2c_scan_screen_overflowing", &canvas);
+}
+
+#[test]
+fn i2c_scan_screen_bus_error() {
+    let mut canvas = Canvas::new();
+    ui::I2cScanScreen {
+        bus: "SDA 16, SCL 17",
+        result: Err(ScanError::TooManyDevices),
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("i2c_scan_screen_bus_error", &canvas);
+}
+
+#[test]
+fn oled_screen() {
+    let mut buffer = OledBuffer::new(OledSize::W128H32);
+    TestPattern::Inverted.draw(&mut buffer);
+    let mut canvas = Canvas::new();
+    ui::OledScreen {
+        bus: "SDA 8, SCL 15",
+        result: Ok(0x3c),
+        pattern: TestPattern::Inverted,
+        buffer: &buffer,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("oled_screen", &canvas);
+}
+
+#[test]
+fn oled_screen_not_found() {
+    let mut buffer = OledBuffer::new(OledSize::W128H64);
+    TestPattern::Checkerboard.draw(&mut buffer);
+    let mut canvas = Canvas::new();
+    ui::OledScreen {
+        bus: "SDA 16, SCL 17",
+        result: Err(OledError::NotFound),
+        pattern: TestPattern::Checkerboard,
+        buffer: &buffer,
+    }
+    .draw(&mut canvas)
+    .unwrap();
+
+    assert_golden("oled_screen_not_found", &canvas);
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn assert_golden(name: &str, canvas: &Canvas) {
+    let golden_path = manifest_dir()
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    let actual = canvas.to_screenshot().to_rgb888();
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        canvas
+            .write_png(File::create(&golden_path).unwrap())
+            .unwrap();
+        return;
+    }
+
+    let expected = read_png(&golden_path);
+    assert_eq!(expected.len(), actual.len(), "{name}: image size differs");
+
+    let mismatches = rgb_pixels(&expected)
+        .iter()
+        .zip(rgb_pixels(&actual))
+        .filter(|(e, a)| e != a)
+        .count();
+
+    if mismatches > 0 {
+        let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        fs::create_dir_all(&out_dir).unwrap();
+
+        let actual_path = out_dir.join(format!("{name}.actual.png"));
+        let diff_path = out_dir.join(format!("{name}.diff.png"));
+
+        canvas
+            .write_png(File::create(&actual_path).unwrap())
+            .unwrap();
+        write_diff(&diff_path, &expected, &actual);
+
+        panic!(
+            "{name}: {mismatches} pixels differ from {}\nactual: {}\ndiff: {}",
+            golden_path.display(),
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+fn read_png(path: &Path) -> Vec<u8> {
+    let file = File::open(path).unwrap_or_else(|e| {
+        panic!(
+            "couldn't open {}: {e} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display()
+        )
+    });
+
+    let mut reader = png::Decoder::new(BufReader::new(file)).read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut buf).unwrap();
+
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    assert_eq!(info.bit_depth, png::BitDepth::Eight);
+
+    buf.truncate(info.buffer_size());
+    buf
+}
+
+fn rgb_pixels(buf: &[u8]) -> &[[u8; 3]] {
+    buf.as_chunks().0
+}
+
+/// Writes the expected image dimmed, with differing pixels in bright magenta.
+fn write_diff(path: &Path, expected: &[u8], actual: &[u8]) {
+    let mut diff = Canvas::new().to_screenshot();
+
+    for (i, (e, a)) in rgb_pixels(expected)
+        .iter()
+        .zip(rgb_pixels(actual))
+        .enumerate()
+    {
+        let [r, g, b] = if e == a {
+            [e[0] / 4, e[1] / 4, e[2] / 4]
+        } else {
+            [0xff, 0x00, 0xff]
+        };
+        let raw = (u16::from(r >> 3) << 11) | (u16::from(g >> 2) << 5) | u16::from(b >> 3);
+        diff.pixels[i * 2..i * 2 + 2].copy_from_slice(&raw.to_be_bytes());
+    }
+
+    Screenshot::write_png(&diff, File::create(path).unwrap()).unwrap();
+}
@@ -0,0 +1,117 @@
+//! Runs the UI state machine through scripts and compares the recorded frames to the screens the
+//! UI should be showing.
+
+use embedded_graphics::{Drawable, pixelcolor::Rgb565};
+use keyvisor::{
+    board,
+    history::{FIRMWARE_VERSION, TestResult},
+    kbd::{KeySet, N_KEYS},
+    ui,
+};
+use keyvisor_sim::{Canvas, parse_script, run};
+
+fn run_script(script: &str) -> Vec<Canvas> {
+    let steps = parse_script(script).unwrap();
+    let frames = run(board::DEFAULT, &steps).unwrap();
+    assert_eq!(frames.len(), steps.len() + 1, "one frame per step");
+    frames
+}
+
+fn render(screen: impl Drawable<Color = Rgb565>) -> Canvas {
+    let mut canvas = Canvas::new();
+    screen.draw(&mut canvas).unwrap();
+    canvas
+}
+
+/// Result of board 12 finishing right after boot, on the simulator's clock.
+fn result(passed: bool) -> TestResult {
+    let mut result = TestResult {
+        board_id: 12,
+        passed: KeySet::ZERO,
+        chatter: [0; N_KEYS],
+        uptime_ms: 0,
+        firmware: FIRMWARE_VERSION,
+    };
+    result.passed[..N_KEYS].fill(passed);
+    result
+}
+
+/// Starts a session for board 12.
+const START: &str = "tap 1\ntap 2\ntap #\n";
+
+/// Presses and releases every key once.
+const ALL_KEYS: &str = "tap 1\ntap 2\ntap 3\ntap 4\ntap 5\ntap 6\ntap 7\ntap 8\ntap 9\n\
+                        tap *\ntap 0\ntap #\n";
+
+#[test]
+fn starts_on_board_id_screen() {
+    let frames = run_script("");
+
+    assert!(frames[0] == render(ui::BoardIdScreen { input: "" }));
+}
+
+#[test]
+fn types_board_id() {
+    let frames = run_script("tap 1\ntap 0\ntap 4\ntap *\n");
+
+    assert!(frames[6] == render(ui::BoardIdScreen { input: "104" }));
+    assert!(frames[8] == render(ui::BoardIdScreen { input: "10" }));
+}
+
+#[test]
+fn opens_tools_without_board_id() {
+    let frames = run_script("tap #\n");
+
+    let menu = render(ui::MenuScreen {
+        title: "Tools",
+        items: &ui::Tool::ALL.map(ui::Tool::name),
+        footer: "# back",
+    });
+    assert!(frames[2] == menu);
+}
+
+#[test]
+fn shows_passed_session_in_history() {
+    let frames = run_script(&format!("{START}{ALL_KEYS}tap 1\ntap *\n"));
+
+    let result = result(true);
+    let n = frames.len();
+    assert!(
+        frames[n - 5]
+            == render(ui::ResultScreen {
+                title: "Result",
+                result: &result,
+                footer: "Press any key to continue",
+            })
+    );
+    assert!(frames[n - 3] == render(ui::BoardIdScreen { input: "" }));
+    assert!(
+        frames[n - 1]
+            == render(ui::ResultScreen {
+                title: "History 1/1",
+                result: &result,
+                footer: "4 older   6 newer   # back",
+            })
+    );
+}
+
+#[test]
+fn idle_session_times_out() {
+    let frames = run_script(&format!("{START}wait 29999\nwait 1\n"));
+
+    let n = frames.len();
+    assert!(frames[n - 2] != frames[n - 1]);
+    // Finished 30 s after the session started, which was right after boot.
+    let result = TestResult {
+        uptime_ms: 30_000,
+        ..result(false)
+    };
+    assert!(
+        frames[n - 1]
+            == render(ui::ResultScreen {
+                title: "Result",
+                result: &result,
+                footer: "Press any key to continue",
+            })
+    );
+}
@@ -0,0 +1,393 @@
+//! Event stream sent by the keyvisor tester to host tools.
+//!
+//! After the `stream on` console command, the tester sends a line of JSON for every key event, a
+//! summary of the key scanner once a second and the verdict of every test session. The stream
+//! shares the USB-serial-JTAG port with defmt log frames and console replies, so an event line
+//! may be preceded by other data: [`find_event`] locates the event in it. Lines without an event
+//! or that fail to decode, e.g. because a log frame got in between, are meant to be skipped.
+//!
+//! # Schema, version 1
+//!
+//! Every event is a flat JSON object starting with the version `v`, followed by its `type`. Fields
+//! may be added without a new version, so decoders ignore fields they don't know. Numbers are
+//! unsigned integers. Strings are printable ASCII without `"` and `\`, so they never contain
+//! escapes. `ms` is the time since the tester booted, in milliseconds.
+//!
+//! - `hello`: the `firmware` version, e.g. `"0.1.0"`, and the name of the `board` profile. Sent
+//!   when the stream is switched on.
+//! - `key`: `ms`, the `key` label, e.g. `"5"`, its matrix `row` and `col`, and its `state`,
+//!   `"down"` or `"up"`.
+//! - `scan`: `ms`, the number of matrix `scans` since the previous `scan` event, the raw level
+//!   `changes` seen and the debounced `events` reported in that time, and the `debounce` ticks.
+//! - `verdict`: `ms`, the `board` ID, the `result`, `"pass"` or `"fail"`, the labels of the
+//!   `failed` keys separated by spaces, and the total `chatter` count.
+//!
+//! ```text
+//! {"v":1,"type":"hello","firmware":"0.1.0","board":"PCB rev 2"}
+//! {"v":1,"type":"key","ms":81234,"key":"5","row":1,"col":1,"state":"down"}
+//! {"v":1,"type":"verdict","ms":95020,"board":1234,"result":"fail","failed":"4 #","chatter":2}
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+pub const VERSION: u64 = 1;
+
+/// Start of every event.
+pub const PREFIX: &str = "{\"v\":";
+
+/// Most fields an event can have, including unknown ones.
+const MAX_FIELDS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// Sent when the stream is switched on.
+    Hello { firmware: &'a str, board: &'a str },
+    Key {
+        ms: u64,
+        key: &'a str,
+        row: u8,
+        col: u8,
+        pressed: bool,
+    },
+    /// Key scanner activity since the previous `Scan` event.
+    Scan {
+        ms: u64,
+        scans: u32,
+        changes: u32,
+        events: u32,
+        debounce: u8,
+    },
+    /// Outcome of a test session.
+    Verdict {
+        ms: u64,
+        board: u32,
+        passed: bool,
+        failed: &'a str,
+        chatter: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Not a flat JSON object of strings, numbers and booleans.
+    Syntax,
+    UnsupportedVersion(u64),
+    UnknownType,
+    MissingField(&'static str),
+    InvalidField(&'static str),
+}
+
+/// Finds the event in a line received from the tester, skipping log frames or other data in
+/// front of it.
+pub fn find_event(line: &[u8]) -> Option<&str> {
+    let start = line
+        .windows(PREFIX.len())
+        .rposition(|window| window == PREFIX.as_bytes())?;
+
+    core::str::from_utf8(&line[start..]).ok().map(str::trim_end)
+}
+
+impl<'a> Event<'a> {
+    /// Writes the event as JSON, without a line break. Characters not allowed in strings are
+    /// replaced by `?`.
+    pub fn write(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        let mut object = ObjectWriter::start(w, self.type_name())?;
+
+        match *self {
+            Event::Hello { firmware, board } => {
+                object.str("firmware", firmware)?;
+                object.str("board", board)?;
+            }
+            Event::Key {
+                ms,
+                key,
+                row,
+                col,
+                pressed,
+            } => {
+                object.num("ms", ms)?;
+                object.str("key", key)?;
+                object.num("row", row)?;
+                object.num("col", col)?;
+                object.str("state", if pressed { "down" } else { "up" })?;
+            }
+            Event::Scan {
+                ms,
+                scans,
+                changes,
+                events,
+                debounce,
+            } => {
+                object.num("ms", ms)?;
+                object.num("scans", scans)?;
+                object.num("changes", changes)?;
+                object.num("events", events)?;
+                object.num("debounce", debounce)?;
+            }
+            Event::Verdict {
+                ms,
+                board,
+                passed,
+                failed,
+                chatter,
+            } => {
+                object.num("ms", ms)?;
+                object.num("board", board)?;
+                object.str("result", if passed { "pass" } else { "fail" })?;
+                object.str("failed", failed)?;
+                object.num("chatter", chatter)?;
+            }
+        }
+
+        object.finish()
+    }
+
+    /// Decodes an event written by [`write`](Self::write), possibly by a newer firmware.
+    pub fn parse(json: &'a str) -> Result<Self, DecodeError> {
+        let fields = Fields::parse(json)?;
+
+        let version = fields.num("v")?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        match fields.str("type")? {
+            "hello" => Ok(Event::Hello {
+                firmware: fields.str("firmware")?,
+                board: fields.str("board")?,
+            }),
+            "key" => Ok(Event::Key {
+                ms: fields.num("ms")?,
+                key: fields.str("key")?,
+                row: fields.num("row")?,
+                col: fields.num("col")?,
+                pressed: match fields.str("state")? {
+                    "down" => true,
+                    "up" => false,
+                    _ => return Err(DecodeError::InvalidField("state")),
+                },
+            }),
+            "scan" => Ok(Event::Scan {
+                ms: fields.num("ms")?,
+                scans: fields.num("scans")?,
+                changes: fields.num("changes")?,
+                events: fields.num("events")?,
+                debounce: fields.num("debounce")?,
+            }),
+            "verdict" => Ok(Event::Verdict {
+                ms: fields.num("ms")?,
+                board: fields.num("board")?,
+                passed: match fields.str("result")? {
+                    "pass" => true,
+                    "fail" => false,
+                    _ => return Err(DecodeError::InvalidField("result")),
+                },
+                failed: fields.str("failed")?,
+                chatter: fields.num("chatter")?,
+            }),
+            _ => Err(DecodeError::UnknownType),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Event::Hello { .. } => "hello",
+            Event::Key { .. } => "key",
+            Event::Scan { .. } => "scan",
+            Event::Verdict { .. } => "verdict",
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Syntax => f.write_str("not a flat JSON object"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported event version {version}")
+            }
+            DecodeError::UnknownType => f.write_str("unknown event type"),
+            DecodeError::MissingField(name) => write!(f, "missing field `{name}`"),
+            DecodeError::InvalidField(name) => write!(f, "invalid field `{name}`"),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+struct ObjectWriter<'w, W> {
+    w: &'w mut W,
+}
+
+impl<'w, W: fmt::Write> ObjectWriter<'w, W> {
+    fn start(w: &'w mut W, type_name: &str) -> Result<Self, fmt::Error> {
+        write!(w, "{PREFIX}{VERSION},\"type\":\"{type_name}\"")?;
+        Ok(Self { w })
+    }
+
+    fn num(&mut self, name: &str, value: impl fmt::Display) -> fmt::Result {
+        write!(self.w, ",\"{name}\":{value}")
+    }
+
+    fn str(&mut self, name: &str, value: &str) -> fmt::Result {
+        write!(self.w, ",\"{name}\":\"")?;
+        for c in value.chars() {
+            let plain = c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\';
+            self.w.write_char(if plain { c } else { '?' })?;
+        }
+        self.w.write_char('"')
+    }
+
+    fn finish(self) -> fmt::Result {
+        self.w.write_char('}')
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    Str(&'a str),
+    Num(u64),
+    /// A boolean, which no field of version 1 uses.
+    Other,
+}
+
+/// Fields of a flat JSON object.
+struct Fields<'a> {
+    entries: [(&'a str, Value<'a>); MAX_FIELDS],
+    len: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn parse(json: &'a str) -> Result<Self, DecodeError> {
+        let mut fields = Self {
+            entries: [("", Value::Other); MAX_FIELDS],
+            len: 0,
+        };
+        let mut parser = Parser { json, pos: 0 };
+
+        parser.expect(b'{')?;
+        if !parser.eat(b'}') {
+            loop {
+                let name = parser.string()?;
+                parser.expect(b':')?;
+                let value = parser.value()?;
+
+                *fields
+                    .entries
+                    .get_mut(fields.len)
+                    .ok_or(DecodeError::Syntax)? = (name, value);
+                fields.len += 1;
+
+                if !parser.eat(b',') {
+                    parser.expect(b'}')?;
+                    break;
+                }
+            }
+        }
+
+        parser.skip_whitespace();
+        if parser.pos != json.len() {
+            return Err(DecodeError::Syntax);
+        }
+
+        Ok(fields)
+    }
+
+    fn get(&self, name: &'static str) -> Result<Value<'a>, DecodeError> {
+        self.entries[..self.len]
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|&(_, value)| value)
+            .ok_or(DecodeError::MissingField(name))
+    }
+
+    fn str(&self, name: &'static str) -> Result<&'a str, DecodeError> {
+        match self.get(name)? {
+            Value::Str(value) => Ok(value),
+            _ => Err(DecodeError::InvalidField(name)),
+        }
+    }
+
+    fn num<T: TryFrom<u64>>(&self, name: &'static str) -> Result<T, DecodeError> {
+        match self.get(name)? {
+            Value::Num(value) => T::try_from(value).map_err(|_| DecodeError::InvalidField(name)),
+            _ => Err(DecodeError::InvalidField(name)),
+        }
+    }
+}
+
+struct Parser<'a> {
+    json: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), DecodeError> {
+        self.eat(byte).then_some(()).ok_or(DecodeError::Syntax)
+    }
+
+    fn eat_literal(&mut self, literal: &str) -> bool {
+        let found = self.json[self.pos..].starts_with(literal);
+        if found {
+            self.pos += literal.len();
+        }
+        found
+    }
+
+    /// A string without escapes.
+    fn string(&mut self) -> Result<&'a str, DecodeError> {
+        self.expect(b'"')?;
+
+        let rest = &self.json[self.pos..];
+        let len = rest.find('"').ok_or(DecodeError::Syntax)?;
+        let value = &rest[..len];
+        if value.contains('\\') {
+            return Err(DecodeError::Syntax);
+        }
+
+        self.pos += len + 1;
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value<'a>, DecodeError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'0'..=b'9') => {
+                let mut value: u64 = 0;
+                while let Some(digit @ b'0'..=b'9') = self.peek() {
+                    value = value
+                        .checked_mul(10)
+                        .and_then(|value| value.checked_add(u64::from(digit - b'0')))
+                        .ok_or(DecodeError::Syntax)?;
+                    self.pos += 1;
+                }
+                Ok(Value::Num(value))
+            }
+            _ if self.eat_literal("true") || self.eat_literal("false") => Ok(Value::Other),
+            _ => Err(DecodeError::Syntax),
+        }
+    }
+}
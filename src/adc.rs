@@ -0,0 +1,210 @@
+//! Analog inputs like thumbsticks, slide potentiometers and other potentiometers, read by ADC1.
+//!
+//! Each input is sampled regularly into [`InputStats`], which tracks the range it was moved
+//! through. Whenever the input rests, it also measures its noise and, once it was moved and came
+//! back, how far it rests from where it started, e.g. a thumbstick that doesn't spring back to its
+//! centre.
+
+use defmt::Format;
+
+#[cfg(target_os = "none")]
+mod inputs;
+
+#[cfg(target_os = "none")]
+pub use self::inputs::{init, read};
+
+/// Most inputs tested at once, as many as fit on the screen.
+pub const MAX_INPUTS: usize = 4;
+
+/// Largest reading, at the top of the input range.
+pub const FULL_SCALE: u16 = 4095;
+
+/// Number of the latest samples an input has to be steady for to count as resting.
+pub const WINDOW: usize = 16;
+
+/// Largest difference between the samples of a resting input.
+pub const SETTLED_SPAN: u16 = 40;
+
+/// Distance from the starting position after which an input counts as moved, and within which it
+/// counts as back at it.
+pub const MOVED_DISTANCE: u16 = 400;
+
+/// Limits an input has to stay within to pass.
+#[derive(Clone, Debug, Format, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Smallest difference between the lowest and highest reading, as when moving a
+    /// potentiometer from end to end.
+    pub min_range: u16,
+    /// Largest standard deviation while resting, in tenths of a count.
+    pub max_noise_tenths: u16,
+    /// Largest distance between the starting position and where the input rests after moving.
+    pub max_drift: u16,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            min_range: FULL_SCALE / 5 * 4,
+            max_noise_tenths: 80,
+            max_drift: FULL_SCALE / 50,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum Check {
+    /// Not measured yet, e.g. because the input wasn't moved far enough.
+    Pending,
+    Passed,
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub struct Verdict {
+    pub range: Check,
+    pub noise: Check,
+    pub drift: Check,
+}
+
+impl Verdict {
+    /// Failed if any check failed, passed once the range and noise passed. The drift is only
+    /// measured for inputs that return to where they started, unlike a potentiometer left at the
+    /// end of its travel, so it doesn't hold back a pass.
+    pub fn overall(&self) -> Check {
+        let checks = [self.range, self.noise, self.drift];
+        if checks.contains(&Check::Failed) {
+            Check::Failed
+        } else if [self.range, self.noise]
+            .iter()
+            .all(|&check| check == Check::Passed)
+        {
+            Check::Passed
+        } else {
+            Check::Pending
+        }
+    }
+}
+
+/// Statistics of the samples of an input.
+#[derive(Clone, Debug)]
+pub struct InputStats {
+    /// The latest samples, in a ring.
+    window: [u16; WINDOW],
+    samples: u32,
+    min: u16,
+    max: u16,
+    /// Mean of the first window the input rested for.
+    centre: Option<u16>,
+    moved: bool,
+    /// Standard deviation of the latest window the input rested for.
+    noise_tenths: Option<u16>,
+    /// Distance of the latest resting position near the centre from it, once the input was moved.
+    drift: Option<i16>,
+}
+
+impl Default for InputStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputStats {
+    pub const fn new() -> Self {
+        Self {
+            window: [0; WINDOW],
+            samples: 0,
+            min: u16::MAX,
+            max: 0,
+            centre: None,
+            moved: false,
+            noise_tenths: None,
+            drift: None,
+        }
+    }
+
+    pub fn push(&mut self, reading: u16) {
+        self.window[self.samples as usize % WINDOW] = reading;
+        self.samples = self.samples.saturating_add(1);
+        self.min = self.min.min(reading);
+        self.max = self.max.max(reading);
+
+        if let Some(centre) = self.centre
+            && reading.abs_diff(centre) >= MOVED_DISTANCE
+        {
+            self.moved = true;
+        }
+
+        if self.samples < WINDOW as u32 {
+            return;
+        }
+        let lowest = self.window.iter().copied().min().unwrap_or(0);
+        let highest = self.window.iter().copied().max().unwrap_or(0);
+        if highest - lowest > SETTLED_SPAN {
+            return;
+        }
+
+        let n = WINDOW as u64;
+        let sum: u64 = self.window.iter().map(|&x| u64::from(x)).sum();
+        let sum_of_squares: u64 = self.window.iter().map(|&x| u64::from(x).pow(2)).sum();
+        let mean = (sum / n) as u16;
+        // The variance times n², scaled to give the deviation in tenths.
+        let scaled_variance = 100 * (n * sum_of_squares - sum * sum);
+        self.noise_tenths = Some((scaled_variance.isqrt() / n) as u16);
+
+        match self.centre {
+            None => self.centre = Some(mean),
+            Some(centre) if self.moved && mean.abs_diff(centre) < MOVED_DISTANCE => {
+                self.drift = Some(mean as i16 - centre as i16);
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// The latest sample, if any.
+    pub fn latest(&self) -> Option<u16> {
+        let latest = (self.samples as usize + WINDOW - 1) % WINDOW;
+        (self.samples > 0).then_some(self.window[latest])
+    }
+
+    /// Lowest and highest sample, if any.
+    pub fn min_max(&self) -> Option<(u16, u16)> {
+        (self.samples > 0).then_some((self.min, self.max))
+    }
+
+    pub fn range(&self) -> u16 {
+        self.max.saturating_sub(self.min)
+    }
+
+    pub fn centre(&self) -> Option<u16> {
+        self.centre
+    }
+
+    pub fn noise_tenths(&self) -> Option<u16> {
+        self.noise_tenths
+    }
+
+    pub fn drift(&self) -> Option<i16> {
+        self.drift
+    }
+
+    pub fn verdict(&self, thresholds: &Thresholds) -> Verdict {
+        let within = |value: Option<u16>, max| match value {
+            None => Check::Pending,
+            Some(value) if value <= max => Check::Passed,
+            Some(_) => Check::Failed,
+        };
+
+        Verdict {
+            range: if self.range() >= thresholds.min_range {
+                Check::Passed
+            } else {
+                Check::Pending
+            },
+            noise: within(self.noise_tenths, thresholds.max_noise_tenths),
+            drift: within(
+                self.drift.map(|drift| drift.unsigned_abs()),
+                thresholds.max_drift,
+            ),
+        }
+    }
+}
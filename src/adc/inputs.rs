@@ -0,0 +1,120 @@
+//! ADC1, reading pins chosen at runtime.
+//!
+//! The ADC driver only takes the typed GPIOs of its channels, so the pins of a board profile
+//! are turned back into these and kept as one of the variants of [`Channel`]. The keypad scanner
+//! and the UI share the ADC through [`read`].
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use esp_hal::{
+    Blocking,
+    analog::adc::{Adc, AdcChannel, AdcConfig, AdcPin, Attenuation},
+    gpio::{AnyPin, Pin as _},
+    peripherals::{ADC1, GPIO0, GPIO1, GPIO2, GPIO3, GPIO4, GPIO5, GPIO6},
+};
+
+use crate::board::ADC_PINS;
+
+/// Attenuation for inputs up to about 3.3 V.
+const ATTENUATION: Attenuation = Attenuation::_11dB;
+
+static INPUTS: Mutex<CriticalSectionRawMutex, Option<AnalogInputs<'static>>> = Mutex::new(None);
+
+/// Enables `pins` as analog inputs, to be read with [`read`].
+///
+/// # Panics
+///
+/// If one of the pins has no channel of ADC1, see [`ADC_PINS`], or the ADC was already set up.
+pub fn init(adc: ADC1<'static>, pins: impl IntoIterator<Item = AnyPin<'static>>) {
+    let mut inputs = INPUTS.try_lock().expect("ADC already set up");
+    assert!(inputs.is_none(), "ADC already set up");
+    *inputs = Some(AnalogInputs::new(adc, pins));
+}
+
+/// Converts the voltage at `gpio`, from 0 to [`FULL_SCALE`](super::FULL_SCALE). Returns `None`
+/// if the pin wasn't enabled.
+pub async fn read(gpio: u8) -> Option<u16> {
+    INPUTS.lock().await.as_mut()?.read(gpio)
+}
+
+macro_rules! channels {
+    ($($gpio:ident),*) => {
+        #[allow(clippy::upper_case_acronyms, reason = "named after the GPIOs")]
+        enum Channel<'d> {
+            $($gpio(AdcPin<$gpio<'d>, ADC1<'d>>),)*
+        }
+
+        impl<'d> Channel<'d> {
+            /// Enables `pin` as an input, or returns it if it has no channel of ADC1.
+            fn enable(
+                config: &mut AdcConfig<ADC1<'d>>,
+                pin: AnyPin<'d>,
+            ) -> Result<Self, AnyPin<'d>> {
+                $(
+                    let pin = match pin.downcast::<$gpio<'d>>() {
+                        Ok(pin) => return Ok(Channel::$gpio(config.enable_pin(pin, ATTENUATION))),
+                        Err(pin) => pin,
+                    };
+                )*
+                Err(pin)
+            }
+
+            fn read(&mut self, adc: &mut Adc<'d, ADC1<'d>, Blocking>) -> u16 {
+                match self {
+                    $(Channel::$gpio(pin) => read_blocking(adc, pin),)*
+                }
+            }
+        }
+    };
+}
+
+channels!(GPIO0, GPIO1, GPIO2, GPIO3, GPIO4, GPIO5, GPIO6);
+
+/// Waits for a conversion, which takes about 50 µs.
+fn read_blocking<'d, P: AdcChannel>(
+    adc: &mut Adc<'d, ADC1<'d>, Blocking>,
+    pin: &mut AdcPin<P, ADC1<'d>>,
+) -> u16 {
+    loop {
+        // The only error is that the conversion isn't done yet.
+        if let Ok(value) = adc.read_oneshot(pin) {
+            return value;
+        }
+    }
+}
+
+/// Pins read by ADC1.
+pub struct AnalogInputs<'d> {
+    adc: Adc<'d, ADC1<'d>, Blocking>,
+    /// Indexed by GPIO number.
+    channels: [Option<Channel<'d>>; ADC_PINS.end as usize],
+}
+
+impl<'d> AnalogInputs<'d> {
+    /// Enables `pins` as analog inputs.
+    ///
+    /// # Panics
+    ///
+    /// If one of the pins has no channel of ADC1, see [`ADC_PINS`].
+    pub fn new(adc: ADC1<'d>, pins: impl IntoIterator<Item = AnyPin<'d>>) -> Self {
+        let mut config = AdcConfig::new();
+        let mut channels = [const { None }; ADC_PINS.end as usize];
+        for pin in pins {
+            let gpio = usize::from(pin.number());
+            let Ok(channel) = Channel::enable(&mut config, pin) else {
+                panic!("GPIO{} has no ADC channel", gpio);
+            };
+            channels[gpio] = Some(channel);
+        }
+
+        Self {
+            adc: Adc::new(adc, config),
+            channels,
+        }
+    }
+
+    /// Converts the voltage at `gpio`. Returns `None` if the pin wasn't enabled.
+    pub fn read(&mut self, gpio: u8) -> Option<u16> {
+        let channel = self.channels.get_mut(usize::from(gpio))?.as_mut()?;
+        Some(channel.read(&mut self.adc))
+    }
+}
@@ -0,0 +1,521 @@
+//! Board profiles describing how the tester is wired.
+//!
+//! The profile is chosen at boot, in this order:
+//!
+//! 1. The profile stored in the [settings](crate::settings).
+//! 2. The board ID strap: PCBs from revision 2 on tie [`BOARD_ID_PIN`] to ground.
+//! 3. The cargo feature `board-pcb-rev1` or `board-breadboard`. If both are enabled, the
+//!    breadboard wins.
+//! 4. The latest PCB revision.
+
+use core::ops::Range;
+
+use crate::kbd::{Key, N_COLS, N_KEYS, N_ROWS, backend::ExpanderChip};
+
+#[cfg(target_os = "none")]
+mod pins;
+
+#[cfg(target_os = "none")]
+pub use self::pins::{BoardPins, ExtraPins, KeyLeds, Keypad, PinSet, read_strap};
+
+/// GPIO read at boot to tell boards apart. Must not be used by any profile.
+pub const BOARD_ID_PIN: u8 = 14;
+
+/// GPIOs with a channel of ADC1.
+pub const ADC_PINS: Range<u8> = 0..7;
+
+/// GPIOs that can be wired up. The module's flash takes GPIO24 to GPIO30.
+pub const GPIO_PINS: Range<u8> = 0..24;
+
+/// Tells whether `sda` and `scl` are different GPIOs that can be wired up.
+pub fn valid_i2c_pins(sda: u8, scl: u8) -> bool {
+    sda != scl && GPIO_PINS.contains(&sda) && GPIO_PINS.contains(&scl)
+}
+
+/// Most select lines of the multiplexers of an analog keypad, enough for 16 channels.
+pub const MAX_SELECT_LINES: usize = 4;
+
+/// GPIO numbers of everything connected to the tester.
+#[derive(Debug, defmt::Format)]
+pub struct BoardProfile {
+    /// Stable identifier, e.g. for storing the selection.
+    pub id: u8,
+    pub name: &'static str,
+    pub display: DisplayPins,
+    pub keypad: KeypadPins,
+    /// Pins wired to parts of the tester the firmware doesn't use, like the boot button, kept
+    /// off the pins that `set analog` and `set i2c` may choose.
+    pub reserved: &'static [u8],
+    /// Header for a rotary encoder of the board under test.
+    pub encoder: Option<EncoderPins>,
+    /// Data line of a chain of WS2812-style LEDs on the board under test, see [`crate::rgb`].
+    pub rgb_chain: Option<u8>,
+    /// Single-colour LEDs under the keys of the board under test.
+    pub key_leds: Option<KeyLedPins>,
+}
+
+#[derive(Debug, defmt::Format)]
+pub struct DisplayPins {
+    pub scl: u8,
+    pub sda: u8,
+    pub rst: u8,
+    pub dc: u8,
+    pub cs: u8,
+    pub backlight: u8,
+}
+
+/// How the keys are wired to the tester.
+#[derive(Debug, defmt::Format)]
+pub enum KeypadPins {
+    /// Columns driven low one at a time, with the rows read through pull-ups.
+    Matrix(MatrixPins),
+    /// A pin for each key, in the order of [`Key::index`], shorted to ground while pressed.
+    Direct([u8; N_KEYS]),
+    /// A duplex matrix, with two keys at each crossing whose diodes face opposite ways.
+    Duplex(DuplexPins),
+    /// A matrix driven through shift registers on the keypad's board, see
+    /// [`ShiftRegisters`](crate::kbd::backend::ShiftRegisters).
+    ShiftRegisters(ShiftRegisterPins),
+    /// A matrix driven through an I/O expander on the keypad's board, see
+    /// [`Expander`](crate::kbd::backend::Expander).
+    Expander(ExpanderPins),
+    /// Hall-effect sensors read by the ADC, see [`crate::kbd::analog`].
+    Analog(AnalogPins),
+}
+
+impl KeypadPins {
+    /// Tells whether the contacts are read straight from GPIOs, which the bounce analyser needs.
+    pub fn on_gpios(&self) -> bool {
+        matches!(
+            self,
+            KeypadPins::Matrix(_) | KeypadPins::Direct(_) | KeypadPins::Duplex(_)
+        )
+    }
+}
+
+#[derive(Debug, defmt::Format)]
+pub struct MatrixPins {
+    pub columns: [u8; N_COLS],
+    pub rows: [u8; N_ROWS],
+}
+
+/// Pins of a duplex matrix. Keys in even rows are read with their column pin driven low, keys in
+/// odd rows with their row pin driven low; see [`Key::duplex`].
+#[derive(Debug, defmt::Format)]
+pub struct DuplexPins {
+    pub columns: [u8; N_COLS],
+    pub rows: [u8; N_ROWS / 2],
+}
+
+/// SPI lines to the shift registers of a keypad, driven in software.
+#[derive(Debug, defmt::Format)]
+pub struct ShiftRegisterPins {
+    pub clock: u8,
+    /// To the serial input of the column driver.
+    pub data_out: u8,
+    /// From the serial output of the row reader.
+    pub data_in: u8,
+    pub latch: u8,
+}
+
+#[derive(Debug, defmt::Format)]
+pub struct ExpanderPins {
+    pub chip: ExpanderChip,
+    /// 7-bit I2C address.
+    pub address: u8,
+    pub sda: u8,
+    pub scl: u8,
+}
+
+/// ADC inputs of Hall-effect sensors, each wired directly or through an analog multiplexer
+/// like the 74HC4067. All multiplexers share the select lines.
+///
+/// Keys are assigned in the order of [`Key::index`], filling up the channels of the first input
+/// before the next one.
+#[derive(Debug, defmt::Format)]
+pub struct AnalogPins {
+    /// Pins from [`ADC_PINS`].
+    pub inputs: &'static [u8],
+    /// Select lines S0, S1, ... of the multiplexers, none if the sensors are on the inputs. At
+    /// most [`MAX_SELECT_LINES`].
+    pub select: &'static [u8],
+}
+
+impl AnalogPins {
+    /// Index into [`inputs`](Self::inputs) and multiplexer channel of the sensor of `key`.
+    pub fn sensor(&self, key: Key) -> (usize, u8) {
+        let channels = 1 << self.select.len();
+        (key.index() / channels, (key.index() % channels) as u8)
+    }
+
+    /// Tells whether each key has a sensor on an ADC pin.
+    pub const fn covers_keys(&self) -> bool {
+        let mut i = 0;
+        while i < self.inputs.len() {
+            if self.inputs[i] >= ADC_PINS.end {
+                return false;
+            }
+            i += 1;
+        }
+        self.select.len() <= MAX_SELECT_LINES && self.inputs.len() << self.select.len() >= N_KEYS
+    }
+}
+
+/// How the LEDs under the keys are wired to the tester, see [`crate::key_leds`].
+#[derive(Debug, defmt::Format)]
+pub enum KeyLedPins {
+    /// An LED on each pin, active high, for the keys in the order of [`Key::index`]. Keys beyond
+    /// the last pin have no LED.
+    Gpio(&'static [u8]),
+    /// An IS31FL3731 or IS31FL3733, told apart by its address. Takes the I2C peripheral, so the
+    /// keypad mustn't be on an expander.
+    I2c {
+        sda: u8,
+        scl: u8,
+        /// Address of the driver. Without it, the driver is looked for only at the addresses
+        /// where it can be told from an EEPROM or a pressure sensor without writing to it, which
+        /// excludes an IS31FL3733 at 0x50-0x57.
+        address: Option<u8>,
+    },
+}
+
+/// Quadrature outputs of a rotary encoder, active low.
+#[derive(Debug, defmt::Format)]
+pub struct EncoderPins {
+    pub a: u8,
+    pub b: u8,
+    /// Key of the matrix the encoder's push switch is wired to, if any.
+    pub switch: Option<Key>,
+}
+
+/// First PCB revision. Its matrix rows share GPIO12 and GPIO13 with the USB port.
+pub const PCB_REV1: BoardProfile = BoardProfile {
+    id: 1,
+    name: "PCB rev 1",
+    display: DisplayPins {
+        scl: 19,
+        sda: 20,
+        rst: 21,
+        dc: 22,
+        cs: 23,
+        backlight: 15,
+    },
+    keypad: KeypadPins::Matrix(MatrixPins {
+        columns: [11, 10, 1],
+        rows: [8, 12, 13, 0],
+    }),
+    reserved: &[],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+/// Second PCB revision, with the matrix moved off the USB pins. Its boot button on GPIO9 and
+/// status LED on GPIO18 are reserved.
+pub const PCB_REV2: BoardProfile = BoardProfile {
+    id: 2,
+    name: "PCB rev 2",
+    display: DisplayPins {
+        scl: 19,
+        sda: 20,
+        rst: 21,
+        dc: 22,
+        cs: 23,
+        backlight: 15,
+    },
+    keypad: KeypadPins::Matrix(MatrixPins {
+        columns: [11, 10, 1],
+        rows: [8, 2, 3, 0],
+    }),
+    reserved: &[9, 18],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+/// ESP32-C6-DevKitC-1 on a breadboard, with the display and keypad on the pins of one header, an
+/// encoder whose push switch takes the place of the `0` key, and headers for an RGB LED chain and
+/// an LED driver. The driver's pull-ups keep the strapping pins GPIO8 and GPIO15 high.
+pub const BREADBOARD: BoardProfile = BoardProfile {
+    id: 3,
+    name: "breadboard",
+    display: DisplayPins {
+        scl: 6,
+        sda: 7,
+        rst: 4,
+        dc: 5,
+        cs: 18,
+        backlight: 19,
+    },
+    keypad: KeypadPins::Matrix(MatrixPins {
+        columns: [0, 1, 2],
+        rows: [3, 10, 11, 20],
+    }),
+    reserved: &[9],
+    encoder: Some(EncoderPins {
+        a: 21,
+        b: 22,
+        switch: Some(Key { col: 1, row: 3 }),
+    }),
+    rgb_chain: Some(23),
+    key_leds: Some(KeyLedPins::I2c {
+        sda: 8,
+        scl: 15,
+        address: None,
+    }),
+};
+
+/// The breadboard with a keypad that has a wire for each key instead of a matrix. Keys on
+/// GPIO8 and GPIO15 mustn't be held during a reset, since these are strapping pins.
+pub const BREADBOARD_DIRECT: BoardProfile = BoardProfile {
+    id: 4,
+    name: "breadboard, direct",
+    display: BREADBOARD.display,
+    keypad: KeypadPins::Direct([0, 1, 2, 3, 10, 11, 20, 21, 22, 23, 15, 8]),
+    reserved: &[9],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+/// The breadboard with a duplex keypad, which needs only the first five of the matrix pins.
+pub const BREADBOARD_DUPLEX: BoardProfile = BoardProfile {
+    id: 5,
+    name: "breadboard, duplex",
+    display: BREADBOARD.display,
+    keypad: KeypadPins::Duplex(DuplexPins {
+        columns: [0, 1, 2],
+        rows: [3, 10],
+    }),
+    reserved: &[9],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+/// The breadboard with a keypad driven by a 74HC595 and read by a 74HC165, which leaves pins for
+/// the LEDs of the keys `1` to `6`.
+pub const BREADBOARD_SHIFT_REGISTERS: BoardProfile = BoardProfile {
+    id: 6,
+    name: "breadboard, 74HC595/165",
+    display: BREADBOARD.display,
+    keypad: KeypadPins::ShiftRegisters(ShiftRegisterPins {
+        clock: 0,
+        data_out: 1,
+        data_in: 2,
+        latch: 3,
+    }),
+    reserved: &[9],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: Some(KeyLedPins::Gpio(&[10, 11, 20, 21, 22, 23])),
+};
+
+/// The breadboard with a keypad behind an MCP23017 at its default address.
+pub const BREADBOARD_MCP23017: BoardProfile = BoardProfile {
+    id: 7,
+    name: "breadboard, MCP23017",
+    display: BREADBOARD.display,
+    keypad: KeypadPins::Expander(ExpanderPins {
+        chip: ExpanderChip::Mcp23017,
+        address: 0x20,
+        sda: 0,
+        scl: 1,
+    }),
+    reserved: &[9],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+/// The breadboard with a keypad behind a PCA9555 at its default address.
+pub const BREADBOARD_PCA9555: BoardProfile = BoardProfile {
+    id: 8,
+    name: "breadboard, PCA9555",
+    display: BREADBOARD.display,
+    keypad: KeypadPins::Expander(ExpanderPins {
+        chip: ExpanderChip::Pca9555,
+        address: 0x20,
+        sda: 0,
+        scl: 1,
+    }),
+    reserved: &[9],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+/// The breadboard with Hall-effect keys, whose sensors go through a 74HC4067 to GPIO0.
+pub const BREADBOARD_HALL_EFFECT: BoardProfile = BoardProfile {
+    id: 9,
+    name: "breadboard, Hall effect",
+    display: BREADBOARD.display,
+    keypad: KeypadPins::Analog(AnalogPins {
+        inputs: &[0],
+        select: &[1, 2, 3, 10],
+    }),
+    reserved: &[9],
+    encoder: None,
+    rgb_chain: None,
+    key_leds: None,
+};
+
+pub const PROFILES: [&BoardProfile; 9] = [
+    &PCB_REV1,
+    &PCB_REV2,
+    &BREADBOARD,
+    &BREADBOARD_DIRECT,
+    &BREADBOARD_DUPLEX,
+    &BREADBOARD_SHIFT_REGISTERS,
+    &BREADBOARD_MCP23017,
+    &BREADBOARD_PCA9555,
+    &BREADBOARD_HALL_EFFECT,
+];
+
+const _: () = {
+    let mut i = 0;
+    while i < PROFILES.len() {
+        assert!(
+            PROFILES[i].pins_are_unique(),
+            "board profile uses a pin twice"
+        );
+        if let KeypadPins::Analog(analog) = &PROFILES[i].keypad {
+            assert!(analog.covers_keys(), "analog keypad misses keys");
+        }
+        i += 1;
+    }
+};
+
+/// Profile used when the board doesn't identify itself.
+pub const DEFAULT: &BoardProfile = if cfg!(feature = "board-breadboard") {
+    &BREADBOARD
+} else if cfg!(feature = "board-pcb-rev1") {
+    &PCB_REV1
+} else {
+    &PCB_REV2
+};
+
+/// Level of [`BOARD_ID_PIN`].
+#[derive(Copy, Clone, Debug, defmt::Format, PartialEq, Eq)]
+pub enum Strap {
+    Floating,
+    Low,
+    High,
+}
+
+impl BoardProfile {
+    pub fn by_id(id: u8) -> Option<&'static BoardProfile> {
+        PROFILES.into_iter().find(|profile| profile.id == id)
+    }
+
+    /// Picks the profile for the given board ID strap.
+    pub fn detect(strap: Strap) -> &'static BoardProfile {
+        match strap {
+            Strap::Low => &PCB_REV2,
+            Strap::Floating | Strap::High => DEFAULT,
+        }
+    }
+
+    /// Checks that no pin is assigned twice and that the board ID pin is left alone.
+    pub const fn pins_are_unique(&self) -> bool {
+        self.used_pins().is_some()
+    }
+
+    /// Of the ADC pins set in `mask`, the ones the profile leaves free for testing analog
+    /// inputs.
+    pub fn free_adc_pins(&self, mask: u8) -> u8 {
+        let used = self.used_pins().unwrap_or(u64::MAX);
+        ADC_PINS
+            .filter(|&pin| mask & (1 << pin) != 0 && used & (1 << pin) == 0)
+            .fold(0, |free, pin| free | 1 << pin)
+    }
+
+    /// The SDA and SCL pins in `pins` to scan for I2C devices, if the profile leaves both free
+    /// and they aren't among the analog inputs in `analog_pins`.
+    pub fn free_i2c_pins(&self, pins: Option<(u8, u8)>, analog_pins: u8) -> Option<(u8, u8)> {
+        let used = self.used_pins().unwrap_or(u64::MAX) | u64::from(analog_pins);
+        pins.filter(|&(sda, scl)| valid_i2c_pins(sda, scl) && used & (1 << sda | 1 << scl) == 0)
+    }
+
+    /// Mask of the pins used by the profile and the board ID pin, or `None` if a pin is
+    /// assigned twice.
+    const fn used_pins(&self) -> Option<u64> {
+        let mut used = 1u64 << BOARD_ID_PIN;
+
+        let display = &self.display;
+        let fixed = [
+            display.scl,
+            display.sda,
+            display.rst,
+            display.dc,
+            display.cs,
+            display.backlight,
+        ];
+        let encoder_pins;
+        let encoder: &[u8] = match &self.encoder {
+            Some(encoder) => {
+                encoder_pins = [encoder.a, encoder.b];
+                &encoder_pins
+            }
+            None => &[],
+        };
+        let spi_pins;
+        let i2c_pins;
+        let keypad: [&[u8]; 2] = match &self.keypad {
+            KeypadPins::Matrix(matrix) => [&matrix.columns, &matrix.rows],
+            KeypadPins::Direct(pins) => [pins, &[]],
+            KeypadPins::Duplex(duplex) => [&duplex.columns, &duplex.rows],
+            KeypadPins::ShiftRegisters(spi) => {
+                spi_pins = [spi.clock, spi.data_out, spi.data_in, spi.latch];
+                [&spi_pins, &[]]
+            }
+            KeypadPins::Expander(i2c) => {
+                i2c_pins = [i2c.sda, i2c.scl];
+                [&i2c_pins, &[]]
+            }
+            KeypadPins::Analog(analog) => [analog.inputs, analog.select],
+        };
+        let rgb_chain_pin;
+        let rgb_chain: &[u8] = match self.rgb_chain {
+            Some(pin) => {
+                rgb_chain_pin = [pin];
+                &rgb_chain_pin
+            }
+            None => &[],
+        };
+        let key_led_i2c;
+        let key_leds: &[u8] = match &self.key_leds {
+            Some(KeyLedPins::Gpio(pins)) => pins,
+            Some(KeyLedPins::I2c { sda, scl, .. }) => {
+                key_led_i2c = [*sda, *scl];
+                &key_led_i2c
+            }
+            None => &[],
+        };
+        let groups: [&[u8]; 7] = [
+            &fixed,
+            keypad[0],
+            keypad[1],
+            self.reserved,
+            encoder,
+            rgb_chain,
+            key_leds,
+        ];
+
+        let mut g = 0;
+        while g < groups.len() {
+            let mut i = 0;
+            while i < groups[g].len() {
+                let pin = groups[g][i];
+                if pin >= 64 || used & (1 << pin) != 0 {
+                    return None;
+                }
+                used |= 1 << pin;
+                i += 1;
+            }
+            g += 1;
+        }
+
+        Some(used)
+    }
+}
@@ -0,0 +1,211 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_time::Timer;
+use esp_hal::gpio::{AnyPin, Input, InputConfig, Pull};
+
+use super::{AnalogPins, BoardProfile, KeyLedPins, KeypadPins, Strap};
+use crate::kbd::{N_COLS, N_KEYS, N_ROWS, backend::ExpanderChip};
+
+static PINS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Time for the board ID pin to follow a change of its pull resistor.
+const STRAP_SETTLE_MICROS: u64 = 50;
+
+/// The pins of a [`BoardProfile`], ready to be handed to the drivers.
+pub struct BoardPins {
+    pub scl: AnyPin<'static>,
+    pub sda: AnyPin<'static>,
+    pub rst: AnyPin<'static>,
+    pub dc: AnyPin<'static>,
+    pub cs: AnyPin<'static>,
+    pub backlight: AnyPin<'static>,
+    pub keypad: Keypad,
+    /// A and B lines of the encoder.
+    pub encoder: Option<(AnyPin<'static>, AnyPin<'static>)>,
+    /// Data line of the RGB LED chain.
+    pub rgb_chain: Option<AnyPin<'static>>,
+    pub key_leds: Option<KeyLeds>,
+    /// SDA and SCL of the I2C bus to scan, see [`BoardProfile::free_i2c_pins`].
+    pub i2c_scan: Option<(AnyPin<'static>, AnyPin<'static>)>,
+    /// Inputs of the ADC: the sensors of an analog keypad and the analog inputs to test.
+    pub adc: PinSet,
+}
+
+/// The pins of the keys, wired as described by [`KeypadPins`].
+pub enum Keypad {
+    Matrix {
+        columns: [AnyPin<'static>; N_COLS],
+        rows: [AnyPin<'static>; N_ROWS],
+    },
+    Direct([AnyPin<'static>; N_KEYS]),
+    Duplex {
+        columns: [AnyPin<'static>; N_COLS],
+        rows: [AnyPin<'static>; N_ROWS / 2],
+    },
+    ShiftRegisters {
+        clock: AnyPin<'static>,
+        data_out: AnyPin<'static>,
+        data_in: AnyPin<'static>,
+        latch: AnyPin<'static>,
+    },
+    Expander {
+        chip: ExpanderChip,
+        address: u8,
+        sda: AnyPin<'static>,
+        scl: AnyPin<'static>,
+    },
+    /// The inputs are among [`BoardPins::adc`].
+    Analog {
+        pins: &'static AnalogPins,
+        select: ExtraPins,
+    },
+}
+
+/// The pins of the LEDs under the keys, wired as described by [`KeyLedPins`].
+pub enum KeyLeds {
+    /// The LED of each key, indexed by [`Key::index`](crate::kbd::Key::index).
+    Gpio([Option<AnyPin<'static>>; N_KEYS]),
+    /// The bus of an LED driver, on the I2C peripheral, and its address if the profile names it.
+    I2c {
+        sda: AnyPin<'static>,
+        scl: AnyPin<'static>,
+        address: Option<u8>,
+    },
+}
+
+/// Pins whose number varies, like the select lines of an analog keypad's multiplexers.
+pub struct ExtraPins(&'static [u8]);
+
+impl Iterator for ExtraPins {
+    type Item = AnyPin<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&pin, rest) = self.0.split_first()?;
+        self.0 = rest;
+        // SAFETY: handed out once, see `BoardProfile::take_pins`.
+        Some(unsafe { AnyPin::steal(pin) })
+    }
+}
+
+/// Pins given by a mask, with bit n set for GPIOn.
+pub struct PinSet(u64);
+
+impl Iterator for PinSet {
+    type Item = AnyPin<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let pin = self.0.trailing_zeros() as u8;
+        self.0 &= !(1 << pin);
+        // SAFETY: handed out once, see `BoardProfile::take_pins`.
+        Some(unsafe { AnyPin::steal(pin) })
+    }
+}
+
+impl BoardProfile {
+    /// Claims the pins of the profile, along with the ADC pins in `analog_pins` and the I2C pins
+    /// in `i2c_pins` that the profile leaves free.
+    ///
+    /// # Safety
+    ///
+    /// None of the profile's pins may be used through [`esp_hal::peripherals::Peripherals`].
+    ///
+    /// # Panics
+    ///
+    /// If the pins of a profile were already taken, or the profile uses a pin twice.
+    pub unsafe fn take_pins(
+        &'static self,
+        analog_pins: u8,
+        i2c_pins: Option<(u8, u8)>,
+    ) -> BoardPins {
+        assert!(self.pins_are_unique(), "board profile uses a pin twice");
+        assert!(
+            !PINS_TAKEN.swap(true, Ordering::Relaxed),
+            "board pins already taken"
+        );
+
+        // SAFETY: each pin is unique within the profile, and the caller guarantees it isn't used
+        // elsewhere.
+        let steal = |pin| unsafe { AnyPin::steal(pin) };
+
+        let keypad_adc = match &self.keypad {
+            KeypadPins::Analog(analog) => analog.inputs.iter().fold(0, |mask, pin| mask | 1 << pin),
+            _ => 0,
+        };
+
+        BoardPins {
+            scl: steal(self.display.scl),
+            sda: steal(self.display.sda),
+            rst: steal(self.display.rst),
+            dc: steal(self.display.dc),
+            cs: steal(self.display.cs),
+            backlight: steal(self.display.backlight),
+            keypad: match &self.keypad {
+                KeypadPins::Matrix(matrix) => Keypad::Matrix {
+                    columns: matrix.columns.map(steal),
+                    rows: matrix.rows.map(steal),
+                },
+                KeypadPins::Direct(pins) => Keypad::Direct(pins.map(steal)),
+                KeypadPins::Duplex(duplex) => Keypad::Duplex {
+                    columns: duplex.columns.map(steal),
+                    rows: duplex.rows.map(steal),
+                },
+                KeypadPins::ShiftRegisters(spi) => Keypad::ShiftRegisters {
+                    clock: steal(spi.clock),
+                    data_out: steal(spi.data_out),
+                    data_in: steal(spi.data_in),
+                    latch: steal(spi.latch),
+                },
+                KeypadPins::Expander(i2c) => Keypad::Expander {
+                    chip: i2c.chip,
+                    address: i2c.address,
+                    sda: steal(i2c.sda),
+                    scl: steal(i2c.scl),
+                },
+                KeypadPins::Analog(analog) => Keypad::Analog {
+                    pins: analog,
+                    select: ExtraPins(analog.select),
+                },
+            },
+            encoder: self
+                .encoder
+                .as_ref()
+                .map(|encoder| (steal(encoder.a), steal(encoder.b))),
+            rgb_chain: self.rgb_chain.map(steal),
+            key_leds: self.key_leds.as_ref().map(|leds| match leds {
+                KeyLedPins::Gpio(pins) => {
+                    KeyLeds::Gpio(core::array::from_fn(|i| pins.get(i).copied().map(steal)))
+                }
+                KeyLedPins::I2c { sda, scl, address } => KeyLeds::I2c {
+                    sda: steal(*sda),
+                    scl: steal(*scl),
+                    address: *address,
+                },
+            }),
+            i2c_scan: self
+                .free_i2c_pins(i2c_pins, analog_pins)
+                .map(|(sda, scl)| (steal(sda), steal(scl))),
+            adc: PinSet(u64::from(self.free_adc_pins(analog_pins)) | keypad_adc),
+        }
+    }
+}
+
+/// Tells whether the board ID pin is tied low, tied high or left floating by reading it with
+/// the pull-up and then the pull-down enabled.
+pub async fn read_strap(pin: AnyPin<'static>) -> Strap {
+    let mut input = Input::new(pin, InputConfig::default().with_pull(Pull::Up));
+    Timer::after_micros(STRAP_SETTLE_MICROS).await;
+    let pulled_up = input.is_high();
+
+    input.apply_config(&InputConfig::default().with_pull(Pull::Down));
+    Timer::after_micros(STRAP_SETTLE_MICROS).await;
+    let pulled_down = input.is_high();
+
+    match (pulled_up, pulled_down) {
+        (false, false) => Strap::Low,
+        (true, true) => Strap::High,
+        _ => Strap::Floating,
+    }
+}
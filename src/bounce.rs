@@ -0,0 +1,114 @@
+//! Bounce analysis of a single key.
+//!
+//! While the analyser runs, the key scanner waits for an edge on the key's row and then samples
+//! the row as fast as it can, far faster than the regular scan rate, feeding each sample to a
+//! [`Capture`] until the contact settled. The capture keeps the times of the edges, from which
+//! the bounce time is measured and the waveform is plotted.
+
+use embassy_time::Duration;
+
+use crate::kbd::Key;
+
+/// Most edges kept per capture. Later ones still count towards the bounce time.
+pub const MAX_EDGES: usize = 48;
+
+/// The contact counts as settled once it didn't change for this long.
+pub const SETTLE_TIME: Duration = Duration::from_millis(5);
+
+/// Captures end after this long even if the contact keeps changing.
+pub const MAX_CAPTURE_TIME: Duration = Duration::from_millis(30);
+
+#[derive(Clone, Debug)]
+pub struct Capture {
+    pub key: Key,
+    /// Whether the contact was closed before the first edge.
+    pub closed_before: bool,
+    /// Times of the kept edges in µs, the first one being at 0.
+    edges: [u32; MAX_EDGES],
+    len: usize,
+    /// Edges that didn't fit into `edges`.
+    pub dropped_edges: u32,
+    last_edge_us: u32,
+    pub duration_us: u32,
+    pub samples: u32,
+}
+
+impl Capture {
+    /// Starts a capture at the first edge, which changed the contact from `closed_before`.
+    pub fn new(key: Key, closed_before: bool) -> Self {
+        Self {
+            key,
+            closed_before,
+            edges: [0; MAX_EDGES],
+            len: 1,
+            dropped_edges: 0,
+            last_edge_us: 0,
+            duration_us: 0,
+            samples: 0,
+        }
+    }
+
+    /// Adds a sample taken `t_us` after the first edge. Returns whether sampling should go on.
+    pub fn sample(&mut self, t_us: u32, closed: bool) -> bool {
+        self.samples += 1;
+        self.duration_us = t_us;
+
+        if closed != self.closed_after() {
+            if self.len < MAX_EDGES {
+                self.edges[self.len] = t_us;
+                self.len += 1;
+            } else {
+                self.dropped_edges += 1;
+            }
+            self.last_edge_us = t_us;
+        }
+
+        t_us - self.last_edge_us < SETTLE_TIME.as_micros() as u32
+            && t_us < MAX_CAPTURE_TIME.as_micros() as u32
+    }
+
+    /// Whether the contact closed, rather than opened, at the first edge.
+    pub fn is_press(&self) -> bool {
+        !self.closed_before
+    }
+
+    /// Whether the contact was closed at the last sample.
+    pub fn closed_after(&self) -> bool {
+        self.closed_before ^ (self.edge_count() % 2 == 1)
+    }
+
+    pub fn edge_count(&self) -> u32 {
+        self.len as u32 + self.dropped_edges
+    }
+
+    /// Times of the kept edges in µs.
+    pub fn edges(&self) -> &[u32] {
+        &self.edges[..self.len]
+    }
+
+    /// Time from the first to the last edge in µs.
+    pub fn bounce_us(&self) -> u32 {
+        self.last_edge_us
+    }
+
+    pub fn sample_rate_hz(&self) -> u32 {
+        if self.duration_us == 0 {
+            return 0;
+        }
+        (u64::from(self.samples) * 1_000_000 / u64::from(self.duration_us)) as u32
+    }
+
+    /// Whether the contact was closed at `t_us`, which may be before the first edge.
+    pub fn closed_at(&self, t_us: i64) -> bool {
+        let edges = self.edges().iter().filter(|&&edge| i64::from(edge) <= t_us);
+        self.closed_before ^ (edges.count() % 2 == 1)
+    }
+
+    /// Number of kept edges in `[from_us, to_us)`.
+    pub fn edges_between(&self, from_us: i64, to_us: i64) -> usize {
+        self.edges()
+            .iter()
+            .filter(|&&edge| (from_us..to_us).contains(&i64::from(edge)))
+            .count()
+    }
+}
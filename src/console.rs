@@ -0,0 +1,674 @@
+//! Line-based command console on the serial port.
+//!
+//! Parsing and dispatching don't depend on the transport: received bytes are split into lines
+//! by a [`LineBuffer`], and [`dispatch`] writes the reply to any [`Write`]r. Every reply ends
+//! with a line reading `ok` or starting with `error:`. Lines of the event [stream] can appear
+//! between replies.
+
+use core::fmt::{self, Write as _};
+
+use embedded_io_async::Write;
+
+use crate::{
+    adc,
+    board::{self, ADC_PINS, BoardProfile},
+    history::{CSV_HEADER, EXPORT_BEGIN, EXPORT_END, FIRMWARE_VERSION, TestResult},
+    kbd::{
+        Key, LAYOUTS, SCAN_SPEED_HZ,
+        latency::{self, Histogram, LatencyMeter, Millis},
+        recording::{self, Recorder},
+    },
+    settings::{ACTUATION_RANGE_PCT, CHAIN_LEN_RANGE, Rotation, Settings},
+    storage::Cursor,
+    stream,
+    text::TextBuf,
+};
+
+/// Longest command line accepted.
+pub const LINE_CAPACITY: usize = 64;
+
+/// Longest line of a reply: a CSV line of a result with all keys failed and chattering.
+const REPLY_LINE_CAPACITY: usize = 160;
+
+const SET_BRIGHTNESS: &str = "set brightness <1-100>";
+const SET_DEBOUNCE: &str = "set debounce <1-255>";
+const SET_LAYOUT: &str = "set layout <n>";
+const SET_ROTATION: &str = "set rotation <0|90|180|270>";
+const SET_BOARD: &str = "set board <id|auto>";
+const SET_ACTUATION: &str = "set actuation <10-90>";
+const SET_ANALOG: &str = "set analog <gpio,..|none>";
+const SET_CHAIN: &str = "set chain <1-128>";
+const SET_I2C: &str = "set i2c <sda,scl|board>";
+
+/// Usage and description of the commands, as listed by `help`.
+const HELP: &[(&str, &str)] = &[
+    ("help", "list the commands"),
+    ("status", "show the board, settings and results"),
+    (SET_BRIGHTNESS, "backlight brightness in percent"),
+    (SET_DEBOUNCE, "scans a key has to be stable for"),
+    (SET_LAYOUT, "keypad layout, see `layout list`"),
+    (SET_ROTATION, "display rotation, after a restart"),
+    (SET_BOARD, "board profile, after a restart"),
+    (SET_ACTUATION, "analog key actuation in percent"),
+    (SET_ANALOG, "ADC pins to test, after a restart"),
+    (SET_CHAIN, "number of LEDs in the RGB chain"),
+    (SET_I2C, "I2C pins to scan, after a restart"),
+    ("layout list", "list the keypad layouts"),
+    ("screenshot", "send the screen contents"),
+    ("results export", "send the test results as CSV"),
+    ("stream <on|off>", "send events as JSON lines"),
+    ("trace <record|stop>", "record raw key scans"),
+    ("trace export", "send the recorded key scans"),
+    ("latency <start|stop>", "measure key-to-event latency"),
+    ("latency show", "show the latency statistics"),
+];
+
+/// Collects received bytes into lines.
+pub struct LineBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflow: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Complete(&'a [u8]),
+    /// The line didn't fit into the buffer and was dropped.
+    TooLong,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Adds a received byte. Returns the line once it's terminated by `\r` or `\n`.
+    pub fn push(&mut self, byte: u8) -> Option<Line<'_>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflow) {
+                    Some(Line::TooLong)
+                } else {
+                    Some(Line::Complete(&self.buf[..len]))
+                }
+            }
+            _ if self.len == N => {
+                self.overflow = true;
+                None
+            }
+            _ => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Status,
+    Set(Setting),
+    LayoutList,
+    Screenshot,
+    ResultsExport,
+    /// Switches the event [stream](crate::stream) on or off.
+    Stream(bool),
+    /// Starts a new [recording](crate::kbd::recording) of the raw key scans.
+    TraceRecord,
+    TraceStop,
+    TraceExport,
+    /// Starts new [latency](crate::kbd::latency) measurements.
+    LatencyStart,
+    LatencyStop,
+    LatencyShow,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Setting {
+    Brightness(u8),
+    Debounce(u8),
+    Layout(u8),
+    Rotation(Rotation),
+    /// Board profile ID, or `None` to detect the board.
+    Board(Option<u8>),
+    /// Actuation point of analog keys in percent of their travel.
+    Actuation(u8),
+    /// ADC pins to test as analog inputs, with bit n set for GPIOn.
+    AnalogPins(u8),
+    /// Number of LEDs in the RGB LED chain.
+    ChainLen(u8),
+    /// SDA and SCL of the I2C bus to scan, or `None` for the board's own bus.
+    I2cPins(Option<(u8, u8)>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    UnknownCommand,
+    UnknownSetting,
+    /// A missing, extra or invalid argument. Holds the usage of the command.
+    Usage(&'static str),
+}
+
+impl Command {
+    /// Parses a command line. Returns `None` for blank lines.
+    pub fn parse(line: &str) -> Result<Option<Self>, ParseError> {
+        let mut words = line.split_ascii_whitespace();
+        let Some(first) = words.next() else {
+            return Ok(None);
+        };
+
+        let (command, usage) = match first {
+            "help" => (Command::Help, "help"),
+            "status" => (Command::Status, "status"),
+            "set" => {
+                let name = words
+                    .next()
+                    .ok_or(ParseError::Usage("set <name> <value>"))?;
+                let (setting, usage) = Setting::parse(name, words.next())?;
+                (Command::Set(setting), usage)
+            }
+            "layout" => match words.next() {
+                Some("list") => (Command::LayoutList, "layout list"),
+                _ => return Err(ParseError::Usage("layout list")),
+            },
+            "screenshot" => (Command::Screenshot, "screenshot"),
+            "results" => match words.next() {
+                Some("export") => (Command::ResultsExport, "results export"),
+                _ => return Err(ParseError::Usage("results export")),
+            },
+            "stream" => match words.next() {
+                Some("on") => (Command::Stream(true), "stream <on|off>"),
+                Some("off") => (Command::Stream(false), "stream <on|off>"),
+                _ => return Err(ParseError::Usage("stream <on|off>")),
+            },
+            "trace" => match words.next() {
+                Some("record") => (Command::TraceRecord, "trace record"),
+                Some("stop") => (Command::TraceStop, "trace stop"),
+                Some("export") => (Command::TraceExport, "trace export"),
+                _ => return Err(ParseError::Usage("trace <record|stop|export>")),
+            },
+            "latency" => match words.next() {
+                Some("start") => (Command::LatencyStart, "latency start"),
+                Some("stop") => (Command::LatencyStop, "latency stop"),
+                Some("show") => (Command::LatencyShow, "latency show"),
+                _ => return Err(ParseError::Usage("latency <start|stop|show>")),
+            },
+            _ => return Err(ParseError::UnknownCommand),
+        };
+
+        if words.next().is_some() {
+            return Err(ParseError::Usage(usage));
+        }
+
+        Ok(Some(command))
+    }
+}
+
+impl Setting {
+    fn parse(name: &str, value: Option<&str>) -> Result<(Self, &'static str), ParseError> {
+        let (setting, usage) = match name {
+            "brightness" => (
+                value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|pct| (1..=100).contains(pct))
+                    .map(Setting::Brightness),
+                SET_BRIGHTNESS,
+            ),
+            "debounce" => (
+                value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&ticks| ticks > 0)
+                    .map(Setting::Debounce),
+                SET_DEBOUNCE,
+            ),
+            "layout" => (
+                value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&layout| usize::from(layout) < LAYOUTS.len())
+                    .map(Setting::Layout),
+                SET_LAYOUT,
+            ),
+            "rotation" => (
+                value
+                    .and_then(|value| value.parse().ok())
+                    .and_then(Rotation::from_degrees)
+                    .map(Setting::Rotation),
+                SET_ROTATION,
+            ),
+            "board" => (
+                match value {
+                    Some("auto") => Some(Setting::Board(None)),
+                    value => value
+                        .and_then(|value| value.parse().ok())
+                        .and_then(BoardProfile::by_id)
+                        .map(|profile| Setting::Board(Some(profile.id))),
+                },
+                SET_BOARD,
+            ),
+            "actuation" => (
+                value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|pct| ACTUATION_RANGE_PCT.contains(pct))
+                    .map(Setting::Actuation),
+                SET_ACTUATION,
+            ),
+            "analog" => (
+                value.and_then(parse_analog_pins).map(Setting::AnalogPins),
+                SET_ANALOG,
+            ),
+            "chain" => (
+                value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|len| CHAIN_LEN_RANGE.contains(len))
+                    .map(Setting::ChainLen),
+                SET_CHAIN,
+            ),
+            "i2c" => (
+                match value {
+                    Some("board") => Some(Setting::I2cPins(None)),
+                    value => value
+                        .and_then(parse_i2c_pins)
+                        .map(|pins| Setting::I2cPins(Some(pins))),
+                },
+                SET_I2C,
+            ),
+            _ => return Err(ParseError::UnknownSetting),
+        };
+
+        Ok((setting.ok_or(ParseError::Usage(usage))?, usage))
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        match *self {
+            Setting::Brightness(pct) => settings.brightness_pct = pct,
+            Setting::Debounce(ticks) => settings.debounce_ticks = ticks,
+            Setting::Layout(layout) => settings.layout = layout,
+            Setting::Rotation(rotation) => settings.rotation = rotation,
+            Setting::Board(board) => settings.board = board,
+            Setting::Actuation(pct) => settings.actuation_pct = pct,
+            Setting::AnalogPins(mask) => settings.analog_pins = mask,
+            Setting::ChainLen(len) => settings.chain_len = len,
+            Setting::I2cPins(pins) => settings.i2c_pins = pins,
+        }
+    }
+}
+
+/// Parses a list of ADC pins like `4,5` into a mask, or `none` into an empty one.
+fn parse_analog_pins(value: &str) -> Option<u8> {
+    if value == "none" {
+        return Some(0);
+    }
+
+    let mut mask = 0u8;
+    for pin in value.split(',') {
+        let pin: u8 = pin.parse().ok().filter(|pin| ADC_PINS.contains(pin))?;
+        mask |= 1 << pin;
+    }
+    (mask.count_ones() as usize <= adc::MAX_INPUTS).then_some(mask)
+}
+
+/// Parses the SDA and SCL pins like `6,7`.
+fn parse_i2c_pins(value: &str) -> Option<(u8, u8)> {
+    let (sda, scl) = value.split_once(',')?;
+    let (sda, scl) = (sda.parse().ok()?, scl.parse().ok()?);
+    board::valid_i2c_pins(sda, scl).then_some((sda, scl))
+}
+
+/// Formats a mask of pins like `4,5`, or `none` if it's empty.
+struct PinList(u8);
+
+impl fmt::Display for PinList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("none");
+        }
+        let mut separator = "";
+        for pin in (0..8).filter(|pin| self.0 & (1 << pin) != 0) {
+            write!(f, "{separator}{pin}")?;
+            separator = ",";
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownCommand => f.write_str("unknown command, try `help`"),
+            ParseError::UnknownSetting => f.write_str("unknown setting, try `help`"),
+            ParseError::Usage(usage) => write!(f, "usage: {usage}"),
+        }
+    }
+}
+
+/// What the console acts on: the tester itself, or a test double on the host.
+pub trait Device {
+    type Error: fmt::Display;
+
+    fn board(&self) -> &'static BoardProfile;
+
+    fn uptime_ms(&self) -> u64;
+
+    fn settings(&self) -> Settings;
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), Self::Error>;
+
+    /// Asks for the screen contents to be sent, which happens outside of the reply.
+    fn request_screenshot(&mut self);
+
+    fn set_streaming(&mut self, enabled: bool);
+
+    /// Gives access to the recording of the raw key scans.
+    fn with_recorder<R>(&mut self, f: impl FnOnce(&mut Recorder) -> R) -> R;
+
+    /// Gives access to the key-to-event latency measurements.
+    fn with_latency_meter<R>(&mut self, f: impl FnOnce(&mut LatencyMeter) -> R) -> R;
+
+    fn results_newest_first(&mut self) -> Result<Cursor, Self::Error>;
+
+    /// Reads the result at the cursor and moves the cursor to the next older one.
+    fn next_result(&mut self, cursor: &mut Cursor) -> Result<Option<TestResult>, Self::Error>;
+}
+
+/// Runs a command line and writes the reply to `out`. Blank lines are ignored.
+pub async fn dispatch<W: Write, D: Device>(
+    line: &[u8],
+    out: &mut W,
+    device: &mut D,
+) -> Result<(), W::Error> {
+    let Ok(line) = core::str::from_utf8(line) else {
+        return write_line(out, format_args!("error: not a text line")).await;
+    };
+
+    match Command::parse(line) {
+        Ok(None) => Ok(()),
+        Ok(Some(command)) => run(command, out, device).await,
+        Err(error) => write_line(out, format_args!("error: {error}")).await,
+    }
+}
+
+async fn run<W: Write, D: Device>(
+    command: Command,
+    out: &mut W,
+    device: &mut D,
+) -> Result<(), W::Error> {
+    let result = match command {
+        Command::Help => {
+            for (usage, description) in HELP {
+                write_line(out, format_args!("{usage:<28}{description}")).await?;
+            }
+            Ok(())
+        }
+        Command::Status => status(out, device).await?,
+        Command::Set(setting) => {
+            let mut settings = device.settings();
+            setting.apply(&mut settings);
+            device.save_settings(&settings)
+        }
+        Command::LayoutList => {
+            let current = usize::from(device.settings().layout);
+            for (i, layout) in LAYOUTS.iter().enumerate() {
+                let marker = if i == current { '*' } else { ' ' };
+                write_line(
+                    out,
+                    format_args!("{marker} {i} {}: {}", layout.name, layout.description),
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        Command::Screenshot => {
+            device.request_screenshot();
+            Ok(())
+        }
+        Command::ResultsExport => export(out, device).await?,
+        Command::Stream(enabled) => {
+            if enabled {
+                let mut hello = TextBuf::<{ stream::LINE_CAPACITY }>::new();
+                let _ = stream::write_hello(device.board().name, &mut hello);
+                write_line(out, format_args!("{}", hello.as_str())).await?;
+            }
+            device.set_streaming(enabled);
+            Ok(())
+        }
+        Command::TraceRecord => {
+            let debounce_ticks = device.settings().debounce_ticks;
+            device.with_recorder(|recorder| recorder.start(debounce_ticks));
+            Ok(())
+        }
+        Command::TraceStop => {
+            device.with_recorder(Recorder::stop);
+            Ok(())
+        }
+        Command::TraceExport => {
+            export_trace(out, device).await?;
+            Ok(())
+        }
+        Command::LatencyStart => {
+            device.with_latency_meter(LatencyMeter::start);
+            Ok(())
+        }
+        Command::LatencyStop => {
+            device.with_latency_meter(LatencyMeter::stop);
+            Ok(())
+        }
+        Command::LatencyShow => {
+            show_latency(out, device).await?;
+            Ok(())
+        }
+    };
+
+    match result {
+        Ok(()) => write_line(out, format_args!("ok")).await,
+        Err(error) => write_line(out, format_args!("error: {error}")).await,
+    }
+}
+
+/// Writes the status. The outer result is the transport's, the inner one the device's.
+async fn status<W: Write, D: Device>(
+    out: &mut W,
+    device: &mut D,
+) -> Result<Result<(), D::Error>, W::Error> {
+    let [major, minor, patch] = FIRMWARE_VERSION;
+    write_line(out, format_args!("firmware: {major}.{minor}.{patch}")).await?;
+
+    let board = device.board();
+    write_line(out, format_args!("board: {} ({})", board.name, board.id)).await?;
+    write_line(out, format_args!("uptime: {} s", device.uptime_ms() / 1000)).await?;
+
+    let settings = device.settings();
+    write_line(out, format_args!("brightness: {}", settings.brightness_pct)).await?;
+    write_line(out, format_args!("debounce: {}", settings.debounce_ticks)).await?;
+    let layout = LAYOUTS
+        .get(usize::from(settings.layout))
+        .map_or("unknown", |layout| layout.name);
+    write_line(out, format_args!("layout: {} ({layout})", settings.layout)).await?;
+    write_line(
+        out,
+        format_args!("rotation: {}", settings.rotation.degrees()),
+    )
+    .await?;
+    match settings.board {
+        Some(id) => write_line(out, format_args!("board setting: {id}")).await?,
+        None => write_line(out, format_args!("board setting: auto")).await?,
+    }
+    write_line(out, format_args!("actuation: {}", settings.actuation_pct)).await?;
+    write_line(
+        out,
+        format_args!("analog: {}", PinList(settings.analog_pins)),
+    )
+    .await?;
+    write_line(out, format_args!("chain: {}", settings.chain_len)).await?;
+    match settings.i2c_pins {
+        Some((sda, scl)) => write_line(out, format_args!("i2c: {sda},{scl}")).await?,
+        None => write_line(out, format_args!("i2c: board")).await?,
+    }
+
+    let (recording, ticks) =
+        device.with_recorder(|recorder| (recorder.is_recording(), recorder.len()));
+    let state = if recording { ", recording" } else { "" };
+    write_line(out, format_args!("trace: {ticks} ticks{state}")).await?;
+
+    let (running, count) =
+        device.with_latency_meter(|meter| (meter.is_running(), meter.overall().count()));
+    let state = if running { ", running" } else { "" };
+    write_line(out, format_args!("latency: {count} events{state}")).await?;
+
+    match count_results(device) {
+        Ok(count) => write_line(out, format_args!("results: {count}")).await?,
+        Err(error) => return Ok(Err(error)),
+    }
+
+    Ok(Ok(()))
+}
+
+fn count_results<D: Device>(device: &mut D) -> Result<usize, D::Error> {
+    let mut cursor = device.results_newest_first()?;
+    let mut count = 0;
+
+    while device.next_result(&mut cursor)?.is_some() {
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Writes all results as CSV, newest first, between [`EXPORT_BEGIN`] and [`EXPORT_END`].
+async fn export<W: Write, D: Device>(
+    out: &mut W,
+    device: &mut D,
+) -> Result<Result<(), D::Error>, W::Error> {
+    let mut cursor = match device.results_newest_first() {
+        Ok(cursor) => cursor,
+        Err(error) => return Ok(Err(error)),
+    };
+
+    write_line(out, format_args!("{EXPORT_BEGIN}")).await?;
+    write_line(out, format_args!("{CSV_HEADER}")).await?;
+
+    loop {
+        let result = match device.next_result(&mut cursor) {
+            Ok(Some(result)) => result,
+            Ok(None) => break,
+            Err(error) => return Ok(Err(error)),
+        };
+
+        let mut line = TextBuf::<REPLY_LINE_CAPACITY>::new();
+        if result.write_csv(&mut line).is_err() {
+            defmt::warn!("result too long for export: {}", result);
+            continue;
+        }
+        write_line(out, format_args!("{}", line.as_str())).await?;
+    }
+
+    write_line(out, format_args!("{EXPORT_END}")).await?;
+    Ok(Ok(()))
+}
+
+/// Stops the recording and writes it between [`recording::EXPORT_BEGIN`] and
+/// [`recording::EXPORT_END`].
+async fn export_trace<W: Write, D: Device>(out: &mut W, device: &mut D) -> Result<(), W::Error> {
+    let debounce_ticks = device.with_recorder(|recorder| {
+        recorder.stop();
+        recorder.debounce_ticks()
+    });
+
+    write_line(out, format_args!("{}", recording::EXPORT_BEGIN)).await?;
+    write_line(out, format_args!("scan_hz {SCAN_SPEED_HZ}")).await?;
+    write_line(out, format_args!("debounce {debounce_ticks}")).await?;
+
+    let mut tick = 0;
+    while let Some(run) = device.with_recorder(|recorder| recorder.run_at(tick)) {
+        write_line(out, format_args!("{run}")).await?;
+        tick += run.ticks as usize;
+    }
+
+    write_line(out, format_args!("{}", recording::EXPORT_END)).await
+}
+
+/// Writes the latency statistics, overall and of each key that has any, followed by the
+/// non-empty buckets of the overall histogram.
+async fn show_latency<W: Write, D: Device>(out: &mut W, device: &mut D) -> Result<(), W::Error> {
+    write_line(
+        out,
+        format_args!("latency in ms, from the scan that read a change to its event"),
+    )
+    .await?;
+    write_line(
+        out,
+        format_args!(
+            "{:<4}{:>4}{:>8}{:>8}{:>8}{:>8}",
+            "key", "n", "min", "avg", "max", "p99"
+        ),
+    )
+    .await?;
+
+    let overall = device.with_latency_meter(|meter| meter.overall().clone());
+    write_latency(out, "all", &overall).await?;
+    for key in Key::all() {
+        let histogram = device.with_latency_meter(|meter| meter.key(key).clone());
+        if !histogram.is_empty() {
+            let mut label = [0; 4];
+            write_latency(out, key.char().encode_utf8(&mut label), &histogram).await?;
+        }
+    }
+
+    write_line(out, format_args!("histogram, ms: count")).await?;
+    let ms = |bucket: usize| bucket as u32 * latency::BUCKET_US / 1000;
+    for (bucket, &count) in overall.buckets().iter().enumerate() {
+        if count > 0 {
+            write_line(
+                out,
+                format_args!("{:>3}-{:<3}{count:>6}", ms(bucket), ms(bucket + 1)),
+            )
+            .await?;
+        }
+    }
+    if overall.overflow() > 0 {
+        let from = ms(latency::BUCKETS);
+        write_line(out, format_args!("{from:>3}+   {:>6}", overall.overflow())).await?;
+    }
+
+    Ok(())
+}
+
+async fn write_latency<W: Write>(
+    out: &mut W,
+    label: &str,
+    histogram: &Histogram,
+) -> Result<(), W::Error> {
+    write_line(
+        out,
+        format_args!(
+            "{label:<4}{:>4}{:>8}{:>8}{:>8}{:>8}",
+            histogram.count(),
+            Millis(histogram.min_us()),
+            Millis(histogram.avg_us()),
+            Millis(histogram.max_us()),
+            Millis(histogram.percentile_us(99)),
+        ),
+    )
+    .await
+}
+
+/// Writes a line of the reply. Lines longer than [`REPLY_LINE_CAPACITY`] are cut off.
+async fn write_line<W: Write>(out: &mut W, args: fmt::Arguments<'_>) -> Result<(), W::Error> {
+    let mut line = TextBuf::<REPLY_LINE_CAPACITY>::new();
+    let _ = line.write_fmt(args);
+
+    out.write_all(line.as_bytes()).await?;
+    out.write_all(b"\n").await
+}
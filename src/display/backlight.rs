@@ -0,0 +1,142 @@
+use embassy_time::{Duration, Timer};
+use esp_hal::{
+    gpio::AnyPin,
+    ledc::{
+        self, LSGlobalClkSource, Ledc,
+        channel::{ChannelHW as _, ChannelIFace as _},
+        timer::{LSClockSource, TimerIFace as _, config::Duty},
+    },
+    peripherals::LEDC,
+    time::Rate,
+};
+use static_cell::StaticCell;
+
+use crate::error::{AppError, Context, ResultExt as _};
+
+/// Duty resolution of the backlight PWM. At 24 kHz, the APB clock leaves room for 11 bits.
+const BACKLIGHT_DUTY: Duty = Duty::Duty11Bit;
+const BACKLIGHT_MAX_DUTY: u32 = (1 << BACKLIGHT_DUTY as u32) - 1;
+
+/// The LEDC fade engine ramps the duty linearly, so a fade is split into several segments
+/// following the gamma curve to make it look linear to the eye.
+const FADE_SEGMENTS: u32 = 8;
+
+/// Upper bound of the duty step count, step duration and step size of a hardware fade.
+const FADE_HW_MAX: u32 = 1023;
+
+pub struct Backlight {
+    pwm_timer: &'static ledc::timer::Timer<'static, ledc::LowSpeed>,
+    pwm_channel: ledc::channel::Channel<'static, ledc::LowSpeed>,
+    brightness_pct: u8,
+}
+
+impl Backlight {
+    pub fn init(ledc: LEDC<'static>, bl: AnyPin<'static>) -> Result<Self, AppError> {
+        let mut ledc = Ledc::new(ledc);
+        ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+
+        static PWM_TIMER: StaticCell<ledc::timer::Timer<'static, ledc::LowSpeed>> =
+            StaticCell::new();
+
+        let pwm_timer = PWM_TIMER.init(ledc.timer(ledc::timer::Number::Timer0));
+        pwm_timer
+            .configure(ledc::timer::config::Config {
+                duty: BACKLIGHT_DUTY,
+                clock_source: LSClockSource::APBClk,
+                frequency: Rate::from_khz(24),
+            })
+            .context(Context::BacklightInit)?;
+
+        let mut pwm_channel = ledc.channel(esp_hal::ledc::channel::Number::Channel0, bl);
+        pwm_channel
+            .configure(ledc::channel::config::Config {
+                timer: pwm_timer,
+                duty_pct: 0,
+                drive_mode: esp_hal::gpio::DriveMode::PushPull,
+            })
+            .context(Context::BacklightInit)?;
+
+        Ok(Self {
+            pwm_timer,
+            pwm_channel,
+            brightness_pct: 0,
+        })
+    }
+
+    pub fn brightness_pct(&self) -> u8 {
+        self.brightness_pct
+    }
+
+    /// Immediately switches to the given perceived brightness.
+    pub fn set_brightness_pct(&mut self, brightness: u8) {
+        let brightness = brightness.min(100);
+        self.pwm_channel.set_duty_hw(gamma_duty(brightness));
+        self.brightness_pct = brightness;
+    }
+
+    /// Smoothly transitions from the current to the given perceived brightness using the LEDC
+    /// hardware fade engine.
+    ///
+    /// The fade can be cancelled by dropping the future. The brightness is then that of the last
+    /// finished segment, and the engine finishes the current one unless a new brightness is set.
+    pub async fn fade_to(&mut self, brightness: u8, duration: Duration) {
+        let brightness = brightness.min(100);
+        if brightness == self.brightness_pct {
+            return;
+        }
+
+        let start = i32::from(self.brightness_pct);
+        let delta = i32::from(brightness) - start;
+        let segment_duration = duration / FADE_SEGMENTS;
+
+        for segment in 1..=FADE_SEGMENTS as i32 {
+            let from = start + delta * (segment - 1) / FADE_SEGMENTS as i32;
+            let to = start + delta * segment / FADE_SEGMENTS as i32;
+            self.fade_segment(
+                gamma_duty(from as u8),
+                gamma_duty(to as u8),
+                segment_duration,
+            )
+            .await;
+            self.brightness_pct = to as u8;
+        }
+    }
+
+    async fn fade_segment(&mut self, start_duty: u32, end_duty: u32, duration: Duration) {
+        let duty_diff = end_duty.abs_diff(start_duty);
+
+        if duty_diff == 0 {
+            Timer::after(duration).await;
+            return;
+        }
+
+        let pwm_cycles =
+            (duration.as_micros() * u64::from(self.pwm_timer.frequency()) / 1_000_000) as u32;
+        let duty_per_cycle = duty_diff.div_ceil(FADE_HW_MAX);
+        let duty_steps = duty_diff / duty_per_cycle;
+        let cycles_per_step = (pwm_cycles / duty_steps).clamp(1, FADE_HW_MAX);
+
+        self.pwm_channel.start_duty_fade_hw(
+            start_duty,
+            end_duty > start_duty,
+            duty_steps as u16,
+            cycles_per_step as u16,
+            duty_per_cycle as u16,
+        );
+
+        Timer::after(duration).await;
+
+        while self.pwm_channel.is_duty_fade_running() {
+            Timer::after_millis(1).await;
+        }
+
+        // The fade stops at the last whole step, which may be short of the target.
+        self.pwm_channel.set_duty_hw(end_duty);
+    }
+}
+
+/// Maps perceived brightness in percent to a raw duty value using a gamma of 2.
+fn gamma_duty(brightness_pct: u8) -> u32 {
+    let pct = u32::from(brightness_pct.min(100));
+    BACKLIGHT_MAX_DUTY * pct * pct / (100 * 100)
+}
@@ -0,0 +1,147 @@
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+use embedded_graphics::pixelcolor::Rgb565;
+use esp_hal::{
+    dma::{DmaRxBuf, DmaTxBuf},
+    gpio::{AnyPin, Level, Output},
+    peripherals::{DMA_CH0, SPI2},
+    spi::{self, master::Spi},
+    time::Rate,
+};
+use lcd_async::{
+    Builder, Display,
+    interface::SpiInterface,
+    models::ST7789,
+    options::{ColorInversion, Orientation, Rotation},
+    raw_framebuf::RawFrameBuf,
+};
+use static_cell::{ConstStaticCell, StaticCell};
+
+use super::{Backlight, HEIGHT, PIXEL_SIZE, WIDTH};
+use crate::{
+    error::{AppError, Context, ResultExt as _},
+    settings,
+};
+
+pub struct DisplayState {
+    pub display: Panel,
+    pub fb: FrameBuffer,
+    pub backlight: Backlight,
+}
+
+type Panel = Display<DisplayInterface, ST7789, Output<'static>>;
+pub type FrameBuffer = RawFrameBuf<Rgb565, &'static mut [u8]>;
+
+type DisplayInterface =
+    SpiInterface<SpiDevice<'static, NoopRawMutex, SpiBus, Output<'static>>, Output<'static>>;
+
+pub struct DisplayPeripherals {
+    pub scl: AnyPin<'static>,
+    pub sda: AnyPin<'static>,
+    pub rst: AnyPin<'static>,
+    pub dc: AnyPin<'static>,
+    pub cs: AnyPin<'static>,
+    pub spi: SPI2<'static>,
+    pub dma_ch: DMA_CH0<'static>,
+}
+
+/// Returned when the panel couldn't be initialized. The backlight is handed back so it can still
+/// be used to signal the failure.
+pub struct DisplayInitError {
+    pub error: AppError,
+    pub backlight: Backlight,
+}
+
+impl DisplayState {
+    pub async fn init(
+        peripherals: DisplayPeripherals,
+        backlight: Backlight,
+        rotation: settings::Rotation,
+    ) -> Result<Self, DisplayInitError> {
+        match init_panel(peripherals, rotation).await {
+            Ok((display, fb)) => Ok(Self {
+                display,
+                fb,
+                backlight,
+            }),
+            Err(error) => Err(DisplayInitError { error, backlight }),
+        }
+    }
+}
+
+async fn init_panel(
+    peripherals: DisplayPeripherals,
+    rotation: settings::Rotation,
+) -> Result<(Panel, FrameBuffer), AppError> {
+    let rst = Output::new(peripherals.rst, Level::Low, Default::default());
+    let dc = Output::new(peripherals.dc, Level::Low, Default::default());
+    let cs = Output::new(peripherals.cs, Level::High, Default::default());
+
+    let spi_bus = init_spi_bus(SpiBusPerhipherals {
+        scl: peripherals.scl,
+        sda: peripherals.sda,
+        spi: peripherals.spi,
+        dma_ch: peripherals.dma_ch,
+    })?;
+    let spi_device = SpiDevice::new(spi_bus, cs);
+    let di = SpiInterface::new(spi_device, dc);
+
+    let display = Builder::new(ST7789, di)
+        .reset_pin(rst)
+        .display_size(WIDTH, HEIGHT)
+        .orientation(Orientation {
+            rotation: match rotation {
+                settings::Rotation::Deg0 => Rotation::Deg0,
+                settings::Rotation::Deg90 => Rotation::Deg90,
+                settings::Rotation::Deg180 => Rotation::Deg180,
+                settings::Rotation::Deg270 => Rotation::Deg270,
+            },
+            mirrored: false,
+        })
+        .display_offset(0, 0)
+        .invert_colors(ColorInversion::Inverted)
+        .init(&mut embassy_time::Delay)
+        .await
+        .context(Context::PanelInit)?;
+
+    const FRAME_SIZE: usize = (WIDTH as usize) * (HEIGHT as usize) * PIXEL_SIZE;
+    static FRAME_BUFFER: ConstStaticCell<[u8; FRAME_SIZE]> = ConstStaticCell::new([0; FRAME_SIZE]);
+
+    let fb_bytes = FRAME_BUFFER.take();
+    let fb = RawFrameBuf::new(fb_bytes.as_mut_slice(), WIDTH.into(), HEIGHT.into());
+
+    Ok((display, fb))
+}
+
+struct SpiBusPerhipherals {
+    scl: AnyPin<'static>,
+    sda: AnyPin<'static>,
+    spi: SPI2<'static>,
+    dma_ch: DMA_CH0<'static>,
+}
+
+type SpiBus = spi::master::SpiDmaBus<'static, esp_hal::Async>;
+type SpiBusMutex = Mutex<NoopRawMutex, SpiBus>;
+
+fn init_spi_bus(peripherals: SpiBusPerhipherals) -> Result<&'static SpiBusMutex, AppError> {
+    let (rx_buffer, rx_descriptors, tx_buffer, tx_descriptors) = esp_hal::dma_buffers!(4, 32_000);
+    let dma_rx_buf = DmaRxBuf::new(rx_descriptors, rx_buffer).context(Context::SpiBusInit)?;
+    let dma_tx_buf = DmaTxBuf::new(tx_descriptors, tx_buffer).context(Context::SpiBusInit)?;
+
+    static SPI_BUS: StaticCell<SpiBusMutex> = StaticCell::new();
+
+    let spi_bus = Spi::new(
+        peripherals.spi,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_mhz(20))
+            .with_mode(spi::Mode::_0),
+    )
+    .context(Context::SpiBusInit)?
+    .with_sck(peripherals.scl)
+    .with_mosi(peripherals.sda)
+    .with_dma(peripherals.dma_ch)
+    .with_buffers(dma_rx_buf, dma_tx_buf)
+    .into_async();
+
+    Ok(SPI_BUS.init(Mutex::new(spi_bus)))
+}
@@ -0,0 +1,118 @@
+//! Rotary encoders with quadrature outputs, like the EC11.
+//!
+//! The encoder task reads the A and B lines whenever one of them changes and feeds the levels to
+//! a [`QuadratureDecoder`], which reports detents and the transitions that went wrong. The push
+//! switch of an encoder is wired into the key matrix and reported as a key.
+
+use defmt::Format;
+
+#[cfg(target_os = "none")]
+mod input;
+
+#[cfg(target_os = "none")]
+pub use self::input::{EncoderInput, subscriber, task};
+
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum Direction {
+    /// A changes before B.
+    Clockwise,
+    CounterClockwise,
+}
+
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum EncoderEvent {
+    /// The encoder was turned by one detent.
+    Detent(Direction),
+    /// Both lines changed between two reads, so a transition was missed.
+    Missed,
+    /// The direction reversed between two detents, usually because a contact bounced.
+    Bounce,
+}
+
+/// Decodes the levels of the A and B lines, which are high while their contacts are open.
+///
+/// The encoder is expected to rest with both contacts open and to go through all four states
+/// between two detents, as the EC11 does. A single missed state is tolerated by assuming the
+/// encoder kept turning the same way.
+pub struct QuadratureDecoder {
+    /// Position within the quadrature cycle, 0 being the rest state.
+    position: u8,
+    /// Quarter steps since the last detent, positive for clockwise.
+    steps: i8,
+    /// Direction of the last quarter step since the last detent, or 0.
+    last_step: i8,
+}
+
+/// What a change of the lines amounted to.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct EncoderUpdate {
+    pub detent: Option<Direction>,
+    pub missed: bool,
+    pub bounced: bool,
+}
+
+impl QuadratureDecoder {
+    /// Starts decoding from the current levels of the lines.
+    pub fn new(a: bool, b: bool) -> Self {
+        Self {
+            position: Self::position(a, b),
+            steps: 0,
+            last_step: 0,
+        }
+    }
+
+    /// Position in the sequence a clockwise turn goes through.
+    fn position(a: bool, b: bool) -> u8 {
+        match (a, b) {
+            (true, true) => 0,
+            (false, true) => 1,
+            (false, false) => 2,
+            (true, false) => 3,
+        }
+    }
+
+    pub fn update(&mut self, a: bool, b: bool) -> EncoderUpdate {
+        let mut update = EncoderUpdate::default();
+
+        let position = Self::position(a, b);
+        let step = match (position + 4 - self.position) % 4 {
+            0 => return update,
+            1 => 1,
+            3 => -1,
+            _ => {
+                update.missed = true;
+                2 * self.last_step
+            }
+        };
+        self.position = position;
+
+        if step != 0 {
+            update.bounced = self.last_step != 0 && step.signum() != self.last_step;
+            self.last_step = step.signum();
+            self.steps += step;
+        }
+
+        if position == 0 {
+            update.detent = match self.steps {
+                2.. => Some(Direction::Clockwise),
+                ..=-2 => Some(Direction::CounterClockwise),
+                _ => None,
+            };
+            self.steps = 0;
+            self.last_step = 0;
+        }
+
+        update
+    }
+}
+
+impl EncoderUpdate {
+    /// Events of the update, problems first.
+    pub fn events(&self) -> impl Iterator<Item = EncoderEvent> + use<> {
+        let missed = self.missed.then_some(EncoderEvent::Missed);
+        let bounced = self.bounced.then_some(EncoderEvent::Bounce);
+        let detent = self.detent.map(EncoderEvent::Detent);
+
+        missed.into_iter().chain(bounced).chain(detent)
+    }
+}
@@ -0,0 +1,59 @@
+use defmt::{debug, info};
+use embassy_futures::select::select;
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    pubsub::{DynSubscriber, PubSubChannel},
+};
+use esp_hal::gpio::{AnyPin, Input, InputConfig, Pull};
+
+use super::{EncoderEvent, QuadratureDecoder};
+use crate::error::{AppError, Context, ResultExt as _};
+
+static CHANNEL: PubSubChannel<CriticalSectionRawMutex, EncoderEvent, 16, 1, 1> =
+    PubSubChannel::new();
+
+pub fn subscriber() -> Result<DynSubscriber<'static, EncoderEvent>, AppError> {
+    CHANNEL
+        .dyn_subscriber()
+        .context(Context::EncoderEventSubscription)
+}
+
+pub struct EncoderInput<'p> {
+    a: Input<'p>,
+    b: Input<'p>,
+}
+
+impl<'p> EncoderInput<'p> {
+    pub fn new(a: AnyPin<'p>, b: AnyPin<'p>) -> Self {
+        let config = InputConfig::default().with_pull(Pull::Up);
+        Self {
+            a: Input::new(a, config),
+            b: Input::new(b, config),
+        }
+    }
+
+    fn levels(&self) -> (bool, bool) {
+        (self.a.is_high(), self.b.is_high())
+    }
+}
+
+#[embassy_executor::task]
+pub async fn task(mut encoder: EncoderInput<'static>) {
+    info!("starting encoder task");
+
+    let (a, b) = encoder.levels();
+    let mut decoder = QuadratureDecoder::new(a, b);
+
+    loop {
+        select(encoder.a.wait_for_any_edge(), encoder.b.wait_for_any_edge()).await;
+
+        let (a, b) = encoder.levels();
+        let update = decoder.update(a, b);
+
+        let publisher = CHANNEL.immediate_publisher();
+        for event in update.events() {
+            debug!("encoder: {}", event);
+            publisher.publish_immediate(event);
+        }
+    }
+}
@@ -1,10 +1,35 @@
-use core::convert::Infallible;
+//! Application errors.
+//!
+//! An [`AppError`] wraps the error returned by a driver together with the [`Context`] it
+//! happened in and the source location it was raised at. Errors are raised with
+//! [`ResultExt::context`]:
+//!
+//! ```ignore
+//! pwm_timer.configure(config).context(Context::BacklightInit)?;
+//! ```
+//!
+//! Every error has a stable numeric [code](AppError::code) of the form `CCKK`, where `CC` is the
+//! context and `KK` the kind of error. It's shown on the error screen so that a photo of the
+//! screen, or just the number, is enough to tell what happened.
+
+use core::{convert::Infallible, fmt, panic::Location};
 
 use embassy_embedded_hal::shared_bus::SpiDeviceError;
 use esp_hal::dma::DmaBufError;
+use esp_storage::FlashStorageError;
+
+use crate::storage;
 
+#[derive(Debug)]
+pub struct AppError {
+    kind: ErrorKind,
+    context: Context,
+    location: &'static Location<'static>,
+}
+
+/// The underlying driver error.
 #[derive(Debug, defmt::Format, derive_more::From)]
-pub enum AppError {
+pub enum ErrorKind {
     LcdAsyncInitError(#[defmt(Debug2Format)] LcdAsyncInitError),
     SpiError(#[defmt(Debug2Format)] LcdAsyncSpiError),
     DmaBufError(DmaBufError),
@@ -12,10 +37,220 @@ pub enum AppError {
     LedcTimerError(esp_hal::ledc::timer::Error),
     LedcChannelError(esp_hal::ledc::channel::Error),
     PubSubError(embassy_sync::pubsub::Error),
-    Unreachable(Infallible),
+    StorageError(storage::Error<FlashStorageError>),
+    StorageUnavailable(storage::Unavailable),
 }
 
 type LcdAsyncSpiError =
     lcd_async::interface::SpiError<SpiDeviceError<esp_hal::spi::Error, Infallible>, Infallible>;
 
 type LcdAsyncInitError = lcd_async::InitError<LcdAsyncSpiError, Infallible>;
+
+/// What the tester was doing when the error happened.
+///
+/// The discriminants are part of the error codes and must not be changed or reused.
+#[derive(Copy, Clone, Debug, defmt::Format, PartialEq, Eq)]
+pub enum Context {
+    SpiBusInit = 1,
+    PanelInit = 2,
+    FrameTransfer = 3,
+    BacklightInit = 4,
+    KeyEventSubscription = 5,
+    SettingsLoad = 6,
+    SettingsSave = 7,
+    HistoryLoad = 8,
+    HistorySave = 9,
+    EncoderEventSubscription = 10,
+}
+
+/// Part of the tester an error originates from. The discriminant doubles as the number of
+/// backlight blinks signalling the error when the display is unusable.
+#[derive(Copy, Clone, Debug, defmt::Format, PartialEq, Eq)]
+pub enum Subsystem {
+    Display = 1,
+    Backlight = 2,
+    Keyboard = 3,
+    Storage = 4,
+}
+
+impl Subsystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Display => "display",
+            Subsystem::Backlight => "backlight",
+            Subsystem::Keyboard => "keyboard",
+            Subsystem::Storage => "storage",
+        }
+    }
+
+    pub fn blink_code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl Context {
+    pub fn subsystem(self) -> Subsystem {
+        match self {
+            Context::SpiBusInit | Context::PanelInit | Context::FrameTransfer => Subsystem::Display,
+            Context::BacklightInit => Subsystem::Backlight,
+            Context::KeyEventSubscription | Context::EncoderEventSubscription => {
+                Subsystem::Keyboard
+            }
+            Context::SettingsLoad
+            | Context::SettingsSave
+            | Context::HistoryLoad
+            | Context::HistorySave => Subsystem::Storage,
+        }
+    }
+
+    /// Short human-readable description, completing "while ...".
+    pub fn description(self) -> &'static str {
+        match self {
+            Context::SpiBusInit => "setting up SPI",
+            Context::PanelInit => "starting the panel",
+            Context::FrameTransfer => "sending a frame",
+            Context::BacklightInit => "setting up PWM",
+            Context::KeyEventSubscription => "subscribing to keys",
+            Context::SettingsLoad => "loading settings",
+            Context::SettingsSave => "saving settings",
+            Context::HistoryLoad => "reading results",
+            Context::HistorySave => "saving the result",
+            Context::EncoderEventSubscription => "subscribing to the encoder",
+        }
+    }
+}
+
+impl ErrorKind {
+    /// Part of the error codes; must not be changed or reused.
+    fn code(&self) -> u16 {
+        match self {
+            ErrorKind::LcdAsyncInitError(_) => 1,
+            ErrorKind::SpiError(_) => 2,
+            ErrorKind::DmaBufError(_) => 3,
+            ErrorKind::SpiConfigError(_) => 4,
+            ErrorKind::LedcTimerError(_) => 5,
+            ErrorKind::LedcChannelError(_) => 6,
+            ErrorKind::PubSubError(_) => 7,
+            ErrorKind::StorageError(_) => 8,
+            ErrorKind::StorageUnavailable(_) => 9,
+        }
+    }
+
+    /// Short human-readable description of what went wrong.
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorKind::LcdAsyncInitError(_) => "Panel init failed",
+            ErrorKind::SpiError(_) => "SPI transfer failed",
+            ErrorKind::DmaBufError(_) => "DMA buffer error",
+            ErrorKind::SpiConfigError(_) => "Bad SPI config",
+            ErrorKind::LedcTimerError(_) => "PWM timer error",
+            ErrorKind::LedcChannelError(_) => "PWM channel error",
+            ErrorKind::PubSubError(_) => "Event queue error",
+            ErrorKind::StorageError(_) => "Flash access failed",
+            ErrorKind::StorageUnavailable(_) => "No storage partition",
+        }
+    }
+}
+
+impl AppError {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn context(&self) -> Context {
+        self.context
+    }
+
+    pub fn subsystem(&self) -> Subsystem {
+        self.context.subsystem()
+    }
+
+    /// Source location the error was raised at.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Stable numeric code identifying the context and kind of the error.
+    pub fn code(&self) -> u16 {
+        self.context as u16 * 100 + self.kind.code()
+    }
+
+    /// What the user can do about the error, with lines short enough to fit the screen.
+    pub fn hint(&self) -> &'static str {
+        match self.kind {
+            ErrorKind::LcdAsyncInitError(_) | ErrorKind::SpiError(_) => {
+                "Check the display ribbon and the\nSPI wiring, then reset the tester."
+            }
+            ErrorKind::DmaBufError(_)
+            | ErrorKind::SpiConfigError(_)
+            | ErrorKind::LedcTimerError(_)
+            | ErrorKind::LedcChannelError(_)
+            | ErrorKind::PubSubError(_) => {
+                "This is a firmware bug. Please\nreport it along with the log."
+            }
+            ErrorKind::StorageError(_) => {
+                "The flash may be worn out. Flash\nthe tester again or replace it."
+            }
+            ErrorKind::StorageUnavailable(_) => {
+                "Flash the firmware along with\nthe partition table."
+            }
+        }
+    }
+}
+
+/// Formats the error as a single line for humans, e.g.
+/// `E302: SPI transfer failed while sending a frame (display)`.
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "E{}: {} while {} ({})",
+            self.code(),
+            self.kind.message(),
+            self.context.description(),
+            self.subsystem().name(),
+        )
+    }
+}
+
+impl defmt::Format for AppError {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "E{=u16} {} in {} at {=str}:{=u32}: {}",
+            self.code(),
+            self.context,
+            self.subsystem(),
+            self.location.file(),
+            self.location.line(),
+            self.kind,
+        );
+    }
+}
+
+/// Lets `?` pass through infallible results, e.g. from drawing to the frame buffer.
+impl From<Infallible> for AppError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+/// Attaches a [`Context`] to driver errors, turning them into [`AppError`]s.
+pub trait ResultExt<T> {
+    /// Records the caller's location along with the context.
+    #[track_caller]
+    fn context(self, context: Context) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<ErrorKind>> ResultExt<T> for Result<T, E> {
+    #[track_caller]
+    fn context(self, context: Context) -> Result<T, AppError> {
+        let location = Location::caller();
+
+        self.map_err(|error| AppError {
+            kind: error.into(),
+            context,
+            location,
+        })
+    }
+}
@@ -0,0 +1,103 @@
+use defmt::{error, warn};
+use embassy_time::{Duration, Timer};
+use embedded_graphics::Drawable as _;
+
+use crate::{
+    display::{self, Backlight, DisplayState},
+    error::{AppError, Context, ResultExt as _},
+    ui,
+};
+
+/// How many times the tester reboots to retry initializing the display before giving up.
+const MAX_DISPLAY_INIT_ATTEMPTS: u8 = 3;
+
+/// Number of times the blink code is repeated before rebooting.
+const BLINK_CODE_REPEATS: usize = 3;
+
+const BLINK_ON: Duration = Duration::from_millis(250);
+const BLINK_OFF: Duration = Duration::from_millis(350);
+const BLINK_PAUSE: Duration = Duration::from_millis(1500);
+
+/// Counts failed display initializations across software resets. Zeroed on power-on.
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut DISPLAY_INIT_ATTEMPTS: u8 = 0;
+
+/// Shows the error on the display.
+///
+/// Fails if the display itself can't be written to.
+pub async fn show_error_screen(
+    display_state: &mut DisplayState,
+    error: &AppError,
+) -> Result<(), AppError> {
+    ui::ErrorScreen {
+        code: error.code(),
+        kind: error.kind().message(),
+        subsystem: error.subsystem().name(),
+        context: error.context().description(),
+        hint: error.hint(),
+    }
+    .draw(&mut display_state.fb)?;
+
+    display_state
+        .display
+        .show_raw_data(
+            0,
+            0,
+            display::WIDTH,
+            display::HEIGHT,
+            display_state.fb.as_bytes(),
+        )
+        .await
+        .context(Context::FrameTransfer)?;
+
+    Ok(())
+}
+
+/// Marks the display as working so that a later failure starts over with all retries.
+pub fn display_init_succeeded() {
+    // SAFETY: only accessed by tasks of the single executor, without holding references to it
+    // or awaiting in between.
+    unsafe { DISPLAY_INIT_ATTEMPTS = 0 };
+}
+
+/// Handles a display that can't be used: the error is signalled by blinking the backlight, and
+/// the tester reboots to retry initializing the display a few times before it gives up and keeps
+/// blinking.
+pub async fn display_failed(error: AppError, mut backlight: Backlight) -> ! {
+    // SAFETY: see `display_init_succeeded`.
+    let attempts = unsafe {
+        DISPLAY_INIT_ATTEMPTS = DISPLAY_INIT_ATTEMPTS.saturating_add(1);
+        DISPLAY_INIT_ATTEMPTS
+    };
+
+    error!(
+        "display unusable (attempt {}/{}): {}",
+        attempts, MAX_DISPLAY_INIT_ATTEMPTS, error
+    );
+
+    let code = error.subsystem().blink_code();
+
+    if attempts < MAX_DISPLAY_INIT_ATTEMPTS {
+        for _ in 0..BLINK_CODE_REPEATS {
+            blink_code(&mut backlight, code).await;
+        }
+
+        warn!("rebooting to retry display initialization");
+        esp_hal::system::software_reset();
+    }
+
+    loop {
+        blink_code(&mut backlight, code).await;
+    }
+}
+
+async fn blink_code(backlight: &mut Backlight, code: u8) {
+    for _ in 0..code {
+        backlight.set_brightness_pct(100);
+        Timer::after(BLINK_ON).await;
+        backlight.set_brightness_pct(0);
+        Timer::after(BLINK_OFF).await;
+    }
+
+    Timer::after(BLINK_PAUSE).await;
+}
@@ -0,0 +1,322 @@
+//! Results of completed test sessions, kept in a [`RecordLog`] in the `history` flash partition.
+//!
+//! When the partition is full, the oldest results are dropped a sector at a time.
+
+use core::fmt;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::{
+    kbd::{Key, KeySet, N_KEYS},
+    storage::{self, Cursor, RecordLog},
+};
+
+#[cfg(target_os = "none")]
+mod flash;
+
+#[cfg(target_os = "none")]
+pub use self::flash::{
+    append, browse, count, init, is_available, newer, newest_first, older, read_next,
+};
+
+/// Format of the record payload.
+const VERSION: u8 = 1;
+const PAYLOAD_LEN: usize = 4 + 2 + N_KEYS + 4 + 3;
+
+/// Size of the history records in flash.
+pub const SLOT_SIZE: usize = 64;
+
+/// Results a [`Browser`] can step back to without reading the log from the newest result again.
+const TRAIL_LEN: usize = 16;
+
+/// Version of the running firmware as stored in the results.
+pub const FIRMWARE_VERSION: [u8; 3] = [
+    parse_u8(env!("CARGO_PKG_VERSION_MAJOR")),
+    parse_u8(env!("CARGO_PKG_VERSION_MINOR")),
+    parse_u8(env!("CARGO_PKG_VERSION_PATCH")),
+];
+
+/// Line preceding the CSV export, followed by the format version.
+pub const EXPORT_BEGIN: &str = "#keyvisor-history 1";
+/// Line following the CSV export.
+pub const EXPORT_END: &str = "#end";
+
+/// First line of the CSV export, naming the columns written by [`TestResult::write_csv`].
+pub const CSV_HEADER: &str = "board,result,failed_keys,chatter,uptime_ms,firmware";
+
+#[derive(Clone, Debug, defmt::Format, PartialEq, Eq)]
+pub struct TestResult {
+    /// ID entered on the keypad before testing.
+    pub board_id: u32,
+    /// Keys that were pressed and released.
+    #[defmt(Debug2Format)]
+    pub passed: KeySet,
+    /// Number of chattering presses per key, see [`crate::session::CHATTER_WINDOW`].
+    pub chatter: [u8; N_KEYS],
+    /// Time since boot when the session ended.
+    pub uptime_ms: u32,
+    pub firmware: [u8; 3],
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.passed[..N_KEYS].all()
+    }
+
+    pub fn failed_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        Key::all().filter(|key| !self.passed[key.index()])
+    }
+
+    pub fn total_chatter(&self) -> u32 {
+        self.chatter.iter().map(|&count| u32::from(count)).sum()
+    }
+
+    pub fn encode(&self) -> [u8; PAYLOAD_LEN] {
+        let mut payload = [0; PAYLOAD_LEN];
+        payload[0..4].copy_from_slice(&self.board_id.to_le_bytes());
+        payload[4..6].copy_from_slice(&self.passed.into_inner()[0].to_le_bytes());
+        payload[6..6 + N_KEYS].copy_from_slice(&self.chatter);
+        payload[6 + N_KEYS..10 + N_KEYS].copy_from_slice(&self.uptime_ms.to_le_bytes());
+        payload[10 + N_KEYS..].copy_from_slice(&self.firmware);
+        payload
+    }
+
+    /// Decodes a record payload. Returns `None` for unknown versions and truncated payloads.
+    pub fn decode(version: u8, payload: &[u8]) -> Option<Self> {
+        if version != VERSION || payload.len() < PAYLOAD_LEN {
+            return None;
+        }
+
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                payload[offset],
+                payload[offset + 1],
+                payload[offset + 2],
+                payload[offset + 3],
+            ])
+        };
+
+        let mut chatter = [0; N_KEYS];
+        chatter.copy_from_slice(&payload[6..6 + N_KEYS]);
+
+        Some(Self {
+            board_id: u32_at(0),
+            passed: KeySet::new([u16::from_le_bytes([payload[4], payload[5]])]),
+            chatter,
+            uptime_ms: u32_at(6 + N_KEYS),
+            firmware: [
+                payload[10 + N_KEYS],
+                payload[11 + N_KEYS],
+                payload[12 + N_KEYS],
+            ],
+        })
+    }
+
+    /// Writes the labels of the failed keys, separated by spaces.
+    pub fn write_failed_keys(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        for (i, key) in self.failed_keys().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(w, "{separator}{}", key.char())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the result as a line of CSV, without the line break. Failed keys are listed by
+    /// their labels and chatter as `<label>:<count>` for the affected keys, both separated by
+    /// spaces.
+    pub fn write_csv(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        write!(
+            w,
+            "{},{},",
+            self.board_id,
+            if self.passed() { "pass" } else { "fail" }
+        )?;
+
+        self.write_failed_keys(w)?;
+        w.write_char(',')?;
+
+        let chattering = Key::all().filter(|key| self.chatter[key.index()] > 0);
+        for (i, key) in chattering.enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(w, "{separator}{}:{}", key.char(), self.chatter[key.index()])?;
+        }
+
+        let [major, minor, patch] = self.firmware;
+        write!(w, ",{},{major}.{minor}.{patch}", self.uptime_ms)
+    }
+}
+
+/// Position while browsing the results one at a time, see [`HistoryLog::browse`].
+#[derive(Clone)]
+pub struct Browser {
+    /// Index of the current result, 0 for the newest.
+    index: usize,
+    result: TestResult,
+    /// Cursor at the result after the current one.
+    next: Cursor,
+    /// Cursors at the results shown recently, by their index modulo [`TRAIL_LEN`].
+    trail: [Option<(usize, Cursor)>; TRAIL_LEN],
+}
+
+impl Browser {
+    /// Index of the current result, 0 for the newest.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn result(&self) -> &TestResult {
+        &self.result
+    }
+
+    fn cursor_at(&self, index: usize) -> Option<Cursor> {
+        match &self.trail[index % TRAIL_LEN] {
+            Some((at, cursor)) if *at == index => Some(cursor.clone()),
+            _ => None,
+        }
+    }
+
+    fn remember(&mut self, index: usize, cursor: Cursor) {
+        self.trail[index % TRAIL_LEN] = Some((index, cursor));
+    }
+}
+
+/// Test results in flash.
+pub struct HistoryLog<F> {
+    log: RecordLog<F, SLOT_SIZE>,
+}
+
+impl<F: NorFlash> HistoryLog<F> {
+    pub fn open(flash: F) -> Result<Self, storage::Error<F::Error>> {
+        Ok(Self {
+            log: RecordLog::open(flash)?,
+        })
+    }
+
+    pub fn append(&mut self, result: &TestResult) -> Result<(), storage::Error<F::Error>> {
+        self.log.append(VERSION, &result.encode())
+    }
+
+    /// Returns a cursor for [`read_next`](Self::read_next), starting at the newest result.
+    pub fn newest_first(&self) -> Cursor {
+        self.log.newest_first()
+    }
+
+    /// Reads the result at the cursor and moves the cursor to the next older one. Results
+    /// written by incompatible firmware versions are skipped.
+    pub fn read_next(
+        &mut self,
+        cursor: &mut Cursor,
+    ) -> Result<Option<TestResult>, storage::Error<F::Error>> {
+        let mut buf = [0; SLOT_SIZE];
+
+        while let Some(record) = self.log.read_next(cursor, &mut buf)? {
+            if let Some(result) = TestResult::decode(record.version, record.payload) {
+                return Ok(Some(result));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Counts the results. Reads the whole log.
+    pub fn count(&mut self) -> Result<usize, storage::Error<F::Error>> {
+        let mut cursor = self.newest_first();
+        let mut count = 0;
+
+        while self.read_next(&mut cursor)?.is_some() {
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Starts browsing at the newest result, or returns `None` if there is none.
+    pub fn browse(&mut self) -> Result<Option<Browser>, storage::Error<F::Error>> {
+        let start = self.newest_first();
+        let mut next = start.clone();
+        let Some(result) = self.read_next(&mut next)? else {
+            return Ok(None);
+        };
+
+        let mut browser = Browser {
+            index: 0,
+            result,
+            next,
+            trail: [const { None }; TRAIL_LEN],
+        };
+        browser.remember(0, start);
+        Ok(Some(browser))
+    }
+
+    /// Moves to the next older result. Returns `false` if the current one is the oldest.
+    pub fn older(&mut self, browser: &mut Browser) -> Result<bool, storage::Error<F::Error>> {
+        let mut next = browser.next.clone();
+        let Some(result) = self.read_next(&mut next)? else {
+            return Ok(false);
+        };
+
+        let at = core::mem::replace(&mut browser.next, next);
+        browser.index += 1;
+        browser.result = result;
+        browser.remember(browser.index, at);
+        Ok(true)
+    }
+
+    /// Moves to the next newer result. Returns `false` if the current one is the newest. Reads
+    /// the log from the newest result if the newer one wasn't shown recently.
+    pub fn newer(&mut self, browser: &mut Browser) -> Result<bool, storage::Error<F::Error>> {
+        let Some(index) = browser.index.checked_sub(1) else {
+            return Ok(false);
+        };
+
+        let at = match browser.cursor_at(index) {
+            Some(cursor) => cursor,
+            None => {
+                let mut cursor = self.newest_first();
+                for i in 0..index {
+                    browser.remember(i, cursor.clone());
+                    if self.read_next(&mut cursor)?.is_none() {
+                        return Ok(false);
+                    }
+                }
+                cursor
+            }
+        };
+        let mut next = at.clone();
+        let Some(result) = self.read_next(&mut next)? else {
+            return Ok(false);
+        };
+
+        browser.index = index;
+        browser.result = result;
+        browser.next = next;
+        browser.remember(index, at);
+        Ok(true)
+    }
+
+    /// Reads the `n`-th newest result, starting at 0.
+    pub fn nth_newest(&mut self, n: usize) -> Result<Option<TestResult>, storage::Error<F::Error>> {
+        let mut cursor = self.newest_first();
+
+        for _ in 0..n {
+            if self.read_next(&mut cursor)?.is_none() {
+                return Ok(None);
+            }
+        }
+
+        self.read_next(&mut cursor)
+    }
+}
+
+const fn parse_u8(s: &str) -> u8 {
+    let bytes = s.as_bytes();
+    let mut value = 0u8;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0');
+        i += 1;
+    }
+
+    value
+}
@@ -0,0 +1,93 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+
+use super::{Browser, HistoryLog, TestResult};
+use crate::{
+    error::{AppError, Context, ResultExt as _},
+    storage::{Cursor, Partition, Unavailable},
+};
+
+const PARTITION_LABEL: &str = "history";
+
+static STORE: Mutex<CriticalSectionRawMutex, RefCell<Option<HistoryLog<Partition>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Opens the history partition. Without it, results can't be saved.
+///
+/// Requires [`crate::storage::init`].
+pub fn init() {
+    let Some(partition) = Partition::find(PARTITION_LABEL) else {
+        warn!(
+            "no `{=str}` partition, results won't be saved",
+            PARTITION_LABEL
+        );
+        return;
+    };
+
+    match HistoryLog::open(partition).context(Context::HistoryLoad) {
+        Ok(log) => {
+            info!("opened test result history");
+            STORE.lock(|cell| cell.replace(Some(log)));
+        }
+        Err(error) => warn!("results won't be saved: {}", error),
+    }
+}
+
+/// Whether results are saved, which they aren't without the partition, see [`init`].
+pub fn is_available() -> bool {
+    STORE.lock(|cell| cell.borrow().is_some())
+}
+
+fn with_log<R>(
+    context: Context,
+    f: impl FnOnce(&mut HistoryLog<Partition>) -> Result<R, AppError>,
+) -> Result<R, AppError> {
+    STORE.lock(|cell| {
+        let mut log = cell.borrow_mut();
+        f(log.as_mut().ok_or(Unavailable).context(context)?)
+    })
+}
+
+pub fn append(result: &TestResult) -> Result<(), AppError> {
+    with_log(Context::HistorySave, |log| {
+        log.append(result).context(Context::HistorySave)
+    })
+}
+
+pub fn count() -> Result<usize, AppError> {
+    with_log(Context::HistoryLoad, |log| {
+        log.count().context(Context::HistoryLoad)
+    })
+}
+
+/// Starts browsing at the newest result, see [`HistoryLog::browse`].
+pub fn browse() -> Result<Option<Browser>, AppError> {
+    with_log(Context::HistoryLoad, |log| {
+        log.browse().context(Context::HistoryLoad)
+    })
+}
+
+pub fn older(browser: &mut Browser) -> Result<bool, AppError> {
+    with_log(Context::HistoryLoad, |log| {
+        log.older(browser).context(Context::HistoryLoad)
+    })
+}
+
+pub fn newer(browser: &mut Browser) -> Result<bool, AppError> {
+    with_log(Context::HistoryLoad, |log| {
+        log.newer(browser).context(Context::HistoryLoad)
+    })
+}
+
+pub fn newest_first() -> Result<Cursor, AppError> {
+    with_log(Context::HistoryLoad, |log| Ok(log.newest_first()))
+}
+
+/// Reads the result at the cursor and moves the cursor to the next older one.
+pub fn read_next(cursor: &mut Cursor) -> Result<Option<TestResult>, AppError> {
+    with_log(Context::HistoryLoad, |log| {
+        log.read_next(cursor).context(Context::HistoryLoad)
+    })
+}
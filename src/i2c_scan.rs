@@ -0,0 +1,142 @@
+//! Scanning the I2C bus of the board under test for devices and telling them apart.
+//!
+//! Each address is probed with a one-byte read, which devices answer without side effects. The
+//! devices that answer are named by their address, with a few registers read where chips share
+//! addresses. Only register pointers are written, so nothing on the board is reconfigured.
+
+use core::ops::RangeInclusive;
+
+use defmt::Format;
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c};
+
+use crate::{kbd::backend::ExpanderChip, key_leds::LedDriverChip};
+
+#[cfg(target_os = "none")]
+mod bus;
+
+#[cfg(target_os = "none")]
+pub use self::bus::open_bus;
+
+/// Addresses devices can have, the others being reserved.
+pub const ADDRESSES: RangeInclusive<u8> = 0x08..=0x77;
+
+/// Most devices a scan reports. More than that answering usually means SDA is stuck low.
+pub const MAX_DEVICES: usize = 16;
+
+/// Chip ID register of the Bosch pressure sensors.
+const BOSCH_CHIP_ID: u8 = 0xd0;
+const BME280_ID: u8 = 0x60;
+const BMP280_ID: u8 = 0x58;
+
+/// A device that answered, as far as it could be identified.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum Device {
+    /// An SSD1306 or a compatible OLED controller.
+    Oled,
+    Expander(ExpanderChip),
+    /// An I/O expander without the registers of the known ones, like a PCF8574.
+    OtherExpander,
+    /// A 24Cxx EEPROM, or an IS31FL3733 strapped to one of the addresses they share.
+    EepromOrLedDriver,
+    LedDriver(LedDriverChip),
+    Bme280,
+    Bmp280,
+    Unknown,
+}
+
+impl Device {
+    pub fn name(self) -> &'static str {
+        match self {
+            Device::Oled => "SSD1306 OLED",
+            Device::Expander(ExpanderChip::Mcp23017) => "MCP23017 expander",
+            Device::Expander(ExpanderChip::Pca9555) => "PCA9555 expander",
+            Device::OtherExpander => "I/O expander",
+            Device::EepromOrLedDriver => "EEPROM/IS31FL3733",
+            Device::LedDriver(chip) => chip.name(),
+            Device::Bme280 => "BME280 sensor",
+            Device::Bmp280 => "BMP280 sensor",
+            Device::Unknown => "Unknown",
+        }
+    }
+}
+
+/// Why a scan gave up.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum ScanError {
+    /// The bus failed rather than an address going unanswered, e.g. for lack of pull-ups.
+    Bus(ErrorKind),
+    /// More than [`MAX_DEVICES`] answered.
+    TooManyDevices,
+}
+
+/// The devices that answered a scan, by address.
+#[derive(Clone, Debug)]
+pub struct Scan {
+    devices: [(u8, Device); MAX_DEVICES],
+    len: usize,
+}
+
+impl Scan {
+    pub fn devices(&self) -> &[(u8, Device)] {
+        &self.devices[..self.len]
+    }
+}
+
+/// Probes every address of [`ADDRESSES`] and identifies the devices that answer.
+pub fn scan<I: I2c>(i2c: &mut I) -> Result<Scan, ScanError> {
+    let mut scan = Scan {
+        devices: [(0, Device::Unknown); MAX_DEVICES],
+        len: 0,
+    };
+    for address in ADDRESSES {
+        match i2c.read(address, &mut [0]) {
+            Ok(()) => {
+                let found = scan
+                    .devices
+                    .get_mut(scan.len)
+                    .ok_or(ScanError::TooManyDevices)?;
+                *found = (address, identify(i2c, address));
+                scan.len += 1;
+            }
+            Err(error) if matches!(error.kind(), ErrorKind::NoAcknowledge(_)) => {}
+            Err(error) => return Err(ScanError::Bus(error.kind())),
+        }
+    }
+    Ok(scan)
+}
+
+/// Names the device that answered at `address`.
+pub fn identify<I: I2c>(i2c: &mut I, address: u8) -> Device {
+    match address {
+        0x3c | 0x3d => Device::Oled,
+        0x20..=0x27 => identify_expander(i2c, address),
+        0x50..=0x57 => Device::EepromOrLedDriver,
+        // The sensors share addresses with the IS31FL3731, which has no chip ID.
+        0x76 | 0x77 => match read_registers(i2c, address, BOSCH_CHIP_ID) {
+            Some([BME280_ID, _]) => Device::Bme280,
+            Some([BMP280_ID, _]) => Device::Bmp280,
+            _ => Device::LedDriver(LedDriverChip::Is31fl3731),
+        },
+        _ => LedDriverChip::at_address(address).map_or(Device::Unknown, Device::LedDriver),
+    }
+}
+
+/// Tells the expanders apart by the registers that make all pins inputs after a reset: the
+/// PCA9555's configuration registers at 6 and 7, and the MCP23017's IODIRA and IODIRB at 0 and 1.
+/// The MCP23017 has DEFVALA and DEFVALB at 6 and 7, which reset to 0.
+fn identify_expander<I: I2c>(i2c: &mut I, address: u8) -> Device {
+    if read_registers(i2c, address, 0x06) == Some([0xff; 2]) {
+        Device::Expander(ExpanderChip::Pca9555)
+    } else if read_registers(i2c, address, 0x00) == Some([0xff; 2]) {
+        Device::Expander(ExpanderChip::Mcp23017)
+    } else {
+        Device::OtherExpander
+    }
+}
+
+/// Reads the registers from `register` on, which the chips increment.
+fn read_registers<I: I2c>(i2c: &mut I, address: u8, register: u8) -> Option<[u8; 2]> {
+    let mut values = [0; 2];
+    i2c.write_read(address, &[register], &mut values).ok()?;
+    Some(values)
+}
@@ -0,0 +1,20 @@
+use esp_hal::{
+    Blocking,
+    gpio::AnyPin,
+    i2c::master::{Config, I2c},
+    peripherals::I2C0,
+    time::Rate,
+};
+
+/// Opens the bus on `sda` and `scl` at 100 kHz, which every device supports.
+pub fn open_bus<'a>(
+    i2c: &'a mut I2C0<'static>,
+    sda: &'a mut AnyPin<'static>,
+    scl: &'a mut AnyPin<'static>,
+) -> I2c<'a, Blocking> {
+    let config = Config::default().with_frequency(Rate::from_khz(100));
+    I2c::new(i2c.reborrow(), config)
+        .expect("invalid scan I2C config")
+        .with_sda(sda.reborrow())
+        .with_scl(scl.reborrow())
+}
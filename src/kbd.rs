@@ -1,23 +1,45 @@
 use bitvec::prelude::*;
-use defmt::{Format, debug, info, trace};
-use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex,
-    pubsub::{DynSubscriber, PubSubChannel},
+use defmt::Format;
+
+pub mod analog;
+pub mod backend;
+mod debounce;
+pub mod latency;
+pub mod recording;
+#[cfg(target_os = "none")]
+mod scan;
+#[cfg(target_os = "none")]
+mod soft_spi;
+
+pub use self::debounce::{ColumnState, ColumnUpdateResult, Debouncer, TickCount};
+#[cfg(target_os = "none")]
+pub use self::scan::{
+    KeyboardInterface, analyse_bounce, next_capture, subscriber, task, with_analog_keys,
+    with_latency_meter, with_recorder,
 };
-use embassy_time::{Duration, Ticker, Timer};
-use esp_hal::gpio::{AnyPin, DriveMode, Input, InputConfig, Level, Output, OutputConfig, Pull};
 
-use crate::error::AppError;
+/// Rate at which the whole matrix is read.
+pub const SCAN_SPEED_HZ: u64 = 400;
 
 pub const N_COLS: usize = 3;
 pub const N_ROWS: usize = 4;
+pub const N_KEYS: usize = N_COLS * N_ROWS;
 
-const SCAN_SPEED_HZ: u64 = 400;
-const SCAN_READ_DELAY_MICROS: u64 = 2;
-const DEBOUNCE_TICKS: TickCount = 10;
+/// A set of keys, indexed by [`Key::index`].
+pub type KeySet = BitArr!(for N_KEYS, in u16);
 
-type ColumnState = BitArr!(for N_ROWS, in u8);
-type TickCount = u8;
+/// Arrangement of the keys on the board under test, selected by
+/// [`Settings::layout`](crate::settings::Settings::layout).
+#[derive(Debug, Format)]
+pub struct Layout {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub const LAYOUTS: &[Layout] = &[Layout {
+    name: "phone",
+    description: "3x4 keypad, 1 2 3 at the top",
+}];
 
 #[derive(Copy, Clone, Debug, Format, PartialEq, Eq)]
 pub struct Key {
@@ -26,6 +48,33 @@ pub struct Key {
 }
 
 impl Key {
+    /// All keys, row by row.
+    pub fn all() -> impl Iterator<Item = Key> {
+        (0..N_KEYS).map(Key::from_index)
+    }
+
+    /// Position of the key when counting row by row.
+    pub fn index(self) -> usize {
+        self.row as usize * N_COLS + self.col as usize
+    }
+
+    pub fn from_index(index: usize) -> Key {
+        Key {
+            col: (index % N_COLS) as u8,
+            row: (index / N_COLS) as u8,
+        }
+    }
+
+    /// Key of a duplex matrix at the crossing of column `col` and row pin `pin`. Each crossing
+    /// carries the key of an even row, read while the column is driven low, and the key of the
+    /// odd row below it, read while the row pin is driven low.
+    pub fn duplex(col: usize, pin: usize, column_driven: bool) -> Key {
+        Key {
+            col: col as u8,
+            row: (2 * pin + usize::from(!column_driven)) as u8,
+        }
+    }
+
     pub fn char(self) -> char {
         match (self.col, self.row) {
             (0, 0) => '1',
@@ -45,168 +94,8 @@ impl Key {
     }
 }
 
-#[derive(Clone, Copy, Debug, Format)]
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
 pub enum KeyEvent {
     KeyDown(Key),
     KeyUp(Key),
 }
-
-static CHANNEL: PubSubChannel<CriticalSectionRawMutex, KeyEvent, 32, 1, 1> = PubSubChannel::new();
-
-pub fn subscriber() -> Result<DynSubscriber<'static, KeyEvent>, AppError> {
-    CHANNEL.dyn_subscriber().map_err(<_>::into)
-}
-
-pub struct KeyboardInterface<'p> {
-    columns: [Output<'p>; N_COLS],
-    rows: [Input<'p>; N_ROWS],
-}
-
-impl<'p> KeyboardInterface<'p> {
-    pub fn new(columns: [AnyPin<'p>; N_COLS], rows: [AnyPin<'p>; N_ROWS]) -> Self {
-        Self {
-            columns: columns.map(|pin| {
-                Output::new(
-                    pin,
-                    Level::High,
-                    OutputConfig::default().with_drive_mode(DriveMode::OpenDrain),
-                )
-            }),
-            rows: rows.map(|pin| Input::new(pin, InputConfig::default().with_pull(Pull::Up))),
-        }
-    }
-
-    fn clear(&mut self) {
-        for column in &mut self.columns {
-            column.set_high();
-        }
-    }
-
-    fn scan(&mut self, col: usize) {
-        self.clear();
-        self.columns[col].set_low();
-    }
-
-    fn read(&mut self) -> ColumnState {
-        let mut col = ColumnState::ZERO;
-        for (i, row) in self.rows.iter().enumerate() {
-            col.set(i, row.is_low());
-        }
-        col
-    }
-}
-
-struct ColumnUpdate<'a> {
-    stable_state: &'a mut ColumnState,
-    staging_state: &'a mut ColumnState,
-    tick_counts: &'a mut [TickCount; N_ROWS],
-}
-
-#[derive(Default, Format)]
-struct ColumnUpdateResult {
-    #[defmt(Debug2Format)]
-    pressed_keys: ColumnState,
-    #[defmt(Debug2Format)]
-    released_keys: ColumnState,
-}
-
-impl<'a> ColumnUpdate<'a> {
-    fn new(
-        stable_state: &'a mut ColumnState,
-        staging_state: &'a mut ColumnState,
-        tick_counts: &'a mut [TickCount; N_ROWS],
-    ) -> Self {
-        Self {
-            stable_state,
-            staging_state,
-            tick_counts,
-        }
-    }
-
-    fn apply(&mut self, new_state: ColumnState) -> ColumnUpdateResult {
-        let mut result = ColumnUpdateResult::default();
-
-        for r in 0..N_ROWS {
-            if new_state[r] != self.staging_state[r] {
-                self.tick_counts[r] = 0;
-                self.staging_state.set(r, new_state[r]);
-                continue;
-            }
-
-            if self.tick_counts[r] < DEBOUNCE_TICKS {
-                self.tick_counts[r] += 1;
-                continue;
-            }
-
-            if new_state[r] == self.stable_state[r] {
-                continue;
-            }
-
-            self.stable_state.set(r, new_state[r]);
-
-            if new_state[r] {
-                result.pressed_keys.set(r, true);
-            } else {
-                result.released_keys.set(r, true);
-            }
-        }
-
-        result
-    }
-}
-
-impl ColumnUpdateResult {
-    fn any(&self) -> bool {
-        (self.pressed_keys | self.released_keys).any()
-    }
-}
-
-#[embassy_executor::task]
-pub async fn task(mut kbd: KeyboardInterface<'static>) {
-    info!("starting kbd task");
-
-    let mut ticker = Ticker::every(Duration::from_hz(SCAN_SPEED_HZ));
-
-    let mut stable_states = [ColumnState::ZERO; N_COLS];
-    let mut staging_states = [ColumnState::ZERO; N_COLS];
-    let mut tick_counts = [[0u8; N_ROWS]; N_COLS];
-
-    loop {
-        for col in 0..N_COLS {
-            kbd.scan(col);
-            Timer::after_micros(SCAN_READ_DELAY_MICROS).await;
-            let mask = kbd.read();
-
-            trace!("col {} mask: {}", col, mask.into_inner()[0]);
-
-            let updates = ColumnUpdate::new(
-                &mut stable_states[col],
-                &mut staging_states[col],
-                &mut tick_counts[col],
-            )
-            .apply(mask);
-
-            if updates.any() {
-                debug!("col {} updates: {:?}", col, updates);
-
-                let publisher = CHANNEL.immediate_publisher();
-
-                for row in updates.released_keys.iter_ones() {
-                    publisher.publish_immediate(KeyEvent::KeyUp(Key {
-                        col: col as u8,
-                        row: row as u8,
-                    }));
-                }
-
-                for row in updates.pressed_keys.iter_ones() {
-                    publisher.publish_immediate(KeyEvent::KeyDown(Key {
-                        col: col as u8,
-                        row: row as u8,
-                    }));
-                }
-            }
-        }
-
-        ticker.next().await;
-    }
-}
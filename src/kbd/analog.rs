@@ -0,0 +1,161 @@
+//! Keys with a Hall-effect sensor, whose travel is measured instead of a contact read.
+//!
+//! A sensor reads a steady value while its key is at rest, which changes as the magnet in the
+//! key comes closer. Whether the reading rises or falls depends on the sensor and on which way
+//! the magnet faces, so each key is calibrated by taking its first reading as the rest value and
+//! the reading furthest from it as the bottom-out value. A key is pressed once its travel passes
+//! the actuation point, and the pressed keys go through the same debouncing as a matrix.
+
+use defmt::Format;
+
+use super::{ColumnState, Key, KeySet, N_COLS, N_KEYS};
+
+/// Travel of a key pressed all the way down. Travel is measured in thousandths of this.
+pub const FULL_TRAVEL: u16 = 1000;
+
+/// Smallest difference between the rest and bottom-out readings of a calibrated key. Smaller
+/// changes are taken for noise.
+pub const MIN_SPAN: u16 = 100;
+
+/// How far a pressed key has to come back above the actuation point to be released, so that
+/// noise at the actuation point doesn't make it chatter.
+pub const RELEASE_HYSTERESIS: u16 = 50;
+
+/// Readings of a key's sensor at rest and pressed all the way down.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub struct Calibration {
+    pub rest: u16,
+    pub bottom_out: u16,
+}
+
+impl Calibration {
+    /// Starts calibrating a key that gives `reading` at rest.
+    pub fn at_rest(reading: u16) -> Self {
+        Self {
+            rest: reading,
+            bottom_out: reading,
+        }
+    }
+
+    /// Takes `reading` as the bottom-out value if it's further from rest than the current one.
+    pub fn update(&mut self, reading: u16) {
+        if reading.abs_diff(self.rest) > self.span() {
+            self.bottom_out = reading;
+        }
+    }
+
+    /// Difference between the rest and bottom-out readings.
+    pub fn span(&self) -> u16 {
+        self.rest.abs_diff(self.bottom_out)
+    }
+
+    /// Tells whether the key went down far enough to tell its travel from noise.
+    pub fn is_complete(&self) -> bool {
+        self.span() >= MIN_SPAN
+    }
+
+    /// Travel of the key at `reading`, or `None` until the calibration is complete. Readings
+    /// beyond rest or bottom-out are clamped.
+    pub fn travel(&self, reading: u16) -> Option<u16> {
+        if !self.is_complete() {
+            return None;
+        }
+        let moved = if self.bottom_out > self.rest {
+            reading.saturating_sub(self.rest)
+        } else {
+            self.rest.saturating_sub(reading)
+        };
+        let span = u32::from(self.span());
+        Some((u32::from(moved).min(span) * u32::from(FULL_TRAVEL) / span) as u16)
+    }
+}
+
+/// Calibration and state of the analog keys, updated on each scan.
+#[derive(Clone)]
+pub struct AnalogKeys {
+    /// Indexed by [`Key::index`], `None` until the key has been read.
+    calibrations: [Option<Calibration>; N_KEYS],
+    readings: [u16; N_KEYS],
+    pressed: KeySet,
+}
+
+impl Default for AnalogKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalogKeys {
+    pub const fn new() -> Self {
+        Self {
+            calibrations: [None; N_KEYS],
+            readings: [0; N_KEYS],
+            pressed: KeySet::ZERO,
+        }
+    }
+
+    /// Forgets the calibration of every key. The next readings are taken as the rest values, so
+    /// the keys should be released.
+    pub fn recalibrate(&mut self) {
+        self.calibrations = [None; N_KEYS];
+        self.pressed = KeySet::ZERO;
+    }
+
+    /// Takes the readings of a scan, in the order of [`Key::index`], and returns the keys whose
+    /// travel passed `actuation` the way a matrix would read them. A key whose sensor couldn't be
+    /// read keeps its previous reading and state.
+    pub fn update(
+        &mut self,
+        readings: [Option<u16>; N_KEYS],
+        actuation: u16,
+    ) -> [ColumnState; N_COLS] {
+        let release_below = actuation.saturating_sub(RELEASE_HYSTERESIS);
+        let mut tick = [ColumnState::ZERO; N_COLS];
+
+        for (i, &reading) in readings.iter().enumerate() {
+            if let Some(reading) = reading {
+                let calibration = self.calibrations[i].get_or_insert(Calibration::at_rest(reading));
+                calibration.update(reading);
+
+                let travel = calibration.travel(reading).unwrap_or(0);
+                let pressed = if self.pressed[i] {
+                    travel >= release_below
+                } else {
+                    travel >= actuation
+                };
+                self.pressed.set(i, pressed);
+                self.readings[i] = reading;
+            }
+
+            let key = Key::from_index(i);
+            tick[usize::from(key.col)].set(usize::from(key.row), self.pressed[i]);
+        }
+
+        tick
+    }
+
+    /// Latest reading of the key's sensor.
+    pub fn reading(&self, key: Key) -> u16 {
+        self.readings[key.index()]
+    }
+
+    pub fn calibration(&self, key: Key) -> Option<Calibration> {
+        self.calibrations[key.index()]
+    }
+
+    /// Travel of the key at its latest reading, or `None` while it isn't calibrated.
+    pub fn travel(&self, key: Key) -> Option<u16> {
+        self.calibration(key)?.travel(self.reading(key))
+    }
+
+    /// Tells whether the key is past the actuation point, before debouncing.
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.pressed[key.index()]
+    }
+}
+
+/// Converts an actuation point in percent, as in the
+/// [settings](crate::settings::Settings::actuation_pct), to thousandths of the travel.
+pub fn actuation_from_pct(pct: u8) -> u16 {
+    u16::from(pct) * (FULL_TRAVEL / 100)
+}
@@ -0,0 +1,296 @@
+//! Hardware that drives the columns of a key matrix and reads its rows.
+//!
+//! Besides the tester's own GPIOs, the matrix can be driven through shift registers or an I/O
+//! expander on the keypad's board. Each [`MatrixBackend`] selects one column at a time by pulling
+//! it low and reports the rows that read low.
+
+use defmt::Format;
+use embedded_hal::{
+    digital::{InputPin, OutputPin},
+    i2c::I2c,
+    spi::SpiBus,
+};
+
+use super::{ColumnState, Key, N_COLS, N_ROWS};
+
+pub trait MatrixBackend {
+    type Error;
+
+    /// Prepares the hardware. Called again after a read failed, e.g. because the keypad was
+    /// disconnected.
+    fn init(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Drives column `col` low and releases the others.
+    fn select_column(&mut self, col: usize) -> Result<(), Self::Error>;
+
+    /// Reads the rows of the selected column, set for the rows that read low.
+    fn read_rows(&mut self) -> Result<ColumnState, Self::Error>;
+}
+
+/// Mask of the rows in an 8-bit port whose bits follow the rows.
+const ROW_BITS: u8 = (1 << N_ROWS) - 1;
+
+/// Rows that read low in an 8-bit port whose bits follow the rows.
+fn rows_low(port: u8) -> ColumnState {
+    ColumnState::new([!port & ROW_BITS])
+}
+
+/// Columns and rows on GPIOs. The column pins are expected to be open-drain, with the rows pulled
+/// up.
+pub struct GpioMatrix<C, R> {
+    columns: [C; N_COLS],
+    rows: [R; N_ROWS],
+}
+
+impl<C, R> GpioMatrix<C, R> {
+    pub fn new(columns: [C; N_COLS], rows: [R; N_ROWS]) -> Self {
+        Self { columns, rows }
+    }
+
+    /// Pin of row `row`, e.g. to wait for it to change.
+    pub fn row_mut(&mut self, row: usize) -> &mut R {
+        &mut self.rows[row]
+    }
+}
+
+impl<C, R> MatrixBackend for GpioMatrix<C, R>
+where
+    C: OutputPin,
+    R: InputPin<Error = C::Error>,
+{
+    type Error = C::Error;
+
+    fn select_column(&mut self, col: usize) -> Result<(), Self::Error> {
+        for column in &mut self.columns {
+            column.set_high()?;
+        }
+        self.columns[col].set_low()
+    }
+
+    fn read_rows(&mut self) -> Result<ColumnState, Self::Error> {
+        let mut mask = ColumnState::ZERO;
+        for (row, pin) in self.rows.iter_mut().enumerate() {
+            mask.set(row, pin.is_low()?);
+        }
+        Ok(mask)
+    }
+}
+
+/// Lines of a [`DuplexMatrix`] driven low in turn while scanning: the columns, then the row pins.
+pub const DUPLEX_LINES: usize = N_COLS + N_ROWS / 2;
+
+/// A duplex matrix on GPIOs, see [`Key::duplex`]. Every line is open-drain and pulled up, and is
+/// read while one of the others is driven low.
+///
+/// Not a [`MatrixBackend`], since its keys are read by driving the rows as well.
+pub struct DuplexMatrix<P> {
+    columns: [P; N_COLS],
+    rows: [P; N_ROWS / 2],
+}
+
+impl<P: InputPin + OutputPin> DuplexMatrix<P> {
+    pub fn new(columns: [P; N_COLS], rows: [P; N_ROWS / 2]) -> Self {
+        Self { columns, rows }
+    }
+
+    /// Drives line `line` of [`DUPLEX_LINES`] low and releases all others.
+    pub fn select_line(&mut self, line: usize) -> Result<(), P::Error> {
+        self.release()?;
+        match line.checked_sub(N_COLS) {
+            None => self.columns[line].set_low(),
+            Some(pin) => self.rows[pin].set_low(),
+        }
+    }
+
+    /// Reads the keys of the selected `line` into `tick`.
+    pub fn read_line(
+        &mut self,
+        line: usize,
+        tick: &mut [ColumnState; N_COLS],
+    ) -> Result<(), P::Error> {
+        let mut set = |key: Key, closed| tick[usize::from(key.col)].set(key.row.into(), closed);
+        match line.checked_sub(N_COLS) {
+            None => {
+                for (pin, row) in self.rows.iter_mut().enumerate() {
+                    set(Key::duplex(line, pin, true), row.is_low()?);
+                }
+            }
+            Some(pin) => {
+                for (col, column) in self.columns.iter_mut().enumerate() {
+                    set(Key::duplex(col, pin, false), column.is_low()?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases every line.
+    pub fn release(&mut self) -> Result<(), P::Error> {
+        for line in self.columns.iter_mut().chain(&mut self.rows) {
+            line.set_high()?;
+        }
+        Ok(())
+    }
+
+    /// Drives the line that connects the contact of `key` and returns the pin that reads low
+    /// while it's closed. Leaves the line driven, see [`release`](Self::release).
+    pub fn select_key(&mut self, key: Key) -> Result<&mut P, P::Error> {
+        let col = usize::from(key.col);
+        let pin = usize::from(key.row) / 2;
+        if key.row.is_multiple_of(2) {
+            self.select_line(col)?;
+            Ok(&mut self.rows[pin])
+        } else {
+            self.select_line(N_COLS + pin)?;
+            Ok(&mut self.columns[col])
+        }
+    }
+}
+
+/// A 74HC595 driving the columns from its outputs Q0 to Q2, chained with a 74HC165 reading the
+/// rows on its inputs D0 to D3, which are pulled up.
+///
+/// Both share the SPI clock, with the 595's serial input on MOSI and the 165's serial output on
+/// MISO. The latch pin goes to the 595's RCLK and to the 165's SH/LD, so that pulsing it low
+/// loads the rows into the 165 while the rising edge updates the 595's outputs.
+pub struct ShiftRegisters<S, L> {
+    spi: S,
+    latch: L,
+    /// Byte shifted into the 595: all ones but the selected column.
+    columns: u8,
+}
+
+#[derive(Debug, Format)]
+pub enum ShiftRegisterError<S, L> {
+    Spi(S),
+    Latch(L),
+}
+
+impl<S: SpiBus, L: OutputPin> ShiftRegisters<S, L> {
+    pub fn new(spi: S, latch: L) -> Self {
+        Self {
+            spi,
+            latch,
+            columns: 0xff,
+        }
+    }
+
+    fn pulse_latch(&mut self) -> Result<(), ShiftRegisterError<S::Error, L::Error>> {
+        self.latch.set_low().map_err(ShiftRegisterError::Latch)?;
+        self.latch.set_high().map_err(ShiftRegisterError::Latch)
+    }
+}
+
+impl<S: SpiBus, L: OutputPin> MatrixBackend for ShiftRegisters<S, L> {
+    type Error = ShiftRegisterError<S::Error, L::Error>;
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        self.latch.set_high().map_err(ShiftRegisterError::Latch)
+    }
+
+    fn select_column(&mut self, col: usize) -> Result<(), Self::Error> {
+        self.columns = !(1 << col);
+        self.spi
+            .write(&[self.columns])
+            .map_err(ShiftRegisterError::Spi)?;
+        self.pulse_latch()
+    }
+
+    fn read_rows(&mut self) -> Result<ColumnState, Self::Error> {
+        self.pulse_latch()?;
+
+        // Shifting the rows out shifts the same columns in again, so the 595 keeps them on the
+        // next latch.
+        let mut rows = [0];
+        self.spi
+            .transfer(&mut rows, &[self.columns])
+            .map_err(ShiftRegisterError::Spi)?;
+        Ok(rows_low(rows[0]))
+    }
+}
+
+/// I/O expanders with two 8-bit ports, addressed over I2C.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum ExpanderChip {
+    Mcp23017,
+    Pca9555,
+}
+
+/// Registers of the ports used for the columns and rows.
+struct Registers {
+    column_direction: u8,
+    column_output: u8,
+    row_input: u8,
+    /// Pull-up enable of the row port, if the pull-ups aren't always on.
+    row_pull_up: Option<u8>,
+}
+
+impl ExpanderChip {
+    fn registers(self) -> Registers {
+        match self {
+            // Port A and B, with the register addresses of IOCON.BANK = 0.
+            ExpanderChip::Mcp23017 => Registers {
+                column_direction: 0x00,
+                column_output: 0x14,
+                row_input: 0x13,
+                row_pull_up: Some(0x0d),
+            },
+            // Port 0 and 1.
+            ExpanderChip::Pca9555 => Registers {
+                column_direction: 0x06,
+                column_output: 0x02,
+                row_input: 0x01,
+                row_pull_up: None,
+            },
+        }
+    }
+}
+
+/// An I/O expander with the columns on bits 0 to 2 of its first port and the rows on bits 0 to 3
+/// of its second port.
+///
+/// The column outputs are kept low and the selected column is switched to an output, so that the
+/// others float like open-drain outputs.
+pub struct Expander<I> {
+    i2c: I,
+    chip: ExpanderChip,
+    address: u8,
+}
+
+impl<I: I2c> Expander<I> {
+    pub fn new(i2c: I, chip: ExpanderChip, address: u8) -> Self {
+        Self { i2c, chip, address }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), I::Error> {
+        self.i2c.write(self.address, &[register, value])
+    }
+}
+
+impl<I: I2c> MatrixBackend for Expander<I> {
+    type Error = I::Error;
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        let registers = self.chip.registers();
+        self.write_register(registers.column_direction, 0xff)?;
+        self.write_register(registers.column_output, 0x00)?;
+        if let Some(pull_up) = registers.row_pull_up {
+            self.write_register(pull_up, ROW_BITS)?;
+        }
+        Ok(())
+    }
+
+    fn select_column(&mut self, col: usize) -> Result<(), Self::Error> {
+        // Set bits configure inputs.
+        self.write_register(self.chip.registers().column_direction, !(1 << col))
+    }
+
+    fn read_rows(&mut self) -> Result<ColumnState, Self::Error> {
+        let mut port = [0];
+        self.i2c
+            .write_read(self.address, &[self.chip.registers().row_input], &mut port)?;
+        Ok(rows_low(port[0]))
+    }
+}
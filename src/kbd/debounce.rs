@@ -0,0 +1,137 @@
+use bitvec::prelude::*;
+use defmt::Format;
+
+use super::{Key, KeyEvent, N_COLS, N_ROWS};
+
+/// Rows of a column that read as pressed, as returned by one read of the matrix.
+pub type ColumnState = BitArr!(for N_ROWS, in u8);
+pub type TickCount = u8;
+
+/// Debounce state of the whole matrix, fed one column read at a time.
+pub struct Debouncer {
+    stable_states: [ColumnState; N_COLS],
+    staging_states: [ColumnState; N_COLS],
+    tick_counts: [[TickCount; N_ROWS]; N_COLS],
+}
+
+impl Debouncer {
+    pub const fn new() -> Self {
+        Self {
+            stable_states: [ColumnState::ZERO; N_COLS],
+            staging_states: [ColumnState::ZERO; N_COLS],
+            tick_counts: [[0; N_ROWS]; N_COLS],
+        }
+    }
+
+    /// Applies a read of column `col`. A key has to read the same for more than `debounce_ticks`
+    /// reads of its column before the change is reported.
+    pub fn update(
+        &mut self,
+        col: usize,
+        mask: ColumnState,
+        debounce_ticks: TickCount,
+    ) -> ColumnUpdateResult {
+        ColumnUpdate::new(
+            debounce_ticks,
+            &mut self.stable_states[col],
+            &mut self.staging_states[col],
+            &mut self.tick_counts[col],
+        )
+        .apply(mask)
+    }
+}
+
+impl Default for Debouncer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct ColumnUpdate<'a> {
+    debounce_ticks: TickCount,
+    stable_state: &'a mut ColumnState,
+    staging_state: &'a mut ColumnState,
+    tick_counts: &'a mut [TickCount; N_ROWS],
+}
+
+#[derive(Default, Format)]
+pub struct ColumnUpdateResult {
+    #[defmt(Debug2Format)]
+    pub pressed_keys: ColumnState,
+    #[defmt(Debug2Format)]
+    pub released_keys: ColumnState,
+    /// Rows whose raw level changed, including bounces.
+    pub changes: u8,
+}
+
+impl<'a> ColumnUpdate<'a> {
+    fn new(
+        debounce_ticks: TickCount,
+        stable_state: &'a mut ColumnState,
+        staging_state: &'a mut ColumnState,
+        tick_counts: &'a mut [TickCount; N_ROWS],
+    ) -> Self {
+        Self {
+            debounce_ticks,
+            stable_state,
+            staging_state,
+            tick_counts,
+        }
+    }
+
+    fn apply(&mut self, new_state: ColumnState) -> ColumnUpdateResult {
+        let mut result = ColumnUpdateResult::default();
+
+        for r in 0..N_ROWS {
+            if new_state[r] != self.staging_state[r] {
+                result.changes += 1;
+                self.tick_counts[r] = 0;
+                self.staging_state.set(r, new_state[r]);
+                continue;
+            }
+
+            if self.tick_counts[r] < self.debounce_ticks {
+                self.tick_counts[r] += 1;
+                continue;
+            }
+
+            if new_state[r] == self.stable_state[r] {
+                continue;
+            }
+
+            self.stable_state.set(r, new_state[r]);
+
+            if new_state[r] {
+                result.pressed_keys.set(r, true);
+            } else {
+                result.released_keys.set(r, true);
+            }
+        }
+
+        result
+    }
+}
+
+impl ColumnUpdateResult {
+    pub fn any(&self) -> bool {
+        (self.pressed_keys | self.released_keys).any()
+    }
+
+    /// Key events of column `col`, releases first.
+    pub fn events(&self, col: usize) -> impl Iterator<Item = KeyEvent> + '_ {
+        let key = move |row: usize| Key {
+            col: col as u8,
+            row: row as u8,
+        };
+        let released = self
+            .released_keys
+            .iter_ones()
+            .map(move |row| KeyEvent::KeyUp(key(row)));
+        let pressed = self
+            .pressed_keys
+            .iter_ones()
+            .map(move |row| KeyEvent::KeyDown(key(row)));
+
+        released.chain(pressed)
+    }
+}
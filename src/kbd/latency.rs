@@ -0,0 +1,217 @@
+//! Measurement of the time from the first raw change of a key to the [`KeyEvent`] reporting it.
+//!
+//! The scanner hands every column read to [`LatencyMeter::read`] and every published event to
+//! [`LatencyMeter::published`]. A change is timed from the read that first saw it, so the
+//! latencies are short by the time from the actual edge to that read, up to one scan period.
+//! Changes that the debouncer drops as glitches aren't counted.
+
+use core::fmt::{self, Write as _};
+
+use embassy_time::Instant;
+
+use super::{ColumnState, Key, KeyEvent, KeySet, N_KEYS, N_ROWS, TickCount};
+use crate::text::TextBuf;
+
+/// Width of a histogram bucket in µs.
+pub const BUCKET_US: u32 = 1000;
+/// Number of histogram buckets. Longer latencies are counted in an extra overflow bucket.
+pub const BUCKETS: usize = 64;
+
+/// A latency in µs, displayed in milliseconds with two decimals, or as `-` if there is none.
+pub struct Millis(pub Option<u32>);
+
+#[derive(Clone)]
+pub struct Histogram {
+    /// Counts per bucket, followed by the overflow bucket.
+    counts: [u16; BUCKETS + 1],
+    count: u32,
+    sum_us: u64,
+    min_us: u32,
+    max_us: u32,
+}
+
+pub struct LatencyMeter {
+    running: bool,
+    overall: Histogram,
+    keys: [Histogram; N_KEYS],
+    /// Keys the debouncer reported as pressed.
+    stable: KeySet,
+    /// Time of the first read that differed from the stable state, for each key.
+    first_edge: [Option<Instant>; N_KEYS],
+    /// Reads that matched the stable state again since then.
+    settled_reads: [u16; N_KEYS],
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            counts: [0; BUCKETS + 1],
+            count: 0,
+            sum_us: 0,
+            min_us: u32::MAX,
+            max_us: 0,
+        }
+    }
+
+    pub fn add(&mut self, us: u32) {
+        let bucket = ((us / BUCKET_US) as usize).min(BUCKETS);
+        self.counts[bucket] = self.counts[bucket].saturating_add(1);
+        self.count += 1;
+        self.sum_us += u64::from(us);
+        self.min_us = self.min_us.min(us);
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn min_us(&self) -> Option<u32> {
+        (!self.is_empty()).then_some(self.min_us)
+    }
+
+    pub fn avg_us(&self) -> Option<u32> {
+        (!self.is_empty()).then(|| (self.sum_us / u64::from(self.count)) as u32)
+    }
+
+    pub fn max_us(&self) -> Option<u32> {
+        (!self.is_empty()).then_some(self.max_us)
+    }
+
+    /// Upper bound of the latency of `pct` percent of the samples: the end of the bucket holding
+    /// that sample, or the maximum if that's lower.
+    pub fn percentile_us(&self, pct: u32) -> Option<u32> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let rank = (u64::from(self.count) * u64::from(pct))
+            .div_ceil(100)
+            .max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts[..BUCKETS].iter().enumerate() {
+            seen += u64::from(count);
+            if seen >= rank {
+                return Some(((bucket as u32 + 1) * BUCKET_US).min(self.max_us));
+            }
+        }
+        Some(self.max_us)
+    }
+
+    /// Counts per bucket of [`BUCKET_US`], without the overflow bucket.
+    pub fn buckets(&self) -> &[u16] {
+        &self.counts[..BUCKETS]
+    }
+
+    /// Number of latencies of [`BUCKETS`] × [`BUCKET_US`] or more.
+    pub fn overflow(&self) -> u16 {
+        self.counts[BUCKETS]
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyMeter {
+    pub const fn new() -> Self {
+        Self {
+            running: false,
+            overall: Histogram::new(),
+            keys: [const { Histogram::new() }; N_KEYS],
+            stable: KeySet::ZERO,
+            first_edge: [None; N_KEYS],
+            settled_reads: [0; N_KEYS],
+        }
+    }
+
+    /// Discards the previous measurements and starts new ones.
+    pub fn start(&mut self) {
+        self.running = true;
+        self.overall = Histogram::new();
+        self.keys = [const { Histogram::new() }; N_KEYS];
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn overall(&self) -> &Histogram {
+        &self.overall
+    }
+
+    pub fn key(&self, key: Key) -> &Histogram {
+        &self.keys[key.index()]
+    }
+
+    /// Notes a read of column `col` at `at`. Reads are followed by the events they caused.
+    pub fn read(&mut self, col: usize, mask: ColumnState, debounce_ticks: TickCount, at: Instant) {
+        for row in 0..N_ROWS {
+            let i = Key {
+                col: col as u8,
+                row: row as u8,
+            }
+            .index();
+
+            if mask[row] != self.stable[i] {
+                self.first_edge[i].get_or_insert(at);
+                self.settled_reads[i] = 0;
+            } else if self.first_edge[i].is_some() {
+                // Forgets the change once the reads are back to the stable state for long enough
+                // that the debouncer won't report it.
+                self.settled_reads[i] = self.settled_reads[i].saturating_add(1);
+                if self.settled_reads[i] > u16::from(debounce_ticks) {
+                    self.first_edge[i] = None;
+                }
+            }
+        }
+    }
+
+    /// Notes an event published at `at`, and measures its latency while running.
+    pub fn published(&mut self, event: KeyEvent, at: Instant) {
+        let (key, pressed) = match event {
+            KeyEvent::KeyDown(key) => (key, true),
+            KeyEvent::KeyUp(key) => (key, false),
+        };
+        let i = key.index();
+        self.stable.set(i, pressed);
+
+        let Some(first_edge) = self.first_edge[i].take() else {
+            return;
+        };
+        if self.running {
+            let us = at.saturating_duration_since(first_edge).as_micros();
+            let us = u32::try_from(us).unwrap_or(u32::MAX);
+            self.overall.add(us);
+            self.keys[i].add(us);
+        }
+    }
+}
+
+impl Default for LatencyMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(us) = self.0 else {
+            return f.pad("-");
+        };
+
+        let mut text = TextBuf::<12>::new();
+        write!(text, "{}.{:02}", us / 1000, us % 1000 / 10)?;
+        f.pad(text.as_str())
+    }
+}
@@ -0,0 +1,146 @@
+//! Recording of raw matrix reads, to replay them through the [`Debouncer`](super::Debouncer)
+//! with other settings.
+//!
+//! The scanner hands every [`Tick`] to the [`Recorder`] while it's recording. Recordings are
+//! exported as [`Run`]s of identical ticks between [`EXPORT_BEGIN`] and [`EXPORT_END`], preceded
+//! by the scan rate and the debounce setting the recording was made with:
+//!
+//! ```text
+//! #keyvisor-trace 1
+//! scan_hz 400
+//! debounce 10
+//! 812 0 0 0
+//! 3 0 2 0
+//! #end
+//! ```
+
+use core::fmt;
+
+use super::{ColumnState, N_COLS, N_ROWS, TickCount};
+
+/// Line preceding the exported recording, followed by the format version.
+pub const EXPORT_BEGIN: &str = "#keyvisor-trace 1";
+/// Line following the exported recording.
+pub const EXPORT_END: &str = "#end";
+
+/// Ticks a recording holds, 20 s at [`SCAN_SPEED_HZ`](super::SCAN_SPEED_HZ).
+pub const RECORDING_TICKS: usize = 8000;
+
+/// Reads of all columns during one scan, indexed by column.
+pub type Tick = [ColumnState; N_COLS];
+
+pub struct Recorder<const N: usize = RECORDING_TICKS> {
+    ticks: [Tick; N],
+    len: usize,
+    recording: bool,
+    debounce_ticks: TickCount,
+}
+
+/// Consecutive ticks with the same reads. Displayed as the number of ticks followed by the rows
+/// read as pressed in each column, as a hex bit mask.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Run {
+    pub ticks: u32,
+    pub masks: Tick,
+}
+
+impl<const N: usize> Recorder<N> {
+    pub const fn new() -> Self {
+        Self {
+            ticks: [[ColumnState::ZERO; N_COLS]; N],
+            len: 0,
+            recording: false,
+            debounce_ticks: 0,
+        }
+    }
+
+    /// Discards the previous recording and starts a new one. `debounce_ticks` is the setting in
+    /// effect, for reference.
+    pub fn start(&mut self, debounce_ticks: TickCount) {
+        self.len = 0;
+        self.recording = true;
+        self.debounce_ticks = debounce_ticks;
+    }
+
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    /// Adds a tick while recording. The recording stops once it's full.
+    pub fn push(&mut self, tick: Tick) {
+        if !self.recording {
+            return;
+        }
+
+        self.ticks[self.len] = tick;
+        self.len += 1;
+        self.recording = self.len < N;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Number of recorded ticks.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn debounce_ticks(&self) -> TickCount {
+        self.debounce_ticks
+    }
+
+    /// Returns the run starting at tick `start`, or `None` past the end of the recording.
+    pub fn run_at(&self, start: usize) -> Option<Run> {
+        let ticks = self.ticks.get(start..self.len)?;
+        let masks = *ticks.first()?;
+        let len = ticks.iter().take_while(|&&tick| tick == masks).count();
+
+        Some(Run {
+            ticks: len as u32,
+            masks,
+        })
+    }
+}
+
+impl<const N: usize> Default for Recorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Run {
+    /// Parses a line written by the [`Display`](fmt::Display) implementation.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_ascii_whitespace();
+
+        let ticks = words.next()?.parse().ok().filter(|&ticks| ticks > 0)?;
+        let mut masks = [ColumnState::ZERO; N_COLS];
+        for mask in &mut masks {
+            let bits = u8::from_str_radix(words.next()?, 16)
+                .ok()
+                .filter(|&bits| bits >> N_ROWS == 0)?;
+            *mask = ColumnState::new([bits]);
+        }
+
+        if words.next().is_some() {
+            return None;
+        }
+
+        Some(Self { ticks, masks })
+    }
+}
+
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ticks)?;
+        for mask in self.masks {
+            write!(f, " {:x}", mask.into_inner()[0])?;
+        }
+        Ok(())
+    }
+}
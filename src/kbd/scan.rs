@@ -0,0 +1,455 @@
+use core::{cell::RefCell, future::pending};
+
+use defmt::{debug, info, trace, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    channel::Channel,
+    pubsub::{DynSubscriber, PubSubChannel},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use esp_hal::{
+    Blocking,
+    gpio::{AnyPin, DriveMode, Flex, Input, InputConfig, Level, Output, OutputConfig, Pull},
+    i2c::{self, master::I2c},
+    time::Rate,
+};
+
+use super::{
+    ColumnState, Debouncer, Key, KeyEvent, N_COLS, N_KEYS, N_ROWS, SCAN_SPEED_HZ, TickCount,
+    analog::{self, AnalogKeys},
+    backend::{
+        DUPLEX_LINES, DuplexMatrix, Expander, ExpanderChip, GpioMatrix, MatrixBackend,
+        ShiftRegisters,
+    },
+    latency::LatencyMeter,
+    recording::Recorder,
+    soft_spi::SoftSpi,
+};
+use crate::{
+    adc,
+    board::{AnalogPins, MAX_SELECT_LINES},
+    bounce::Capture,
+    error::{AppError, Context, ResultExt as _},
+    settings,
+    stream::{self, ScanStats, Update},
+};
+
+const SCAN_READ_DELAY_MICROS: u64 = 2;
+
+/// Time for a sensor's output to reach the ADC after switching the multiplexers.
+const MUX_SETTLE_MICROS: u64 = 10;
+
+/// Period of the scanner statistics sent to the [stream].
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+static CHANNEL: PubSubChannel<CriticalSectionRawMutex, KeyEvent, 32, 1, 1> = PubSubChannel::new();
+
+static RECORDER: Mutex<CriticalSectionRawMutex, RefCell<Recorder>> =
+    Mutex::new(RefCell::new(Recorder::new()));
+
+static LATENCY_METER: Mutex<CriticalSectionRawMutex, RefCell<LatencyMeter>> =
+    Mutex::new(RefCell::new(LatencyMeter::new()));
+
+static ANALOG_KEYS: Mutex<CriticalSectionRawMutex, RefCell<AnalogKeys>> =
+    Mutex::new(RefCell::new(AnalogKeys::new()));
+
+static BOUNCE_KEY: Signal<CriticalSectionRawMutex, Option<Key>> = Signal::new();
+
+static CAPTURES: Channel<CriticalSectionRawMutex, Capture, 2> = Channel::new();
+
+pub fn subscriber() -> Result<DynSubscriber<'static, KeyEvent>, AppError> {
+    CHANNEL
+        .dyn_subscriber()
+        .context(Context::KeyEventSubscription)
+}
+
+/// Gives access to the recording of the raw matrix reads.
+pub fn with_recorder<R>(f: impl FnOnce(&mut Recorder) -> R) -> R {
+    RECORDER.lock(|recorder| f(&mut recorder.borrow_mut()))
+}
+
+/// Gives access to the key-to-event latency measurements.
+pub fn with_latency_meter<R>(f: impl FnOnce(&mut LatencyMeter) -> R) -> R {
+    LATENCY_METER.lock(|meter| f(&mut meter.borrow_mut()))
+}
+
+/// Gives access to the travel and calibration of analog keys.
+pub fn with_analog_keys<R>(f: impl FnOnce(&mut AnalogKeys) -> R) -> R {
+    ANALOG_KEYS.lock(|keys| f(&mut keys.borrow_mut()))
+}
+
+/// Starts capturing the bounce of `key` between scans, or stops with `None`. Captures not yet
+/// received are discarded.
+pub fn analyse_bounce(key: Option<Key>) {
+    CAPTURES.clear();
+    BOUNCE_KEY.signal(key);
+}
+
+/// Waits for the next bounce capture of the key being analysed.
+pub async fn next_capture() -> Capture {
+    CAPTURES.receive().await
+}
+
+pub struct KeyboardInterface<'p> {
+    wiring: Wiring<'p>,
+    /// Whether the backend has to be prepared before the next read.
+    needs_init: bool,
+    /// Whether the last read failed.
+    failing: bool,
+    /// Actuation point of analog keys, in thousandths of their travel.
+    actuation: u16,
+}
+
+enum Wiring<'p> {
+    Matrix(GpioMatrix<Output<'p>, Flex<'p>>),
+    /// Pins in the order of [`Key::index`].
+    Direct([Flex<'p>; N_KEYS]),
+    Duplex(DuplexMatrix<Flex<'p>>),
+    ShiftRegisters(ShiftRegisters<SoftSpi<'p>, Output<'p>>),
+    Expander(Expander<I2c<'p, Blocking>>),
+    /// Sensors read through [`adc::read`].
+    Analog {
+        pins: &'static AnalogPins,
+        /// Select lines of the multiplexers, S0 first.
+        select: [Option<Output<'p>>; MAX_SELECT_LINES],
+    },
+}
+
+/// Configures a pin that is only read.
+fn pulled_up(pin: AnyPin<'_>) -> Flex<'_> {
+    Input::new(pin, InputConfig::default().with_pull(Pull::Up)).into_flex()
+}
+
+/// Configures a matrix column, which is only driven low to select its keys.
+fn column(pin: AnyPin<'_>) -> Output<'_> {
+    Output::new(
+        pin,
+        Level::High,
+        OutputConfig::default().with_drive_mode(DriveMode::OpenDrain),
+    )
+}
+
+/// Configures a pin that is driven low to select its keys, and pulled up and read otherwise.
+fn open_drain(pin: AnyPin<'_>) -> Flex<'_> {
+    let config = OutputConfig::default()
+        .with_drive_mode(DriveMode::OpenDrain)
+        .with_pull(Pull::Up);
+    let mut pin = Output::new(pin, Level::High, config).into_flex();
+    pin.set_input_enable(true);
+    pin
+}
+
+/// Reads every column of a matrix, preparing the backend first if needed.
+async fn read_prepared<B: MatrixBackend>(
+    backend: &mut B,
+    needs_init: bool,
+) -> Result<[ColumnState; N_COLS], B::Error> {
+    if needs_init {
+        backend.init()?;
+    }
+    read_matrix(backend).await
+}
+
+/// Reads every column of a matrix.
+async fn read_matrix<B: MatrixBackend>(backend: &mut B) -> Result<[ColumnState; N_COLS], B::Error> {
+    let mut tick = [ColumnState::ZERO; N_COLS];
+    for (col, mask) in tick.iter_mut().enumerate() {
+        backend.select_column(col)?;
+        Timer::after_micros(SCAN_READ_DELAY_MICROS).await;
+        *mask = backend.read_rows()?;
+    }
+    Ok(tick)
+}
+
+impl<'p> KeyboardInterface<'p> {
+    fn new(wiring: Wiring<'p>) -> Self {
+        Self {
+            wiring,
+            needs_init: true,
+            failing: false,
+            actuation: analog::FULL_TRAVEL / 2,
+        }
+    }
+
+    pub fn matrix(columns: [AnyPin<'p>; N_COLS], rows: [AnyPin<'p>; N_ROWS]) -> Self {
+        Self::new(Wiring::Matrix(GpioMatrix::new(
+            columns.map(column),
+            rows.map(pulled_up),
+        )))
+    }
+
+    /// Reads each key from its own pin, in the order of [`Key::index`].
+    pub fn direct(pins: [AnyPin<'p>; N_KEYS]) -> Self {
+        Self::new(Wiring::Direct(pins.map(pulled_up)))
+    }
+
+    /// Reads a duplex matrix by driving the columns and then the rows, see [`Key::duplex`].
+    pub fn duplex(columns: [AnyPin<'p>; N_COLS], rows: [AnyPin<'p>; N_ROWS / 2]) -> Self {
+        Self::new(Wiring::Duplex(DuplexMatrix::new(
+            columns.map(open_drain),
+            rows.map(open_drain),
+        )))
+    }
+
+    /// Reads a matrix through a 74HC595 and a 74HC165, see [`ShiftRegisters`].
+    pub fn shift_registers(
+        clock: AnyPin<'p>,
+        data_out: AnyPin<'p>,
+        data_in: AnyPin<'p>,
+        latch: AnyPin<'p>,
+    ) -> Self {
+        let output = |pin| Output::new(pin, Level::Low, OutputConfig::default());
+        let spi = SoftSpi {
+            clock: output(clock),
+            data_out: output(data_out),
+            data_in: Input::new(data_in, InputConfig::default()),
+        };
+        Self::new(Wiring::ShiftRegisters(ShiftRegisters::new(
+            spi,
+            Output::new(latch, Level::High, OutputConfig::default()),
+        )))
+    }
+
+    /// Reads a matrix through an I/O expander, see [`Expander`].
+    ///
+    /// # Panics
+    ///
+    /// If the I2C bus can't be configured.
+    pub fn expander(
+        i2c: impl i2c::master::Instance + 'p,
+        sda: AnyPin<'p>,
+        scl: AnyPin<'p>,
+        chip: ExpanderChip,
+        address: u8,
+    ) -> Self {
+        let config = i2c::master::Config::default().with_frequency(Rate::from_khz(400));
+        let i2c = I2c::new(i2c, config)
+            .expect("invalid keypad I2C config")
+            .with_sda(sda)
+            .with_scl(scl);
+        Self::new(Wiring::Expander(Expander::new(i2c, chip, address)))
+    }
+
+    /// Reads the Hall-effect sensors of analog keys, with the multiplexers switched by `select`,
+    /// as described by `pins`. The inputs must have been enabled with [`adc::init`].
+    pub fn analog(pins: &'static AnalogPins, select: impl IntoIterator<Item = AnyPin<'p>>) -> Self {
+        let mut select = select.into_iter();
+        Self::new(Wiring::Analog {
+            pins,
+            select: core::array::from_fn(|_| {
+                select
+                    .next()
+                    .map(|pin| Output::new(pin, Level::Low, OutputConfig::default()))
+            }),
+        })
+    }
+
+    /// Reads every key once, or returns `None` if the keypad couldn't be read.
+    async fn read(&mut self) -> Option<[ColumnState; N_COLS]> {
+        let result = match &mut self.wiring {
+            Wiring::Matrix(matrix) => {
+                let Ok(tick) = read_matrix(matrix).await;
+                Ok(tick)
+            }
+            Wiring::Direct(pins) => {
+                let mut tick = [ColumnState::ZERO; N_COLS];
+                for (i, pin) in pins.iter().enumerate() {
+                    let key = Key::from_index(i);
+                    tick[usize::from(key.col)].set(usize::from(key.row), pin.is_low());
+                }
+                Ok(tick)
+            }
+            Wiring::Duplex(matrix) => {
+                let mut tick = [ColumnState::ZERO; N_COLS];
+                for line in 0..DUPLEX_LINES {
+                    let Ok(()) = matrix.select_line(line);
+                    Timer::after_micros(SCAN_READ_DELAY_MICROS).await;
+                    let Ok(()) = matrix.read_line(line, &mut tick);
+                }
+                let Ok(()) = matrix.release();
+                Ok(tick)
+            }
+            Wiring::ShiftRegisters(registers) => {
+                let Ok(tick) = read_prepared(registers, self.needs_init).await;
+                Ok(tick)
+            }
+            Wiring::Expander(expander) => read_prepared(expander, self.needs_init).await,
+            Wiring::Analog { pins, select } => {
+                let mut readings = [None; N_KEYS];
+                for (i, reading) in readings.iter_mut().enumerate() {
+                    let (input, channel) = pins.sensor(Key::from_index(i));
+                    if !pins.select.is_empty() {
+                        for (bit, line) in select.iter_mut().flatten().enumerate() {
+                            line.set_level(Level::from(channel & (1 << bit) != 0));
+                        }
+                        Timer::after_micros(MUX_SETTLE_MICROS).await;
+                    }
+                    *reading = adc::read(pins.inputs[input]).await;
+                }
+                Ok(with_analog_keys(|keys| {
+                    keys.update(readings, self.actuation)
+                }))
+            }
+        };
+
+        match result {
+            Ok(tick) => {
+                if self.failing {
+                    info!("keypad read again");
+                }
+                self.needs_init = false;
+                self.failing = false;
+                Some(tick)
+            }
+            Err(error) => {
+                if !self.failing {
+                    warn!("couldn't read the keypad: {}", error);
+                }
+                self.needs_init = true;
+                self.failing = true;
+                None
+            }
+        }
+    }
+
+    /// Connects the contact of `key` to a pin and returns that pin, which reads low while the
+    /// contact is closed. Returns `None` if the contacts aren't on GPIOs.
+    fn select_key(&mut self, key: Key) -> Option<&mut Flex<'p>> {
+        let col = usize::from(key.col);
+        let row = usize::from(key.row);
+        match &mut self.wiring {
+            Wiring::Matrix(matrix) => {
+                let Ok(()) = matrix.select_column(col);
+                Some(matrix.row_mut(row))
+            }
+            Wiring::Direct(pins) => Some(&mut pins[key.index()]),
+            Wiring::Duplex(matrix) => {
+                let Ok(pin) = matrix.select_key(key);
+                Some(pin)
+            }
+            Wiring::ShiftRegisters(_) | Wiring::Expander(_) | Wiring::Analog { .. } => None,
+        }
+    }
+
+    /// Waits for the contact of `key` to change, then samples it until the contact settled.
+    ///
+    /// Sampling busy-waits and so blocks the executor for up to
+    /// [`MAX_CAPTURE_TIME`](crate::bounce::MAX_CAPTURE_TIME). Edges within the interrupt latency
+    /// after the first one are missed.
+    async fn capture_bounce(&mut self, key: Key) -> Capture {
+        let Some(pin) = self.select_key(key) else {
+            return pending().await;
+        };
+        Timer::after_micros(SCAN_READ_DELAY_MICROS).await;
+
+        let closed_before = pin.is_low();
+        pin.wait_for_any_edge().await;
+
+        let start = Instant::now();
+        let mut capture = Capture::new(key, closed_before);
+        while capture.sample(start.elapsed().as_micros() as u32, pin.is_low()) {}
+
+        capture
+    }
+
+    /// Releases the lines [`select_key`](Self::select_key) left driven, which would otherwise
+    /// connect keys that aren't being read.
+    fn release_key(&mut self) {
+        if let Wiring::Duplex(matrix) = &mut self.wiring {
+            let Ok(()) = matrix.release();
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn task(
+    mut kbd: KeyboardInterface<'static>,
+    mut debounce_ticks: TickCount,
+    actuation_pct: u8,
+) {
+    info!("starting kbd task");
+
+    kbd.actuation = analog::actuation_from_pct(actuation_pct);
+    let mut settings_changes = settings::anon_receiver();
+
+    let mut ticker = Ticker::every(Duration::from_hz(SCAN_SPEED_HZ));
+
+    let mut debouncer = Debouncer::new();
+
+    let mut stats = ScanStats::default();
+    let mut stats_since = Instant::now();
+
+    let mut bounce_key = None;
+
+    loop {
+        if let Some(settings) = settings_changes.try_changed() {
+            debounce_ticks = settings.debounce_ticks;
+            kbd.actuation = analog::actuation_from_pct(settings.actuation_pct);
+        }
+
+        if let Some(key) = BOUNCE_KEY.try_take() {
+            bounce_key = key;
+        }
+
+        let now = Instant::now();
+        if now - stats_since >= STATS_INTERVAL {
+            stream::emit(Update::Scan {
+                stats: core::mem::take(&mut stats),
+                debounce_ticks,
+                at: now,
+            });
+            stats_since = now;
+        }
+        stats.scans += 1;
+
+        let Some(tick) = kbd.read().await else {
+            ticker.next().await;
+            continue;
+        };
+        let read_at = Instant::now();
+        for (col, &mask) in tick.iter().enumerate() {
+            with_latency_meter(|meter| meter.read(col, mask, debounce_ticks, read_at));
+
+            trace!("col {} mask: {}", col, mask.into_inner()[0]);
+
+            let updates = debouncer.update(col, mask, debounce_ticks);
+
+            stats.changes += u32::from(updates.changes);
+
+            if updates.any() {
+                debug!("col {} updates: {:?}", col, updates);
+
+                let publisher = CHANNEL.immediate_publisher();
+                let at = Instant::now();
+
+                for event in updates.events(col) {
+                    publisher.publish_immediate(event);
+                    with_latency_meter(|meter| meter.published(event, Instant::now()));
+                    stream::emit(Update::Key { event, at });
+                    stats.events += 1;
+                }
+            }
+        }
+
+        with_recorder(|recorder| recorder.push(tick));
+
+        // The time until the next scan is spent waiting for the key being analysed to bounce.
+        let Some(key) = bounce_key else {
+            ticker.next().await;
+            continue;
+        };
+        let captured = select(kbd.capture_bounce(key), ticker.next()).await;
+        kbd.release_key();
+        if let Either::First(capture) = captured {
+            debug!(
+                "bounce of {}: {} edges in {} us",
+                key,
+                capture.edge_count(),
+                capture.bounce_us()
+            );
+            let _ = CAPTURES.try_send(capture);
+        }
+    }
+}
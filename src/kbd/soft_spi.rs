@@ -0,0 +1,67 @@
+use core::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, SpiBus};
+use esp_hal::gpio::{Input, Output};
+
+/// SPI in mode 0, MSB first, driven in software for the keypad's shift registers since the
+/// display has the SPI peripheral.
+pub struct SoftSpi<'p> {
+    pub clock: Output<'p>,
+    pub data_out: Output<'p>,
+    pub data_in: Input<'p>,
+}
+
+impl SoftSpi<'_> {
+    fn transfer_byte(&mut self, out: u8) -> u8 {
+        let mut read = 0;
+        for bit in (0..8).rev() {
+            self.data_out.set_level((out >> bit & 1 != 0).into());
+            // Read before the rising edge, which makes a 74HC165 shift out its next bit.
+            read = read << 1 | u8::from(self.data_in.is_high());
+            self.clock.set_high();
+            self.clock.set_low();
+        }
+        read
+    }
+}
+
+impl ErrorType for SoftSpi<'_> {
+    type Error = Infallible;
+}
+
+impl SpiBus for SoftSpi<'_> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        for word in words {
+            *word = self.transfer_byte(0);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        for &word in words {
+            self.transfer_byte(word);
+        }
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        for i in 0..read.len().max(write.len()) {
+            let word = self.transfer_byte(write.get(i).copied().unwrap_or(0));
+            if let Some(read) = read.get_mut(i) {
+                *read = word;
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        for word in words {
+            *word = self.transfer_byte(*word);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Single-colour LEDs under the keys, as on boards without RGB lighting.
+//!
+//! The LEDs are either on the tester's GPIOs or PWM channels, one for each key, or on an LED
+//! driver like the IS31FL3731 or IS31FL3733 over I2C. A driver lights the LED of the key with
+//! [`Key::index`] n through its PWM register n: the first row of the IS31FL3733's matrix, or the
+//! first rows of the IS31FL3731's matrices A and B, eight LEDs each.
+
+use defmt::Format;
+use embedded_hal::{
+    digital::OutputPin,
+    i2c::{I2c, Operation},
+    pwm::{self, SetDutyCycle},
+};
+
+use crate::{
+    i2c_scan::{self, Device},
+    kbd::{Key, KeySet, N_KEYS},
+};
+
+#[cfg(target_os = "none")]
+mod outputs;
+
+#[cfg(target_os = "none")]
+pub use self::outputs::{KeyLedError, KeyLedOutputs};
+
+/// The key after `key` in the order of [`Key::index`] that has an LED, starting over after the
+/// last key. Returns `key` itself if no other key has an LED.
+pub fn next_led(key: Key, leds: &KeySet) -> Key {
+    (1..=N_KEYS)
+        .map(|step| Key::from_index((key.index() + step) % N_KEYS))
+        .find(|next| leds[next.index()])
+        .unwrap_or(key)
+}
+
+/// LEDs under the keys, set one at a time.
+pub trait KeyLedBackend {
+    type Error;
+
+    /// Prepares the hardware with all LEDs off.
+    fn init(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Sets the brightness of the LED of `key`, 0 being off. Keys without an LED are skipped.
+    fn set(&mut self, key: Key, brightness: u8) -> Result<(), Self::Error>;
+}
+
+/// An LED on a PWM channel for each key that has one.
+pub struct PwmLeds<P> {
+    leds: [Option<P>; N_KEYS],
+}
+
+impl<P> PwmLeds<P> {
+    /// Takes the LED of each key, indexed by [`Key::index`].
+    pub fn new(leds: [Option<P>; N_KEYS]) -> Self {
+        Self { leds }
+    }
+
+    /// The keys that have an LED.
+    pub fn leds(&self) -> KeySet {
+        let mut leds = KeySet::ZERO;
+        for (i, led) in self.leds.iter().enumerate() {
+            leds.set(i, led.is_some());
+        }
+        leds
+    }
+}
+
+impl<P: SetDutyCycle> KeyLedBackend for PwmLeds<P> {
+    type Error = P::Error;
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        for led in self.leds.iter_mut().flatten() {
+            led.set_duty_cycle_fully_off()?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: Key, brightness: u8) -> Result<(), Self::Error> {
+        let Some(led) = &mut self.leds[key.index()] else {
+            return Ok(());
+        };
+        // Rounded up, so that a dim LED still lights on a channel with few steps.
+        let duty = (u32::from(led.max_duty_cycle()) * u32::from(brightness)).div_ceil(255);
+        led.set_duty_cycle(duty as u16)
+    }
+}
+
+/// A GPIO used as a PWM channel that is either fully on or off.
+pub struct OnOff<P>(pub P);
+
+impl<P: OutputPin<Error = core::convert::Infallible>> pwm::ErrorType for OnOff<P> {
+    type Error = core::convert::Infallible;
+}
+
+impl<P: OutputPin<Error = core::convert::Infallible>> SetDutyCycle for OnOff<P> {
+    fn max_duty_cycle(&self) -> u16 {
+        1
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.0.set_state((duty > 0).into())
+    }
+}
+
+/// LED drivers with a matrix of LEDs and a PWM register for each, addressed over I2C.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum LedDriverChip {
+    Is31fl3731,
+    Is31fl3733,
+}
+
+/// Selects the page the other registers are on.
+const COMMAND_REGISTER: u8 = 0xfd;
+/// Unlocks the IS31FL3733's command register for the next write.
+const COMMAND_LOCK: u8 = 0xfe;
+const UNLOCK: u8 = 0xc5;
+
+/// Current of the IS31FL3733's LEDs, out of 255 for the maximum set by its resistor.
+const GLOBAL_CURRENT: u8 = 0x80;
+
+impl LedDriverChip {
+    /// The chip that can be strapped to `address`.
+    pub fn at_address(address: u8) -> Option<Self> {
+        match address {
+            0x74..=0x77 => Some(LedDriverChip::Is31fl3731),
+            0x50..=0x5f => Some(LedDriverChip::Is31fl3733),
+            _ => None,
+        }
+    }
+
+    /// Addresses the chips can be strapped to, in the order they're looked for.
+    pub fn addresses() -> impl Iterator<Item = u8> {
+        (0x74..=0x77).chain(0x50..=0x5f)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LedDriverChip::Is31fl3731 => "IS31FL3731",
+            LedDriverChip::Is31fl3733 => "IS31FL3733",
+        }
+    }
+
+    /// Page and first register of the PWM registers.
+    fn pwm(self) -> (u8, u8) {
+        match self {
+            // Frame 1, which shows in picture mode.
+            LedDriverChip::Is31fl3731 => (0x00, 0x24),
+            LedDriverChip::Is31fl3733 => (0x01, 0x00),
+        }
+    }
+}
+
+/// Looks for an LED driver on the bus, or at `address` if the board profile names it, and
+/// returns the first one that answers.
+///
+/// Nothing is written before the driver is found, since that would overwrite a 24Cxx EEPROM or
+/// reconfigure a BME280 or BMP280 sharing its addresses. Devices are told apart like in
+/// [`i2c_scan::identify`], which can't tell an IS31FL3733 at 0x50-0x57 from an EEPROM, so there
+/// it's only used at the profile's `address`.
+pub fn detect<I: I2c>(i2c: &mut I, address: Option<u8>) -> Option<(LedDriverChip, u8)> {
+    if let Some(address) = address {
+        let chip = LedDriverChip::at_address(address)?;
+        return i2c
+            .read(address, &mut [0])
+            .is_ok()
+            .then_some((chip, address));
+    }
+    LedDriverChip::addresses().find_map(|address| {
+        i2c.read(address, &mut [0]).ok()?;
+        match i2c_scan::identify(i2c, address) {
+            Device::LedDriver(chip) => Some((chip, address)),
+            _ => None,
+        }
+    })
+}
+
+/// An IS31FL3731 or IS31FL3733, showing its first frame with every LED enabled and lit through
+/// its PWM register.
+pub struct LedDriver<I> {
+    i2c: I,
+    chip: LedDriverChip,
+    address: u8,
+}
+
+impl<I: I2c> LedDriver<I> {
+    pub fn new(i2c: I, chip: LedDriverChip, address: u8) -> Self {
+        Self { i2c, chip, address }
+    }
+
+    pub fn chip(&self) -> LedDriverChip {
+        self.chip
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Writes `data` to the registers from `register` on, which the chips increment.
+    fn write(&mut self, register: u8, data: &[u8]) -> Result<(), I::Error> {
+        self.i2c.transaction(
+            self.address,
+            &mut [Operation::Write(&[register]), Operation::Write(data)],
+        )
+    }
+
+    fn select_page(&mut self, page: u8) -> Result<(), I::Error> {
+        if self.chip == LedDriverChip::Is31fl3733 {
+            self.write(COMMAND_LOCK, &[UNLOCK])?;
+        }
+        self.write(COMMAND_REGISTER, &[page])
+    }
+}
+
+impl<I: I2c> KeyLedBackend for LedDriver<I> {
+    type Error = I::Error;
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        // Shut down while the LEDs are set up, then left on the page of the PWM registers.
+        match self.chip {
+            LedDriverChip::Is31fl3731 => {
+                const FUNCTION_PAGE: u8 = 0x0b;
+                const SHUTDOWN: u8 = 0x0a;
+
+                self.select_page(FUNCTION_PAGE)?;
+                self.write(SHUTDOWN, &[0x00])?;
+                // Picture mode, showing frame 1.
+                self.write(0x00, &[0x00, 0x00])?;
+                self.select_page(0x00)?;
+                self.write(0x00, &[0xff; 18])?;
+                self.write(0x24, &[0x00; 144])?;
+                self.select_page(FUNCTION_PAGE)?;
+                self.write(SHUTDOWN, &[0x01])?;
+            }
+            LedDriverChip::Is31fl3733 => {
+                const FUNCTION_PAGE: u8 = 0x03;
+                const CONFIGURATION: u8 = 0x00;
+
+                self.select_page(FUNCTION_PAGE)?;
+                self.write(CONFIGURATION, &[0x00, GLOBAL_CURRENT])?;
+                self.select_page(0x00)?;
+                self.write(0x00, &[0xff; 24])?;
+                self.select_page(0x01)?;
+                self.write(0x00, &[0x00; 192])?;
+                self.select_page(FUNCTION_PAGE)?;
+                self.write(CONFIGURATION, &[0x01])?;
+            }
+        }
+        self.select_page(self.chip.pwm().0)
+    }
+
+    fn set(&mut self, key: Key, brightness: u8) -> Result<(), Self::Error> {
+        self.write(self.chip.pwm().1 + key.index() as u8, &[brightness])
+    }
+}
@@ -0,0 +1,101 @@
+use esp_hal::{
+    Blocking,
+    gpio::{Level, Output, OutputConfig},
+    i2c::master::{self, Config, ConfigError, I2c},
+    peripherals::I2C0,
+    time::Rate,
+};
+
+use super::{KeyLedBackend, LedDriver, LedDriverChip, OnOff, PwmLeds, detect};
+use crate::{
+    board::KeyLeds,
+    kbd::{Key, KeySet, N_KEYS},
+};
+
+#[derive(Debug, defmt::Format)]
+pub enum KeyLedError {
+    /// The LEDs are behind an LED driver, but the keypad uses the I2C peripheral.
+    BusTaken,
+    /// No LED driver was found on the bus, or none answered at the profile's address.
+    NoDriver,
+    I2cConfig(ConfigError),
+    I2c(master::Error),
+}
+
+/// The LEDs under the keys, on the pins borrowed from [`KeyLeds`] for the duration of a test.
+pub enum KeyLedOutputs<'d> {
+    Gpio(PwmLeds<OnOff<Output<'d>>>),
+    Driver(LedDriver<I2c<'d, Blocking>>),
+}
+
+impl<'d> KeyLedOutputs<'d> {
+    /// Sets up the LEDs with all of them off, looking for the LED driver on the bus if they're
+    /// behind one.
+    pub fn new(
+        pins: &'d mut KeyLeds,
+        i2c: Option<&'d mut I2C0<'static>>,
+    ) -> Result<Self, KeyLedError> {
+        let mut outputs = match pins {
+            KeyLeds::Gpio(pins) => KeyLedOutputs::Gpio(PwmLeds::new(pins.each_mut().map(|pin| {
+                let pin = pin.as_mut()?.reborrow();
+                Some(OnOff(Output::new(pin, Level::Low, OutputConfig::default())))
+            }))),
+            KeyLeds::I2c { sda, scl, address } => {
+                let i2c = i2c.ok_or(KeyLedError::BusTaken)?;
+                let config = Config::default().with_frequency(Rate::from_khz(400));
+                let mut i2c = I2c::new(i2c.reborrow(), config)
+                    .map_err(KeyLedError::I2cConfig)?
+                    .with_sda(sda.reborrow())
+                    .with_scl(scl.reborrow());
+                let (chip, address) = detect(&mut i2c, *address).ok_or(KeyLedError::NoDriver)?;
+                KeyLedOutputs::Driver(LedDriver::new(i2c, chip, address))
+            }
+        };
+        outputs.init()?;
+        Ok(outputs)
+    }
+
+    /// The LED driver and its address, or `None` for LEDs on GPIOs.
+    pub fn driver(&self) -> Option<(LedDriverChip, u8)> {
+        match self {
+            KeyLedOutputs::Gpio(_) => None,
+            KeyLedOutputs::Driver(driver) => Some((driver.chip(), driver.address())),
+        }
+    }
+
+    /// The keys that have an LED, which are all of them on an LED driver.
+    pub fn leds(&self) -> KeySet {
+        match self {
+            KeyLedOutputs::Gpio(leds) => leds.leds(),
+            KeyLedOutputs::Driver(_) => {
+                let mut leds = KeySet::ZERO;
+                leds[..N_KEYS].fill(true);
+                leds
+            }
+        }
+    }
+}
+
+impl KeyLedBackend for KeyLedOutputs<'_> {
+    type Error = KeyLedError;
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        match self {
+            KeyLedOutputs::Gpio(leds) => {
+                let Ok(()) = leds.init();
+                Ok(())
+            }
+            KeyLedOutputs::Driver(driver) => driver.init().map_err(KeyLedError::I2c),
+        }
+    }
+
+    fn set(&mut self, key: Key, brightness: u8) -> Result<(), Self::Error> {
+        match self {
+            KeyLedOutputs::Gpio(leds) => {
+                let Ok(()) = leds.set(key, brightness);
+                Ok(())
+            }
+            KeyLedOutputs::Driver(driver) => driver.set(key, brightness).map_err(KeyLedError::I2c),
+        }
+    }
+}
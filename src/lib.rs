@@ -1,6 +1,28 @@
 #![no_std]
 
+pub mod adc;
+pub mod board;
+pub mod bounce;
+pub mod console;
 pub mod display;
+pub mod encoder;
+#[cfg(target_os = "none")]
 pub mod error;
+#[cfg(target_os = "none")]
+pub mod fault;
+pub mod history;
+pub mod i2c_scan;
 pub mod kbd;
+pub mod key_leds;
+pub mod oled;
+pub mod rgb;
+#[cfg(target_os = "none")]
+pub mod screenshot;
+#[cfg(target_os = "none")]
+pub mod serial;
+pub mod session;
+pub mod settings;
+pub mod storage;
+pub mod stream;
+pub mod text;
 pub mod ui;
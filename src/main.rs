@@ -7,13 +7,20 @@
 )]
 #![deny(clippy::large_stack_frames)]
 
+use defmt::info;
 use embassy_executor::Spawner;
 use esp_hal::clock::CpuClock;
 use esp_hal::timer::timg::TimerGroup;
 use keyvisor::{
-    display::{DisplayPeripherals, DisplayState},
+    adc,
+    board::{self, BoardProfile, Keypad},
+    display::{Backlight, DisplayInitError, DisplayPeripherals, DisplayState},
+    encoder::{self, EncoderInput},
+    fault, history,
     kbd::{self, KeyboardInterface},
-    ui,
+    rgb::LedChain,
+    serial, settings, storage, stream,
+    ui::{self, ToolHardware},
 };
 use {esp_backtrace as _, esp_println as _};
 
@@ -37,35 +44,102 @@ async fn main(spawner: Spawner) {
         esp_hal::interrupt::software::SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
     esp_rtos::start(timg0.timer0, sw_interrupt.software_interrupt0);
 
-    let display_state = DisplayState::init(DisplayPeripherals {
-        scl: peripherals.GPIO19.into(),
-        sda: peripherals.GPIO20.into(),
-        rst: peripherals.GPIO21.into(),
-        dc: peripherals.GPIO22.into(),
-        cs: peripherals.GPIO23.into(),
-        bl: peripherals.GPIO15.into(),
-        ledc: peripherals.LEDC,
-        spi: peripherals.SPI2,
-        dma_ch: peripherals.DMA_CH0,
-    })
+    storage::init(peripherals.FLASH);
+    let settings = settings::load();
+    history::init();
+
+    let strap = board::read_strap(peripherals.GPIO14.into()).await;
+    let profile = settings
+        .board
+        .and_then(BoardProfile::by_id)
+        .unwrap_or_else(|| BoardProfile::detect(strap));
+    info!("board: {} (ID strap {})", profile.name, strap);
+
+    // SAFETY: GPIOs are only accessed through the board pins from here on.
+    let pins = unsafe { profile.take_pins(settings.analog_pins, settings.i2c_pins) };
+
+    let backlight =
+        Backlight::init(peripherals.LEDC, pins.backlight).expect("couldn't initialize backlight");
+
+    let display_state = match DisplayState::init(
+        DisplayPeripherals {
+            scl: pins.scl,
+            sda: pins.sda,
+            rst: pins.rst,
+            dc: pins.dc,
+            cs: pins.cs,
+            spi: peripherals.SPI2,
+            dma_ch: peripherals.DMA_CH0,
+        },
+        backlight,
+        settings.rotation,
+    )
     .await
-    .expect("couldn't initialize display");
-
-    spawner.must_spawn(ui::task(display_state));
-
-    let kbd = KeyboardInterface::new(
-        [
-            peripherals.GPIO11.into(),
-            peripherals.GPIO10.into(),
-            peripherals.GPIO1.into(),
-        ],
-        [
-            peripherals.GPIO8.into(),
-            peripherals.GPIO12.into(),
-            peripherals.GPIO13.into(),
-            peripherals.GPIO0.into(),
-        ],
-    );
-
-    spawner.must_spawn(kbd::task(kbd));
+    {
+        Ok(display_state) => {
+            fault::display_init_succeeded();
+            display_state
+        }
+        Err(DisplayInitError { error, backlight }) => fault::display_failed(error, backlight).await,
+    };
+
+    let (serial_rx, serial_tx) = serial::init(peripherals.USB_DEVICE);
+    spawner.must_spawn(serial::task(serial_rx, serial_tx, profile));
+    spawner.must_spawn(stream::task(serial_tx));
+
+    let led_chain = pins
+        .rgb_chain
+        .map(|pin| LedChain::new(peripherals.RMT, pin).expect("couldn't initialize the LED chain"));
+
+    if let Some((a, b)) = pins.encoder {
+        spawner.must_spawn(encoder::task(EncoderInput::new(a, b)));
+    }
+
+    adc::init(peripherals.ADC1, pins.adc);
+
+    // Left to the tools unless the keypad is on an expander.
+    let mut i2c = Some(peripherals.I2C0);
+
+    let kbd = match pins.keypad {
+        Keypad::Matrix { columns, rows } => KeyboardInterface::matrix(columns, rows),
+        Keypad::Direct(pins) => KeyboardInterface::direct(pins),
+        Keypad::Duplex { columns, rows } => KeyboardInterface::duplex(columns, rows),
+        Keypad::ShiftRegisters {
+            clock,
+            data_out,
+            data_in,
+            latch,
+        } => KeyboardInterface::shift_registers(clock, data_out, data_in, latch),
+        Keypad::Expander {
+            chip,
+            address,
+            sda,
+            scl,
+        } => {
+            let i2c = i2c.take().expect("I2C peripheral already taken");
+            KeyboardInterface::expander(i2c, sda, scl, chip, address)
+        }
+        Keypad::Analog { pins, select } => KeyboardInterface::analog(pins, select),
+    };
+
+    let settings_changes = settings::receiver().expect("settings receiver already taken");
+    spawner.must_spawn(ui::task(
+        display_state,
+        serial_tx,
+        profile,
+        settings.clone(),
+        settings_changes,
+        ToolHardware {
+            led_chain,
+            key_leds: pins.key_leds,
+            i2c,
+            i2c_scan: pins.i2c_scan,
+        },
+    ));
+
+    spawner.must_spawn(kbd::task(
+        kbd,
+        settings.debounce_ticks,
+        settings.actuation_pct,
+    ));
 }
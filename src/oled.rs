@@ -0,0 +1,311 @@
+//! Test patterns for an SSD1306 OLED on the I2C bus of the board under test.
+//!
+//! The patterns are drawn with `embedded-graphics` into an [`OledBuffer`] laid out like the
+//! controller's memory: eight pages of eight rows, one byte per column and page, the top row in
+//! the lowest bit. [`Ssd1306`] sets the controller up for a 128x32 or 128x64 panel and sends it
+//! the buffer.
+
+use defmt::Format;
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::PrimitiveStyle,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c, Operation};
+use u8g2_fonts::{U8g2TextStyle, fonts::u8g2_font_helvB12_tr};
+
+/// Addresses an SSD1306 can be strapped to.
+pub const ADDRESSES: [u8; 2] = [0x3c, 0x3d];
+
+pub const WIDTH: u32 = 128;
+
+/// Bytes of the largest panel's memory.
+const MAX_BUFFER_LEN: usize = (WIDTH * 64 / 8) as usize;
+
+/// Side of the checkerboard's squares, which makes each square a page high.
+const SQUARE: u32 = 8;
+
+/// The first byte of a write, telling commands from display data.
+const COMMANDS: u8 = 0x00;
+const DATA: u8 = 0x40;
+
+const DISPLAY_OFF: u8 = 0xae;
+const DISPLAY_ON: u8 = 0xaf;
+const NORMAL: u8 = 0xa6;
+const INVERTED: u8 = 0xa7;
+
+/// The panels an SSD1306 commonly drives.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum OledSize {
+    W128H32,
+    W128H64,
+}
+
+impl OledSize {
+    pub fn height(self) -> u32 {
+        match self {
+            OledSize::W128H32 => 32,
+            OledSize::W128H64 => 64,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OledSize::W128H32 => "128x32",
+            OledSize::W128H64 => "128x64",
+        }
+    }
+
+    /// The other size, to switch when the panel doesn't match.
+    pub fn other(self) -> Self {
+        match self {
+            OledSize::W128H32 => OledSize::W128H64,
+            OledSize::W128H64 => OledSize::W128H32,
+        }
+    }
+
+    fn buffer_len(self) -> usize {
+        (WIDTH * self.height() / 8) as usize
+    }
+}
+
+/// What an OLED under test shows.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Squares of 8x8 pixels, showing dead rows and columns and mixed-up pages.
+    Checkerboard,
+    /// The outermost pixels, showing a panel that is offset or set up for the wrong size.
+    Border,
+    Text,
+    /// The text with the display inverted by the controller, lighting every other pixel.
+    Inverted,
+}
+
+impl TestPattern {
+    /// The patterns in the order they're cycled through.
+    pub const ALL: [TestPattern; 4] = [
+        TestPattern::Checkerboard,
+        TestPattern::Border,
+        TestPattern::Text,
+        TestPattern::Inverted,
+    ];
+
+    pub const TEXT: &str = "keyvisor OK";
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TestPattern::Checkerboard => "Checkerboard",
+            TestPattern::Border => "Border",
+            TestPattern::Text => "Text",
+            TestPattern::Inverted => "Inverted",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&pattern| pattern == self);
+        Self::ALL[i.map_or(0, |i| (i + 1) % Self::ALL.len())]
+    }
+
+    /// Whether the controller inverts the display.
+    pub fn inverted(self) -> bool {
+        self == TestPattern::Inverted
+    }
+
+    /// Draws the pattern into a cleared `buffer`.
+    pub fn draw(self, buffer: &mut OledBuffer) {
+        buffer.clear();
+        let area = buffer.bounding_box();
+        let result = match self {
+            TestPattern::Checkerboard => {
+                let on = area.points().filter(|point| {
+                    (point.x as u32 / SQUARE + point.y as u32 / SQUARE).is_multiple_of(2)
+                });
+                buffer.draw_iter(on.map(|point| Pixel(point, BinaryColor::On)))
+            }
+            TestPattern::Border => area
+                .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                .draw(buffer),
+            TestPattern::Text | TestPattern::Inverted => Text::with_text_style(
+                Self::TEXT,
+                area.center(),
+                U8g2TextStyle::new(u8g2_font_helvB12_tr, BinaryColor::On),
+                TextStyleBuilder::new()
+                    .alignment(Alignment::Center)
+                    .baseline(Baseline::Middle)
+                    .build(),
+            )
+            .draw(buffer)
+            .map(|_| ()),
+        };
+        // Drawing into the buffer can't fail.
+        let Ok(()) = result;
+    }
+}
+
+/// The display memory of a panel, see the [module](self) for its layout.
+pub struct OledBuffer {
+    size: OledSize,
+    bytes: [u8; MAX_BUFFER_LEN],
+}
+
+impl OledBuffer {
+    pub fn new(size: OledSize) -> Self {
+        Self {
+            size,
+            bytes: [0; MAX_BUFFER_LEN],
+        }
+    }
+
+    pub fn oled_size(&self) -> OledSize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.fill(0);
+    }
+
+    /// Whether the pixel at `x`, `y` is lit, before any inversion by the controller.
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        let (index, bit) = Self::position(x, y);
+        self.bytes[index] & bit != 0
+    }
+
+    /// The bytes sent to the controller, page by page.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.size.buffer_len()]
+    }
+
+    fn position(x: u32, y: u32) -> (usize, u8) {
+        ((y / 8 * WIDTH + x) as usize, 1 << (y % 8))
+    }
+}
+
+impl OriginDimensions for OledBuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, self.size.height())
+    }
+}
+
+impl DrawTarget for OledBuffer {
+    type Color = BinaryColor;
+
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let area = self.bounding_box();
+        for Pixel(point, color) in pixels {
+            if !area.contains(point) {
+                continue;
+            }
+            let (index, bit) = Self::position(point.x as u32, point.y as u32);
+            if color.is_on() {
+                self.bytes[index] |= bit;
+            } else {
+                self.bytes[index] &= !bit;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why an OLED couldn't be shown a pattern.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum OledError {
+    /// Nothing answered at [`ADDRESSES`].
+    NotFound,
+    Bus(ErrorKind),
+}
+
+/// An SSD1306 on I2C.
+pub struct Ssd1306<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Ssd1306<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    /// Looks for an OLED at [`ADDRESSES`].
+    pub fn find(mut i2c: I) -> Result<Self, OledError> {
+        let address = ADDRESSES
+            .into_iter()
+            .find(|&address| i2c.read(address, &mut [0]).is_ok())
+            .ok_or(OledError::NotFound)?;
+        Ok(Self::new(i2c, address))
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Sets the controller up for a panel of `size`, with the display left off.
+    pub fn init(&mut self, size: OledSize) -> Result<(), I::Error> {
+        let height = size.height() as u8;
+        // Panels of 32 rows use every other COM line, and need less contrast.
+        let (com_pins, contrast) = match size {
+            OledSize::W128H32 => (0x02, 0x8f),
+            OledSize::W128H64 => (0x12, 0xcf),
+        };
+        self.commands(&[
+            DISPLAY_OFF,
+            0xd5,
+            0x80, // Clock divider and oscillator frequency.
+            0xa8,
+            height - 1, // Multiplex ratio.
+            0xd3,
+            0x00, // No display offset.
+            0x40, // Start line 0.
+            0x8d,
+            0x14, // Charge pump on.
+            0x20,
+            0x00, // Horizontal addressing.
+            0xa1, // Column 127 at SEG0, so that the panel isn't mirrored.
+            0xc8, // COM scan from the bottom.
+            0xda,
+            com_pins,
+            0x81,
+            contrast,
+            0xd9,
+            0xf1, // Pre-charge period.
+            0xdb,
+            0x40, // VCOMH level.
+            0xa4, // Show the display memory.
+            NORMAL,
+            0x2e, // Scrolling off.
+        ])
+    }
+
+    /// Sends `buffer` to the display memory and turns the display on, inverted if `inverted`.
+    pub fn show(&mut self, buffer: &OledBuffer, inverted: bool) -> Result<(), I::Error> {
+        let last_page = (buffer.oled_size().height() / 8 - 1) as u8;
+        self.commands(&[0x21, 0, (WIDTH - 1) as u8, 0x22, 0, last_page])?;
+        self.i2c.transaction(
+            self.address,
+            &mut [Operation::Write(&[DATA]), Operation::Write(buffer.bytes())],
+        )?;
+        self.commands(&[if inverted { INVERTED } else { NORMAL }, DISPLAY_ON])
+    }
+
+    fn commands(&mut self, commands: &[u8]) -> Result<(), I::Error> {
+        self.i2c.transaction(
+            self.address,
+            &mut [Operation::Write(&[COMMANDS]), Operation::Write(commands)],
+        )
+    }
+}
+
+/// Finds the OLED on the bus, sets it up for the size of `buffer` and shows the buffer, inverted
+/// if `inverted`. Returns the address of the OLED.
+pub fn find_and_show<I: I2c>(i2c: I, buffer: &OledBuffer, inverted: bool) -> Result<u8, OledError> {
+    let mut oled = Ssd1306::find(i2c)?;
+    let bus = |error: I::Error| OledError::Bus(error.kind());
+    oled.init(buffer.oled_size()).map_err(bus)?;
+    oled.show(buffer, inverted).map_err(bus)?;
+    Ok(oled.address())
+}
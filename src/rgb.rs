@@ -0,0 +1,187 @@
+//! Chains of addressable RGB LEDs like the WS2812 and SK6812, as used for per-key lighting and
+//! underglow.
+//!
+//! Each LED takes the first 24 bits it receives, green, red and blue with the most significant
+//! bit first, and passes everything after them on to the next LED. A bit is a high pulse followed
+//! by a low one, the high pulse being longer for a one, and the line staying low for at least
+//! 80 µs latches the colours. Since the data only reaches an LED through all the ones before it,
+//! a dead LED usually leaves the rest of the chain dark as well.
+//!
+//! The bits are sent by the RMT peripheral, which takes a pulse code for each bit:
+//!
+//! | Bit 31 | Bits 30-16    | Bit 15 | Bits 14-0      |
+//! |--------|---------------|--------|----------------|
+//! | low    | low, in ticks | high   | high, in ticks |
+
+use defmt::Format;
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+
+#[cfg(target_os = "none")]
+mod chain;
+
+#[cfg(target_os = "none")]
+pub use self::chain::LedChain;
+
+/// Longest chain that can be tested, enough for a full-size keyboard and its underglow.
+pub const MAX_LEDS: u8 = 128;
+
+/// Clock of the RMT peripheral, giving ticks of 12.5 ns.
+pub const RMT_CLOCK_MHZ: u32 = 80;
+
+pub const BITS_PER_LED: usize = 24;
+
+/// Durations of the high and low pulses in ticks, within the tolerances of both the WS2812 and
+/// the SK6812, which wants slightly shorter high pulses.
+const ZERO_HIGH: u16 = 28;
+const ZERO_LOW: u16 = 72;
+const ONE_HIGH: u16 = 56;
+const ONE_LOW: u16 = 44;
+
+/// Time the line stays low after the bits to latch the colours, 80 µs.
+const LATCH_TICKS: u16 = 6400;
+
+/// Pulse code of a zero bit.
+pub const ZERO: u32 = bit_code(ZERO_HIGH, ZERO_LOW);
+/// Pulse code of a one bit.
+pub const ONE: u32 = bit_code(ONE_HIGH, ONE_LOW);
+/// Pulse code keeping the line low for the latch time, split across both halves.
+pub const LATCH: u32 = (LATCH_TICKS / 2) as u32 | ((LATCH_TICKS / 2) as u32) << 16;
+/// Pulse code ending the transmission.
+pub const END: u32 = 0;
+
+/// Longest frame, see [`frame_len`].
+pub const MAX_FRAME_LEN: usize = frame_len(MAX_LEDS as usize);
+
+/// Brightness of the patterns, out of 255, low enough for a long chain powered by USB.
+pub const LEVEL: u8 = 64;
+
+/// Animation frames a walking LED stays lit for.
+pub const WALK_FRAMES: u32 = 5;
+
+/// Hue change of the rainbow per animation frame, out of 256 for the whole wheel.
+const RAINBOW_SPEED: u32 = 4;
+
+const fn bit_code(high: u16, low: u16) -> u32 {
+    high as u32 | 1 << 15 | (low as u32) << 16
+}
+
+/// Number of pulse codes of a frame for `leds` LEDs, including the latch and the end.
+pub const fn frame_len(leds: usize) -> usize {
+    leds * BITS_PER_LED + 2
+}
+
+/// Encodes the colours of the LEDs, first LED first, into pulse codes followed by the latch and
+/// the end. Returns the number of codes written.
+///
+/// Panics if `codes` is too short, see [`frame_len`].
+pub fn encode(colors: impl IntoIterator<Item = Rgb888>, codes: &mut [u32]) -> usize {
+    let mut len = 0;
+    for color in colors {
+        for byte in [color.g(), color.r(), color.b()] {
+            for bit in (0..8).rev() {
+                codes[len] = if byte & (1 << bit) != 0 { ONE } else { ZERO };
+                len += 1;
+            }
+        }
+    }
+    codes[len] = LATCH;
+    codes[len + 1] = END;
+    len + 2
+}
+
+/// What the LEDs of a chain show.
+#[derive(Clone, Copy, Debug, Format, PartialEq, Eq)]
+pub enum Pattern {
+    /// A single white LED moving along the chain.
+    Walk,
+    Red,
+    Green,
+    Blue,
+    /// The colour wheel spread over the chain, turning.
+    Rainbow,
+    /// A single white LED, moved by hand.
+    Step(u8),
+}
+
+impl Pattern {
+    /// The patterns in the order they're cycled through.
+    pub const ALL: [Pattern; 5] = [
+        Pattern::Walk,
+        Pattern::Red,
+        Pattern::Green,
+        Pattern::Blue,
+        Pattern::Rainbow,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Walk => "Walk",
+            Pattern::Red => "Red",
+            Pattern::Green => "Green",
+            Pattern::Blue => "Blue",
+            Pattern::Rainbow => "Rainbow",
+            Pattern::Step(_) => "Step",
+        }
+    }
+
+    /// The next pattern of [`ALL`](Self::ALL). Stepping continues with the first one.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&pattern| pattern == self);
+        Self::ALL[i.map_or(0, |i| (i + 1) % Self::ALL.len())]
+    }
+
+    /// Steps to the next LED, or the previous one unless `forward`, in a chain of `len` LEDs.
+    /// Other patterns start stepping at the LED that was lit at `frame`, or at the first one.
+    pub fn step(self, forward: bool, len: u8, frame: u32) -> Self {
+        let Pattern::Step(led) = self else {
+            return Pattern::Step(self.lit_led(len, frame).unwrap_or(0));
+        };
+        let led = if forward {
+            led.saturating_add(1).min(len.saturating_sub(1))
+        } else {
+            led.saturating_sub(1)
+        };
+        Pattern::Step(led)
+    }
+
+    /// The only LED lit at `frame` in a chain of `len` LEDs, for the patterns that light one.
+    pub fn lit_led(self, len: u8, frame: u32) -> Option<u8> {
+        match self {
+            Pattern::Walk if len > 0 => Some((frame / WALK_FRAMES % u32::from(len)) as u8),
+            Pattern::Step(led) => Some(led),
+            _ => None,
+        }
+    }
+
+    /// Colour of LED `led` at animation frame `frame` in a chain of `len` LEDs.
+    pub fn color(self, led: u8, len: u8, frame: u32) -> Rgb888 {
+        match self {
+            Pattern::Walk | Pattern::Step(_) => {
+                if self.lit_led(len, frame) == Some(led) {
+                    Rgb888::new(LEVEL, LEVEL, LEVEL)
+                } else {
+                    Rgb888::BLACK
+                }
+            }
+            Pattern::Red => Rgb888::new(LEVEL, 0, 0),
+            Pattern::Green => Rgb888::new(0, LEVEL, 0),
+            Pattern::Blue => Rgb888::new(0, 0, LEVEL),
+            Pattern::Rainbow => {
+                let hue = u32::from(led) * 256 / u32::from(len.max(1)) + frame * RAINBOW_SPEED;
+                wheel(hue as u8)
+            }
+        }
+    }
+}
+
+/// Colour at `hue` on a wheel fading from red to green, to blue and back to red, at [`LEVEL`].
+fn wheel(hue: u8) -> Rgb888 {
+    let scale = |value: u8| (u16::from(value) * u16::from(LEVEL) / 255) as u8;
+    let t = hue % 86 * 3;
+    let (rising, falling) = (scale(t), scale(255 - t));
+    match hue / 86 {
+        0 => Rgb888::new(falling, rising, 0),
+        1 => Rgb888::new(0, falling, rising),
+        _ => Rgb888::new(rising, 0, falling),
+    }
+}
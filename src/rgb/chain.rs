@@ -0,0 +1,56 @@
+use embedded_graphics::pixelcolor::Rgb888;
+use esp_hal::{
+    Blocking,
+    gpio::{AnyPin, Level},
+    peripherals::RMT,
+    rmt::{Channel, Error, Rmt, Tx, TxChannelConfig, TxChannelCreator as _},
+    time::Rate,
+};
+use static_cell::ConstStaticCell;
+
+use super::{MAX_FRAME_LEN, MAX_LEDS, RMT_CLOCK_MHZ, encode};
+
+/// A chain of WS2812-style LEDs driven by the first RMT channel.
+pub struct LedChain {
+    /// Taken while a frame is sent, and lost if sending couldn't start.
+    channel: Option<Channel<'static, Blocking, Tx>>,
+    codes: &'static mut [u32; MAX_FRAME_LEN],
+}
+
+impl LedChain {
+    pub fn new(rmt: RMT<'static>, data: AnyPin<'static>) -> Result<Self, Error> {
+        static CODES: ConstStaticCell<[u32; MAX_FRAME_LEN]> =
+            ConstStaticCell::new([0; MAX_FRAME_LEN]);
+
+        let rmt = Rmt::new(rmt, Rate::from_mhz(RMT_CLOCK_MHZ))?;
+        // Two blocks of channel RAM leave more time to refill it while a long frame is sent.
+        let config = TxChannelConfig::default()
+            .with_clk_divider(1)
+            .with_idle_output(true)
+            .with_idle_output_level(Level::Low)
+            .with_memsize(2);
+        let channel = rmt.channel0.configure_tx(data, config)?;
+
+        Ok(Self {
+            channel: Some(channel),
+            codes: CODES.take(),
+        })
+    }
+
+    /// Sends the colours of the LEDs, first LED first, up to [`MAX_LEDS`]. Blocks for about
+    /// 30 µs per LED, since the channel RAM has to be refilled while sending.
+    pub fn show(&mut self, colors: impl IntoIterator<Item = Rgb888>) -> Result<(), Error> {
+        let len = encode(colors.into_iter().take(usize::from(MAX_LEDS)), self.codes);
+        let channel = self.channel.take().ok_or(Error::InvalidArgument)?;
+        match channel.transmit(&self.codes[..len])?.wait() {
+            Ok(channel) => {
+                self.channel = Some(channel);
+                Ok(())
+            }
+            Err((error, channel)) => {
+                self.channel = Some(channel);
+                Err(error)
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crc::{CRC_32_ISO_HDLC, Crc};
+use embedded_io_async::Write as _;
+
+use crate::{display, error::AppError, serial};
+
+/// Marks the start of a screenshot frame in the serial stream, which is otherwise occupied by
+/// defmt log frames.
+pub const MAGIC: [u8; 4] = *b"KVSS";
+pub const VERSION: u8 = 2;
+
+pub const HEADER_SIZE: usize = 18;
+
+/// Checksum of the payload. Log frames from other tasks don't wait for the serial port's lock,
+/// so one can end up in the middle of the pixels.
+pub const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Copy, Clone, Debug, defmt::Format, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PixelFormat {
+    /// 16-bit RGB565, big-endian, as sent to the ST7789.
+    Rgb565Be = 1,
+}
+
+/// Header preceding the raw framebuffer contents. All multi-byte fields are little-endian.
+///
+/// | offset | size | field         |
+/// |--------|------|---------------|
+/// | 0      | 4    | magic `KVSS`  |
+/// | 4      | 1    | version       |
+/// | 5      | 1    | pixel format  |
+/// | 6      | 2    | width         |
+/// | 8      | 2    | height        |
+/// | 10     | 4    | payload bytes |
+/// | 14     | 4    | payload CRC   |
+///
+/// The CRC is a CRC-32 (ISO-HDLC) of the payload, see [`CRC`].
+#[derive(Copy, Clone, Debug, defmt::Format)]
+pub struct Header {
+    pub format: PixelFormat,
+    pub width: u16,
+    pub height: u16,
+    pub payload_len: u32,
+    pub payload_crc: u32,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4] = VERSION;
+        buf[5] = self.format as u8;
+        buf[6..8].copy_from_slice(&self.width.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.height.to_le_bytes());
+        buf[10..14].copy_from_slice(&self.payload_len.to_le_bytes());
+        buf[14..18].copy_from_slice(&self.payload_crc.to_le_bytes());
+        buf
+    }
+}
+
+/// Streams the framebuffer contents to the host.
+pub async fn send(tx: &serial::TxMutex, fb_bytes: &[u8]) -> Result<(), AppError> {
+    let header = Header {
+        format: PixelFormat::Rgb565Be,
+        width: display::WIDTH,
+        height: display::HEIGHT,
+        payload_len: fb_bytes.len() as u32,
+        payload_crc: CRC.checksum(fb_bytes),
+    };
+
+    defmt::info!("sending screenshot: {}", header);
+
+    let mut tx = tx.lock().await;
+    tx.write_all(&header.encode()).await?;
+    tx.write_all(fb_bytes).await?;
+    tx.flush().await?;
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use defmt::{info, warn};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::Instant;
+use embedded_io_async::{Read as _, Write as _};
+use esp_hal::{
+    Async,
+    peripherals::USB_DEVICE,
+    usb_serial_jtag::{UsbSerialJtag, UsbSerialJtagRx, UsbSerialJtagTx},
+};
+use static_cell::StaticCell;
+
+use crate::{
+    board::BoardProfile,
+    console::{self, Line, LineBuffer},
+    error::AppError,
+    history::{self, TestResult},
+    kbd::{self, latency::LatencyMeter, recording::Recorder},
+    settings::{self, Settings},
+    storage::Cursor,
+    stream,
+};
+
+pub type Tx = UsbSerialJtagTx<'static, Async>;
+pub type Rx = UsbSerialJtagRx<'static, Async>;
+pub type TxMutex = Mutex<NoopRawMutex, Tx>;
+
+pub static SCREENSHOT_REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Sets up the USB-serial-JTAG port. The receiving half is meant to be handed over to [`task`].
+///
+/// The defmt logger keeps writing to the same port, so anything sent by the firmware has to be
+/// framed in a way that lets the host tell it apart from log frames.
+pub fn init(usb_device: USB_DEVICE<'static>) -> (Rx, &'static TxMutex) {
+    let (rx, tx) = UsbSerialJtag::new(usb_device).into_async().split();
+
+    static TX: StaticCell<TxMutex> = StaticCell::new();
+
+    (rx, TX.init(Mutex::new(tx)))
+}
+
+#[embassy_executor::task]
+pub async fn task(mut rx: Rx, tx: &'static TxMutex, board: &'static BoardProfile) {
+    info!("starting serial task");
+
+    let mut lines = LineBuffer::<{ console::LINE_CAPACITY }>::new();
+    let mut device = Tester { board };
+
+    loop {
+        let mut buf = [0u8; 16];
+        let Ok(n) = rx.read(&mut buf).await;
+
+        for &byte in &buf[..n] {
+            match lines.push(byte) {
+                Some(Line::Complete(line)) => {
+                    let mut tx = tx.lock().await;
+                    let Ok(()) = console::dispatch(line, &mut *tx, &mut device).await;
+                    let Ok(()) = tx.flush().await;
+                }
+                Some(Line::TooLong) => warn!("command line too long"),
+                None => {}
+            }
+        }
+    }
+}
+
+/// The tester as seen by the [console].
+struct Tester {
+    board: &'static BoardProfile,
+}
+
+impl console::Device for Tester {
+    type Error = AppError;
+
+    fn board(&self) -> &'static BoardProfile {
+        self.board
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        Instant::now().as_millis()
+    }
+
+    fn settings(&self) -> Settings {
+        settings::current()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), AppError> {
+        info!("saving settings: {}", settings);
+        settings::save(settings)
+    }
+
+    fn request_screenshot(&mut self) {
+        SCREENSHOT_REQUEST.signal(());
+    }
+
+    fn set_streaming(&mut self, enabled: bool) {
+        info!("event stream {=str}", if enabled { "on" } else { "off" });
+        stream::set_enabled(enabled);
+    }
+
+    fn with_recorder<R>(&mut self, f: impl FnOnce(&mut Recorder) -> R) -> R {
+        kbd::with_recorder(f)
+    }
+
+    fn with_latency_meter<R>(&mut self, f: impl FnOnce(&mut LatencyMeter) -> R) -> R {
+        kbd::with_latency_meter(f)
+    }
+
+    fn results_newest_first(&mut self) -> Result<Cursor, AppError> {
+        history::newest_first()
+    }
+
+    fn next_result(&mut self, cursor: &mut Cursor) -> Result<Option<TestResult>, AppError> {
+        history::read_next(cursor)
+    }
+}
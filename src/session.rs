@@ -0,0 +1,135 @@
+//! Testing a board: entering its ID, then pressing every key once.
+
+use embassy_time::{Duration, Instant};
+
+use crate::{
+    history::{FIRMWARE_VERSION, TestResult},
+    kbd::{Key, KeyEvent, KeySet, N_KEYS},
+};
+
+/// A press following the release of the same key this quickly is counted as chatter: a bounce
+/// that got through debouncing rather than a second press by a human.
+pub const CHATTER_WINDOW: Duration = Duration::from_millis(50);
+
+/// Board IDs are entered as up to this many decimal digits.
+pub const MAX_BOARD_ID_DIGITS: usize = 9;
+
+/// Board ID being typed on the keypad. `#` confirms it, `*` deletes the last digit. With nothing
+/// typed, `#` asks for the tools and `*` for the history.
+#[derive(Default)]
+pub struct BoardIdInput {
+    digits: [u8; MAX_BOARD_ID_DIGITS],
+    len: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BoardIdAction {
+    /// The input changed and needs to be redrawn.
+    Edited,
+    Confirmed(u32),
+    ShowHistory,
+    ShowTools,
+}
+
+impl BoardIdInput {
+    pub fn handle(&mut self, event: KeyEvent) -> Option<BoardIdAction> {
+        let KeyEvent::KeyDown(key) = event else {
+            return None;
+        };
+
+        match key.char() {
+            digit @ '0'..='9' if self.len < MAX_BOARD_ID_DIGITS => {
+                self.digits[self.len] = digit as u8;
+                self.len += 1;
+                Some(BoardIdAction::Edited)
+            }
+            '*' if self.len == 0 => Some(BoardIdAction::ShowHistory),
+            '*' => {
+                self.len -= 1;
+                Some(BoardIdAction::Edited)
+            }
+            '#' if self.len == 0 => Some(BoardIdAction::ShowTools),
+            '#' => Some(BoardIdAction::Confirmed(self.as_str().parse().ok()?)),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII digits are stored.
+        core::str::from_utf8(&self.digits[..self.len]).unwrap_or_default()
+    }
+}
+
+/// Tracks the keys of the board under test. A key passes once it was pressed and released.
+pub struct Session {
+    board_id: u32,
+    passed: KeySet,
+    held: KeySet,
+    chatter: [u8; N_KEYS],
+    last_release: [Option<Instant>; N_KEYS],
+}
+
+impl Session {
+    pub fn new(board_id: u32) -> Self {
+        Self {
+            board_id,
+            passed: KeySet::ZERO,
+            held: KeySet::ZERO,
+            chatter: [0; N_KEYS],
+            last_release: [None; N_KEYS],
+        }
+    }
+
+    pub fn board_id(&self) -> u32 {
+        self.board_id
+    }
+
+    /// Records a key event that happened at `now`. Returns the key if it just passed.
+    ///
+    /// Releasing a key that was pressed before the session started doesn't count.
+    pub fn handle(&mut self, event: KeyEvent, now: Instant) -> Option<Key> {
+        match event {
+            KeyEvent::KeyDown(key) => {
+                let i = key.index();
+                self.held.set(i, true);
+
+                if self.last_release[i].is_some_and(|release| now - release < CHATTER_WINDOW) {
+                    self.chatter[i] = self.chatter[i].saturating_add(1);
+                }
+
+                None
+            }
+            KeyEvent::KeyUp(key) => {
+                let i = key.index();
+                if !self.held[i] {
+                    return None;
+                }
+                self.held.set(i, false);
+                self.last_release[i] = Some(now);
+
+                let newly_passed = !self.passed[i];
+                self.passed.set(i, true);
+                newly_passed.then_some(key)
+            }
+        }
+    }
+
+    pub fn passed(&self, key: Key) -> bool {
+        self.passed[key.index()]
+    }
+
+    /// All keys passed and were let go of.
+    pub fn is_complete(&self) -> bool {
+        self.passed[..N_KEYS].all() && self.held.not_any()
+    }
+
+    pub fn finish(&self, uptime: Duration) -> TestResult {
+        TestResult {
+            board_id: self.board_id,
+            passed: self.passed,
+            chatter: self.chatter,
+            uptime_ms: uptime.as_millis() as u32,
+            firmware: FIRMWARE_VERSION,
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Settings that survive a reboot.
+//!
+//! Every change is appended as a record to a [`RecordLog`] in the `settings` flash partition,
+//! and the newest record is loaded at boot.
+
+use core::ops::RangeInclusive;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::{
+    board::{self, ADC_PINS},
+    rgb,
+    storage::{self, RecordLog},
+};
+
+#[cfg(target_os = "none")]
+mod flash;
+
+#[cfg(target_os = "none")]
+pub use self::flash::{anon_receiver, current, load, receiver, save};
+
+/// Format of the record payload. Bump it when the meaning of a field changes; fields can be
+/// added to the end without a new version, as older records simply lack them.
+const VERSION: u8 = 1;
+const PAYLOAD_LEN: usize = 10;
+
+/// Size of the settings records in flash, leaving room for future fields.
+pub const SLOT_SIZE: usize = 32;
+
+/// Actuation points that leave room for the release hysteresis and for noise at the bottom.
+pub const ACTUATION_RANGE_PCT: RangeInclusive<u8> = 10..=90;
+
+/// Lengths of the RGB LED chain that can be tested.
+pub const CHAIN_LEN_RANGE: RangeInclusive<u8> = 1..=rgb::MAX_LEDS;
+
+pub type SettingsLog<F> = RecordLog<F, SLOT_SIZE>;
+
+#[derive(Clone, Debug, defmt::Format, PartialEq, Eq)]
+pub struct Settings {
+    /// Backlight brightness while the tester is in use.
+    pub brightness_pct: u8,
+    /// Number of scans a key has to be stable for before a change is reported.
+    pub debounce_ticks: u8,
+    /// Keypad layout shown on the screen.
+    pub layout: u8,
+    pub rotation: Rotation,
+    /// ID of the board profile to use instead of detecting it, see [`crate::board`].
+    pub board: Option<u8>,
+    /// Travel at which analog keys are pressed, in percent, see [`crate::kbd::analog`].
+    pub actuation_pct: u8,
+    /// ADC pins tested as analog inputs, with bit n set for GPIOn, see [`crate::adc`].
+    pub analog_pins: u8,
+    /// Number of LEDs in the RGB LED chain, see [`crate::rgb`].
+    pub chain_len: u8,
+    /// SDA and SCL of the I2C bus to scan, or `None` for the board's own bus, see
+    /// [`crate::i2c_scan`].
+    pub i2c_pins: Option<(u8, u8)>,
+}
+
+#[derive(Copy, Clone, Debug, defmt::Format, PartialEq, Eq)]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            brightness_pct: 40,
+            debounce_ticks: 10,
+            layout: 0,
+            rotation: Rotation::Deg0,
+            board: None,
+            actuation_pct: 50,
+            analog_pins: 0,
+            chain_len: 64,
+            i2c_pins: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn encode(&self) -> [u8; PAYLOAD_LEN] {
+        let (sda, scl) = self.i2c_pins.unwrap_or((u8::MAX, u8::MAX));
+        [
+            self.brightness_pct,
+            self.debounce_ticks,
+            self.layout,
+            self.rotation as u8,
+            self.board.unwrap_or(0),
+            self.actuation_pct,
+            self.analog_pins,
+            self.chain_len,
+            sda,
+            scl,
+        ]
+    }
+
+    /// Decodes a record payload. Fields missing from the payload or holding invalid values are
+    /// set to their defaults. Returns `None` for unknown versions.
+    pub fn decode(version: u8, payload: &[u8]) -> Option<Self> {
+        if version != VERSION {
+            return None;
+        }
+
+        let mut settings = Self::default();
+        let field = |index: usize| payload.get(index).copied();
+
+        if let Some(pct) = field(0).filter(|&pct| pct <= 100) {
+            settings.brightness_pct = pct;
+        }
+        if let Some(ticks) = field(1).filter(|&ticks| ticks > 0) {
+            settings.debounce_ticks = ticks;
+        }
+        if let Some(layout) = field(2) {
+            settings.layout = layout;
+        }
+        if let Some(rotation) = field(3).and_then(Rotation::from_u8) {
+            settings.rotation = rotation;
+        }
+        settings.board = field(4).filter(|&id| id != 0);
+        if let Some(pct) = field(5).filter(|pct| ACTUATION_RANGE_PCT.contains(pct)) {
+            settings.actuation_pct = pct;
+        }
+        if let Some(mask) = field(6) {
+            settings.analog_pins = mask & ((1 << ADC_PINS.end) - 1);
+        }
+        if let Some(len) = field(7).filter(|len| CHAIN_LEN_RANGE.contains(len)) {
+            settings.chain_len = len;
+        }
+        settings.i2c_pins = field(8)
+            .zip(field(9))
+            .filter(|&(sda, scl)| board::valid_i2c_pins(sda, scl));
+
+        Some(settings)
+    }
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::Deg0),
+            90 => Some(Rotation::Deg90),
+            180 => Some(Rotation::Deg180),
+            270 => Some(Rotation::Deg270),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(self) -> u16 {
+        match self {
+            Rotation::Deg0 => 0,
+            Rotation::Deg90 => 90,
+            Rotation::Deg180 => 180,
+            Rotation::Deg270 => 270,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Rotation::Deg0),
+            1 => Some(Rotation::Deg90),
+            2 => Some(Rotation::Deg180),
+            3 => Some(Rotation::Deg270),
+            _ => None,
+        }
+    }
+}
+
+/// The current settings along with the log they're persisted in.
+pub struct SettingsStore<F> {
+    log: SettingsLog<F>,
+    current: Settings,
+}
+
+impl<F: NorFlash> SettingsStore<F> {
+    /// Loads the newest settings, falling back to the defaults if there are none or they were
+    /// written by an incompatible firmware version.
+    pub fn open(flash: F) -> Result<Self, storage::Error<F::Error>> {
+        let mut log = SettingsLog::open(flash)?;
+
+        let mut buf = [0; SLOT_SIZE];
+        let current = log
+            .newest(&mut buf)?
+            .and_then(|record| Settings::decode(record.version, record.payload))
+            .unwrap_or_default();
+
+        Ok(Self { log, current })
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.current
+    }
+
+    /// Persists the settings. Nothing is written if they didn't change.
+    pub fn save(&mut self, settings: &Settings) -> Result<(), storage::Error<F::Error>> {
+        if *settings == self.current {
+            return Ok(());
+        }
+
+        self.log.append(VERSION, &settings.encode())?;
+        self.current = settings.clone();
+
+        Ok(())
+    }
+}
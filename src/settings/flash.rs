@@ -0,0 +1,85 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    watch::{DynAnonReceiver, DynReceiver, Watch},
+};
+
+use super::{Settings, SettingsStore};
+use crate::{
+    error::{AppError, Context, ResultExt as _},
+    storage::{Partition, Unavailable},
+};
+
+const PARTITION_LABEL: &str = "settings";
+
+static STORE: Mutex<CriticalSectionRawMutex, RefCell<Option<SettingsStore<Partition>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// The settings in effect, updated whenever they're saved.
+static CURRENT: Watch<CriticalSectionRawMutex, Settings, 1> = Watch::new();
+
+/// Loads the settings from flash. The defaults are used if the settings partition is missing or
+/// can't be read, in which case changes can't be saved.
+///
+/// Requires [`crate::storage::init`].
+pub fn load() -> Settings {
+    let settings = open_store();
+    CURRENT.sender().send(settings.clone());
+    settings
+}
+
+fn open_store() -> Settings {
+    let Some(partition) = Partition::find(PARTITION_LABEL) else {
+        warn!(
+            "no `{=str}` partition, using default settings",
+            PARTITION_LABEL
+        );
+        return Settings::default();
+    };
+
+    match SettingsStore::open(partition).context(Context::SettingsLoad) {
+        Ok(store) => {
+            let settings = store.settings().clone();
+            info!("loaded settings: {}", settings);
+            STORE.lock(|cell| cell.replace(Some(store)));
+            settings
+        }
+        Err(error) => {
+            warn!("using default settings: {}", error);
+            Settings::default()
+        }
+    }
+}
+
+/// Persists the settings, to be picked up by [`load`] at the next boot, and passes them on to
+/// the [receivers](receiver).
+pub fn save(settings: &Settings) -> Result<(), AppError> {
+    STORE.lock(|cell| {
+        let mut store = cell.borrow_mut();
+        let store = store
+            .as_mut()
+            .ok_or(Unavailable)
+            .context(Context::SettingsSave)?;
+
+        store.save(settings).context(Context::SettingsSave)
+    })?;
+
+    CURRENT.sender().send(settings.clone());
+    Ok(())
+}
+
+/// The settings in effect. Defaults until [`load`] was called.
+pub fn current() -> Settings {
+    CURRENT.try_get().unwrap_or_default()
+}
+
+/// Waits for saved settings. Only one task can do so; others can poll an [`anon_receiver`].
+pub fn receiver() -> Option<DynReceiver<'static, Settings>> {
+    CURRENT.dyn_receiver()
+}
+
+pub fn anon_receiver() -> DynAnonReceiver<'static, Settings> {
+    CURRENT.dyn_anon_receiver()
+}
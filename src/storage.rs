@@ -0,0 +1,274 @@
+//! Logs of small records in NOR flash.
+//!
+//! A [`RecordLog`] uses its flash region as a ring of fixed-size slots. Records are appended to
+//! the next free slot, and when the log reaches a new sector, that sector is erased first,
+//! dropping the oldest records in it. This spreads the erase cycles evenly over the region, and
+//! a record that was torn by a reset is simply skipped.
+//!
+//! Each slot holds one record, little-endian:
+//!
+//! | Offset  | Size | Content                                            |
+//! |---------|------|----------------------------------------------------|
+//! | 0       | 2    | Magic `b"KR"`                                      |
+//! | 2       | 1    | Payload format version, up to the user of the log  |
+//! | 3       | 1    | Payload length `n`                                 |
+//! | 4       | 4    | Sequence number, increasing with every record      |
+//! | 8       | `n`  | Payload                                            |
+//! | 8 + `n` | 4    | CRC-32 (ISO-HDLC) of all of the above              |
+//!
+//! The rest of the slot stays erased.
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+use embedded_storage::nor_flash::NorFlash;
+
+#[cfg(target_os = "none")]
+mod partition;
+
+#[cfg(target_os = "none")]
+pub use self::partition::{Partition, init};
+
+const MAGIC: [u8; 2] = *b"KR";
+const HEADER_SIZE: usize = 8;
+const CRC_SIZE: usize = 4;
+const ERASED: u8 = 0xff;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug, defmt::Format)]
+pub enum Error<E> {
+    Flash(E),
+    /// The region has room for less than two sectors, so erasing one would lose the newest
+    /// record.
+    RegionTooSmall,
+    PayloadTooLarge,
+}
+
+/// Returned when using storage whose partition is missing or couldn't be opened.
+#[derive(Debug, defmt::Format)]
+pub struct Unavailable;
+
+/// A record read from a [`RecordLog`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub seq: u32,
+    pub version: u8,
+    pub payload: &'a [u8],
+}
+
+/// Position in a [`RecordLog`] while reading from the newest to the oldest record.
+#[derive(Clone, Debug)]
+pub struct Cursor {
+    offset: u32,
+    remaining_slots: u32,
+    /// Sequence number of the record returned last. Older records have lower ones.
+    last_seq: Option<u32>,
+}
+
+/// Ring of records with `SLOT_SIZE` bytes each, see the [module docs](self).
+pub struct RecordLog<F, const SLOT_SIZE: usize> {
+    flash: F,
+    /// Size of the part of the region made up of whole sectors.
+    size: u32,
+    /// Offset and sequence number of the newest record.
+    newest: Option<(u32, u32)>,
+    /// Offset of the slot to try first for the next record.
+    next: u32,
+}
+
+impl<F: NorFlash, const SLOT_SIZE: usize> RecordLog<F, SLOT_SIZE> {
+    pub const MAX_PAYLOAD_LEN: usize = SLOT_SIZE - HEADER_SIZE - CRC_SIZE;
+
+    /// Scans the region for the newest record.
+    pub fn open(mut flash: F) -> Result<Self, Error<F::Error>> {
+        const {
+            assert!(
+                SLOT_SIZE > HEADER_SIZE + CRC_SIZE && Self::MAX_PAYLOAD_LEN <= u8::MAX as usize
+            );
+            assert!(
+                SLOT_SIZE.is_multiple_of(F::READ_SIZE) && SLOT_SIZE.is_multiple_of(F::WRITE_SIZE)
+            );
+            assert!(F::ERASE_SIZE.is_multiple_of(SLOT_SIZE));
+        }
+
+        let sectors = flash.capacity() / F::ERASE_SIZE;
+        if sectors < 2 {
+            return Err(Error::RegionTooSmall);
+        }
+
+        let size = (sectors * F::ERASE_SIZE) as u32;
+        let mut newest: Option<(u32, u32)> = None;
+        let mut buf = [0; SLOT_SIZE];
+
+        for sector in (0..size).step_by(F::ERASE_SIZE) {
+            for offset in (sector..sector + F::ERASE_SIZE as u32).step_by(SLOT_SIZE) {
+                flash.read(offset, &mut buf).map_err(Error::Flash)?;
+
+                if is_erased(&buf) {
+                    // Slots are filled in order, so the rest of the sector is free.
+                    break;
+                }
+
+                if let Some(record) = decode(&buf)
+                    && newest.is_none_or(|(_, seq)| record.seq > seq)
+                {
+                    newest = Some((offset, record.seq));
+                }
+            }
+        }
+
+        let mut log = Self {
+            flash,
+            size,
+            newest,
+            next: 0,
+        };
+        if let Some((offset, _)) = newest {
+            log.next = log.slot_after(offset);
+        }
+
+        Ok(log)
+    }
+
+    /// Reads the newest record into `buf`.
+    pub fn newest<'b>(
+        &mut self,
+        buf: &'b mut [u8; SLOT_SIZE],
+    ) -> Result<Option<Record<'b>>, Error<F::Error>> {
+        let Some((offset, _)) = self.newest else {
+            return Ok(None);
+        };
+
+        self.flash.read(offset, buf).map_err(Error::Flash)?;
+        Ok(decode(buf))
+    }
+
+    /// Returns a cursor for [`read_next`](Self::read_next), starting at the newest record.
+    pub fn newest_first(&self) -> Cursor {
+        match self.newest {
+            Some((offset, _)) => Cursor {
+                offset,
+                remaining_slots: self.size / SLOT_SIZE as u32,
+                last_seq: None,
+            },
+            None => Cursor {
+                offset: 0,
+                remaining_slots: 0,
+                last_seq: None,
+            },
+        }
+    }
+
+    /// Reads the record at the cursor into `buf` and moves the cursor to the next older one.
+    pub fn read_next<'b>(
+        &mut self,
+        cursor: &mut Cursor,
+        buf: &'b mut [u8; SLOT_SIZE],
+    ) -> Result<Option<Record<'b>>, Error<F::Error>> {
+        while cursor.remaining_slots > 0 {
+            let offset = cursor.offset;
+            cursor.offset = self.slot_before(offset);
+            cursor.remaining_slots -= 1;
+
+            self.flash.read(offset, buf).map_err(Error::Flash)?;
+
+            let Some(seq) = decode(buf).map(|record| record.seq) else {
+                // Erased or torn slot.
+                continue;
+            };
+
+            if cursor.last_seq.is_some_and(|last_seq| seq >= last_seq) {
+                // Went around the ring into newer records.
+                break;
+            }
+            cursor.last_seq = Some(seq);
+
+            return Ok(decode(buf));
+        }
+
+        cursor.remaining_slots = 0;
+        Ok(None)
+    }
+
+    /// Appends a record, erasing the next sector first if the current one is full.
+    pub fn append(&mut self, version: u8, payload: &[u8]) -> Result<(), Error<F::Error>> {
+        if payload.len() > Self::MAX_PAYLOAD_LEN {
+            return Err(Error::PayloadTooLarge);
+        }
+
+        let seq = self.newest.map_or(0, |(_, seq)| seq.wrapping_add(1));
+        let offset = self.free_slot()?;
+
+        let mut buf = [ERASED; SLOT_SIZE];
+        buf[0..2].copy_from_slice(&MAGIC);
+        buf[2] = version;
+        buf[3] = payload.len() as u8;
+        buf[4..8].copy_from_slice(&seq.to_le_bytes());
+
+        let crc_offset = HEADER_SIZE + payload.len();
+        buf[HEADER_SIZE..crc_offset].copy_from_slice(payload);
+        let crc = CRC.checksum(&buf[..crc_offset]);
+        buf[crc_offset..crc_offset + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+        self.flash.write(offset, &buf).map_err(Error::Flash)?;
+
+        self.newest = Some((offset, seq));
+        self.next = self.slot_after(offset);
+
+        Ok(())
+    }
+
+    /// Finds the next writable slot, skipping the remains of torn writes.
+    fn free_slot(&mut self) -> Result<u32, Error<F::Error>> {
+        let mut buf = [0; SLOT_SIZE];
+
+        loop {
+            let offset = self.next;
+
+            if (offset as usize).is_multiple_of(F::ERASE_SIZE) {
+                self.flash
+                    .erase(offset, offset + F::ERASE_SIZE as u32)
+                    .map_err(Error::Flash)?;
+                return Ok(offset);
+            }
+
+            self.flash.read(offset, &mut buf).map_err(Error::Flash)?;
+            if is_erased(&buf) {
+                return Ok(offset);
+            }
+
+            self.next = self.slot_after(offset);
+        }
+    }
+
+    fn slot_after(&self, offset: u32) -> u32 {
+        (offset + SLOT_SIZE as u32) % self.size
+    }
+
+    fn slot_before(&self, offset: u32) -> u32 {
+        (offset + self.size - SLOT_SIZE as u32) % self.size
+    }
+}
+
+fn is_erased(slot: &[u8]) -> bool {
+    slot.iter().all(|&byte| byte == ERASED)
+}
+
+fn decode(slot: &[u8]) -> Option<Record<'_>> {
+    if slot[0..2] != MAGIC {
+        return None;
+    }
+
+    let len = slot[3] as usize;
+    let crc_offset = HEADER_SIZE + len;
+    let crc = slot.get(crc_offset..crc_offset + CRC_SIZE)?;
+
+    if CRC.checksum(&slot[..crc_offset]).to_le_bytes() != crc {
+        return None;
+    }
+
+    Some(Record {
+        seq: u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]),
+        version: slot[2],
+        payload: &slot[HEADER_SIZE..crc_offset],
+    })
+}
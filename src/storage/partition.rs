@@ -0,0 +1,96 @@
+use core::cell::RefCell;
+
+use defmt::warn;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+use esp_bootloader_esp_idf::partitions::{self, PARTITION_TABLE_MAX_LEN};
+use esp_hal::peripherals::FLASH;
+use esp_storage::{FlashStorage, FlashStorageError};
+
+/// Flash shared by all partitions.
+static FLASH_STORAGE: Mutex<CriticalSectionRawMutex, RefCell<Option<FlashStorage<'static>>>> =
+    Mutex::new(RefCell::new(None));
+
+pub fn init(flash: FLASH<'static>) {
+    FLASH_STORAGE.lock(|storage| storage.replace(Some(FlashStorage::new(flash))));
+}
+
+fn with_flash<R>(f: impl FnOnce(&mut FlashStorage<'static>) -> R) -> R {
+    FLASH_STORAGE.lock(|storage| {
+        f(storage
+            .borrow_mut()
+            .as_mut()
+            .expect("storage not initialized"))
+    })
+}
+
+/// A data partition from the partition table, accessed through the shared flash.
+#[derive(Debug)]
+pub struct Partition {
+    offset: u32,
+    size: u32,
+}
+
+impl Partition {
+    /// Looks up a partition by its label in the partition table (see `partitions.csv`).
+    pub fn find(label: &str) -> Option<Self> {
+        let mut table_buf = [0; PARTITION_TABLE_MAX_LEN];
+
+        let table =
+            match with_flash(|flash| partitions::read_partition_table(flash, &mut table_buf)) {
+                Ok(table) => table,
+                Err(error) => {
+                    warn!("couldn't read the partition table: {}", error);
+                    return None;
+                }
+            };
+
+        table
+            .iter()
+            .find(|entry| entry.label_as_str() == label)
+            .map(|entry| Self {
+                offset: entry.offset(),
+                size: entry.len(),
+            })
+    }
+
+    fn check_bounds(&self, offset: u32, len: usize) -> Result<u32, FlashStorageError> {
+        match offset.checked_add(len as u32) {
+            Some(end) if end <= self.size => Ok(self.offset + offset),
+            _ => Err(FlashStorageError::OutOfBounds),
+        }
+    }
+}
+
+impl ErrorType for Partition {
+    type Error = FlashStorageError;
+}
+
+impl ReadNorFlash for Partition {
+    const READ_SIZE: usize = FlashStorage::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let address = self.check_bounds(offset, bytes.len())?;
+        with_flash(|flash| ReadNorFlash::read(flash, address, bytes))
+    }
+
+    fn capacity(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl NorFlash for Partition {
+    const WRITE_SIZE: usize = FlashStorage::WRITE_SIZE;
+    const ERASE_SIZE: usize = FlashStorage::ERASE_SIZE;
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let address = self.check_bounds(offset, bytes.len())?;
+        with_flash(|flash| NorFlash::write(flash, address, bytes))
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let len = to.checked_sub(from).ok_or(FlashStorageError::OutOfBounds)?;
+        let start = self.check_bounds(from, len as usize)?;
+        with_flash(|flash| flash.erase(start, start + len))
+    }
+}
@@ -0,0 +1,114 @@
+//! Event stream for host tools, see [`keyvisor_protocol`] for the format.
+//!
+//! The key scanner and the UI hand [`Update`]s to the stream while it's switched on, and they're
+//! sent over the serial port as protocol events.
+
+use core::fmt::{self, Write as _};
+
+use embassy_time::Instant;
+use keyvisor_protocol::Event;
+
+use crate::{
+    history::{FIRMWARE_VERSION, TestResult},
+    kbd::{KeyEvent, N_KEYS},
+    text::TextBuf,
+};
+
+#[cfg(target_os = "none")]
+mod channel;
+
+#[cfg(target_os = "none")]
+pub use self::channel::{emit, set_enabled, task};
+
+/// Longest event line.
+pub const LINE_CAPACITY: usize = 128;
+
+#[derive(Clone, Debug, defmt::Format)]
+pub enum Update {
+    Key {
+        event: KeyEvent,
+        at: Instant,
+    },
+    Scan {
+        stats: ScanStats,
+        debounce_ticks: u8,
+        at: Instant,
+    },
+    Verdict {
+        result: TestResult,
+        at: Instant,
+    },
+}
+
+/// Activity of the key scanner over a period of time.
+#[derive(Clone, Debug, Default, defmt::Format, PartialEq, Eq)]
+pub struct ScanStats {
+    /// Scans of the whole matrix.
+    pub scans: u32,
+    /// Raw level changes of any key, including bounces.
+    pub changes: u32,
+    /// Debounced key events.
+    pub events: u32,
+}
+
+impl Update {
+    /// Writes the update as a protocol event, without a line break.
+    pub fn write_json(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            Update::Key { event, at } => {
+                let (key, pressed) = match *event {
+                    KeyEvent::KeyDown(key) => (key, true),
+                    KeyEvent::KeyUp(key) => (key, false),
+                };
+                let mut label = [0; 4];
+
+                Event::Key {
+                    ms: at.as_millis(),
+                    key: key.char().encode_utf8(&mut label),
+                    row: key.row,
+                    col: key.col,
+                    pressed,
+                }
+                .write(w)
+            }
+            Update::Scan {
+                stats,
+                debounce_ticks,
+                at,
+            } => Event::Scan {
+                ms: at.as_millis(),
+                scans: stats.scans,
+                changes: stats.changes,
+                events: stats.events,
+                debounce: *debounce_ticks,
+            }
+            .write(w),
+            Update::Verdict { result, at } => {
+                let mut failed = TextBuf::<{ 2 * N_KEYS }>::new();
+                result.write_failed_keys(&mut failed)?;
+
+                Event::Verdict {
+                    ms: at.as_millis(),
+                    board: result.board_id,
+                    passed: result.passed(),
+                    failed: failed.as_str(),
+                    chatter: result.total_chatter(),
+                }
+                .write(w)
+            }
+        }
+    }
+}
+
+/// Writes the event announcing the stream, without a line break.
+pub fn write_hello(board: &str, w: &mut impl fmt::Write) -> fmt::Result {
+    let [major, minor, patch] = FIRMWARE_VERSION;
+    let mut firmware = TextBuf::<12>::new();
+    write!(firmware, "{major}.{minor}.{patch}")?;
+
+    Event::Hello {
+        firmware: firmware.as_str(),
+        board,
+    }
+    .write(w)
+}
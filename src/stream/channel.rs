@@ -0,0 +1,47 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::{debug, info, warn};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embedded_io_async::Write as _;
+
+use super::{LINE_CAPACITY, Update};
+use crate::{serial, text::TextBuf};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CHANNEL: Channel<CriticalSectionRawMutex, Update, 32> = Channel::new();
+
+/// Switches the stream on or off. Updates still waiting to be sent are dropped when switching
+/// it off.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        CHANNEL.clear();
+    }
+}
+
+/// Queues an update to be sent if the stream is on. Updates are dropped while the queue is full.
+pub fn emit(update: Update) {
+    if ENABLED.load(Ordering::Relaxed) && CHANNEL.try_send(update).is_err() {
+        debug!("stream queue full, dropping update");
+    }
+}
+
+#[embassy_executor::task]
+pub async fn task(tx: &'static serial::TxMutex) {
+    info!("starting stream task");
+
+    loop {
+        let update = CHANNEL.receive().await;
+
+        let mut line = TextBuf::<LINE_CAPACITY>::new();
+        if update.write_json(&mut line).is_err() {
+            warn!("update too long for the stream: {}", update);
+            continue;
+        }
+
+        let mut tx = tx.lock().await;
+        let Ok(()) = tx.write_all(line.as_bytes()).await;
+        let Ok(()) = tx.write_all(b"\n").await;
+        let Ok(()) = tx.flush().await;
+    }
+}
@@ -1,25 +1,45 @@
+use core::fmt::Write as _;
+
 use embedded_graphics::{
-    pixelcolor::Rgb565,
+    pixelcolor::{Rgb565, Rgb888},
     prelude::*,
-    primitives::{PrimitiveStyleBuilder, Rectangle, RoundedRectangle},
+    primitives::{
+        Circle, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, RoundedRectangle, Sector,
+    },
     text::{Alignment, Baseline, Text, TextStyleBuilder},
 };
-use u8g2_fonts::{U8g2TextStyle, fonts::u8g2_font_helvB18_te};
+use u8g2_fonts::{
+    U8g2TextStyle,
+    fonts::{
+        u8g2_font_helvB12_tr, u8g2_font_helvB14_tr, u8g2_font_helvB18_te, u8g2_font_helvB24_tr,
+        u8g2_font_helvR10_tr, u8g2_font_helvR12_tr, u8g2_font_helvR14_tr,
+    },
+};
 
 use crate::{
-    display::{self, DisplayState},
-    error::AppError,
-    kbd::{Key, KeyEvent, N_COLS, N_ROWS},
+    adc::{self, Check, InputStats, Thresholds},
+    bounce::Capture,
+    display, encoder,
+    history::TestResult,
+    i2c_scan::{Device, ScanError},
+    kbd::{
+        Key, KeyEvent, KeySet, N_COLS, N_KEYS, N_ROWS,
+        analog::{self, AnalogKeys},
+        latency::{self, Histogram, Millis},
+    },
+    oled::{self, OledBuffer, OledError, TestPattern},
+    rgb::{self, Pattern},
+    text::TextBuf,
 };
 
-#[embassy_executor::task]
-pub async fn task(display_state: DisplayState) {
-    defmt::info!("starting display task");
-    ui_main(display_state).await.expect("ui task error");
-}
+pub mod app;
+
+#[cfg(target_os = "none")]
+pub use self::app::{ToolHardware, task};
 
-async fn ui_main(mut display_state: DisplayState) -> Result<(), AppError> {
-    display_state.fb.clear(Rgb565::BLACK);
+/// Draws the keypad with all keys released.
+pub fn draw_keypad<D: DrawTarget<Color = Rgb565>>(target: &mut D) -> Result<(), D::Error> {
+    target.clear(Rgb565::BLACK)?;
 
     for row in 0..N_ROWS {
         for col in 0..N_COLS {
@@ -27,42 +47,36 @@ async fn ui_main(mut display_state: DisplayState) -> Result<(), AppError> {
                 row: row as u8,
                 col: col as u8,
             };
-            Button::new(key, button_pos(row, col), ButtonStyle::unpressed())
-                .draw(&mut display_state.fb);
+            Button::new(key, button_pos(row, col), ButtonStyle::unpressed()).draw(target)?;
         }
     }
 
-    display_state
-        .display
-        .show_raw_data(
-            0,
-            0,
-            display::WIDTH,
-            display::HEIGHT,
-            display_state.fb.as_bytes(),
-        )
-        .await?;
-
-    let mut kbd_events = crate::kbd::subscriber()?;
-
-    loop {
-        let bounds = match kbd_events.next_message_pure().await {
-            KeyEvent::KeyDown(key) => update(key, Direction::Down, &mut display_state.fb),
-            KeyEvent::KeyUp(key) => update(key, Direction::Up, &mut display_state.fb),
-        }?;
-
-        let y = bounds.top_left.y as usize;
-        let height = bounds.size.height as usize;
+    Ok(())
+}
 
-        let stripe_start = y * display_state.fb.width() * display::PIXEL_SIZE;
-        let stripe_end = (y + height) * display_state.fb.width() * display::PIXEL_SIZE;
+/// Redraws the button of a key that passed the test and returns the area that changed.
+pub fn draw_key_passed<D: DrawTarget<Color = Rgb565>>(
+    key: Key,
+    target: &mut D,
+) -> Result<Rectangle, D::Error> {
+    let btn = Button::new(
+        key,
+        button_pos(key.row as usize, key.col as usize),
+        ButtonStyle::passed(),
+    );
+    btn.draw(target)?;
 
-        let pixel_data = &display_state.fb.as_bytes()[stripe_start..stripe_end];
+    Ok(btn.bounds())
+}
 
-        display_state
-            .display
-            .show_raw_data(0, y as u16, display::WIDTH, height as u16, pixel_data)
-            .await?;
+/// Redraws the button of the key affected by the event and returns the area that changed.
+pub fn draw_key_event<D: DrawTarget<Color = Rgb565>>(
+    event: KeyEvent,
+    target: &mut D,
+) -> Result<Rectangle, D::Error> {
+    match event {
+        KeyEvent::KeyDown(key) => update(key, Direction::Down, target),
+        KeyEvent::KeyUp(key) => update(key, Direction::Up, target),
     }
 }
 
@@ -110,6 +124,14 @@ impl ButtonStyle {
         }
     }
 
+    fn passed() -> Self {
+        Self {
+            bg_color: Rgb565::CSS_BLACK,
+            border_color: Rgb565::CSS_LIME_GREEN,
+            text_color: Rgb565::CSS_LIME_GREEN,
+        }
+    }
+
     fn unpressed() -> Self {
         Self {
             bg_color: Rgb565::CSS_BLACK,
@@ -181,3 +203,1578 @@ impl Drawable for Button {
         Ok(())
     }
 }
+
+const MARGIN: i32 = 10;
+const HEADER_HEIGHT: u32 = 36;
+
+/// Top of the area below the header.
+const BODY_TOP: i32 = HEADER_HEIGHT as i32 + 16;
+
+fn left_top() -> embedded_graphics::text::TextStyle {
+    TextStyleBuilder::new()
+        .alignment(Alignment::Left)
+        .baseline(Baseline::Top)
+        .build()
+}
+
+/// Clears the screen and draws a header bar with a title. Returns the end of the title.
+fn draw_header<D: DrawTarget<Color = Rgb565>>(
+    title: &str,
+    color: Rgb565,
+    target: &mut D,
+) -> Result<Point, D::Error> {
+    target.clear(Rgb565::BLACK)?;
+
+    let header = Rectangle::new(
+        Point::zero(),
+        Size::new(display::WIDTH.into(), HEADER_HEIGHT),
+    );
+    header
+        .into_styled(PrimitiveStyleBuilder::new().fill_color(color).build())
+        .draw(target)?;
+
+    Text::with_text_style(
+        title,
+        Point::new(MARGIN, header.center().y),
+        U8g2TextStyle::new(u8g2_font_helvB14_tr, Rgb565::CSS_WHITE),
+        TextStyleBuilder::new()
+            .alignment(Alignment::Left)
+            .baseline(Baseline::Middle)
+            .build(),
+    )
+    .draw(target)
+}
+
+/// Draws a line of text at the bottom, usually telling which keys do what.
+fn draw_footer<D: DrawTarget<Color = Rgb565>>(text: &str, target: &mut D) -> Result<(), D::Error> {
+    Text::with_text_style(
+        text,
+        Point::new(display::WIDTH as i32 / 2, display::HEIGHT as i32 - MARGIN),
+        U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_DIM_GRAY),
+        TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Bottom)
+            .build(),
+    )
+    .draw(target)?;
+
+    Ok(())
+}
+
+/// Draws a line of text at `y` and returns where the next line starts.
+fn draw_line<D: DrawTarget<Color = Rgb565>>(
+    text: &str,
+    y: i32,
+    style: U8g2TextStyle<Rgb565>,
+    gap: i32,
+    target: &mut D,
+) -> Result<i32, D::Error> {
+    let line = Text::with_text_style(text, Point::new(MARGIN, y), style, left_top());
+    line.draw(target)?;
+
+    Ok(line.bounding_box().bottom_right().map_or(y, |p| p.y) + gap)
+}
+
+/// Full-screen report of an error that stopped the UI.
+pub struct ErrorScreen<'a> {
+    /// Stable numeric error code, shown in the header.
+    pub code: u16,
+    /// Short description of what went wrong.
+    pub kind: &'a str,
+    /// Part of the tester the error originates from.
+    pub subsystem: &'a str,
+    /// What the tester was doing when the error happened.
+    pub context: &'a str,
+    /// What the user can do about it. May span several lines separated by `\n`.
+    pub hint: &'a str,
+}
+
+impl Drawable for ErrorScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let title_end = draw_header("Error", Rgb565::CSS_DARK_RED, target)?;
+
+        let mut code = TextBuf::<6>::new();
+        let _ = write!(code, "E{}", self.code);
+        Text::with_text_style(
+            code.as_str(),
+            title_end + Point::new(8, 0),
+            U8g2TextStyle::new(u8g2_font_helvR14_tr, Rgb565::CSS_LIGHT_GRAY),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Left)
+                .baseline(Baseline::Middle)
+                .build(),
+        )
+        .draw(target)?;
+
+        let mut y = draw_line(
+            self.kind,
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvB18_te, Rgb565::CSS_SALMON),
+            8,
+            target,
+        )?;
+
+        let subsystem = Text::with_text_style(
+            self.subsystem,
+            Point::new(MARGIN, y),
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, Rgb565::CSS_LIGHT_GRAY),
+            left_top(),
+        );
+        let subsystem_end = subsystem.draw(target)?;
+        Text::with_text_style(
+            self.context,
+            subsystem_end + Point::new(6, 0),
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY),
+            left_top(),
+        )
+        .draw(target)?;
+        y = subsystem.bounding_box().bottom_right().map_or(y, |p| p.y) + 20;
+
+        draw_line(
+            self.hint,
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_WHITE),
+            0,
+            target,
+        )?;
+
+        draw_footer("Press any key to restart", target)
+    }
+}
+
+/// Asks for the ID of the board about to be tested.
+pub struct BoardIdScreen<'a> {
+    /// Digits typed so far.
+    pub input: &'a str,
+}
+
+impl Drawable for BoardIdScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("New test", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let y = draw_line(
+            "Board ID",
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, Rgb565::CSS_LIGHT_GRAY),
+            8,
+            target,
+        )?;
+
+        let field = Rectangle::new(
+            Point::new(MARGIN, y),
+            Size::new(display::WIDTH as u32 - 2 * MARGIN as u32, 44),
+        );
+        RoundedRectangle::with_equal_corners(field, Size::new(6, 6))
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .stroke_color(Rgb565::CSS_WHITE)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(target)?;
+
+        let (input, color) = if self.input.is_empty() {
+            ("-", Rgb565::CSS_DIM_GRAY)
+        } else {
+            (self.input, Rgb565::CSS_WHITE)
+        };
+        Text::with_text_style(
+            input,
+            field.center(),
+            U8g2TextStyle::new(u8g2_font_helvB24_tr, color),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Middle)
+                .build(),
+        )
+        .draw(target)?;
+
+        draw_line(
+            "Type the ID on the board's keypad.\n#  start the test, or open the tools\n*  delete, or show the history",
+            field.bottom_right().map_or(y, |p| p.y) + 16,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_WHITE),
+            0,
+            target,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Outcome of a test session, either right after it or from the history.
+pub struct ResultScreen<'a> {
+    pub title: &'a str,
+    pub result: &'a TestResult,
+    pub footer: &'a str,
+}
+
+impl Drawable for ResultScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let result = self.result;
+        let (verdict, header_color, verdict_color) = if result.passed() {
+            ("PASS", Rgb565::CSS_DARK_GREEN, Rgb565::CSS_LIME_GREEN)
+        } else {
+            ("FAIL", Rgb565::CSS_DARK_RED, Rgb565::CSS_SALMON)
+        };
+
+        draw_header(self.title, header_color, target)?;
+
+        let mut line = TextBuf::<48>::new();
+        let _ = write!(line, "Board {}", result.board_id);
+        let y = draw_line(
+            line.as_str(),
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvB18_te, Rgb565::CSS_WHITE),
+            8,
+            target,
+        )?;
+
+        let mut y = draw_line(
+            verdict,
+            y,
+            U8g2TextStyle::new(u8g2_font_helvB24_tr, verdict_color),
+            12,
+            target,
+        )?;
+
+        let details = U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY);
+
+        let mut line = TextBuf::<48>::new();
+        if result.passed() {
+            let _ = write!(line, "All {N_KEYS} keys passed");
+        } else {
+            let _ = line.write_str("Failed: ");
+            let _ = result.write_failed_keys(&mut line);
+        }
+        y = draw_line(line.as_str(), y, details.clone(), 6, target)?;
+
+        let mut line = TextBuf::<48>::new();
+        match result.total_chatter() {
+            0 => {
+                let _ = line.write_str("No chatter");
+            }
+            total => {
+                let _ = write!(line, "Chatter: {total} (");
+                let chattering = Key::all().filter(|key| result.chatter[key.index()] > 0);
+                for (i, key) in chattering.enumerate() {
+                    let separator = if i == 0 { "" } else { " " };
+                    let count = result.chatter[key.index()];
+                    let _ = write!(line, "{separator}{}:{count}", key.char());
+                }
+                let _ = line.write_char(')');
+            }
+        }
+        y = draw_line(line.as_str(), y, details, 12, target)?;
+
+        let mut line = TextBuf::<48>::new();
+        let minutes = result.uptime_ms / 60_000;
+        if minutes == 0 {
+            let _ = write!(line, "{} s", result.uptime_ms / 1000);
+        } else {
+            let _ = write!(line, "{minutes} min");
+        }
+        let [major, minor, patch] = result.firmware;
+        let _ = write!(line, " after boot, firmware {major}.{minor}.{patch}");
+        draw_line(
+            line.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_DIM_GRAY),
+            0,
+            target,
+        )?;
+
+        draw_footer(self.footer, target)
+    }
+}
+
+/// A title and a short message, e.g. for an empty history.
+pub struct NoticeScreen<'a> {
+    pub title: &'a str,
+    /// May span several lines separated by `\n`.
+    pub text: &'a str,
+    pub footer: &'a str,
+}
+
+impl Drawable for NoticeScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header(self.title, Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        draw_line(
+            self.text,
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            0,
+            target,
+        )?;
+
+        draw_footer(self.footer, target)
+    }
+}
+
+/// Tools offered besides testing boards, numbered from 1 in the menu.
+#[derive(Clone, Copy)]
+pub enum Tool {
+    Bounce,
+    Latency,
+    Encoder,
+    Travel,
+    Analog,
+    RgbChain,
+    KeyLeds,
+    I2cScan,
+    Oled,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 9] = [
+        Tool::Bounce,
+        Tool::Latency,
+        Tool::Encoder,
+        Tool::Travel,
+        Tool::Analog,
+        Tool::RgbChain,
+        Tool::KeyLeds,
+        Tool::I2cScan,
+        Tool::Oled,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Bounce => "Bounce analyser",
+            Tool::Latency => "Key-to-event latency",
+            Tool::Encoder => "Rotary encoder",
+            Tool::Travel => "Analog key travel",
+            Tool::Analog => "Analog inputs",
+            Tool::RgbChain => "RGB LED chain",
+            Tool::KeyLeds => "Key LEDs",
+            Tool::I2cScan => "I2C scanner",
+            Tool::Oled => "OLED patterns",
+        }
+    }
+}
+
+/// A numbered list of choices, selected with the digit keys.
+pub struct MenuScreen<'a> {
+    pub title: &'a str,
+    /// Items numbered from 1.
+    pub items: &'a [&'a str],
+    pub footer: &'a str,
+}
+
+impl MenuScreen<'_> {
+    /// Pixels per item, including a gap.
+    const MAX_PITCH: i32 = 30;
+    /// Where the footer starts.
+    const BOTTOM: i32 = display::HEIGHT as i32 - MARGIN - 16;
+}
+
+impl Drawable for MenuScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header(self.title, Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        // Items move closer together as the list grows, so that up to nine fit above the footer.
+        let pitch = (Self::BOTTOM - BODY_TOP) / self.items.len().max(1) as i32;
+        for (i, item) in self.items.iter().enumerate() {
+            let mut line = TextBuf::<32>::new();
+            let _ = write!(line, "{}  {item}", i + 1);
+            draw_line(
+                line.as_str(),
+                BODY_TOP + i as i32 * pitch.min(Self::MAX_PITCH),
+                U8g2TextStyle::new(u8g2_font_helvR14_tr, Rgb565::CSS_WHITE),
+                0,
+                target,
+            )?;
+        }
+
+        draw_footer(self.footer, target)
+    }
+}
+
+/// Bounce waveforms of the last press and release of a key, as captured by the bounce analyser.
+pub struct BounceScreen<'a> {
+    pub key: Key,
+    pub press: Option<&'a Capture>,
+    pub release: Option<&'a Capture>,
+}
+
+impl BounceScreen<'_> {
+    const PLOT_HEIGHT: u32 = 40;
+    /// Width of the part of the plot before the first edge.
+    const PRE_TRIGGER: i32 = 10;
+    /// Shortest time shown after the first edge.
+    const MIN_SPAN_US: u32 = 100;
+
+    /// Time after the first edge shown in the plots, the same for both so that they compare.
+    fn span_us(&self) -> u32 {
+        let bounce_us = [self.press, self.release]
+            .into_iter()
+            .flatten()
+            .map(Capture::bounce_us)
+            .max()
+            .unwrap_or(0);
+
+        // Leaves some room after the last edge, then rounds up to 1, 2 or 5 times a power of 10.
+        let span_us = (bounce_us + bounce_us / 8).max(Self::MIN_SPAN_US);
+        let mut decade = 1;
+        while decade * 10 <= span_us {
+            decade *= 10;
+        }
+        [1, 2, 5, 10]
+            .into_iter()
+            .map(|step| step * decade)
+            .find(|&span| span >= span_us)
+            .unwrap_or(10 * decade)
+    }
+
+    /// Draws a caption and a plot of `capture` at `y`, and returns where the next one starts.
+    fn draw_capture<D: DrawTarget<Color = Rgb565>>(
+        &self,
+        label: &str,
+        capture: Option<&Capture>,
+        y: i32,
+        target: &mut D,
+    ) -> Result<i32, D::Error> {
+        let mut line = TextBuf::<48>::new();
+        let color = match capture {
+            Some(capture) => {
+                let bounce_us = capture.bounce_us();
+                let _ = write!(
+                    line,
+                    "{label}  {}.{:02} ms, {} edges",
+                    bounce_us / 1000,
+                    bounce_us % 1000 / 10,
+                    capture.edge_count()
+                );
+                Rgb565::CSS_WHITE
+            }
+            None => {
+                let _ = write!(line, "{label}  waiting");
+                Rgb565::CSS_DIM_GRAY
+            }
+        };
+        let y = draw_line(
+            line.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, color),
+            6,
+            target,
+        )?;
+
+        let plot = Rectangle::new(
+            Point::new(MARGIN, y),
+            Size::new(display::WIDTH as u32 - 2 * MARGIN as u32, Self::PLOT_HEIGHT),
+        );
+        plot.into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1))
+            .draw(target)?;
+
+        if let Some(capture) = capture {
+            self.draw_waveform(capture, plot, target)?;
+        }
+
+        Ok(plot.bottom_right().map_or(y, |p| p.y) + 8)
+    }
+
+    /// Plots the row level, high while the contact is open, one pixel column at a time. Columns
+    /// with edges get a vertical line, however many edges fall into them.
+    fn draw_waveform<D: DrawTarget<Color = Rgb565>>(
+        &self,
+        capture: &Capture,
+        plot: Rectangle,
+        target: &mut D,
+    ) -> Result<(), D::Error> {
+        let style = PrimitiveStyle::with_stroke(Rgb565::CSS_LIME_GREEN, 1);
+        let left = plot.top_left.x + 1;
+        let width = plot.size.width as i32 - 2;
+        let high = plot.top_left.y + 5;
+        let low = plot.top_left.y + Self::PLOT_HEIGHT as i32 - 6;
+
+        let span_us = i64::from(self.span_us());
+        let time_at = |x: i32| {
+            i64::from(x - Self::PRE_TRIGGER) * span_us / i64::from(width - Self::PRE_TRIGGER)
+        };
+
+        for x in 0..width {
+            let (from, to) = (time_at(x), time_at(x + 1));
+            let (top, bottom) = if capture.edges_between(from, to) > 0 {
+                (high, low)
+            } else if capture.closed_at(from) {
+                (low, low)
+            } else {
+                (high, high)
+            };
+            Line::new(Point::new(left + x, top), Point::new(left + x, bottom))
+                .into_styled(style)
+                .draw(target)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drawable for BounceScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let mut title = TextBuf::<24>::new();
+        let _ = write!(title, "Bounce: key {}", self.key.char());
+        draw_header(title.as_str(), Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let y = self.draw_capture("Press", self.press, BODY_TOP, target)?;
+        let y = self.draw_capture("Release", self.release, y, target)?;
+
+        let mut caption = TextBuf::<48>::new();
+        let span_us = self.span_us();
+        if span_us >= 1000 {
+            let _ = write!(caption, "{} ms shown", span_us / 1000);
+        } else {
+            let _ = write!(caption, "{span_us} us shown");
+        }
+        let sample_rate = [self.press, self.release]
+            .into_iter()
+            .flatten()
+            .map(Capture::sample_rate_hz)
+            .min();
+        if let Some(sample_rate) = sample_rate {
+            let _ = write!(caption, ", sampled at {} kHz", sample_rate / 1000);
+        }
+        draw_line(
+            caption.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_LIGHT_GRAY),
+            0,
+            target,
+        )?;
+
+        draw_footer("Another key stops", target)
+    }
+}
+
+/// Key-to-event latencies measured so far, with a histogram of all of them and the average of
+/// each key.
+pub struct LatencyScreen<'a> {
+    pub overall: &'a Histogram,
+    /// Average latency of each key in µs, indexed by [`Key::index`].
+    pub key_avg_us: [Option<u32>; N_KEYS],
+}
+
+impl LatencyScreen<'_> {
+    const PLOT_HEIGHT: u32 = 44;
+    /// Horizontal pixels per histogram bucket, including a gap.
+    const BUCKET_WIDTH: i32 = 3;
+
+    /// Draws the histogram of all latencies at `y` and returns where the next line starts.
+    fn draw_histogram<D: DrawTarget<Color = Rgb565>>(
+        &self,
+        y: i32,
+        target: &mut D,
+    ) -> Result<i32, D::Error> {
+        let overall = self.overall;
+
+        let plot = Rectangle::new(
+            Point::new(MARGIN, y),
+            Size::new(display::WIDTH as u32 - 2 * MARGIN as u32, Self::PLOT_HEIGHT),
+        );
+        plot.into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1))
+            .draw(target)?;
+
+        let bars = overall
+            .buckets()
+            .iter()
+            .map(|&count| (count, Rgb565::CSS_LIME_GREEN));
+        let overflow = (overall.overflow(), Rgb565::CSS_SALMON);
+        let highest = overall
+            .buckets()
+            .iter()
+            .copied()
+            .chain([overall.overflow()])
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let max_height = Self::PLOT_HEIGHT as i32 - 4;
+        let bottom = plot.top_left.y + Self::PLOT_HEIGHT as i32 - 2;
+
+        for (bucket, (count, color)) in bars.chain([overflow]).enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let height = (i32::from(count) * max_height / i32::from(highest)).max(1);
+            let x = plot.top_left.x + 2 + bucket as i32 * Self::BUCKET_WIDTH;
+            Rectangle::new(
+                Point::new(x, bottom - height + 1),
+                Size::new(Self::BUCKET_WIDTH as u32 - 1, height as u32),
+            )
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(target)?;
+        }
+
+        let axis = plot.bottom_right().map_or(y, |p| p.y) + 3;
+        let style = U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_DIM_GRAY);
+        for bucket in [0, latency::BUCKETS / 2, latency::BUCKETS] {
+            let ms = bucket as u32 * latency::BUCKET_US / 1000;
+            let unit = if bucket == latency::BUCKETS {
+                " ms"
+            } else {
+                ""
+            };
+            let mut label = TextBuf::<8>::new();
+            let _ = write!(label, "{ms}{unit}");
+            let x = plot.top_left.x + 2 + bucket as i32 * Self::BUCKET_WIDTH;
+            Text::with_text_style(
+                label.as_str(),
+                Point::new(x, axis),
+                style.clone(),
+                TextStyleBuilder::new()
+                    .alignment(Alignment::Center)
+                    .baseline(Baseline::Top)
+                    .build(),
+            )
+            .draw(target)?;
+        }
+        Ok(axis + 18)
+    }
+}
+
+impl Drawable for LatencyScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("Latency", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let overall = self.overall;
+        let mut summary = TextBuf::<48>::new();
+        let mut details = TextBuf::<48>::new();
+        if overall.is_empty() {
+            let _ = summary.write_str("No events yet");
+            let _ = details.write_str("Press and release any keys");
+        } else {
+            let _ = write!(
+                summary,
+                "{} events, avg {} ms",
+                overall.count(),
+                Millis(overall.avg_us())
+            );
+            let _ = write!(
+                details,
+                "min {}   p99 {}   max {} ms",
+                Millis(overall.min_us()),
+                Millis(overall.percentile_us(99)),
+                Millis(overall.max_us())
+            );
+        }
+        let y = draw_line(
+            summary.as_str(),
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, Rgb565::CSS_WHITE),
+            4,
+            target,
+        )?;
+        let y = draw_line(
+            details.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_LIGHT_GRAY),
+            6,
+            target,
+        )?;
+
+        let mut y = self.draw_histogram(y, target)?;
+
+        // Average of each key, laid out like the keypad.
+        let column_width = (display::WIDTH as i32 - 2 * MARGIN) / N_COLS as i32;
+        for row in 0..N_ROWS {
+            for col in 0..N_COLS {
+                let key = Key {
+                    col: col as u8,
+                    row: row as u8,
+                };
+                let avg_us = self.key_avg_us[key.index()];
+                let mut cell = TextBuf::<24>::new();
+                let _ = write!(cell, "{}   ", key.char());
+                let color = if avg_us.is_some() {
+                    let _ = write!(cell, "{} ms", Millis(avg_us));
+                    Rgb565::CSS_WHITE
+                } else {
+                    let _ = cell.write_str("-");
+                    Rgb565::CSS_DIM_GRAY
+                };
+                Text::with_text_style(
+                    cell.as_str(),
+                    Point::new(MARGIN + col as i32 * column_width, y),
+                    U8g2TextStyle::new(u8g2_font_helvR10_tr, color),
+                    left_top(),
+                )
+                .draw(target)?;
+            }
+            y += 13;
+        }
+
+        draw_footer("Hold a key for 2 s to stop", target)
+    }
+}
+
+/// Position of a rotary encoder and the transitions that went wrong while turning it.
+#[derive(Default)]
+pub struct EncoderScreen {
+    /// Detents turned clockwise, minus those turned counter-clockwise.
+    pub position: i32,
+    pub last_direction: Option<encoder::Direction>,
+    pub missed: u32,
+    pub bounces: u32,
+    /// State of the push switch, or `None` if the encoder has none.
+    pub switch_pressed: Option<bool>,
+}
+
+impl EncoderScreen {
+    const DIAL_DIAMETER: u32 = 80;
+    /// Detents per turn of the dial, as on an EC11 with 20 detents.
+    const DETENTS: i32 = 20;
+}
+
+impl Drawable for EncoderScreen {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("Rotary encoder", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let dial = Circle::new(Point::new(MARGIN, BODY_TOP), Self::DIAL_DIAMETER);
+        dial.into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 2))
+            .draw(target)?;
+
+        // Angles go clockwise from 3 o'clock, and position 0 points up.
+        let detent = self.position.rem_euclid(Self::DETENTS);
+        let pointer = (detent * 360 / Self::DETENTS - 90) as f32;
+        Sector::with_center(
+            dial.center(),
+            Self::DIAL_DIAMETER - 12,
+            (pointer - 8.0).deg(),
+            16.0.deg(),
+        )
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_LIME_GREEN))
+        .draw(target)?;
+
+        let x = MARGIN + Self::DIAL_DIAMETER as i32 + 16;
+        let mut position = TextBuf::<12>::new();
+        let _ = write!(position, "{}", self.position);
+        Text::with_text_style(
+            position.as_str(),
+            Point::new(x, BODY_TOP + 8),
+            U8g2TextStyle::new(u8g2_font_helvB24_tr, Rgb565::CSS_WHITE),
+            left_top(),
+        )
+        .draw(target)?;
+
+        let direction = match self.last_direction {
+            Some(encoder::Direction::Clockwise) => "clockwise",
+            Some(encoder::Direction::CounterClockwise) => "counter-clockwise",
+            None => "turn the knob",
+        };
+        Text::with_text_style(
+            direction,
+            Point::new(x, BODY_TOP + 48),
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY),
+            left_top(),
+        )
+        .draw(target)?;
+
+        let mut y = BODY_TOP + Self::DIAL_DIAMETER as i32 + 12;
+        let mut missed = TextBuf::<32>::new();
+        let _ = write!(missed, "Missed steps: {}", self.missed);
+        let color = if self.missed > 0 {
+            Rgb565::CSS_SALMON
+        } else {
+            Rgb565::CSS_WHITE
+        };
+        y = draw_line(
+            missed.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, color),
+            6,
+            target,
+        )?;
+
+        let mut bounces = TextBuf::<32>::new();
+        let _ = write!(bounces, "Bounces: {}", self.bounces);
+        y = draw_line(
+            bounces.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            6,
+            target,
+        )?;
+
+        let switch = match self.switch_pressed {
+            Some(true) => "Push switch: pressed",
+            Some(false) => "Push switch: released",
+            None => "No push switch",
+        };
+        draw_line(
+            switch,
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            0,
+            target,
+        )?;
+
+        draw_footer("Any other key goes back", target)
+    }
+}
+
+/// Travel of analog keys as bars that fill downwards, with the actuation point across them.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TravelScreen {
+    /// Travel of each key in thousandths, or `None` while the key isn't calibrated, indexed by
+    /// [`Key::index`].
+    pub travel: [Option<u16>; N_KEYS],
+    /// Keys past the actuation point.
+    pub pressed: KeySet,
+    /// Actuation point in thousandths of the travel.
+    pub actuation: u16,
+}
+
+impl TravelScreen {
+    const BAR_HEIGHT: u32 = 100;
+    /// Horizontal pixels per bar, including a gap.
+    const BAR_PITCH: i32 = 18;
+
+    pub fn new(keys: &AnalogKeys, actuation: u16) -> Self {
+        Self {
+            travel: core::array::from_fn(|i| keys.travel(Key::from_index(i))),
+            pressed: Key::all().filter(|&key| keys.is_pressed(key)).fold(
+                KeySet::ZERO,
+                |mut pressed, key| {
+                    pressed.set(key.index(), true);
+                    pressed
+                },
+            ),
+            actuation,
+        }
+    }
+
+    /// Height in pixels of `travel` on a bar.
+    fn height(travel: u16) -> i32 {
+        (u32::from(travel) * Self::BAR_HEIGHT / u32::from(analog::FULL_TRAVEL)) as i32
+    }
+}
+
+impl Drawable for TravelScreen {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("Key travel", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let left = MARGIN + 2;
+        let label_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build();
+        for (i, &travel) in self.travel.iter().enumerate() {
+            let bar = Rectangle::new(
+                Point::new(left + i as i32 * Self::BAR_PITCH, BODY_TOP),
+                Size::new(Self::BAR_PITCH as u32 - 4, Self::BAR_HEIGHT),
+            );
+            bar.into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1))
+                .draw(target)?;
+
+            let color = if self.pressed[i] {
+                Rgb565::CSS_LIME_GREEN
+            } else {
+                Rgb565::CSS_STEEL_BLUE
+            };
+            let height = travel.map_or(0, Self::height);
+            if height > 0 {
+                Rectangle::new(bar.top_left, Size::new(bar.size.width, height as u32))
+                    .into_styled(PrimitiveStyle::with_fill(color))
+                    .draw(target)?;
+            }
+
+            let label_color = if travel.is_some() {
+                Rgb565::CSS_WHITE
+            } else {
+                Rgb565::CSS_DIM_GRAY
+            };
+            let mut label = TextBuf::<4>::new();
+            let _ = label.write_char(Key::from_index(i).char());
+            Text::with_text_style(
+                label.as_str(),
+                Point::new(bar.center().x, BODY_TOP + Self::BAR_HEIGHT as i32 + 4),
+                U8g2TextStyle::new(u8g2_font_helvR10_tr, label_color),
+                label_style,
+            )
+            .draw(target)?;
+        }
+
+        let actuation_y = BODY_TOP + Self::height(self.actuation);
+        Line::new(
+            Point::new(MARGIN, actuation_y),
+            Point::new(display::WIDTH as i32 - MARGIN, actuation_y),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_GOLD, 1))
+        .draw(target)?;
+
+        let mut actuation = TextBuf::<32>::new();
+        let _ = write!(
+            actuation,
+            "Actuation at {}%",
+            self.actuation / (analog::FULL_TRAVEL / 100)
+        );
+        let y = draw_line(
+            actuation.as_str(),
+            BODY_TOP + Self::BAR_HEIGHT as i32 + 24,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_GOLD),
+            4,
+            target,
+        )?;
+        let calibration = if self.travel.iter().all(Option::is_some) {
+            "All keys calibrated"
+        } else {
+            "Press each key all the way down"
+        };
+        draw_line(
+            calibration,
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_LIGHT_GRAY),
+            0,
+            target,
+        )?;
+
+        draw_footer("Hold a key for 2 s to stop", target)
+    }
+}
+
+/// Live readings of analog inputs and how they fare against the thresholds.
+pub struct AnalogScreen<'a> {
+    /// GPIO number and statistics of each input, at most [`adc::MAX_INPUTS`].
+    pub inputs: &'a [(u8, InputStats)],
+    pub thresholds: &'a Thresholds,
+}
+
+impl AnalogScreen<'_> {
+    const INPUT_HEIGHT: i32 = 36;
+    const BAR_LEFT: i32 = 120;
+
+    fn check_color(check: Check) -> Rgb565 {
+        match check {
+            Check::Pending => Rgb565::CSS_LIGHT_GRAY,
+            Check::Passed => Rgb565::CSS_LIME_GREEN,
+            Check::Failed => Rgb565::CSS_SALMON,
+        }
+    }
+
+    /// Horizontal position of `reading` on the bars.
+    fn bar_x(reading: u16) -> i32 {
+        let width = display::WIDTH as i32 - MARGIN - Self::BAR_LEFT;
+        Self::BAR_LEFT + i32::from(reading) * (width - 1) / i32::from(adc::FULL_SCALE)
+    }
+
+    fn draw_input<D: DrawTarget<Color = Rgb565>>(
+        &self,
+        gpio: u8,
+        stats: &InputStats,
+        y: i32,
+        target: &mut D,
+    ) -> Result<(), D::Error> {
+        let verdict = stats.verdict(self.thresholds);
+
+        let mut name = TextBuf::<8>::new();
+        let _ = write!(name, "GPIO{gpio}");
+        Text::with_text_style(
+            name.as_str(),
+            Point::new(MARGIN, y),
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, Self::check_color(verdict.overall())),
+            left_top(),
+        )
+        .draw(target)?;
+
+        let mut value = TextBuf::<8>::new();
+        match stats.latest() {
+            Some(reading) => {
+                let _ = write!(value, "{reading}");
+            }
+            None => {
+                let _ = value.write_str("-");
+            }
+        }
+        Text::with_text_style(
+            value.as_str(),
+            Point::new(MARGIN + 60, y),
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            left_top(),
+        )
+        .draw(target)?;
+
+        // The range moved through, the centre and the latest reading.
+        let bar = Rectangle::with_corners(
+            Point::new(Self::BAR_LEFT, y + 2),
+            Point::new(display::WIDTH as i32 - MARGIN - 1, y + 13),
+        );
+        bar.into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1))
+            .draw(target)?;
+        if let Some((min, max)) = stats.min_max() {
+            Rectangle::with_corners(
+                Point::new(Self::bar_x(min), y + 3),
+                Point::new(Self::bar_x(max), y + 12),
+            )
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_DARK_SLATE_GRAY))
+            .draw(target)?;
+        }
+        let marks = [
+            (stats.centre(), Rgb565::CSS_GOLD),
+            (stats.latest(), Rgb565::CSS_WHITE),
+        ];
+        for (reading, color) in marks {
+            if let Some(reading) = reading {
+                let x = Self::bar_x(reading);
+                Line::new(Point::new(x, y + 1), Point::new(x, y + 14))
+                    .into_styled(PrimitiveStyle::with_stroke(color, 2))
+                    .draw(target)?;
+            }
+        }
+
+        let mut range = TextBuf::<16>::new();
+        let _ = write!(
+            range,
+            "range {}%",
+            u32::from(stats.range()) * 100 / u32::from(adc::FULL_SCALE)
+        );
+        let mut noise = TextBuf::<16>::new();
+        match stats.noise_tenths() {
+            Some(tenths) => {
+                let _ = write!(noise, "noise {}.{}", tenths / 10, tenths % 10);
+            }
+            None => {
+                let _ = noise.write_str("noise -");
+            }
+        }
+        let mut drift = TextBuf::<16>::new();
+        match stats.drift() {
+            Some(counts) => {
+                let _ = write!(drift, "drift {counts:+}");
+            }
+            None => {
+                let _ = drift.write_str("drift -");
+            }
+        }
+        let figures = [
+            (range, verdict.range, MARGIN),
+            (noise, verdict.noise, MARGIN + 84),
+            (drift, verdict.drift, MARGIN + 156),
+        ];
+        for (text, check, x) in figures {
+            Text::with_text_style(
+                text.as_str(),
+                Point::new(x, y + 18),
+                U8g2TextStyle::new(u8g2_font_helvR10_tr, Self::check_color(check)),
+                left_top(),
+            )
+            .draw(target)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drawable for AnalogScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("Analog inputs", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let thresholds = self.thresholds;
+        let mut limits = TextBuf::<48>::new();
+        let _ = write!(
+            limits,
+            "Pass: range {}%, noise {}.{}, drift {}",
+            u32::from(thresholds.min_range) * 100 / u32::from(adc::FULL_SCALE),
+            thresholds.max_noise_tenths / 10,
+            thresholds.max_noise_tenths % 10,
+            thresholds.max_drift
+        );
+        let mut y = draw_line(
+            limits.as_str(),
+            HEADER_HEIGHT as i32 + 6,
+            U8g2TextStyle::new(u8g2_font_helvR10_tr, Rgb565::CSS_DIM_GRAY),
+            6,
+            target,
+        )?;
+
+        for (gpio, stats) in self.inputs.iter().take(adc::MAX_INPUTS) {
+            self.draw_input(*gpio, stats, y, target)?;
+            y += Self::INPUT_HEIGHT;
+        }
+
+        draw_footer("Any key goes back", target)
+    }
+}
+
+/// The pattern shown by an RGB LED chain, with a preview of what each LED should look like.
+pub struct RgbChainScreen {
+    pub pattern: Pattern,
+    /// Number of LEDs in the chain.
+    pub len: u8,
+    /// Animation frame the preview shows.
+    pub frame: u32,
+}
+
+impl RgbChainScreen {
+    const LEDS_PER_ROW: u8 = 16;
+    const LED_SIZE: u32 = 11;
+    /// Pixels per LED, including a gap.
+    const LED_PITCH: i32 = 13;
+
+    /// `color` as it's shown in the preview, brightened since the LEDs are driven at
+    /// [`rgb::LEVEL`].
+    fn preview_color(color: Rgb888) -> Rgb565 {
+        let brighten = |value: u8| (u16::from(value) * 255 / u16::from(rgb::LEVEL)).min(255) as u8;
+        Rgb888::new(
+            brighten(color.r()),
+            brighten(color.g()),
+            brighten(color.b()),
+        )
+        .into()
+    }
+}
+
+impl Drawable for RgbChainScreen {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("RGB LED chain", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let mut pattern = TextBuf::<24>::new();
+        let _ = write!(pattern, "Pattern: {}", self.pattern.name());
+        let y = draw_line(
+            pattern.as_str(),
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvR14_tr, Rgb565::CSS_WHITE),
+            4,
+            target,
+        )?;
+
+        let mut leds = TextBuf::<24>::new();
+        match self.pattern.lit_led(self.len, self.frame) {
+            Some(led) => {
+                let _ = write!(leds, "LED {} of {}", led + 1, self.len);
+            }
+            None => {
+                let _ = write!(leds, "{} LEDs", self.len);
+            }
+        }
+        let top = draw_line(
+            leds.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_GOLD),
+            10,
+            target,
+        )?;
+
+        let left = (display::WIDTH as i32 - i32::from(Self::LEDS_PER_ROW) * Self::LED_PITCH) / 2;
+        for led in 0..self.len {
+            let position = Point::new(
+                left + i32::from(led % Self::LEDS_PER_ROW) * Self::LED_PITCH,
+                top + i32::from(led / Self::LEDS_PER_ROW) * Self::LED_PITCH,
+            );
+            let color = self.pattern.color(led, self.len, self.frame);
+            let style = if color == Rgb888::BLACK {
+                PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1)
+            } else {
+                PrimitiveStyle::with_fill(Self::preview_color(color))
+            };
+            Rectangle::new(position, Size::new_equal(Self::LED_SIZE))
+                .into_styled(style)
+                .draw(target)?;
+        }
+
+        draw_footer("* pattern   4 6 step   # back", target)
+    }
+}
+
+/// The key whose LED is lit, on a grid laid out like the keypad.
+pub struct KeyLedScreen<'a> {
+    /// What drives the LEDs, like the LED driver and its address.
+    pub source: &'a str,
+    pub lit: Key,
+    /// Whether the LED is lit because its key is held, rather than in turn.
+    pub held: bool,
+    /// Keys that have an LED.
+    pub leds: KeySet,
+}
+
+impl KeyLedScreen<'_> {
+    const CELL_SIZE: Size = Size::new(52, 22);
+    /// Pixels per cell, including a gap.
+    const CELL_PITCH: Size = Size::new(60, 26);
+}
+
+impl Drawable for KeyLedScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("Key LEDs", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let y = draw_line(
+            self.source,
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            4,
+            target,
+        )?;
+
+        let mut status = TextBuf::<32>::new();
+        let how = if self.held { "held" } else { "in turn" };
+        if self.leds[self.lit.index()] {
+            let _ = write!(status, "LED of {} lit, {how}", self.lit.char());
+        } else {
+            let _ = write!(status, "Key {} has no LED", self.lit.char());
+        }
+        let top = draw_line(
+            status.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_GOLD),
+            10,
+            target,
+        )?;
+
+        let left = (display::WIDTH as i32 - N_COLS as i32 * Self::CELL_PITCH.width as i32) / 2
+            + (Self::CELL_PITCH.width - Self::CELL_SIZE.width) as i32 / 2;
+        let label_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        for key in Key::all() {
+            let cell = Rectangle::new(
+                Point::new(
+                    left + i32::from(key.col) * Self::CELL_PITCH.width as i32,
+                    top + i32::from(key.row) * Self::CELL_PITCH.height as i32,
+                ),
+                Self::CELL_SIZE,
+            );
+            let (style, label_color) = if key == self.lit && self.leds[key.index()] {
+                (
+                    PrimitiveStyle::with_fill(Rgb565::CSS_GOLD),
+                    Rgb565::CSS_BLACK,
+                )
+            } else if self.leds[key.index()] {
+                (
+                    PrimitiveStyle::with_stroke(Rgb565::CSS_LIGHT_GRAY, 1),
+                    Rgb565::CSS_WHITE,
+                )
+            } else {
+                (
+                    PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1),
+                    Rgb565::CSS_DIM_GRAY,
+                )
+            };
+            cell.into_styled(style).draw(target)?;
+
+            let mut label = TextBuf::<4>::new();
+            let _ = label.write_char(key.char());
+            Text::with_text_style(
+                label.as_str(),
+                cell.center(),
+                U8g2TextStyle::new(u8g2_font_helvB12_tr, label_color),
+                label_style,
+            )
+            .draw(target)?;
+        }
+
+        draw_footer("Hold a key for 2 s to stop", target)
+    }
+}
+
+/// The devices found on an I2C bus, or why the scan failed.
+pub struct I2cScanScreen<'a> {
+    /// The pins scanned, like `SDA 8, SCL 15`.
+    pub bus: &'a str,
+    /// The devices that answered, by address.
+    pub result: Result<&'a [(u8, Device)], ScanError>,
+}
+
+impl I2cScanScreen<'_> {
+    /// Lines left for devices above the footer.
+    const MAX_LINES: usize = 6;
+    const LINE_PITCH: i32 = 20;
+}
+
+impl Drawable for I2cScanScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("I2C scan", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let y = draw_line(
+            self.bus,
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            4,
+            target,
+        )?;
+
+        let devices = match self.result {
+            Ok(devices) => devices,
+            Err(error) => {
+                let (summary, hint) = match error {
+                    ScanError::Bus(_) => ("Bus error", "Check SDA, SCL and their\npull-ups."),
+                    ScanError::TooManyDevices => (
+                        "Every address answers",
+                        "Check that SDA isn't\nshorted to ground.",
+                    ),
+                };
+                let y = draw_line(
+                    summary,
+                    y,
+                    U8g2TextStyle::new(u8g2_font_helvB12_tr, Rgb565::CSS_SALMON),
+                    4,
+                    target,
+                )?;
+                draw_line(
+                    hint,
+                    y,
+                    U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY),
+                    0,
+                    target,
+                )?;
+                return draw_footer("* scan again   # back", target);
+            }
+        };
+
+        let mut summary = TextBuf::<24>::new();
+        let color = match devices.len() {
+            0 => {
+                let _ = summary.write_str("No devices answered");
+                Rgb565::CSS_SALMON
+            }
+            1 => {
+                let _ = summary.write_str("1 device");
+                Rgb565::CSS_GOLD
+            }
+            n => {
+                let _ = write!(summary, "{n} devices");
+                Rgb565::CSS_GOLD
+            }
+        };
+        let mut y = draw_line(
+            summary.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, color),
+            6,
+            target,
+        )?;
+        if devices.is_empty() {
+            draw_line(
+                "Check the wiring and that\nthe board is powered.",
+                y,
+                U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY),
+                0,
+                target,
+            )?;
+        }
+
+        // The last line makes way for a count of the devices that don't fit.
+        let shown = if devices.len() > Self::MAX_LINES {
+            Self::MAX_LINES - 1
+        } else {
+            devices.len()
+        };
+        let style = U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE);
+        for &(address, device) in &devices[..shown] {
+            let mut label = TextBuf::<8>::new();
+            let _ = write!(label, "{address:#04x}");
+            Text::with_text_style(
+                label.as_str(),
+                Point::new(MARGIN, y),
+                style.clone(),
+                left_top(),
+            )
+            .draw(target)?;
+            Text::with_text_style(
+                device.name(),
+                Point::new(MARGIN + 48, y),
+                style.clone(),
+                left_top(),
+            )
+            .draw(target)?;
+            y += Self::LINE_PITCH;
+        }
+        if shown < devices.len() {
+            let mut more = TextBuf::<24>::new();
+            let _ = write!(more, "and {} more", devices.len() - shown);
+            draw_line(
+                more.as_str(),
+                y,
+                U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY),
+                0,
+                target,
+            )?;
+        }
+
+        draw_footer("* scan again   # back", target)
+    }
+}
+
+/// The pattern sent to the OLED of the board under test, with a preview of what it should show.
+pub struct OledScreen<'a> {
+    /// The pins of the bus, like `SDA 8, SCL 15`.
+    pub bus: &'a str,
+    /// The address of the OLED shown the pattern, or why it couldn't be.
+    pub result: Result<u8, OledError>,
+    pub pattern: TestPattern,
+    /// The pattern as drawn for the size of panel tried.
+    pub buffer: &'a OledBuffer,
+}
+
+impl Drawable for OledScreen<'_> {
+    type Color = Rgb565;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        draw_header("OLED patterns", Rgb565::CSS_DARK_SLATE_BLUE, target)?;
+
+        let y = draw_line(
+            self.bus,
+            BODY_TOP,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_WHITE),
+            4,
+            target,
+        )?;
+
+        let mut status = TextBuf::<24>::new();
+        let color = match self.result {
+            Ok(address) => {
+                let _ = write!(status, "SSD1306 at {address:#04x}");
+                Rgb565::CSS_GOLD
+            }
+            Err(OledError::NotFound) => {
+                let _ = status.write_str("No OLED at 0x3c or 0x3d");
+                Rgb565::CSS_SALMON
+            }
+            Err(OledError::Bus(_)) => {
+                let _ = status.write_str("Bus error");
+                Rgb565::CSS_SALMON
+            }
+        };
+        let y = draw_line(
+            status.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvB12_tr, color),
+            4,
+            target,
+        )?;
+
+        let mut pattern = TextBuf::<24>::new();
+        let _ = write!(
+            pattern,
+            "{}, {}",
+            self.buffer.oled_size().name(),
+            self.pattern.name()
+        );
+        let y = draw_line(
+            pattern.as_str(),
+            y,
+            U8g2TextStyle::new(u8g2_font_helvR12_tr, Rgb565::CSS_LIGHT_GRAY),
+            10,
+            target,
+        )?;
+
+        // The panel at its own scale, framed so that its edges show when nothing is lit there.
+        let panel = Rectangle::new(
+            Point::new((display::WIDTH as i32 - oled::WIDTH as i32) / 2, y),
+            self.buffer.bounding_box().size,
+        );
+        Rectangle::new(
+            panel.top_left - Point::new(1, 1),
+            panel.size + Size::new(2, 2),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_DIM_GRAY, 1))
+        .draw(target)?;
+        let inverted = self.pattern.inverted();
+        target.draw_iter(self.buffer.bounding_box().points().map(|point| {
+            let lit = self.buffer.pixel(point.x as u32, point.y as u32) != inverted;
+            let color = if lit {
+                Rgb565::CSS_WHITE
+            } else {
+                Rgb565::BLACK
+            };
+            Pixel(panel.top_left + point, color)
+        }))?;
+
+        draw_footer("* pattern   0 size   # back", target)
+    }
+}
@@ -0,0 +1,1000 @@
+//! The UI state machine: the board ID screen, test sessions, the history and the tools.
+//!
+//! It runs on a [`Device`] and the tools use the [`Hardware`] of the board under test, which are
+//! the tester's display, keypad and headers in the firmware, see [`task`], and test doubles in the
+//! simulator on the host.
+
+use core::{convert::Infallible, fmt::Write as _, pin::pin};
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::{
+    Drawable,
+    pixelcolor::{Rgb565, Rgb888, RgbColor as _},
+    prelude::DrawTarget,
+    primitives::Rectangle,
+};
+
+use super::{
+    AnalogScreen, BoardIdScreen, BounceScreen, EncoderScreen, I2cScanScreen, KeyLedScreen,
+    LatencyScreen, MenuScreen, NoticeScreen, OledScreen, ResultScreen, RgbChainScreen, Tool,
+    TravelScreen, draw_key_event, draw_key_passed, draw_keypad,
+};
+use crate::{
+    adc::{self, InputStats, Thresholds},
+    board::{ADC_PINS, BoardProfile, KeypadPins},
+    bounce::Capture,
+    encoder::{Direction, EncoderEvent},
+    history::{Browser, TestResult},
+    i2c_scan::{Scan, ScanError},
+    kbd::{
+        self, Key, KeyEvent, KeySet,
+        analog::{self, AnalogKeys},
+        latency::LatencyMeter,
+    },
+    key_leds::{self, LedDriverChip},
+    oled::{OledBuffer, OledError, OledSize, TestPattern},
+    rgb::Pattern,
+    session::{BoardIdAction, BoardIdInput, Session},
+    settings::Settings,
+    stream::Update,
+    text::TextBuf,
+};
+
+#[cfg(target_os = "none")]
+mod tester;
+
+#[cfg(target_os = "none")]
+pub use self::tester::{ToolHardware, task};
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A test session ends as failed if no key is pressed or released for this long.
+const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Holding a key this long leaves the latency measurement, which takes all other keys.
+const HOLD_TO_STOP: Duration = Duration::from_secs(2);
+
+/// How often the travel of analog keys is redrawn.
+const TRAVEL_REFRESH: Duration = Duration::from_millis(40);
+
+/// How often analog inputs are sampled, and how many samples are taken between redraws.
+const ANALOG_SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+const ANALOG_SAMPLES_PER_FRAME: u32 = 10;
+
+/// How often a frame is sent to the RGB LED chain.
+const LED_FRAME_PERIOD: Duration = Duration::from_millis(40);
+
+/// How long each key LED stays lit in turn.
+const KEY_LED_PERIOD: Duration = Duration::from_millis(400);
+
+const NO_KEY_LEDS: &str = "This board has no\nsingle-colour key LEDs.";
+
+/// Shown by the I2C tools when neither `set i2c` nor the key LEDs give the pins of a bus.
+pub const NO_I2C_PINS: &str = "No I2C pins are set.\nChoose them with\n`set i2c` and restart.";
+
+/// Shown by the key LED test when the profile's LED driver doesn't answer.
+pub const NO_LED_DRIVER: &str = "No IS31FL3731 or\nIS31FL3733 was found\non the bus.";
+
+/// What the UI runs on: the tester's display and keypad, or a test double on the host.
+#[allow(async_fn_in_trait, reason = "the UI runs in a single task")]
+pub trait Device {
+    /// The frame buffer the screens are drawn to.
+    type Frame: DrawTarget<Color = Rgb565>;
+    type Error: defmt::Format + From<<Self::Frame as DrawTarget>::Error>;
+
+    fn board(&self) -> &'static BoardProfile;
+
+    fn settings(&self) -> &Settings;
+
+    fn frame(&mut self) -> &mut Self::Frame;
+
+    /// Sends the frame buffer to the display and restores the brightness of the backlight.
+    async fn flush(&mut self) -> Result<(), Self::Error>;
+
+    /// Sends the rows of the frame buffer covered by `bounds` to the display and restores the
+    /// brightness of the backlight.
+    async fn flush_area(&mut self, bounds: Rectangle) -> Result<(), Self::Error>;
+
+    /// Restores the brightness of the backlight.
+    async fn wake(&mut self);
+
+    /// Starts dimming the backlight, which fades while the UI waits for input. A key event cuts
+    /// the fade short.
+    fn dim(&mut self);
+
+    /// Waits for a key event or the output of `other`, whichever comes first. Returns `None` if
+    /// neither came within `timeout`.
+    async fn next_input<T>(
+        &mut self,
+        timeout: Duration,
+        other: impl Future<Output = T>,
+    ) -> Result<Option<Input<T>>, Self::Error>;
+
+    /// Hands an update to the event stream for host tools.
+    fn emit(&mut self, update: Update);
+
+    /// Whether results are saved, which they aren't without a `history` partition.
+    fn history_available(&self) -> bool;
+
+    fn save_result(&mut self, result: &TestResult) -> Result<(), Self::Error>;
+
+    fn result_count(&mut self) -> Result<usize, Self::Error>;
+
+    /// Starts browsing at the newest result, see
+    /// [`HistoryLog::browse`](crate::history::HistoryLog::browse).
+    fn browse_results(&mut self) -> Result<Option<Browser>, Self::Error>;
+
+    fn older_result(&mut self, browser: &mut Browser) -> Result<bool, Self::Error>;
+
+    fn newer_result(&mut self, browser: &mut Browser) -> Result<bool, Self::Error>;
+}
+
+/// Hardware of the board under test that only the tools use.
+#[allow(async_fn_in_trait, reason = "the UI runs in a single task")]
+pub trait Hardware {
+    type Error;
+
+    /// Captures the press and release of `key` from now on, or stops capturing.
+    fn analyse_bounce(&mut self, key: Option<Key>);
+
+    /// Waits for the next press or release captured since
+    /// [`analyse_bounce`](Self::analyse_bounce).
+    async fn next_capture(&mut self) -> Capture;
+
+    /// Gives access to the key-to-event latency measurements.
+    fn with_latency_meter<R>(&mut self, f: impl FnOnce(&mut LatencyMeter) -> R) -> R;
+
+    /// Starts or stops passing the events of the rotary encoder on to
+    /// [`next_encoder_event`](Self::next_encoder_event).
+    fn follow_encoder(&mut self, follow: bool) -> Result<(), Self::Error>;
+
+    async fn next_encoder_event(&mut self) -> EncoderEvent;
+
+    /// Gives access to the calibration and readings of the analog keys.
+    fn with_analog_keys<R>(&mut self, f: impl FnOnce(&mut AnalogKeys) -> R) -> R;
+
+    /// Reads the analog input on `gpio`, or returns `None` if it isn't enabled.
+    async fn read_adc(&mut self, gpio: u8) -> Option<u16>;
+
+    fn has_led_chain(&self) -> bool;
+
+    /// Sends the colours to the RGB LED chain, if there is one. Failures are only logged, since
+    /// the LEDs are what's being tested.
+    fn show_leds(&mut self, colors: impl IntoIterator<Item = Rgb888>);
+
+    /// Sets up the LEDs under the keys with all of them off and runs `f` on them, or on the text
+    /// telling why they can't be used. The LEDs are turned off again afterwards.
+    async fn with_key_leds<R>(
+        &mut self,
+        f: impl AsyncFnOnce(Result<&mut dyn KeyLedControl, &'static str>) -> R,
+    ) -> R;
+
+    /// Lists the devices on the bus chosen with `set i2c`, or else the bus of the board's LED
+    /// driver. Returns the pins of the bus along with the outcome, or why there is no bus.
+    fn scan_i2c(&mut self) -> Result<(TextBuf<24>, Result<Scan, ScanError>), &'static str>;
+
+    /// Shows `buffer` on the OLED found on the same bus as [`scan_i2c`](Self::scan_i2c), and
+    /// returns its address.
+    fn show_oled(
+        &mut self,
+        buffer: &OledBuffer,
+        inverted: bool,
+    ) -> Result<(TextBuf<24>, Result<u8, OledError>), &'static str>;
+}
+
+/// The LEDs under the keys while [`Hardware::with_key_leds`] runs.
+pub trait KeyLedControl {
+    /// The LED driver and its address, or `None` for LEDs on GPIOs.
+    fn driver(&self) -> Option<(LedDriverChip, u8)>;
+
+    /// The keys that have an LED.
+    fn leds(&self) -> KeySet;
+
+    /// Sets the brightness of the LED of `key`. Failures are only logged, since the LEDs are
+    /// what's being tested.
+    fn set(&mut self, key: Key, brightness: u8);
+}
+
+/// Something [`Device::next_input`] waited for.
+pub enum Input<T> {
+    Key(KeyEvent),
+    Other(T),
+}
+
+/// Runs the UI until the device fails.
+pub async fn run<D, H>(device: &mut D, hardware: &mut H) -> Result<Infallible, D::Error>
+where
+    D: Device,
+    H: Hardware,
+    D::Error: From<H::Error>,
+{
+    // The analyser may still be running if the UI failed while it was shown.
+    hardware.analyse_bounce(None);
+
+    let mut ui = Ui { device };
+
+    loop {
+        let board_id = match ui.enter_board_id().await? {
+            Start::Test(board_id) => board_id,
+            Start::History => {
+                ui.browse_history().await?;
+                continue;
+            }
+            Start::Tools => {
+                ui.tools(hardware).await?;
+                continue;
+            }
+        };
+
+        let result = ui.run_session(board_id).await?;
+        defmt::info!("test finished: {}", result);
+        ui.device.emit(Update::Verdict {
+            result: result.clone(),
+            at: Instant::now(),
+        });
+        // The result is shown even if it couldn't be saved, e.g. without a history partition.
+        if let Err(error) = ui.device.save_result(&result) {
+            defmt::warn!("couldn't save the result: {}", error);
+        }
+
+        ui.show(&ResultScreen {
+            title: "Result",
+            result: &result,
+            footer: "Press any key to continue",
+        })
+        .await?;
+        ui.next_key_down().await?;
+    }
+}
+
+/// What to do after the board ID screen.
+enum Start {
+    Test(u32),
+    History,
+    Tools,
+}
+
+struct Ui<'a, D> {
+    device: &'a mut D,
+}
+
+impl<D: Device> Ui<'_, D> {
+    /// Asks for the board ID, unless the history or the tools are asked for instead.
+    async fn enter_board_id(&mut self) -> Result<Start, D::Error> {
+        let mut input = BoardIdInput::default();
+        self.show(&BoardIdScreen {
+            input: input.as_str(),
+        })
+        .await?;
+
+        loop {
+            let event = self.next_event_dimming().await?;
+            match input.handle(event) {
+                Some(BoardIdAction::Edited) => {
+                    self.show(&BoardIdScreen {
+                        input: input.as_str(),
+                    })
+                    .await?;
+                }
+                Some(BoardIdAction::Confirmed(board_id)) => return Ok(Start::Test(board_id)),
+                Some(BoardIdAction::ShowHistory) => return Ok(Start::History),
+                Some(BoardIdAction::ShowTools) => return Ok(Start::Tools),
+                None => {}
+            }
+        }
+    }
+
+    /// Tests the keys until all of them passed or none was touched for [`SESSION_TIMEOUT`].
+    async fn run_session(&mut self, board_id: u32) -> Result<TestResult, D::Error> {
+        let mut session = Session::new(board_id);
+
+        draw_keypad(self.device.frame())?;
+        self.device.flush().await?;
+
+        while let Some(event) = self.next_event(SESSION_TIMEOUT).await? {
+            let frame = self.device.frame();
+            let bounds = match session.handle(event, Instant::now()) {
+                Some(key) => draw_key_passed(key, frame)?,
+                None => match event {
+                    KeyEvent::KeyUp(key) if session.passed(key) => draw_key_passed(key, frame)?,
+                    _ => draw_key_event(event, frame)?,
+                },
+            };
+            self.device.flush_area(bounds).await?;
+
+            if session.is_complete() {
+                break;
+            }
+        }
+
+        let uptime = Duration::from_ticks(Instant::now().as_ticks());
+        Ok(session.finish(uptime))
+    }
+
+    /// Shows the stored results, newest first. `4` goes to older results, `6` to newer ones,
+    /// `#` and `*` go back.
+    async fn browse_history(&mut self) -> Result<(), D::Error> {
+        if !self.device.history_available() {
+            self.show(&NoticeScreen {
+                title: "History",
+                text: "Results aren't saved\nwithout a `history`\npartition.",
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        }
+
+        let count = self.device.result_count()?;
+        let Some(mut browser) = self.device.browse_results()? else {
+            self.show(&NoticeScreen {
+                title: "History",
+                text: "No results yet.",
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        };
+
+        loop {
+            let mut title = TextBuf::<24>::new();
+            let _ = write!(title, "History {}/{}", browser.index() + 1, count);
+            self.show(&ResultScreen {
+                title: title.as_str(),
+                result: browser.result(),
+                footer: "4 older   6 newer   # back",
+            })
+            .await?;
+
+            loop {
+                let moved = match self.next_key_down().await?.char() {
+                    '4' => self.device.older_result(&mut browser)?,
+                    '6' => self.device.newer_result(&mut browser)?,
+                    '#' | '*' => return Ok(()),
+                    _ => false,
+                };
+                if moved {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Lists the tools. A digit starts one, `#` and `*` go back.
+    async fn tools<H>(&mut self, hardware: &mut H) -> Result<(), D::Error>
+    where
+        H: Hardware,
+        D::Error: From<H::Error>,
+    {
+        loop {
+            self.show(&MenuScreen {
+                title: "Tools",
+                items: &Tool::ALL.map(Tool::name),
+                footer: "# back",
+            })
+            .await?;
+
+            let tool = loop {
+                let key = self.next_key_down().await?.char();
+                if matches!(key, '#' | '*') {
+                    return Ok(());
+                }
+                let n = key.to_digit(10).unwrap_or(0) as usize;
+                if let Some(&tool) = n.checked_sub(1).and_then(|i| Tool::ALL.get(i)) {
+                    break tool;
+                }
+            };
+
+            match tool {
+                Tool::Bounce => self.analyse_bounce(hardware).await?,
+                Tool::Latency => self.measure_latency(hardware).await?,
+                Tool::Encoder => self.test_encoder(hardware).await?,
+                Tool::Travel => self.show_key_travel(hardware).await?,
+                Tool::Analog => self.test_analog_inputs(hardware).await?,
+                Tool::RgbChain => self.test_led_chain(hardware).await?,
+                Tool::KeyLeds => self.test_key_leds(hardware).await?,
+                Tool::I2cScan => self.scan_i2c(hardware).await?,
+                Tool::Oled => self.test_oled(hardware).await?,
+            }
+        }
+    }
+
+    /// Asks for a key, then shows the waveforms of its latest press and release until another
+    /// key is pressed.
+    async fn analyse_bounce(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        if !self.device.board().keypad.on_gpios() {
+            let text = if matches!(self.device.board().keypad, KeypadPins::Analog(_)) {
+                "Analog keys have no\ncontacts that bounce."
+            } else {
+                "This keypad is read\nthrough a bus, too slowly\nto capture bounces."
+            };
+            self.show(&NoticeScreen {
+                title: "Bounce",
+                text,
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        }
+
+        self.show(&NoticeScreen {
+            title: "Bounce",
+            text: "Press the key to analyse.\nIts press and release are\ncaptured from then on.",
+            footer: "",
+        })
+        .await?;
+        let key = self.next_key_down().await?;
+        hardware.analyse_bounce(Some(key));
+
+        let (mut press, mut release) = (None, None);
+        loop {
+            self.show(&BounceScreen {
+                key,
+                press: press.as_ref(),
+                release: release.as_ref(),
+            })
+            .await?;
+
+            loop {
+                match self
+                    .next_input(IDLE_TIMEOUT, hardware.next_capture())
+                    .await?
+                {
+                    Some(Input::Other(capture)) if capture.is_press() => press = Some(capture),
+                    Some(Input::Other(capture)) => release = Some(capture),
+                    Some(Input::Key(KeyEvent::KeyDown(other))) if other != key => {
+                        hardware.analyse_bounce(None);
+                        return Ok(());
+                    }
+                    Some(Input::Key(_)) => continue,
+                    None => {
+                        self.device.dim();
+                        continue;
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    /// Starts new latency measurements and shows them after each key event, until a key is held
+    /// for [`HOLD_TO_STOP`].
+    async fn measure_latency(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        hardware.with_latency_meter(LatencyMeter::start);
+
+        let mut held = None;
+        loop {
+            // Drawn from a copy to keep the meter locked only briefly.
+            let (overall, key_avg_us) = hardware.with_latency_meter(|meter| {
+                let key_avg_us = core::array::from_fn(|i| meter.key(Key::from_index(i)).avg_us());
+                (meter.overall().clone(), key_avg_us)
+            });
+            self.show(&LatencyScreen {
+                overall: &overall,
+                key_avg_us,
+            })
+            .await?;
+
+            let timeout = if held.is_some() {
+                HOLD_TO_STOP
+            } else {
+                IDLE_TIMEOUT
+            };
+            let event = match self.next_event(timeout).await? {
+                Some(event) => event,
+                None if held.is_some() => break,
+                // Nothing changed, so the screen is left as it is until a key is touched.
+                None => {
+                    self.device.dim();
+                    self.next_event_dimming().await?
+                }
+            };
+            match event {
+                KeyEvent::KeyDown(key) => held = Some(key),
+                KeyEvent::KeyUp(key) => {
+                    if held == Some(key) {
+                        held = None;
+                    }
+                }
+            }
+        }
+
+        hardware.with_latency_meter(LatencyMeter::stop);
+        Ok(())
+    }
+
+    /// Counts the detents of the encoder and the transitions that went wrong, until a key other
+    /// than its push switch is pressed.
+    async fn test_encoder<H>(&mut self, hardware: &mut H) -> Result<(), D::Error>
+    where
+        H: Hardware,
+        D::Error: From<H::Error>,
+    {
+        let Some(pins) = &self.device.board().encoder else {
+            self.show(&NoticeScreen {
+                title: "Rotary encoder",
+                text: "This tester has no\nencoder header.",
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        };
+        let switch = pins.switch;
+
+        hardware.follow_encoder(true)?;
+        let mut screen = EncoderScreen {
+            switch_pressed: switch.map(|_| false),
+            ..EncoderScreen::default()
+        };
+
+        loop {
+            self.show(&screen).await?;
+
+            loop {
+                match self
+                    .next_input(IDLE_TIMEOUT, hardware.next_encoder_event())
+                    .await?
+                {
+                    Some(Input::Other(EncoderEvent::Detent(direction))) => {
+                        screen.position += match direction {
+                            Direction::Clockwise => 1,
+                            Direction::CounterClockwise => -1,
+                        };
+                        screen.last_direction = Some(direction);
+                    }
+                    Some(Input::Other(EncoderEvent::Missed)) => screen.missed += 1,
+                    Some(Input::Other(EncoderEvent::Bounce)) => screen.bounces += 1,
+                    Some(Input::Key(KeyEvent::KeyDown(key))) if Some(key) == switch => {
+                        screen.switch_pressed = Some(true);
+                    }
+                    Some(Input::Key(KeyEvent::KeyUp(key))) if Some(key) == switch => {
+                        screen.switch_pressed = Some(false);
+                    }
+                    Some(Input::Key(KeyEvent::KeyDown(_))) => {
+                        hardware.follow_encoder(false)?;
+                        return Ok(());
+                    }
+                    Some(Input::Key(KeyEvent::KeyUp(_))) => continue,
+                    None => {
+                        self.device.dim();
+                        continue;
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    /// Calibrates the analog keys anew and shows their travel until a key is held for
+    /// [`HOLD_TO_STOP`]. While the backlight is dimmed, the screen waits for a key event.
+    async fn show_key_travel(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        if !matches!(self.device.board().keypad, KeypadPins::Analog(_)) {
+            self.show(&NoticeScreen {
+                title: "Key travel",
+                text: "This keypad has no\nanalog keys.",
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        }
+
+        // The key that started the tool is still down, and would be taken as its rest.
+        self.show(&NoticeScreen {
+            title: "Key travel",
+            text: "Release all keys to\ncalibrate their rest\npositions.",
+            footer: "",
+        })
+        .await?;
+        while self.next_event(TRAVEL_REFRESH).await?.is_some() {}
+        hardware.with_analog_keys(AnalogKeys::recalibrate);
+
+        let mut held = None;
+        let mut last_event = Instant::now();
+        loop {
+            let actuation = analog::actuation_from_pct(self.device.settings().actuation_pct);
+            let screen = hardware.with_analog_keys(|keys| TravelScreen::new(keys, actuation));
+            self.show(&screen).await?;
+
+            let event = if last_event.elapsed() >= IDLE_TIMEOUT {
+                self.device.dim();
+                Some(self.next_event_dimming().await?)
+            } else {
+                self.next_event(TRAVEL_REFRESH).await?
+            };
+            match event {
+                Some(KeyEvent::KeyDown(key)) => held = Some((key, Instant::now())),
+                Some(KeyEvent::KeyUp(key)) if held.is_some_and(|(held, _)| held == key) => {
+                    held = None;
+                }
+                Some(KeyEvent::KeyUp(_)) | None => {}
+            }
+            if event.is_some() {
+                last_event = Instant::now();
+            }
+
+            if held.is_some_and(|(_, since)| since.elapsed() >= HOLD_TO_STOP) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Samples the analog inputs chosen with `set analog` and shows their statistics against the
+    /// fixed [`Thresholds::default`] until a key is pressed. Inputs chosen since boot aren't
+    /// enabled yet and show no readings. While the backlight is dimmed, the inputs are still
+    /// sampled but the screen isn't redrawn.
+    async fn test_analog_inputs(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        let mask = self
+            .device
+            .board()
+            .free_adc_pins(self.device.settings().analog_pins);
+        if mask == 0 {
+            self.show(&NoticeScreen {
+                title: "Analog inputs",
+                text: "No analog inputs are set.\nChoose their pins with\n\
+                       `set analog` and restart.",
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        }
+
+        let mut inputs: [(u8, InputStats); adc::MAX_INPUTS] = Default::default();
+        let mut count = 0;
+        for (pin, input) in ADC_PINS
+            .filter(|&pin| mask & (1 << pin) != 0)
+            .zip(&mut inputs)
+        {
+            input.0 = pin;
+            count += 1;
+        }
+        let inputs = &mut inputs[..count];
+        let thresholds = Thresholds::default();
+
+        let mut last_event = Instant::now();
+        for sample in 0.. {
+            sample_inputs(hardware, inputs).await;
+
+            if sample % ANALOG_SAMPLES_PER_FRAME == 0 {
+                self.show(&AnalogScreen {
+                    inputs,
+                    thresholds: &thresholds,
+                })
+                .await?;
+            }
+
+            let event = if last_event.elapsed() >= IDLE_TIMEOUT {
+                Some(
+                    self.next_event_dimmed_while(async {
+                        loop {
+                            Timer::after(ANALOG_SAMPLE_PERIOD).await;
+                            sample_inputs(hardware, inputs).await;
+                        }
+                    })
+                    .await?,
+                )
+            } else {
+                self.next_event(ANALOG_SAMPLE_PERIOD).await?
+            };
+            match event {
+                Some(KeyEvent::KeyDown(_)) => break,
+                Some(KeyEvent::KeyUp(_)) => last_event = Instant::now(),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows test patterns on the RGB LED chain until `#` is pressed. `*` switches to the next
+    /// pattern, `4` and `6` light the previous and next LED on their own. While the backlight is
+    /// dimmed, the pattern goes on but the screen isn't redrawn.
+    async fn test_led_chain(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        if !hardware.has_led_chain() {
+            self.show(&NoticeScreen {
+                title: "RGB LED chain",
+                text: "This board has no\nRGB LED chain.",
+                footer: "Press any key to go back",
+            })
+            .await?;
+            self.next_key_down().await?;
+            return Ok(());
+        }
+
+        let len = self.device.settings().chain_len;
+        let mut pattern = Pattern::Walk;
+        let mut shown = None;
+        let mut frame = 0;
+        let mut last_event = Instant::now();
+        loop {
+            hardware.show_leds((0..len).map(|led| pattern.color(led, len, frame)));
+
+            // Redrawn only when the text changes, since the rainbow turns too fast for the display.
+            let screen = (pattern, pattern.lit_led(len, frame));
+            if shown != Some(screen) {
+                self.show(&RgbChainScreen {
+                    pattern,
+                    len,
+                    frame,
+                })
+                .await?;
+                shown = Some(screen);
+            }
+
+            let event = if last_event.elapsed() >= IDLE_TIMEOUT {
+                Some(
+                    self.next_event_dimmed_while(async {
+                        loop {
+                            Timer::after(LED_FRAME_PERIOD).await;
+                            frame += 1;
+                            hardware.show_leds((0..len).map(|led| pattern.color(led, len, frame)));
+                        }
+                    })
+                    .await?,
+                )
+            } else {
+                self.next_event(LED_FRAME_PERIOD).await?
+            };
+            if event.is_some() {
+                last_event = Instant::now();
+            }
+            if let Some(KeyEvent::KeyDown(key)) = event {
+                match key.char() {
+                    '*' => pattern = pattern.next(),
+                    '4' => pattern = pattern.step(false, len, frame),
+                    '6' => pattern = pattern.step(true, len, frame),
+                    '#' => break,
+                    _ => {}
+                }
+            }
+            frame += 1;
+        }
+
+        hardware.show_leds((0..len).map(|_| Rgb888::BLACK));
+        Ok(())
+    }
+
+    /// Lights the LEDs under the keys one after the other, or the LED of the key that is held
+    /// down, until a key is held for [`HOLD_TO_STOP`].
+    async fn test_key_leds(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        if self.device.board().key_leds.is_none() {
+            return self.show_key_led_notice(NO_KEY_LEDS).await;
+        }
+
+        hardware
+            .with_key_leds(async |leds| match leds {
+                Ok(leds) => self.run_key_leds(leds).await,
+                Err(text) => self.show_key_led_notice(text).await,
+            })
+            .await
+    }
+
+    async fn show_key_led_notice(&mut self, text: &str) -> Result<(), D::Error> {
+        self.show(&NoticeScreen {
+            title: "Key LEDs",
+            text,
+            footer: "Press any key to go back",
+        })
+        .await?;
+        self.next_key_down().await?;
+        Ok(())
+    }
+
+    async fn run_key_leds(&mut self, leds: &mut dyn KeyLedControl) -> Result<(), D::Error> {
+        let mut source = TextBuf::<32>::new();
+        match leds.driver() {
+            Some((chip, address)) => {
+                let _ = write!(source, "{} at {:#04x}", chip.name(), address);
+            }
+            None => {
+                let _ = write!(source, "{} LEDs on GPIOs", leds.leds().count_ones());
+            }
+        }
+        let has_led = leds.leds();
+
+        let mut lit = key_leds::next_led(Key::from_index(kbd::N_KEYS - 1), &has_led);
+        let mut held = None;
+        let mut next_step = Instant::now() + KEY_LED_PERIOD;
+        let mut shown = None;
+        loop {
+            if shown.is_none_or(|(key, _)| key != lit) {
+                if let Some((key, _)) = shown {
+                    leds.set(key, 0);
+                }
+                leds.set(lit, u8::MAX);
+            }
+            if shown != Some((lit, held.is_some())) {
+                self.show(&KeyLedScreen {
+                    source: source.as_str(),
+                    lit,
+                    held: held.is_some(),
+                    leds: has_led,
+                })
+                .await?;
+                shown = Some((lit, held.is_some()));
+            }
+
+            let deadline = held.map_or(next_step, |(_, since)| since + HOLD_TO_STOP);
+            match self
+                .next_event(deadline.saturating_duration_since(Instant::now()))
+                .await?
+            {
+                Some(KeyEvent::KeyDown(key)) => {
+                    lit = key;
+                    held = Some((key, Instant::now()));
+                }
+                Some(KeyEvent::KeyUp(key)) if held.is_some_and(|(held, _)| held == key) => {
+                    // The sequence carries on from the released key.
+                    held = None;
+                    next_step = Instant::now() + KEY_LED_PERIOD;
+                }
+                Some(KeyEvent::KeyUp(_)) => {}
+                None if held.is_some() => return Ok(()),
+                None => {
+                    lit = key_leds::next_led(lit, &has_led);
+                    next_step += KEY_LED_PERIOD;
+                }
+            }
+        }
+    }
+
+    /// Lists the devices on the I2C bus until `#` is pressed. `*` scans the bus again.
+    async fn scan_i2c(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        loop {
+            let (bus, result) = match hardware.scan_i2c() {
+                Ok(scan) => scan,
+                Err(text) => {
+                    self.show(&NoticeScreen {
+                        title: "I2C scan",
+                        text,
+                        footer: "Press any key to go back",
+                    })
+                    .await?;
+                    self.next_key_down().await?;
+                    return Ok(());
+                }
+            };
+            match &result {
+                Ok(scan) => defmt::info!("i2c scan: {} devices", scan.devices().len()),
+                Err(error) => defmt::warn!("i2c scan failed: {}", error),
+            }
+            self.show(&I2cScanScreen {
+                bus: bus.as_str(),
+                result: result.as_ref().map(Scan::devices).map_err(|&error| error),
+            })
+            .await?;
+
+            loop {
+                match self.next_key_down().await?.char() {
+                    '*' => break,
+                    '#' => return Ok(()),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Shows test patterns on the OLED of the board under test until `#` is pressed. `*` shows
+    /// the next pattern and `0` switches between 128x32 and 128x64.
+    async fn test_oled(&mut self, hardware: &mut impl Hardware) -> Result<(), D::Error> {
+        let mut size = OledSize::W128H32;
+        let mut pattern = TestPattern::Checkerboard;
+        loop {
+            let mut buffer = OledBuffer::new(size);
+            pattern.draw(&mut buffer);
+            let (bus, result) = match hardware.show_oled(&buffer, pattern.inverted()) {
+                Ok(shown) => shown,
+                Err(text) => {
+                    self.show(&NoticeScreen {
+                        title: "OLED patterns",
+                        text,
+                        footer: "Press any key to go back",
+                    })
+                    .await?;
+                    self.next_key_down().await?;
+                    return Ok(());
+                }
+            };
+            if let Err(error) = result {
+                defmt::warn!("couldn't drive the OLED: {}", error);
+            }
+            self.show(&OledScreen {
+                bus: bus.as_str(),
+                result,
+                pattern,
+                buffer: &buffer,
+            })
+            .await?;
+
+            loop {
+                match self.next_key_down().await?.char() {
+                    '*' => pattern = pattern.next(),
+                    '0' => size = size.other(),
+                    '#' => return Ok(()),
+                    _ => continue,
+                }
+                break;
+            }
+        }
+    }
+
+    /// Draws a full screen and sends it to the display.
+    async fn show<S>(&mut self, screen: &S) -> Result<(), D::Error>
+    where
+        S: Drawable<Color = Rgb565>,
+    {
+        screen.draw(self.device.frame())?;
+        self.device.flush().await
+    }
+
+    /// Waits for a key event. Returns `None` if no key was pressed or released within `timeout`.
+    async fn next_event(&mut self, timeout: Duration) -> Result<Option<KeyEvent>, D::Error> {
+        let input = self
+            .device
+            .next_input(timeout, core::future::pending::<Infallible>())
+            .await?;
+        Ok(input.map(|Input::Key(event)| event))
+    }
+
+    /// Like [`next_event`](Self::next_event), but also returns the output of `other` if it
+    /// completes first.
+    async fn next_input<T>(
+        &mut self,
+        timeout: Duration,
+        other: impl Future<Output = T>,
+    ) -> Result<Option<Input<T>>, D::Error> {
+        self.device.next_input(timeout, other).await
+    }
+
+    /// Waits for a key event, dimming the backlight after [`IDLE_TIMEOUT`] and restoring it
+    /// when a key is touched.
+    async fn next_event_dimming(&mut self) -> Result<KeyEvent, D::Error> {
+        loop {
+            if let Some(event) = self.next_event(IDLE_TIMEOUT).await? {
+                self.device.wake().await;
+                return Ok(event);
+            }
+            self.device.dim();
+        }
+    }
+
+    /// Dims the backlight and waits for a key event while `background` runs, e.g. to keep a
+    /// tool's hardware going without redrawing the screen, which would wake the backlight.
+    async fn next_event_dimmed_while(
+        &mut self,
+        background: impl Future<Output = Infallible>,
+    ) -> Result<KeyEvent, D::Error> {
+        let mut background = pin!(background);
+        loop {
+            self.device.dim();
+            match self.next_input(IDLE_TIMEOUT, background.as_mut()).await? {
+                Some(Input::Key(event)) => {
+                    self.device.wake().await;
+                    return Ok(event);
+                }
+                Some(Input::Other(never)) => match never {},
+                None => {}
+            }
+        }
+    }
+
+    async fn next_key_down(&mut self) -> Result<Key, D::Error> {
+        loop {
+            if let KeyEvent::KeyDown(key) = self.next_event_dimming().await? {
+                return Ok(key);
+            }
+        }
+    }
+}
+
+/// Takes a sample of each analog input.
+async fn sample_inputs(hardware: &mut impl Hardware, inputs: &mut [(u8, InputStats)]) {
+    for (gpio, stats) in inputs.iter_mut() {
+        if let Some(reading) = hardware.read_adc(*gpio).await {
+            stats.push(reading);
+        }
+    }
+}
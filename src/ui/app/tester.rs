@@ -0,0 +1,444 @@
+use core::{convert::Infallible, fmt::Write as _, future::pending, pin::pin};
+
+use embassy_futures::select::{Either5, select5};
+use embassy_sync::{pubsub::DynSubscriber, watch::DynReceiver};
+use embassy_time::{Duration, Instant, Timer, with_deadline, with_timeout};
+use embedded_graphics::{pixelcolor::Rgb888, primitives::Rectangle};
+use esp_hal::{
+    Blocking,
+    gpio::{AnyPin, Pin as _},
+    i2c::master::I2c,
+    peripherals::I2C0,
+};
+
+use super::{Device, Hardware, Input, KeyLedControl, NO_I2C_PINS, NO_KEY_LEDS, NO_LED_DRIVER};
+use crate::{
+    board::{BoardProfile, KeyLeds},
+    bounce::Capture,
+    display::{self, DisplayState, FrameBuffer},
+    encoder::{self, EncoderEvent},
+    error::{AppError, Context, ResultExt as _},
+    fault,
+    history::{self, Browser, TestResult},
+    i2c_scan::{self, Scan, ScanError},
+    kbd::{self, Key, KeyEvent, KeySet, analog::AnalogKeys, latency::LatencyMeter},
+    key_leds::{KeyLedBackend, KeyLedError, KeyLedOutputs, LedDriverChip},
+    oled::{self, OledBuffer, OledError},
+    rgb::LedChain,
+    screenshot, serial,
+    settings::Settings,
+    stream::{self, Update},
+    text::TextBuf,
+};
+
+const DIMMED_BRIGHTNESS_PCT: u8 = 8;
+
+const BOOT_FADE_DURATION: Duration = Duration::from_millis(800);
+const DIM_FADE_DURATION: Duration = Duration::from_millis(1500);
+const WAKE_FADE_DURATION: Duration = Duration::from_millis(160);
+
+/// How long an error stays on the screen if no key is pressed before the UI restarts.
+const ERROR_SCREEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Hardware of the board under test that only the tools use.
+pub struct ToolHardware {
+    pub led_chain: Option<LedChain>,
+    pub key_leds: Option<KeyLeds>,
+    /// The I2C peripheral, unless the keypad uses it.
+    pub i2c: Option<I2C0<'static>>,
+    /// SDA and SCL of the bus chosen with `set i2c`.
+    pub i2c_scan: Option<(AnyPin<'static>, AnyPin<'static>)>,
+}
+
+const I2C_TAKEN: &str = "The keypad's expander\nuses the I2C peripheral.";
+
+#[embassy_executor::task]
+pub async fn task(
+    mut display_state: DisplayState,
+    serial_tx: &'static serial::TxMutex,
+    profile: &'static BoardProfile,
+    mut settings: Settings,
+    mut settings_changes: DynReceiver<'static, Settings>,
+    mut hardware: ToolHardware,
+) {
+    defmt::info!("starting display task");
+
+    loop {
+        let Err(error) = ui_main(
+            &mut display_state,
+            serial_tx,
+            profile,
+            &mut settings,
+            &mut settings_changes,
+            &mut hardware,
+        )
+        .await;
+
+        defmt::error!("ui error: {}", error);
+
+        if let Err(display_error) = fault::show_error_screen(&mut display_state, &error).await {
+            fault::display_failed(display_error, display_state.backlight).await;
+        }
+
+        display_state
+            .backlight
+            .fade_to(settings.brightness_pct, WAKE_FADE_DURATION)
+            .await;
+
+        wait_for_key_press(ERROR_SCREEN_TIMEOUT).await;
+    }
+}
+
+async fn wait_for_key_press(timeout: Duration) {
+    let Ok(mut kbd_events) = kbd::subscriber() else {
+        Timer::after(timeout).await;
+        return;
+    };
+
+    let _ = with_timeout(timeout, async {
+        while !matches!(kbd_events.next_message_pure().await, KeyEvent::KeyDown(_)) {}
+    })
+    .await;
+}
+
+async fn ui_main(
+    display_state: &mut DisplayState,
+    serial_tx: &'static serial::TxMutex,
+    profile: &'static BoardProfile,
+    settings: &mut Settings,
+    settings_changes: &mut DynReceiver<'static, Settings>,
+    hardware: &mut ToolHardware,
+) -> Result<Infallible, AppError> {
+    let mut tester = Tester {
+        display_state,
+        serial_tx,
+        profile,
+        settings,
+        settings_changes,
+        kbd_events: kbd::subscriber()?,
+        fade_in: BOOT_FADE_DURATION,
+        dimming: false,
+    };
+    let mut tools = Tools {
+        hardware,
+        encoder_events: None,
+    };
+
+    super::run(&mut tester, &mut tools).await
+}
+
+/// The tester's display and keypad.
+struct Tester<'a> {
+    display_state: &'a mut DisplayState,
+    serial_tx: &'static serial::TxMutex,
+    profile: &'static BoardProfile,
+    settings: &'a mut Settings,
+    settings_changes: &'a mut DynReceiver<'static, Settings>,
+    kbd_events: DynSubscriber<'static, KeyEvent>,
+    /// Duration of the next fade to full brightness.
+    fade_in: Duration,
+    /// Set while the backlight should fade to dimmed, which it does while waiting for input.
+    dimming: bool,
+}
+
+impl Tester<'_> {
+    /// Takes over changed settings. A new brightness is faded to unless the backlight is dimmed.
+    async fn apply_settings(&mut self, settings: Settings) {
+        let backlight = &mut self.display_state.backlight;
+        if backlight.brightness_pct() == self.settings.brightness_pct {
+            backlight
+                .fade_to(settings.brightness_pct, WAKE_FADE_DURATION)
+                .await;
+        }
+        *self.settings = settings;
+    }
+}
+
+impl Device for Tester<'_> {
+    type Frame = FrameBuffer;
+    type Error = AppError;
+
+    fn board(&self) -> &'static BoardProfile {
+        self.profile
+    }
+
+    fn settings(&self) -> &Settings {
+        self.settings
+    }
+
+    fn frame(&mut self) -> &mut FrameBuffer {
+        &mut self.display_state.fb
+    }
+
+    async fn flush(&mut self) -> Result<(), AppError> {
+        self.display_state
+            .display
+            .show_raw_data(
+                0,
+                0,
+                display::WIDTH,
+                display::HEIGHT,
+                self.display_state.fb.as_bytes(),
+            )
+            .await
+            .context(Context::FrameTransfer)?;
+
+        self.wake().await;
+        Ok(())
+    }
+
+    async fn flush_area(&mut self, bounds: Rectangle) -> Result<(), AppError> {
+        let fb = &self.display_state.fb;
+
+        let y = bounds.top_left.y as usize;
+        let height = bounds.size.height as usize;
+
+        let stripe_start = y * fb.width() * display::PIXEL_SIZE;
+        let stripe_end = (y + height) * fb.width() * display::PIXEL_SIZE;
+
+        let pixel_data = &fb.as_bytes()[stripe_start..stripe_end];
+
+        self.display_state
+            .display
+            .show_raw_data(0, y as u16, display::WIDTH, height as u16, pixel_data)
+            .await
+            .context(Context::FrameTransfer)?;
+
+        self.wake().await;
+        Ok(())
+    }
+
+    async fn wake(&mut self) {
+        self.dimming = false;
+        let backlight = &mut self.display_state.backlight;
+        if backlight.brightness_pct() != self.settings.brightness_pct {
+            backlight
+                .fade_to(self.settings.brightness_pct, self.fade_in)
+                .await;
+        }
+        self.fade_in = WAKE_FADE_DURATION;
+    }
+
+    fn dim(&mut self) {
+        self.dimming = true;
+    }
+
+    /// Also sends screenshots and applies settings when asked to.
+    async fn next_input<T>(
+        &mut self,
+        timeout: Duration,
+        other: impl Future<Output = T>,
+    ) -> Result<Option<Input<T>>, AppError> {
+        let deadline = Instant::now() + timeout;
+        let mut other = pin!(other);
+
+        loop {
+            let dimming = self.dimming;
+            let dimmed_pct = DIMMED_BRIGHTNESS_PCT.min(self.settings.brightness_pct);
+            let backlight = &mut self.display_state.backlight;
+            let fade = async move {
+                if dimming {
+                    backlight.fade_to(dimmed_pct, DIM_FADE_DURATION).await;
+                } else {
+                    pending::<()>().await;
+                }
+            };
+            let next = select5(
+                self.kbd_events.next_message_pure(),
+                serial::SCREENSHOT_REQUEST.wait(),
+                self.settings_changes.changed(),
+                other.as_mut(),
+                fade,
+            );
+
+            match with_deadline(deadline, next).await {
+                Ok(Either5::First(event)) => {
+                    if self.dimming {
+                        self.dimming = false;
+                        self.display_state
+                            .backlight
+                            .set_brightness_pct(self.settings.brightness_pct);
+                    }
+                    return Ok(Some(Input::Key(event)));
+                }
+                Ok(Either5::Second(())) => {
+                    screenshot::send(self.serial_tx, self.display_state.fb.as_bytes()).await?;
+                }
+                Ok(Either5::Third(settings)) => self.apply_settings(settings).await,
+                Ok(Either5::Fourth(output)) => return Ok(Some(Input::Other(output))),
+                Ok(Either5::Fifth(())) => self.dimming = false,
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+
+    fn emit(&mut self, update: Update) {
+        stream::emit(update);
+    }
+
+    fn history_available(&self) -> bool {
+        history::is_available()
+    }
+
+    fn save_result(&mut self, result: &TestResult) -> Result<(), AppError> {
+        history::append(result)
+    }
+
+    fn result_count(&mut self) -> Result<usize, AppError> {
+        history::count()
+    }
+
+    fn browse_results(&mut self) -> Result<Option<Browser>, AppError> {
+        history::browse()
+    }
+
+    fn older_result(&mut self, browser: &mut Browser) -> Result<bool, AppError> {
+        history::older(browser)
+    }
+
+    fn newer_result(&mut self, browser: &mut Browser) -> Result<bool, AppError> {
+        history::newer(browser)
+    }
+}
+
+/// The tool hardware, along with the encoder events while the encoder test runs.
+struct Tools<'a> {
+    hardware: &'a mut ToolHardware,
+    encoder_events: Option<DynSubscriber<'static, EncoderEvent>>,
+}
+
+impl Tools<'_> {
+    /// Runs `f` on the bus chosen with `set i2c`, or else the bus of the board's LED driver.
+    fn with_i2c_bus<R>(
+        &mut self,
+        f: impl FnOnce(I2c<'_, Blocking>) -> R,
+    ) -> Result<(TextBuf<24>, R), &'static str> {
+        let hardware = &mut *self.hardware;
+        let i2c = hardware.i2c.as_mut().ok_or(I2C_TAKEN)?;
+        let (sda, scl) = match (&mut hardware.i2c_scan, &mut hardware.key_leds) {
+            (Some((sda, scl)), _) | (None, Some(KeyLeds::I2c { sda, scl, .. })) => (sda, scl),
+            _ => return Err(NO_I2C_PINS),
+        };
+        let mut bus = TextBuf::new();
+        let _ = write!(bus, "SDA {}, SCL {}", sda.number(), scl.number());
+        Ok((bus, f(i2c_scan::open_bus(i2c, sda, scl))))
+    }
+}
+
+impl Hardware for Tools<'_> {
+    type Error = AppError;
+
+    fn analyse_bounce(&mut self, key: Option<Key>) {
+        kbd::analyse_bounce(key);
+    }
+
+    async fn next_capture(&mut self) -> Capture {
+        kbd::next_capture().await
+    }
+
+    fn with_latency_meter<R>(&mut self, f: impl FnOnce(&mut LatencyMeter) -> R) -> R {
+        kbd::with_latency_meter(f)
+    }
+
+    fn follow_encoder(&mut self, follow: bool) -> Result<(), AppError> {
+        self.encoder_events = if follow {
+            Some(encoder::subscriber()?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    async fn next_encoder_event(&mut self) -> EncoderEvent {
+        match &mut self.encoder_events {
+            Some(events) => events.next_message_pure().await,
+            None => pending().await,
+        }
+    }
+
+    fn with_analog_keys<R>(&mut self, f: impl FnOnce(&mut AnalogKeys) -> R) -> R {
+        kbd::with_analog_keys(f)
+    }
+
+    async fn read_adc(&mut self, gpio: u8) -> Option<u16> {
+        crate::adc::read(gpio).await
+    }
+
+    fn has_led_chain(&self) -> bool {
+        self.hardware.led_chain.is_some()
+    }
+
+    fn show_leds(&mut self, colors: impl IntoIterator<Item = Rgb888>) {
+        if let Some(chain) = &mut self.hardware.led_chain
+            && let Err(error) = chain.show(colors)
+        {
+            defmt::warn!("couldn't drive the LED chain: {}", error);
+        }
+    }
+
+    async fn with_key_leds<R>(
+        &mut self,
+        f: impl AsyncFnOnce(Result<&mut dyn KeyLedControl, &'static str>) -> R,
+    ) -> R {
+        // Taken out of `self` while the outputs borrow them, and put back however the test ends.
+        let mut pins = self.hardware.key_leds.take();
+        let mut i2c = self.hardware.i2c.take();
+
+        let result = match pins
+            .as_mut()
+            .map(|pins| KeyLedOutputs::new(pins, i2c.as_mut()))
+        {
+            Some(Ok(mut leds)) => {
+                let result = f(Ok(&mut leds)).await;
+                if let Err(error) = leds.init() {
+                    defmt::warn!("couldn't turn the key LEDs off: {}", error);
+                }
+                result
+            }
+            Some(Err(error)) => {
+                defmt::warn!("couldn't set up the key LEDs: {}", error);
+                f(Err(match error {
+                    KeyLedError::BusTaken => I2C_TAKEN,
+                    KeyLedError::NoDriver => NO_LED_DRIVER,
+                    KeyLedError::I2cConfig(_) | KeyLedError::I2c(_) => {
+                        "Couldn't set up the\nLED driver."
+                    }
+                }))
+                .await
+            }
+            None => f(Err(NO_KEY_LEDS)).await,
+        };
+
+        self.hardware.key_leds = pins;
+        self.hardware.i2c = i2c;
+        result
+    }
+
+    fn scan_i2c(&mut self) -> Result<(TextBuf<24>, Result<Scan, ScanError>), &'static str> {
+        self.with_i2c_bus(|mut i2c| i2c_scan::scan(&mut i2c))
+    }
+
+    fn show_oled(
+        &mut self,
+        buffer: &OledBuffer,
+        inverted: bool,
+    ) -> Result<(TextBuf<24>, Result<u8, OledError>), &'static str> {
+        self.with_i2c_bus(|i2c| oled::find_and_show(i2c, buffer, inverted))
+    }
+}
+
+impl KeyLedControl for KeyLedOutputs<'_> {
+    fn driver(&self) -> Option<(LedDriverChip, u8)> {
+        KeyLedOutputs::driver(self)
+    }
+
+    fn leds(&self) -> KeySet {
+        KeyLedOutputs::leds(self)
+    }
+
+    fn set(&mut self, key: Key, brightness: u8) {
+        if let Err(error) = KeyLedBackend::set(self, key, brightness) {
+            defmt::warn!("couldn't set the LED of key {}: {}", key.char(), error);
+        }
+    }
+}